@@ -0,0 +1,22 @@
+//! Content-Length framing: bodies are returned exactly as long as declared
+#![no_main]
+
+use ccgonext::mcp::{read_lsp_message, MAX_CONTENT_LENGTH};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let mut reader = data;
+        for _ in 0..=data.len() {
+            match read_lsp_message(&mut reader).await {
+                Ok(None) => break,
+                Ok(Some(message)) => assert!(message.len() <= MAX_CONTENT_LENGTH),
+                Err(e) if e.is_fatal() => break,
+                Err(_) => {}
+            }
+        }
+    });
+});
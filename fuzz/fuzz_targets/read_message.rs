@@ -0,0 +1,25 @@
+//! Auto-detected framing: any input must yield messages, framing errors or
+//! EOF, never a panic or an endless loop
+#![no_main]
+
+use ccgonext::mcp::{read_message, TransportMode, MAX_CONTENT_LENGTH};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let mut reader = data;
+        let mut mode = TransportMode::AutoDetect;
+        // Every call consumes input, so this ends within len + 1 iterations
+        for _ in 0..=data.len() {
+            match read_message(&mut reader, &mut mode).await {
+                Ok(None) => break,
+                Ok(Some(message)) => assert!(message.len() <= MAX_CONTENT_LENGTH + 2),
+                Err(e) if e.is_fatal() => break,
+                Err(_) => {}
+            }
+        }
+    });
+});
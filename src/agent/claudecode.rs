@@ -42,7 +42,8 @@ use anyhow::Result;
 use async_trait::async_trait;
 use regex::Regex;
 use std::path::Path;
-use std::time::{Duration, Instant};
+use std::time::Duration;
+use tokio::time::Instant;
 
 pub struct ClaudeCodeAgent {
     command: String,
@@ -166,9 +167,12 @@ impl ClaudeCodeAgent {
             pending_line = String::new();
 
             while let Some(line) = lines_iter.next() {
-                // Keep incomplete line for next chunk
-                if !ends_with_newline && lines_iter.peek().is_none() {
-                    pending_line = line.to_string();
+                // Keep incomplete line for next chunk; after a trailing
+                // newline the last piece is empty and not a line at all
+                if lines_iter.peek().is_none() {
+                    if !ends_with_newline {
+                        pending_line = line.to_string();
+                    }
                     break;
                 }
 
@@ -243,7 +247,7 @@ impl Agent for ClaudeCodeAgent {
     fn inject_message_sentinel(&self, message: &str, message_id: &str) -> String {
         // Use comment format to avoid ClaudeCode interpreting it
         // Format: # CCGONEXT_MSG_ID:<uuid>\n<message>\n\nIMPORTANT: End with done marker
-        let done_marker = format!("CCGO_DONE: {}", message_id);
+        let done_marker = self.done_marker(message_id);
         format!(
             "# CCGONEXT_MSG_ID:{}\n{}\n\n\
             IMPORTANT:\n\
@@ -264,6 +268,10 @@ impl Agent for ClaudeCodeAgent {
         &self.done_pattern
     }
 
+    fn done_marker(&self, message_id: &str) -> String {
+        format!("CCGO_DONE: {}", message_id)
+    }
+
     fn is_reply_complete(&self, text: &str, message_id: &str) -> bool {
         text.lines()
             .rev()
@@ -466,4 +474,25 @@ mod tests {
         assert!(injected.contains("IMPORTANT:"));
         assert!(injected.contains("End your reply"));
     }
+
+    #[tokio::test]
+    async fn test_parse_pty_response_across_line_chunks() {
+        let agent = ClaudeCodeAgent::new();
+        let message_id = "12345678-1234-1234-1234-123456789abc";
+        let (pty, driver) = PtyHandle::detached(4096);
+
+        // Chunks that end on a newline must not add blank lines
+        for chunk in [
+            format!("# CCGONEXT_MSG_ID:{}\r\n", message_id),
+            "First line\r\n".to_string(),
+            "\r\nSecond ".to_string(),
+            "line\r\n".to_string(),
+            "\r\n> \r\n".to_string(),
+        ] {
+            driver.output(chunk.as_bytes()).await;
+        }
+
+        let reply = agent.parse_pty_response(&pty, 0, message_id).await.unwrap();
+        assert_eq!(reply, "First line\n\nSecond line\n");
+    }
 }
@@ -32,6 +32,9 @@ pub trait Agent: Send + Sync {
 
     fn get_done_regex(&self) -> &str;
 
+    /// The final line the agent is asked to end its reply to `message_id` with
+    fn done_marker(&self, message_id: &str) -> String;
+
     fn is_reply_complete(&self, text: &str, message_id: &str) -> bool;
 
     fn strip_done_marker(&self, text: &str, message_id: &str) -> String;
@@ -105,7 +108,7 @@ impl Agent for GenericAgent {
             .replace("{id}", message_id)
             .replace("{message}", message);
 
-        let done_marker = self.done_template.replace("{id}", message_id);
+        let done_marker = self.done_marker(message_id);
 
         format!(
             "{}\n\n\
@@ -126,6 +129,10 @@ impl Agent for GenericAgent {
         &self.done_regex
     }
 
+    fn done_marker(&self, message_id: &str) -> String {
+        self.done_template.replace("{id}", message_id)
+    }
+
     fn is_reply_complete(&self, text: &str, message_id: &str) -> bool {
         let pattern = self.done_regex.replace("{id}", &regex::escape(message_id));
         let re = match regex::Regex::new(&pattern) {
@@ -205,6 +212,8 @@ mod tests {
             done_template: "CCGO_DONE: {id}".to_string(),
             done_regex: r"(?mi)^\s*CCGO_DONE:\s*{id}\s*$".to_string(),
             use_stability_heuristic: true,
+            sandbox: None,
+            launch: Default::default(),
         }
     }
 
@@ -276,6 +285,11 @@ mod tests {
         assert!(injected.contains("CCGO_DONE:"));
         assert!(injected.contains("IMPORTANT:"));
         assert!(injected.contains("End your reply"));
+        assert!(injected.ends_with(&agent.done_marker(message_id)));
+        assert_eq!(
+            agent.done_marker(message_id),
+            "CCGO_DONE: 12345678-1234-1234-1234-123456789abc"
+        );
     }
 
     #[test]
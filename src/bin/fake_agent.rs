@@ -0,0 +1,886 @@
+//! Stand-in for the Codex, Gemini, OpenCode and Claude CLIs
+//!
+//! Behaves the way ccgonext sees the real agents: it prints the startup
+//! banner the agent's ready pattern waits for, echoes typed input, streams a
+//! scripted reply to each submitted prompt (ending with the `CCGO_DONE: <id>`
+//! line the prompt asks for) and keeps a session log in the vendor's layout
+//! so the log providers can harvest it. Claude replies are only read from the
+//! terminal, so the claude flavor writes no log.
+//!
+//! `--fail` turns one prompt into a failure: a crash, a hang, rate-limit text
+//! instead of a reply, or a reply without the done marker. Every option can
+//! also be given as a `CCGONEXT_FAKE_*` variable, which is how tests configure
+//! agents started by `ccgonext serve`.
+
+use ccgonext::digest::{hex, sha256_hex};
+use chrono::{DateTime, Utc};
+use clap::{Parser, ValueEnum};
+use regex::Regex;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::fs::{self, OpenOptions};
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Flavor {
+    Codex,
+    Gemini,
+    Opencode,
+    Claude,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Failure {
+    /// Print part of the reply, then exit with status 1
+    Crash,
+    /// Accept the prompt and never answer
+    Hang,
+    /// Answer with the vendor's rate-limit error instead of a reply
+    RateLimit,
+    /// Reply without the done marker
+    NoMarker,
+}
+
+#[derive(Debug, Parser)]
+#[command(name = "ccgonext-fake-agent", version)]
+#[command(about = "Scripted stand-in for AI agent CLIs, for ccgonext tests")]
+struct Args {
+    /// Agent to imitate [env: CCGONEXT_FAKE_FLAVOR]
+    #[arg(long, value_enum, env = "CCGONEXT_FAKE_FLAVOR")]
+    flavor: Flavor,
+
+    /// Reply to every prompt; {prompt} is the prompt without sentinel and instructions, {n} its number [env: CCGONEXT_FAKE_REPLY]
+    #[arg(
+        long,
+        default_value = "Fake reply {n}: {prompt}",
+        env = "CCGONEXT_FAKE_REPLY"
+    )]
+    reply: String,
+
+    /// Failure to script [env: CCGONEXT_FAKE_FAIL]
+    #[arg(long, value_enum, env = "CCGONEXT_FAKE_FAIL")]
+    fail: Option<Failure>,
+
+    /// Which prompt fails (1-based); 0 fails every prompt [env: CCGONEXT_FAKE_FAIL_ON]
+    #[arg(long, default_value = "1", env = "CCGONEXT_FAKE_FAIL_ON")]
+    fail_on: usize,
+
+    /// Delay before the banner in milliseconds [env: CCGONEXT_FAKE_STARTUP_MS]
+    #[arg(long, default_value = "100", env = "CCGONEXT_FAKE_STARTUP_MS")]
+    startup_ms: u64,
+
+    /// Delay between submitting a prompt and the first reply word in milliseconds [env: CCGONEXT_FAKE_THINK_MS]
+    #[arg(long, default_value = "200", env = "CCGONEXT_FAKE_THINK_MS")]
+    think_ms: u64,
+
+    /// Delay between streamed words in milliseconds [env: CCGONEXT_FAKE_WORD_MS]
+    #[arg(long, default_value = "30", env = "CCGONEXT_FAKE_WORD_MS")]
+    word_ms: u64,
+
+    /// Log root in place of the vendor default (the directory ccgonext's provider reads) [env: CCGONEXT_FAKE_LOG_ROOT]
+    #[arg(long, env = "CCGONEXT_FAKE_LOG_ROOT")]
+    log_root: Option<PathBuf>,
+
+    /// Working directory, accepted like the real CLIs' --cwd
+    #[arg(long)]
+    cwd: Option<PathBuf>,
+}
+
+/// Ctrl+C, the interrupt sequence ccgonext sends
+const INTERRUPT: u8 = 0x03;
+
+fn main() {
+    let args = Args::parse();
+    if let Some(cwd) = &args.cwd {
+        if let Err(e) = std::env::set_current_dir(cwd) {
+            eprintln!("Error: cannot change to {:?}: {}", cwd, e);
+            std::process::exit(2);
+        }
+    }
+
+    // Real agents are full-screen TUIs: keystrokes arrive one by one and a
+    // bare CR submits, so newlines inside a prompt do not end it
+    let _raw_mode = io::stdin()
+        .is_terminal()
+        .then(|| crossterm::terminal::enable_raw_mode().ok().map(|_| RawMode))
+        .flatten();
+
+    let log = match SessionLog::open(args.flavor, args.log_root.clone()) {
+        Ok(log) => log,
+        Err(e) => {
+            eprintln!("Error: cannot create session log: {}", e);
+            std::process::exit(2);
+        }
+    };
+    FakeAgent::new(args, log).run();
+}
+
+struct RawMode;
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = crossterm::terminal::disable_raw_mode();
+    }
+}
+
+/// Stdin bytes, read on a thread so streaming can notice an interrupt
+fn spawn_input() -> Receiver<u8> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut stdin = io::stdin();
+        let mut buf = [0u8; 1024];
+        loop {
+            match stdin.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    for &byte in &buf[..n] {
+                        if tx.send(byte).is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+    });
+    rx
+}
+
+/// A submitted prompt, split the way ccgonext builds it
+#[derive(Debug, Clone, PartialEq)]
+struct Prompt {
+    message_id: Option<String>,
+    message: String,
+}
+
+impl Prompt {
+    fn parse(flavor: Flavor, text: &str) -> Self {
+        let sentinel = match flavor {
+            Flavor::Codex => r"^# MSG_ID:([a-f0-9-]+)\n?",
+            Flavor::Gemini => r"^\[MSG_ID:([a-f0-9-]+)\]\n?",
+            Flavor::Opencode => r"^\[\[MSG:([a-f0-9-]+)\]\]\n?",
+            Flavor::Claude => r"^#\s*CCGONEXT_MSG_ID:\s*([0-9a-f-]{36})\n?",
+        };
+        let sentinel = Regex::new(sentinel).expect("valid sentinel regex");
+        let done = Regex::new(r"CCGO_DONE:\s*([0-9a-f-]{36})").expect("valid done regex");
+
+        let body = text.split("\n\nIMPORTANT:").next().unwrap_or_default();
+        let (message_id, message) = match sentinel.captures(body) {
+            Some(c) => (Some(c[1].to_string()), body[c[0].len()..].to_string()),
+            None => (
+                done.captures(text).map(|c| c[1].to_string()),
+                body.to_string(),
+            ),
+        };
+        Self {
+            message_id,
+            message: message.trim().to_string(),
+        }
+    }
+}
+
+struct FakeAgent {
+    args: Args,
+    log: SessionLog,
+    input: Option<Receiver<u8>>,
+    /// Bytes typed while a reply was streaming, processed afterwards
+    pending: Vec<u8>,
+    prompts: usize,
+}
+
+impl FakeAgent {
+    fn new(args: Args, log: SessionLog) -> Self {
+        Self {
+            args,
+            log,
+            input: None,
+            pending: Vec::new(),
+            prompts: 0,
+        }
+    }
+
+    fn run(mut self) {
+        self.input = Some(spawn_input());
+        sleep_ms(self.args.startup_ms);
+        self.banner();
+
+        let mut line: Vec<u8> = Vec::new();
+        while let Some(byte) = self.next_byte() {
+            match byte {
+                b'\r' => {
+                    let text = String::from_utf8_lossy(&line).to_string();
+                    let extra_lines = text.matches('\n').count();
+                    if extra_lines > 0 {
+                        self.out(&format!(" [+{} lines]", extra_lines));
+                    }
+                    self.out("\r\n");
+                    line.clear();
+                    if !text.trim().is_empty() {
+                        self.submit(&text);
+                    }
+                    self.prompt();
+                }
+                // Backspace from a terminal user
+                0x7f | 0x08 => {
+                    if line.pop().is_some() && !line.contains(&b'\n') {
+                        self.out("\x08 \x08");
+                    }
+                }
+                INTERRUPT => {}
+                _ => {
+                    // Only the first line is echoed; TUIs keep the rest of a
+                    // multi-line draft in the input box
+                    if !line.contains(&b'\n') && byte != b'\n' {
+                        self.out_bytes(&[byte]);
+                    }
+                    line.push(byte);
+                }
+            }
+        }
+    }
+
+    fn next_byte(&mut self) -> Option<u8> {
+        if !self.pending.is_empty() {
+            return Some(self.pending.remove(0));
+        }
+        self.input.as_ref()?.recv().ok()
+    }
+
+    /// Sleep, buffering input; true when Ctrl+C arrived
+    fn pause(&mut self, ms: u64) -> bool {
+        sleep_ms(ms);
+        let Some(input) = &self.input else {
+            return false;
+        };
+        loop {
+            match input.try_recv() {
+                Ok(INTERRUPT) => return true,
+                Ok(byte) => self.pending.push(byte),
+                Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => return false,
+            }
+        }
+    }
+
+    fn out(&self, text: &str) {
+        self.out_bytes(text.as_bytes());
+    }
+
+    fn out_bytes(&self, bytes: &[u8]) {
+        let mut stdout = io::stdout().lock();
+        let _ = stdout.write_all(bytes);
+        let _ = stdout.flush();
+    }
+
+    /// The startup screen each agent's ready pattern matches
+    fn banner(&mut self) {
+        match self.args.flavor {
+            Flavor::Codex => {
+                self.out("\x1b[1m>_ OpenAI Codex\x1b[0m (fake)\r\n\r\n");
+                // The ready pattern is anchored, so the prompt must start its
+                // own chunk of output
+                sleep_ms(50);
+                self.out(">");
+                sleep_ms(10);
+                self.out(" Ask Codex to do anything\r\n");
+            }
+            Flavor::Gemini => {
+                self.out("\x1b[1mGemini CLI\x1b[0m (fake)\r\n\r\nTips for getting started:\r\n");
+                self.out("> \x1b[2m  Type your message or @path/to/file\x1b[0m\r\n");
+            }
+            Flavor::Opencode => {
+                self.out("\x1b[1mopencode\x1b[0m (fake)\r\n\r\n");
+                self.out("\x1b[2menter send   ctrl+x h help\x1b[0m\r\n> ");
+            }
+            Flavor::Claude => {
+                self.out("\u{273b} Welcome to Claude Code (fake)\r\n");
+                self.prompt();
+            }
+        }
+    }
+
+    fn prompt(&self) {
+        match self.args.flavor {
+            Flavor::Claude => self.out("\r\n> \r\n"),
+            _ => self.out("\r\n> "),
+        }
+    }
+
+    fn submit(&mut self, text: &str) {
+        self.prompts += 1;
+        let prompt = Prompt::parse(self.args.flavor, text);
+        let failure = self
+            .args
+            .fail
+            .filter(|_| self.args.fail_on == 0 || self.args.fail_on == self.prompts);
+        self.log.user(text);
+
+        if self.pause(self.args.think_ms) {
+            self.interrupted();
+            return;
+        }
+
+        match failure {
+            Some(Failure::Hang) => {
+                self.out("Working\u{2026}\r\n");
+                loop {
+                    std::thread::sleep(Duration::from_secs(3600));
+                }
+            }
+            Some(Failure::RateLimit) => {
+                self.out(&format!("{}\r\n", self.rate_limit_text()));
+                return;
+            }
+            _ => {}
+        }
+
+        let reply = self
+            .args
+            .reply
+            .replace("{prompt}", &prompt.message)
+            .replace("{n}", &self.prompts.to_string());
+        let mut full = reply.clone();
+        if failure != Some(Failure::NoMarker) {
+            if let Some(id) = &prompt.message_id {
+                full.push_str(&format!("\n\nCCGO_DONE: {}", id));
+            }
+        }
+
+        let words: Vec<&str> = full.split_inclusive([' ', '\n']).collect();
+        let mut streamed = String::new();
+        for (i, word) in words.iter().enumerate() {
+            if failure == Some(Failure::Crash) && i == words.len() / 2 {
+                self.out("\r\nError: fake agent crashed (scripted)\r\n");
+                std::process::exit(1);
+            }
+            streamed.push_str(word);
+            self.out(&word.replace('\n', "\r\n"));
+            self.log.progress(&streamed);
+            if self.pause(self.args.word_ms) {
+                self.interrupted();
+                return;
+            }
+        }
+        if failure == Some(Failure::Crash) {
+            self.out("\r\nError: fake agent crashed (scripted)\r\n");
+            std::process::exit(1);
+        }
+        self.out("\r\n");
+
+        let input_tokens = text.split_whitespace().count() as u64;
+        let output_tokens = full.split_whitespace().count() as u64;
+        self.log.reply(&full, input_tokens, output_tokens);
+    }
+
+    fn interrupted(&mut self) {
+        self.out("\r\n\x1b[2mInterrupted\x1b[0m\r\n");
+        self.log.interrupted();
+    }
+
+    fn rate_limit_text(&self) -> &'static str {
+        match self.args.flavor {
+            Flavor::Codex => {
+                "\u{25a0} stream error: exceeded retry limit, last status: 429 Too Many Requests"
+            }
+            Flavor::Gemini => {
+                "\u{2715} [API Error: Quota exceeded for model gemini-2.5-pro. (Status: 429 RESOURCE_EXHAUSTED)]"
+            }
+            Flavor::Opencode => "ERROR AI_APICallError: Rate limit reached (429)",
+            Flavor::Claude => {
+                "API Error: 429 {\"type\":\"error\",\"error\":{\"type\":\"rate_limit_error\",\"message\":\"Rate limit exceeded\"}}"
+            }
+        }
+    }
+}
+
+fn sleep_ms(ms: u64) {
+    if ms > 0 {
+        std::thread::sleep(Duration::from_millis(ms));
+    }
+}
+
+fn millis(time: DateTime<Utc>) -> i64 {
+    time.timestamp_millis()
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+}
+
+fn home() -> PathBuf {
+    dirs::home_dir().unwrap_or_else(|| PathBuf::from("."))
+}
+
+fn env_path(name: &str) -> Option<PathBuf> {
+    std::env::var_os(name)
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+}
+
+fn cwd() -> String {
+    std::env::current_dir()
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// The session file(s) an agent keeps, in the vendor's layout
+enum SessionLog {
+    /// `sessions/YYYY/MM/DD/rollout-*.jsonl`, one event per line
+    Codex {
+        path: PathBuf,
+        total_tokens: u64,
+    },
+    /// `<sha256(cwd)>/chats/session-*.json`, rewritten as the reply streams
+    Gemini {
+        path: PathBuf,
+        chat: Value,
+        streaming: bool,
+    },
+    /// `session/<project>/ses_*.json`, `message/<session>/msg_*.json` and
+    /// `part/<message>/prt_*.json`
+    OpenCode {
+        root: PathBuf,
+        session: Value,
+        session_path: PathBuf,
+        assistant: Option<Value>,
+        next_id: u64,
+    },
+    None,
+}
+
+impl SessionLog {
+    fn open(flavor: Flavor, log_root: Option<PathBuf>) -> io::Result<Self> {
+        let now = Utc::now();
+        let id = uuid::Uuid::new_v4().to_string();
+        match flavor {
+            Flavor::Codex => {
+                let root = log_root
+                    .or_else(|| env_path("CODEX_HOME").map(|h| h.join("sessions")))
+                    .unwrap_or_else(|| home().join(".codex/sessions"));
+                let path = root.join(now.format("%Y/%m/%d").to_string()).join(format!(
+                    "rollout-{}-{}.jsonl",
+                    now.format("%Y-%m-%dT%H-%M-%S"),
+                    id
+                ));
+                let log = Self::Codex {
+                    path,
+                    total_tokens: 0,
+                };
+                log.append(&[json!({
+                    "timestamp": timestamp(now),
+                    "type": "session_meta",
+                    "payload": {
+                        "id": id,
+                        "timestamp": timestamp(now),
+                        "cwd": cwd(),
+                        "originator": "codex_cli_rs",
+                        "cli_version": "0.0.0-fake"
+                    }
+                })])?;
+                Ok(log)
+            }
+            Flavor::Gemini => {
+                let root = log_root
+                    .or_else(|| env_path("GEMINI_ROOT"))
+                    .unwrap_or_else(|| home().join(".gemini/tmp"));
+                let project_hash = sha256_hex(cwd());
+                let path = root.join(&project_hash).join("chats").join(format!(
+                    "session-{}-{}.json",
+                    now.format("%Y-%m-%dT%H-%M"),
+                    &id[..8]
+                ));
+                let log = Self::Gemini {
+                    path,
+                    chat: json!({
+                        "sessionId": id,
+                        "projectHash": project_hash,
+                        "startTime": timestamp(now),
+                        "lastUpdated": timestamp(now),
+                        "messages": []
+                    }),
+                    streaming: false,
+                };
+                log.save()?;
+                Ok(log)
+            }
+            Flavor::Opencode => {
+                let root = log_root
+                    .or_else(|| env_path("OPENCODE_STORAGE_ROOT"))
+                    .or_else(|| env_path("XDG_DATA_HOME").map(|d| d.join("opencode/storage")))
+                    .unwrap_or_else(|| home().join(".local/share/opencode/storage"));
+                let project = hex(&Sha256::digest(cwd().as_bytes())[..8]);
+                let session_id = format!("ses_{:x}{}", millis(now), &id[..8]);
+                let session_path = root
+                    .join("session")
+                    .join(&project)
+                    .join(format!("{}.json", session_id));
+                let log = Self::OpenCode {
+                    root,
+                    session: json!({
+                        "id": session_id,
+                        "projectID": project,
+                        "directory": cwd(),
+                        "title": "Fake session",
+                        "version": "0.0.0-fake",
+                        "time": {"created": millis(now), "updated": millis(now)}
+                    }),
+                    session_path,
+                    assistant: None,
+                    next_id: 0,
+                };
+                log.save()?;
+                Ok(log)
+            }
+            Flavor::Claude => Ok(Self::None),
+        }
+    }
+
+    fn user(&mut self, text: &str) {
+        let now = Utc::now();
+        match self {
+            Self::Codex { .. } => {
+                let _ = self.append(&[
+                    json!({
+                        "timestamp": timestamp(now),
+                        "type": "turn_context",
+                        "payload": {"cwd": cwd(), "model": "fake-codex"}
+                    }),
+                    json!({
+                        "timestamp": timestamp(now),
+                        "type": "response_item",
+                        "payload": {
+                            "type": "message",
+                            "role": "user",
+                            "content": [{"type": "input_text", "text": text}]
+                        }
+                    }),
+                ]);
+            }
+            Self::Gemini {
+                chat, streaming, ..
+            } => {
+                *streaming = false;
+                push_message(
+                    chat,
+                    json!({
+                        "id": uuid::Uuid::new_v4().to_string(),
+                        "timestamp": timestamp(now),
+                        "type": "user",
+                        "content": text
+                    }),
+                );
+                let _ = self.save();
+            }
+            Self::OpenCode { session, .. } => {
+                let session_id = session["id"].clone();
+                let message_id = self.next_id("msg");
+                let message = json!({
+                    "id": message_id,
+                    "sessionID": session_id,
+                    "role": "user",
+                    "time": {"created": millis(now)}
+                });
+                let _ = self.write_message(&message);
+                let _ = self.write_part(&message, text, now);
+                let _ = self.save();
+            }
+            Self::None => {}
+        }
+    }
+
+    /// The reply so far, for logs that are written while it streams
+    fn progress(&mut self, text: &str) {
+        let now = Utc::now();
+        match self {
+            Self::Gemini {
+                chat, streaming, ..
+            } => {
+                if !*streaming {
+                    *streaming = true;
+                    push_message(
+                        chat,
+                        json!({
+                            "id": uuid::Uuid::new_v4().to_string(),
+                            "timestamp": timestamp(now),
+                            "type": "gemini",
+                            "content": "",
+                            "model": "fake-gemini"
+                        }),
+                    );
+                }
+                set_last_content(chat, text, now);
+                let _ = self.save();
+            }
+            Self::OpenCode { .. } => {
+                let message = self.assistant(now);
+                let _ = self.write_part(&message, text, now);
+            }
+            Self::Codex { .. } | Self::None => {}
+        }
+    }
+
+    /// The finished reply
+    fn reply(&mut self, text: &str, input_tokens: u64, output_tokens: u64) {
+        let now = Utc::now();
+        match self {
+            Self::Codex { total_tokens, .. } => {
+                *total_tokens += input_tokens + output_tokens;
+                let total = *total_tokens;
+                let _ = self.append(&[
+                    json!({
+                        "timestamp": timestamp(now),
+                        "type": "response_item",
+                        "payload": {
+                            "type": "message",
+                            "role": "assistant",
+                            "content": [{"type": "output_text", "text": text}]
+                        }
+                    }),
+                    json!({
+                        "timestamp": timestamp(now),
+                        "type": "event_msg",
+                        "payload": {
+                            "type": "token_count",
+                            "info": {
+                                "total_token_usage": {"total_tokens": total},
+                                "last_token_usage": {
+                                    "input_tokens": input_tokens,
+                                    "cached_input_tokens": 0,
+                                    "output_tokens": output_tokens,
+                                    "reasoning_output_tokens": 0,
+                                    "total_tokens": input_tokens + output_tokens
+                                }
+                            }
+                        }
+                    }),
+                ]);
+            }
+            Self::Gemini {
+                chat, streaming, ..
+            } => {
+                *streaming = false;
+                set_last_content(chat, text, now);
+                if let Some(last) = chat["messages"].as_array_mut().and_then(|m| m.last_mut()) {
+                    last["tokens"] = json!({
+                        "input": input_tokens,
+                        "output": output_tokens,
+                        "cached": 0,
+                        "thoughts": 0,
+                        "tool": 0,
+                        "total": input_tokens + output_tokens
+                    });
+                }
+                let _ = self.save();
+            }
+            Self::OpenCode { .. } => {
+                let mut message = self.assistant(now);
+                let _ = self.write_part(&message, text, now);
+                message["time"]["completed"] = json!(millis(now));
+                message["tokens"] = json!({
+                    "input": input_tokens,
+                    "output": output_tokens,
+                    "reasoning": 0,
+                    "cache": {"read": 0, "write": 0}
+                });
+                let _ = self.write_message(&message);
+                if let Self::OpenCode { assistant, .. } = self {
+                    *assistant = None;
+                }
+                let _ = self.save();
+            }
+            Self::None => {}
+        }
+    }
+
+    /// A reply cut short by Ctrl+C is left unfinished, like the real agents do
+    fn interrupted(&mut self) {
+        match self {
+            Self::Gemini { streaming, .. } => *streaming = false,
+            Self::OpenCode { assistant, .. } => *assistant = None,
+            Self::Codex { .. } | Self::None => {}
+        }
+    }
+
+    fn append(&self, lines: &[Value]) -> io::Result<()> {
+        let Self::Codex { path, .. } = self else {
+            return Ok(());
+        };
+        create_parent(path)?;
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut buf = String::new();
+        for line in lines {
+            buf.push_str(&line.to_string());
+            buf.push('\n');
+        }
+        file.write_all(buf.as_bytes())
+    }
+
+    fn save(&self) -> io::Result<()> {
+        match self {
+            Self::Gemini { path, chat, .. } => write_json(path, chat),
+            Self::OpenCode {
+                session,
+                session_path,
+                ..
+            } => {
+                let mut session = session.clone();
+                session["time"]["updated"] = json!(millis(Utc::now()));
+                write_json(session_path, &session)
+            }
+            Self::Codex { .. } | Self::None => Ok(()),
+        }
+    }
+
+    /// OpenCode ids sort by creation time
+    fn next_id(&mut self, prefix: &str) -> String {
+        let Self::OpenCode { next_id, .. } = self else {
+            return String::new();
+        };
+        *next_id += 1;
+        format!("{}_{:012x}{:04}", prefix, millis(Utc::now()), next_id)
+    }
+
+    /// The OpenCode assistant message being streamed, created on first use
+    fn assistant(&mut self, now: DateTime<Utc>) -> Value {
+        if let Self::OpenCode {
+            assistant: Some(message),
+            ..
+        } = self
+        {
+            return message.clone();
+        }
+        let message_id = self.next_id("msg");
+        let part_id = self.next_id("prt");
+        let Self::OpenCode {
+            session, assistant, ..
+        } = self
+        else {
+            return Value::Null;
+        };
+        let message = json!({
+            "id": message_id,
+            "sessionID": session["id"],
+            "role": "assistant",
+            "time": {"created": millis(now)},
+            "modelID": "fake-model",
+            "providerID": "fake",
+            "partID": part_id
+        });
+        *assistant = Some(message.clone());
+        let _ = self.write_message(&message);
+        message
+    }
+
+    fn write_message(&self, message: &Value) -> io::Result<()> {
+        let Self::OpenCode { root, .. } = self else {
+            return Ok(());
+        };
+        let mut message = message.clone();
+        if let Some(fields) = message.as_object_mut() {
+            fields.remove("partID");
+        }
+        let path = root
+            .join("message")
+            .join(message["sessionID"].as_str().unwrap_or_default())
+            .join(format!(
+                "{}.json",
+                message["id"].as_str().unwrap_or_default()
+            ));
+        write_json(&path, &message)
+    }
+
+    /// One text part per message, rewritten as the text grows
+    fn write_part(&mut self, message: &Value, text: &str, now: DateTime<Utc>) -> io::Result<()> {
+        let part_id = match message["partID"].as_str() {
+            Some(id) => id.to_string(),
+            None => self.next_id("prt"),
+        };
+        let Self::OpenCode { root, .. } = self else {
+            return Ok(());
+        };
+        let message_id = message["id"].as_str().unwrap_or_default();
+        let path = root
+            .join("part")
+            .join(message_id)
+            .join(format!("{}.json", part_id));
+        let start = message["time"]["created"].as_i64().unwrap_or(millis(now));
+        write_json(
+            &path,
+            &json!({
+                "id": part_id,
+                "sessionID": message["sessionID"],
+                "messageID": message_id,
+                "type": "text",
+                "text": text,
+                "time": {"start": start}
+            }),
+        )
+    }
+}
+
+fn push_message(chat: &mut Value, message: Value) {
+    if let Some(messages) = chat["messages"].as_array_mut() {
+        messages.push(message);
+    }
+}
+
+fn set_last_content(chat: &mut Value, text: &str, now: DateTime<Utc>) {
+    chat["lastUpdated"] = json!(timestamp(now));
+    if let Some(last) = chat["messages"].as_array_mut().and_then(|m| m.last_mut()) {
+        last["content"] = json!(text);
+    }
+}
+
+fn create_parent(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) => fs::create_dir_all(parent),
+        None => Ok(()),
+    }
+}
+
+fn write_json(path: &Path, value: &Value) -> io::Result<()> {
+    create_parent(path)?;
+    fs::write(
+        path,
+        serde_json::to_string_pretty(value).unwrap_or_default(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "7a3c2d10-36f0-4e2b-9c1d-0b5e8f4a6d21";
+
+    fn submitted(prefix: &str) -> String {
+        format!(
+            "{}\nWhat is 2+2?\n\nIMPORTANT:\n\
+            - Reply normally, in English.\n\
+            - End your reply with this exact final line (verbatim, on its own line):\n\
+            CCGO_DONE: {}",
+            prefix, ID
+        )
+    }
+
+    #[test]
+    fn test_prompt_parse_per_flavor() {
+        for (flavor, prefix) in [
+            (Flavor::Codex, format!("# MSG_ID:{}", ID)),
+            (Flavor::Gemini, format!("[MSG_ID:{}]", ID)),
+            (Flavor::Opencode, format!("[[MSG:{}]]", ID)),
+            (Flavor::Claude, format!("# CCGONEXT_MSG_ID:{}", ID)),
+        ] {
+            let prompt = Prompt::parse(flavor, &submitted(&prefix));
+            assert_eq!(prompt.message_id.as_deref(), Some(ID), "{:?}", flavor);
+            assert_eq!(prompt.message, "What is 2+2?", "{:?}", flavor);
+        }
+    }
+
+    #[test]
+    fn test_prompt_parse_plain_input() {
+        let prompt = Prompt::parse(Flavor::Codex, "hello there");
+        assert_eq!(prompt.message_id, None);
+        assert_eq!(prompt.message, "hello there");
+    }
+}
@@ -0,0 +1,559 @@
+//! Response cache
+//!
+//! Caches successful agent replies keyed by agent name, normalized prompt,
+//! a hash of the attached files and the git HEAD/dirty-tree state of the
+//! agent's working directory, so identical questions about an unchanged tree
+//! are answered without re-asking the agent. Entries expire after a TTL, the
+//! least recently used ones are evicted beyond a size bound, and the cache
+//! can optionally be persisted to a JSONL file. Inserts are appended to the
+//! file; it is rewritten with only the live entries when opened and whenever
+//! it has grown to twice the bound.
+
+use crate::config::LaunchConfig;
+use crate::digest::hex;
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Per-request cache control
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheMode {
+    /// Always ask the agent; the fresh reply still refreshes the cache
+    Bypass,
+    /// Return a cached reply when available, otherwise ask the agent
+    #[default]
+    Prefer,
+    /// Only return a cached reply; fail on a miss
+    Only,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub agent: String,
+    pub response: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// One line of the cache file; later lines for a key win
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheRecord {
+    key: String,
+    #[serde(flatten)]
+    entry: CacheEntry,
+}
+
+struct Slot {
+    entry: CacheEntry,
+    /// Recency stamp; the lowest is evicted first
+    used: u64,
+}
+
+#[derive(Default)]
+struct Entries {
+    slots: HashMap<String, Slot>,
+    clock: u64,
+}
+
+impl Entries {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+}
+
+struct CacheFile {
+    path: PathBuf,
+    file: File,
+    /// Lines in the file, live or superseded
+    lines: usize,
+}
+
+pub struct ResponseCache {
+    ttl: Duration,
+    max_entries: usize,
+    entries: Mutex<Entries>,
+    file: Option<Mutex<CacheFile>>,
+}
+
+fn hex_digest(hasher: Sha256) -> String {
+    hex(&hasher.finalize())
+}
+
+/// Normalize a prompt so that whitespace-only differences share a cache entry
+pub fn normalize_prompt(prompt: &str) -> String {
+    prompt
+        .lines()
+        .map(|l| l.trim_end())
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+/// Attachments larger than this are hashed by size and mtime, not content
+const MAX_ATTACHMENT_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Hash the contents of attached files (paths relative to `base_dir`) on a
+/// blocking thread; see [`hash_attachments`]
+pub async fn attachments_hash(base_dir: &Path, attachments: &[String]) -> std::io::Result<String> {
+    let base_dir = base_dir.to_path_buf();
+    let attachments = attachments.to_vec();
+    tokio::task::spawn_blocking(move || hash_attachments(&base_dir, &attachments))
+        .await
+        .map_err(std::io::Error::other)?
+}
+
+/// Hash the contents of attached files (paths relative to `base_dir`).
+/// Missing files hash as missing so creating them invalidates the entry;
+/// anything but a regular file hashes as such, and large files by size and
+/// mtime. Paths that are absolute or climb out of `base_dir` are rejected.
+pub fn hash_attachments(base_dir: &Path, attachments: &[String]) -> std::io::Result<String> {
+    let mut sorted: Vec<&String> = attachments.iter().collect();
+    sorted.sort();
+    sorted.dedup();
+
+    let mut hasher = Sha256::new();
+    for path in sorted {
+        let Some(relative) = LaunchConfig::project_relative_cwd(path) else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "attachment '{}' must be relative to the working directory and stay inside it",
+                    path
+                ),
+            ));
+        };
+        let file = base_dir.join(relative);
+        hasher.update(path.as_bytes());
+        hasher.update([0]);
+        match fs::metadata(&file) {
+            Err(_) => hasher.update(b"missing"),
+            Ok(meta) if !meta.is_file() => hasher.update(b"not a file"),
+            Ok(meta) if meta.len() > MAX_ATTACHMENT_BYTES => {
+                let mtime = meta
+                    .modified()
+                    .ok()
+                    .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                    .unwrap_or_default();
+                hasher.update(b"large");
+                hasher.update(meta.len().to_le_bytes());
+                hasher.update(mtime.as_nanos().to_le_bytes());
+            }
+            Ok(_) => match fs::read(&file) {
+                Ok(content) => hasher.update(Sha256::digest(&content)),
+                Err(_) => hasher.update(b"missing"),
+            },
+        }
+        hasher.update([0]);
+    }
+    Ok(hex_digest(hasher))
+}
+
+/// Hash of git HEAD plus uncommitted changes in `dir`; "nogit" outside a repository
+pub async fn workspace_state(dir: &Path) -> String {
+    let git = |args: &'static [&'static str]| {
+        let dir = dir.to_path_buf();
+        async move {
+            let output = tokio::process::Command::new("git")
+                .args(args)
+                .current_dir(dir)
+                .stdin(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .output()
+                .await
+                .ok()?;
+            output.status.success().then_some(output.stdout)
+        }
+    };
+
+    let Some(head) = git(&["rev-parse", "HEAD"]).await else {
+        return "nogit".to_string();
+    };
+    let diff = git(&["diff", "HEAD", "--binary"]).await.unwrap_or_default();
+    let status = git(&["status", "--porcelain", "--untracked-files=all"])
+        .await
+        .unwrap_or_default();
+
+    let mut hasher = Sha256::new();
+    hasher.update(String::from_utf8_lossy(&head).trim().as_bytes());
+    hasher.update([0]);
+    hasher.update(&diff);
+    hasher.update([0]);
+    hasher.update(&status);
+    hex_digest(hasher)
+}
+
+/// Compose the cache key from its parts
+pub fn cache_key(agent: &str, prompt: &str, attachments_hash: &str, workspace: &str) -> String {
+    let mut hasher = Sha256::new();
+    for part in [
+        agent,
+        &normalize_prompt(prompt),
+        attachments_hash,
+        workspace,
+    ] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    hex_digest(hasher)
+}
+
+impl ResponseCache {
+    /// Cache that only lives for the lifetime of the process
+    pub fn in_memory(ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            ttl,
+            max_entries: max_entries.max(1),
+            entries: Mutex::new(Entries::default()),
+            file: None,
+        }
+    }
+
+    /// Open (or create) a persisted cache, dropping expired entries and
+    /// compacting the file
+    pub fn open(path: &Path, ttl: Duration, max_entries: usize) -> std::io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+
+        let records = match fs::read_to_string(path) {
+            Ok(content) => parse_records(&content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                tracing::warn!("[ResponseCache] Ignoring unreadable cache file: {}", e);
+                Vec::new()
+            }
+            Err(e) => return Err(e),
+        };
+
+        let mut cache = Self::in_memory(ttl, max_entries);
+        {
+            let mut entries = cache.entries.lock();
+            // Oldest first, so the newest entries survive the bound
+            let mut records = records;
+            records.sort_by_key(|r| r.entry.created_at);
+            for record in records {
+                if !cache.is_expired(&record.entry) {
+                    cache.put(&mut entries, record.key, record.entry);
+                }
+            }
+            tracing::info!(
+                "[ResponseCache] Opened {:?} with {} entries",
+                path,
+                entries.slots.len()
+            );
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut cache_file = CacheFile {
+            path: path.to_path_buf(),
+            file,
+            lines: 0,
+        };
+        cache.compact(&mut cache_file)?;
+        cache.file = Some(Mutex::new(cache_file));
+        Ok(cache)
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn is_expired(&self, entry: &CacheEntry) -> bool {
+        let age = Utc::now()
+            .signed_duration_since(entry.created_at)
+            .to_std()
+            .unwrap_or_default();
+        age >= self.ttl
+    }
+
+    /// Insert, then drop expired entries and the least recently used beyond
+    /// the bound
+    fn put(&self, entries: &mut Entries, key: String, entry: CacheEntry) {
+        let used = entries.tick();
+        entries.slots.insert(key, Slot { entry, used });
+        if entries.slots.len() <= self.max_entries {
+            return;
+        }
+        entries
+            .slots
+            .retain(|_, slot| !self.is_expired(&slot.entry));
+        while entries.slots.len() > self.max_entries {
+            let Some(oldest) = entries
+                .slots
+                .iter()
+                .min_by_key(|(_, slot)| slot.used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            entries.slots.remove(&oldest);
+        }
+    }
+
+    /// Rewrite the file with only the live entries
+    fn compact(&self, cache_file: &mut CacheFile) -> std::io::Result<()> {
+        let mut data = Vec::new();
+        let lines = {
+            let entries = self.entries.lock();
+            for (key, slot) in &entries.slots {
+                serde_json::to_writer(
+                    &mut data,
+                    &CacheRecord {
+                        key: key.clone(),
+                        entry: slot.entry.clone(),
+                    },
+                )?;
+                data.push(b'\n');
+            }
+            entries.slots.len()
+        };
+
+        let mut tmp_path = cache_file.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let mut tmp = File::create(&tmp_path)?;
+        tmp.write_all(&data)?;
+        tmp.sync_all()?;
+        fs::rename(&tmp_path, &cache_file.path)?;
+        cache_file.file = OpenOptions::new().append(true).open(&cache_file.path)?;
+        cache_file.lines = lines;
+        Ok(())
+    }
+
+    fn append(&self, record: &CacheRecord) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        let mut cache_file = file.lock();
+        let result = serde_json::to_vec(record)
+            .map_err(std::io::Error::from)
+            .and_then(|mut line| {
+                line.push(b'\n');
+                cache_file.file.write_all(&line)
+            });
+        if let Err(e) = result {
+            tracing::warn!("[ResponseCache] Failed to persist cache entry: {}", e);
+            return;
+        }
+        cache_file.lines += 1;
+        if cache_file.lines > self.max_entries * 2 {
+            if let Err(e) = self.compact(&mut cache_file) {
+                tracing::warn!("[ResponseCache] Failed to compact cache file: {}", e);
+            }
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<CacheEntry> {
+        let mut entries = self.entries.lock();
+        let used = entries.tick();
+        let slot = entries.slots.get_mut(key)?;
+        if self.is_expired(&slot.entry) {
+            return None;
+        }
+        slot.used = used;
+        Some(slot.entry.clone())
+    }
+
+    pub fn insert(&self, key: String, agent: &str, response: &str) {
+        let record = CacheRecord {
+            key,
+            entry: CacheEntry {
+                agent: agent.to_string(),
+                response: response.to_string(),
+                created_at: Utc::now(),
+            },
+        };
+        {
+            let mut entries = self.entries.lock();
+            self.put(&mut entries, record.key.clone(), record.entry.clone());
+        }
+        self.append(&record);
+    }
+}
+
+/// Records from a JSONL cache file; unreadable lines are skipped
+fn parse_records(content: &str) -> Vec<CacheRecord> {
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(record) => Some(record),
+            Err(e) => {
+                tracing::warn!("[ResponseCache] Ignoring unreadable cache line: {}", e);
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_normalize_prompt_ignores_trailing_whitespace() {
+        assert_eq!(normalize_prompt("  hi  \r\nthere \n\n"), "hi\nthere");
+        assert_eq!(
+            cache_key("codex", "hi\nthere", "a", "w"),
+            cache_key("codex", "hi  \r\nthere\n", "a", "w")
+        );
+        assert_ne!(
+            cache_key("codex", "hi", "a", "w"),
+            cache_key("gemini", "hi", "a", "w")
+        );
+    }
+
+    #[test]
+    fn test_attachments_hash_tracks_content() {
+        let dir = tempdir().unwrap();
+        let files = vec!["a.txt".to_string()];
+        let missing = hash_attachments(dir.path(), &files).unwrap();
+
+        fs::write(dir.path().join("a.txt"), "one").unwrap();
+        let one = hash_attachments(dir.path(), &files).unwrap();
+        fs::write(dir.path().join("a.txt"), "two").unwrap();
+        let two = hash_attachments(dir.path(), &files).unwrap();
+
+        assert_ne!(missing, one);
+        assert_ne!(one, two);
+
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        let not_a_file = hash_attachments(dir.path(), &["sub".to_string()]).unwrap();
+        assert_ne!(
+            not_a_file,
+            hash_attachments(dir.path(), &["nope".to_string()]).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_attachments_must_stay_inside_base_dir() {
+        let dir = tempdir().unwrap();
+        for path in ["../secret", "a/../../secret", "/etc/shadow"] {
+            let err = hash_attachments(dir.path(), &[path.to_string()]).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput, "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_large_attachments_hash_by_size_and_mtime() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("big.bin");
+        let files = vec!["big.bin".to_string()];
+        let file = File::create(&path).unwrap();
+        file.set_len(MAX_ATTACHMENT_BYTES + 1).unwrap();
+        let mtime = std::time::SystemTime::now() - Duration::from_secs(60);
+        file.set_modified(mtime).unwrap();
+        let first = attachments_hash(dir.path(), &files).await.unwrap();
+
+        // Same size and mtime: content changes go unnoticed past the cap
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.write_all(b"changed").unwrap();
+        file.set_modified(mtime).unwrap();
+        assert_eq!(attachments_hash(dir.path(), &files).await.unwrap(), first);
+
+        file.set_len(MAX_ATTACHMENT_BYTES + 2).unwrap();
+        file.set_modified(mtime).unwrap();
+        assert_ne!(attachments_hash(dir.path(), &files).await.unwrap(), first);
+    }
+
+    #[test]
+    fn test_ttl_expiry() {
+        let cache = ResponseCache::in_memory(Duration::from_secs(60), 10);
+        cache.insert("k".to_string(), "codex", "reply");
+        assert_eq!(cache.get("k").unwrap().response, "reply");
+
+        let expired = ResponseCache::in_memory(Duration::ZERO, 10);
+        expired.insert("k".to_string(), "codex", "reply");
+        assert!(expired.get("k").is_none());
+    }
+
+    #[test]
+    fn test_persistence_roundtrip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("cache.json");
+        {
+            let cache = ResponseCache::open(&path, Duration::from_secs(60), 10).unwrap();
+            cache.insert("k".to_string(), "codex", "reply");
+        }
+        let cache = ResponseCache::open(&path, Duration::from_secs(60), 10).unwrap();
+        assert_eq!(cache.get("k").unwrap().agent, "codex");
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let cache = ResponseCache::in_memory(Duration::from_secs(60), 2);
+        cache.insert("a".to_string(), "codex", "1");
+        cache.insert("b".to_string(), "codex", "2");
+        assert!(cache.get("a").is_some());
+        cache.insert("c".to_string(), "codex", "3");
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("c").is_some());
+    }
+
+    #[test]
+    fn test_file_is_appended_and_compacted() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("cache.jsonl");
+        let line_count = || fs::read_to_string(&path).unwrap().lines().count();
+        {
+            let cache = ResponseCache::open(&path, Duration::from_secs(60), 3).unwrap();
+            for i in 0..6 {
+                cache.insert(format!("k{}", i % 2), "codex", &i.to_string());
+            }
+            // Appended, not rewritten, until the file reaches twice the bound
+            assert_eq!(line_count(), 6);
+            cache.insert("k2".to_string(), "codex", "6");
+            assert_eq!(line_count(), 3);
+            for i in 3..5 {
+                cache.insert(format!("k{}", i), "codex", &i.to_string());
+            }
+        }
+
+        let cache = ResponseCache::open(&path, Duration::from_secs(60), 3).unwrap();
+        assert_eq!(line_count(), 3);
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.get("k2").unwrap().response, "6");
+        assert!(cache.get("k0").is_none());
+        assert!(cache.get("k1").is_none());
+        assert!(cache.get("k4").is_some());
+    }
+
+    #[test]
+    fn test_corrupt_file_starts_empty() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("cache.jsonl");
+        fs::write(&path, "{\"k\": {\"agent\": \"codex\"}}\nnot json\n").unwrap();
+
+        let cache = ResponseCache::open(&path, Duration::from_secs(60), 10).unwrap();
+        assert!(cache.is_empty());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+    }
+
+    #[test]
+    fn test_cache_mode_deserialize() {
+        let mode: CacheMode = serde_json::from_str("\"only\"").unwrap();
+        assert_eq!(mode, CacheMode::Only);
+        assert_eq!(CacheMode::default(), CacheMode::Prefer);
+    }
+}
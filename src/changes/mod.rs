@@ -0,0 +1,769 @@
+//! Filesystem change tracking
+//!
+//! Snapshots an agent's working directory (mtime, size, content hash) before a
+//! request is sent and compares it after the reply, reporting created,
+//! modified and deleted files with unified diffs for text files. Works without
+//! git. A file watcher, kept per working directory across requests, records
+//! whether anything happened in the tree at all, so requests that touched
+//! nothing skip the second scan.
+
+use crate::log_provider::{create_debounced_watcher, FileChangeEvent, WatchHandle};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
+use tokio::sync::broadcast::{self, error::TryRecvError};
+
+/// Patterns ignored by default (matched against any path component)
+pub const DEFAULT_IGNORE: &[&str] = &[
+    ".git",
+    ".hg",
+    ".svn",
+    "node_modules",
+    "target",
+    "__pycache__",
+    ".venv",
+    "*.pyc",
+    ".DS_Store",
+];
+
+/// Files above this size are hashed but never diffed
+const MAX_TEXT_BYTES: u64 = 256 * 1024;
+/// Text kept for diffing per snapshot; further files are only hashed
+const MAX_SNAPSHOT_TEXT_BYTES: usize = 32 * 1024 * 1024;
+/// Edits per file beyond which the diff is a whole-file replacement
+const MAX_EDIT_DISTANCE: usize = 1000;
+/// Working directories watched at once
+const MAX_WATCHED_ROOTS: usize = 16;
+/// Stop scanning after this many files
+const MAX_FILES: usize = 100_000;
+/// Cap on the combined diff text returned for one request
+pub(crate) const MAX_DIFF_BYTES: usize = 1024 * 1024;
+/// Lines of context around each hunk
+const CONTEXT_LINES: usize = 3;
+
+#[derive(Debug, Clone)]
+struct FileState {
+    mtime: Option<SystemTime>,
+    size: u64,
+    hash: [u8; 32],
+    /// Content of small text files, kept for diffing
+    text: Option<String>,
+}
+
+/// State of every tracked file under a root, keyed by '/'-separated relative path
+#[derive(Debug, Clone, Default)]
+pub struct TreeSnapshot {
+    files: BTreeMap<String, FileState>,
+}
+
+impl TreeSnapshot {
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+/// Files an agent touched while answering one request
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FileChanges {
+    pub created: Vec<String>,
+    pub modified: Vec<String>,
+    pub deleted: Vec<String>,
+    /// Unified diffs of the text files among the above
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub diff: String,
+}
+
+impl FileChanges {
+    pub fn is_empty(&self) -> bool {
+        self.created.is_empty() && self.modified.is_empty() && self.deleted.is_empty()
+    }
+}
+
+/// Glob-style ignore patterns.
+/// Patterns without '/' match any single path component (`target`, `*.log`);
+/// patterns with '/' match the whole relative path (`docs/*.pdf`, `build/**`).
+#[derive(Debug, Clone)]
+pub struct IgnoreRules {
+    patterns: Vec<String>,
+}
+
+impl Default for IgnoreRules {
+    fn default() -> Self {
+        Self::new(DEFAULT_IGNORE.iter().map(|s| s.to_string()))
+    }
+}
+
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) if rest.first() == Some(&b'*') => {
+            // `**` crosses '/' boundaries; `**/` may also match nothing
+            let rest = &rest[1..];
+            let after_slash = rest.strip_prefix(b"/").unwrap_or(rest);
+            (0..=text.len())
+                .any(|i| glob_match(rest, &text[i..]) || glob_match(after_slash, &text[i..]))
+        }
+        Some((b'*', rest)) => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != b'/')
+            .any(|i| glob_match(rest, &text[i..])),
+        Some((b'?', rest)) => {
+            matches!(text.first(), Some(c) if *c != b'/') && glob_match(rest, &text[1..])
+        }
+        Some((c, rest)) => text.first() == Some(c) && glob_match(rest, &text[1..]),
+    }
+}
+
+impl IgnoreRules {
+    pub fn new(patterns: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            patterns: patterns
+                .into_iter()
+                .map(|p| p.trim().trim_end_matches('/').to_string())
+                .filter(|p| !p.is_empty())
+                .collect(),
+        }
+    }
+
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    /// True when `rel_path` ('/'-separated) or any of its parents is ignored
+    pub fn is_ignored(&self, rel_path: &str) -> bool {
+        self.patterns.iter().any(|pattern| {
+            if pattern.contains('/') {
+                let pattern = pattern.trim_start_matches('/');
+                glob_match(pattern.as_bytes(), rel_path.as_bytes())
+            } else {
+                rel_path
+                    .split('/')
+                    .any(|part| glob_match(pattern.as_bytes(), part.as_bytes()))
+            }
+        })
+    }
+}
+
+fn looks_like_text(content: &[u8]) -> bool {
+    !content[..content.len().min(8192)].contains(&0)
+}
+
+fn read_state(path: &Path, meta: &fs::Metadata) -> Option<FileState> {
+    let content = fs::read(path).ok()?;
+    let text = if meta.len() <= MAX_TEXT_BYTES && looks_like_text(&content) {
+        String::from_utf8(content.clone()).ok()
+    } else {
+        None
+    };
+    Some(FileState {
+        mtime: meta.modified().ok(),
+        size: meta.len(),
+        hash: Sha256::digest(&content).into(),
+        text,
+    })
+}
+
+/// Scan `root`; files whose size and mtime match `previous` are not re-read
+pub fn scan(root: &Path, ignore: &IgnoreRules, previous: Option<&TreeSnapshot>) -> TreeSnapshot {
+    let mut snapshot = TreeSnapshot::default();
+    let mut stack = vec![PathBuf::new()];
+    let mut text_bytes = 0;
+
+    while let Some(rel_dir) = stack.pop() {
+        let Ok(entries) = fs::read_dir(root.join(&rel_dir)) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let rel = rel_dir.join(entry.file_name());
+            let rel_str = rel.to_string_lossy().replace('\\', "/");
+            if ignore.is_ignored(&rel_str) {
+                continue;
+            }
+            // Symlinks are not followed
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                stack.push(rel);
+                continue;
+            }
+            if !file_type.is_file() {
+                continue;
+            }
+            if snapshot.files.len() >= MAX_FILES {
+                tracing::warn!(
+                    "[ChangeTracker] More than {} files under {:?}, ignoring the rest",
+                    MAX_FILES,
+                    root
+                );
+                return snapshot;
+            }
+            let Ok(meta) = entry.metadata() else {
+                continue;
+            };
+
+            let unchanged = previous
+                .and_then(|p| p.files.get(&rel_str))
+                .filter(|old| old.size == meta.len() && old.mtime == meta.modified().ok());
+            let state = match unchanged {
+                Some(old) => Some(old.clone()),
+                None => read_state(&entry.path(), &meta),
+            };
+            if let Some(mut state) = state {
+                if let Some(text) = &state.text {
+                    if text_bytes + text.len() > MAX_SNAPSHOT_TEXT_BYTES {
+                        state.text = None;
+                    } else {
+                        text_bytes += text.len();
+                    }
+                }
+                snapshot.files.insert(rel_str, state);
+            }
+        }
+    }
+    snapshot
+}
+
+/// Compare two snapshots of the same root
+pub fn compare(before: &TreeSnapshot, after: &TreeSnapshot) -> FileChanges {
+    let mut changes = FileChanges::default();
+    let mut diff = String::new();
+
+    for (path, new) in &after.files {
+        match before.files.get(path) {
+            None => {
+                changes.created.push(path.clone());
+                if let Some(text) = &new.text {
+                    diff.push_str(&unified_diff(path, None, Some(text)));
+                }
+            }
+            Some(old) if old.hash != new.hash => {
+                changes.modified.push(path.clone());
+                if let (Some(old_text), Some(new_text)) = (&old.text, &new.text) {
+                    diff.push_str(&unified_diff(path, Some(old_text), Some(new_text)));
+                }
+            }
+            Some(_) => {}
+        }
+    }
+    for (path, old) in &before.files {
+        if !after.files.contains_key(path) {
+            changes.deleted.push(path.clone());
+            if let Some(text) = &old.text {
+                diff.push_str(&unified_diff(path, Some(text), None));
+            }
+        }
+    }
+
+    truncate_diff(&mut diff);
+    changes.diff = diff;
+    changes
+}
+
+/// Cut `diff` to [`MAX_DIFF_BYTES`] with a marker; returns whether it was cut
+pub(crate) fn truncate_diff(diff: &mut String) -> bool {
+    if diff.len() <= MAX_DIFF_BYTES {
+        return false;
+    }
+    let mut end = MAX_DIFF_BYTES;
+    while !diff.is_char_boundary(end) {
+        end -= 1;
+    }
+    diff.truncate(end);
+    diff.push_str("\n... diff truncated\n");
+    true
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EditKind {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// One line of the edit script with its position in the old and new file
+#[derive(Debug, Clone, Copy)]
+struct Edit {
+    kind: EditKind,
+    old: usize,
+    new: usize,
+}
+
+/// Myers' O(ND) line diff. Common leading and trailing lines are matched
+/// first; beyond `MAX_EDIT_DISTANCE` edits the rest is reported as a whole
+/// replacement, which keeps the trace (O(D²)) small.
+fn diff_lines(a: &[&str], b: &[&str]) -> Vec<Edit> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let equal = |old: usize, new: usize| Edit {
+        kind: EditKind::Equal,
+        old,
+        new,
+    };
+
+    let mut edits: Vec<Edit> = (0..prefix).map(|i| equal(i, i)).collect();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+    match myers(a_mid, b_mid) {
+        Some(middle) => edits.extend(middle.into_iter().map(|e| Edit {
+            kind: e.kind,
+            old: e.old + prefix,
+            new: e.new + prefix,
+        })),
+        None => {
+            edits.extend((0..a_mid.len()).map(|i| Edit {
+                kind: EditKind::Delete,
+                old: prefix + i,
+                new: prefix,
+            }));
+            edits.extend((0..b_mid.len()).map(|i| Edit {
+                kind: EditKind::Insert,
+                old: prefix + a_mid.len(),
+                new: prefix + i,
+            }));
+        }
+    }
+    edits.extend((0..suffix).map(|i| equal(a.len() - suffix + i, b.len() - suffix + i)));
+    edits
+}
+
+/// Shortest edit script, or None when it needs more than `MAX_EDIT_DISTANCE` edits
+fn myers(a: &[&str], b: &[&str]) -> Option<Vec<Edit>> {
+    let n = a.len() as isize;
+    let m = b.len() as isize;
+    let max = (n + m).min(MAX_EDIT_DISTANCE as isize);
+    let offset = n + m + 1;
+    let mut v = vec![0isize; 2 * (n + m) as usize + 3];
+    // Diagonals -d..=d of `v` before each step d
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    let mut done = false;
+    'search: for d in 0..=max {
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+        let mut k = -d;
+        while k <= d {
+            let idx = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                done = true;
+                break 'search;
+            }
+            k += 2;
+        }
+    }
+    if !done {
+        return None;
+    }
+
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let at = |k: isize| v[(k + d) as usize];
+        let k = x - y;
+        let (prev_x, prev_y) = if d == 0 {
+            (0, 0)
+        } else {
+            let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+                k + 1
+            } else {
+                k - 1
+            };
+            (at(prev_k), at(prev_k) - prev_k)
+        };
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            edits.push(Edit {
+                kind: EditKind::Equal,
+                old: x as usize,
+                new: y as usize,
+            });
+        }
+        if d > 0 {
+            if x == prev_x {
+                y -= 1;
+                edits.push(Edit {
+                    kind: EditKind::Insert,
+                    old: x as usize,
+                    new: y as usize,
+                });
+            } else {
+                x -= 1;
+                edits.push(Edit {
+                    kind: EditKind::Delete,
+                    old: x as usize,
+                    new: y as usize,
+                });
+            }
+        }
+    }
+    edits.reverse();
+    Some(edits)
+}
+
+fn push_line(out: &mut String, prefix: char, line: &str) {
+    out.push(prefix);
+    out.push_str(line);
+    if !line.ends_with('\n') {
+        out.push_str("\n\\ No newline at end of file\n");
+    }
+}
+
+/// Unified diff of one file; `None` stands for a missing file
+pub fn unified_diff(path: &str, old: Option<&str>, new: Option<&str>) -> String {
+    let a: Vec<&str> = old.unwrap_or_default().split_inclusive('\n').collect();
+    let b: Vec<&str> = new.unwrap_or_default().split_inclusive('\n').collect();
+    let edits = diff_lines(&a, &b);
+    if edits.iter().all(|e| e.kind == EditKind::Equal) {
+        return String::new();
+    }
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "--- {}",
+        if old.is_some() {
+            format!("a/{}", path)
+        } else {
+            "/dev/null".to_string()
+        }
+    );
+    let _ = writeln!(
+        out,
+        "+++ {}",
+        if new.is_some() {
+            format!("b/{}", path)
+        } else {
+            "/dev/null".to_string()
+        }
+    );
+
+    // Group changed lines into hunks with surrounding context
+    let changed: Vec<usize> = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| e.kind != EditKind::Equal)
+        .map(|(i, _)| i)
+        .collect();
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for i in changed {
+        let start = i.saturating_sub(CONTEXT_LINES);
+        let end = (i + CONTEXT_LINES + 1).min(edits.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    for (start, end) in hunks {
+        let slice = &edits[start..end];
+        let old_count = slice.iter().filter(|e| e.kind != EditKind::Insert).count();
+        let new_count = slice.iter().filter(|e| e.kind != EditKind::Delete).count();
+        let old_start = slice[0].old + usize::from(old_count > 0);
+        let new_start = slice[0].new + usize::from(new_count > 0);
+        let _ = writeln!(
+            out,
+            "@@ -{},{} +{},{} @@",
+            old_start, old_count, new_start, new_count
+        );
+        for edit in slice {
+            match edit.kind {
+                EditKind::Equal => push_line(&mut out, ' ', a[edit.old]),
+                EditKind::Delete => push_line(&mut out, '-', a[edit.old]),
+                EditKind::Insert => push_line(&mut out, '+', b[edit.new]),
+            }
+        }
+    }
+    out
+}
+
+/// Snapshot taken before a request plus a subscription noting activity since
+pub struct PendingSnapshot {
+    root: PathBuf,
+    before: TreeSnapshot,
+    events: Option<broadcast::Receiver<FileChangeEvent>>,
+    started: Instant,
+}
+
+struct TreeWatch {
+    root: PathBuf,
+    identity: Option<DirIdentity>,
+    events: Arc<broadcast::Sender<FileChangeEvent>>,
+    _handle: WatchHandle,
+}
+
+impl TreeWatch {
+    /// The watcher thread holds the other sender; it exits when the watch fails
+    fn is_running(&self) -> bool {
+        Arc::strong_count(&self.events) > 1
+    }
+}
+
+/// Tells a directory apart from one recreated at the same path, whose
+/// changes an existing watcher would miss
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DirIdentity {
+    inode: u64,
+    created: Option<SystemTime>,
+}
+
+fn dir_identity(root: &Path) -> Option<DirIdentity> {
+    let meta = fs::metadata(root).ok()?;
+    #[cfg(unix)]
+    let inode = std::os::unix::fs::MetadataExt::ino(&meta);
+    #[cfg(not(unix))]
+    let inode = 0;
+    Some(DirIdentity {
+        inode,
+        created: meta.created().ok(),
+    })
+}
+
+pub struct ChangeTracker {
+    ignore: IgnoreRules,
+    /// Recursive watchers by root, oldest first
+    watches: Mutex<Vec<TreeWatch>>,
+}
+
+impl ChangeTracker {
+    pub fn new(ignore: IgnoreRules) -> Self {
+        Self {
+            ignore,
+            watches: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Subscribe to the running watcher of `root`. A new watcher is started
+    /// when there is none, but it may miss events until it is set up, so it
+    /// is only subscribed to from the next request on.
+    fn subscribe(&self, root: &Path) -> Option<broadcast::Receiver<FileChangeEvent>> {
+        let identity = dir_identity(root);
+        let mut watches = self.watches.lock();
+        if let Some(watch) = watches.iter().find(|w| w.root == root) {
+            if watch.is_running() && watch.identity == identity {
+                return Some(watch.events.subscribe());
+            }
+        }
+
+        watches.retain(|w| w.root != root);
+        if watches.len() >= MAX_WATCHED_ROOTS {
+            watches.remove(0);
+        }
+        if let Some((events, handle)) = create_debounced_watcher(root.to_path_buf(), 0) {
+            watches.push(TreeWatch {
+                root: root.to_path_buf(),
+                identity,
+                events,
+                _handle: handle,
+            });
+        }
+        None
+    }
+
+    pub fn ignore_rules(&self) -> &IgnoreRules {
+        &self.ignore
+    }
+
+    /// Snapshot `root`, watching it from now on
+    pub async fn begin(&self, root: &Path) -> PendingSnapshot {
+        let started = Instant::now();
+        let events = self.subscribe(root);
+
+        let root_buf = root.to_path_buf();
+        let ignore = self.ignore.clone();
+        let before = tokio::task::spawn_blocking(move || scan(&root_buf, &ignore, None))
+            .await
+            .unwrap_or_default();
+
+        // Events caused by the scan itself are not agent activity; without a
+        // subscription `finish` always rescans
+        let events = events.map(|mut rx| {
+            while matches!(rx.try_recv(), Ok(_) | Err(TryRecvError::Lagged(_))) {}
+            rx
+        });
+
+        tracing::debug!(
+            "[ChangeTracker] Snapshot of {:?}: {} files in {:?}",
+            root,
+            before.len(),
+            started.elapsed()
+        );
+        PendingSnapshot {
+            root: root.to_path_buf(),
+            before,
+            events,
+            started,
+        }
+    }
+
+    /// Rescan and report what changed since `begin`
+    pub async fn finish(&self, pending: PendingSnapshot) -> FileChanges {
+        let PendingSnapshot {
+            root,
+            before,
+            events,
+            started,
+        } = pending;
+
+        let quiet = match events {
+            Some(mut rx) => matches!(rx.try_recv(), Err(TryRecvError::Empty)),
+            None => false,
+        };
+        if quiet {
+            tracing::debug!(
+                "[ChangeTracker] No filesystem activity in {:?} during request",
+                root
+            );
+            return FileChanges::default();
+        }
+
+        let ignore = self.ignore.clone();
+        let changes = tokio::task::spawn_blocking(move || {
+            let after = scan(&root, &ignore, Some(&before));
+            compare(&before, &after)
+        })
+        .await
+        .unwrap_or_default();
+        tracing::debug!(
+            "[ChangeTracker] {} created, {} modified, {} deleted ({:?} since snapshot)",
+            changes.created.len(),
+            changes.modified.len(),
+            changes.deleted.len(),
+            started.elapsed()
+        );
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_ignore_rules() {
+        let rules = IgnoreRules::new(
+            ["target", "*.log", "docs/**/*.pdf", "build/"]
+                .iter()
+                .map(|s| s.to_string()),
+        );
+        assert!(rules.is_ignored("target/debug/app"));
+        assert!(rules.is_ignored("sub/target"));
+        assert!(rules.is_ignored("logs/app.log"));
+        assert!(rules.is_ignored("docs/a/b/spec.pdf"));
+        assert!(rules.is_ignored("docs/spec.pdf"));
+        assert!(rules.is_ignored("build/out.o"));
+        assert!(!rules.is_ignored("src/target.rs"));
+        assert!(!rules.is_ignored("docs/spec.md"));
+    }
+
+    #[test]
+    fn test_unified_diff() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\n";
+        let new = "a\nb\nc\nD\ne\nf\ng\nh\ni";
+        let diff = unified_diff("x.txt", Some(old), Some(new));
+        assert_eq!(
+            diff,
+            "--- a/x.txt\n+++ b/x.txt\n\
+             @@ -1,8 +1,9 @@\n a\n b\n c\n-d\n+D\n e\n f\n g\n h\n+i\n\\ No newline at end of file\n"
+        );
+
+        // Deletions and insertions around unchanged lines
+        let diff = unified_diff("w.txt", Some("a\nb\nc\n"), Some("x\nb\nc\ny\n"));
+        assert_eq!(
+            diff,
+            "--- a/w.txt\n+++ b/w.txt\n@@ -1,3 +1,4 @@\n-a\n+x\n b\n c\n+y\n"
+        );
+
+        let created = unified_diff("y.txt", None, Some("hi\n"));
+        assert!(created.starts_with("--- /dev/null\n+++ b/y.txt\n@@ -0,0 +1,1 @@\n+hi\n"));
+        assert!(unified_diff("z.txt", Some("same\n"), Some("same\n")).is_empty());
+    }
+
+    #[test]
+    fn test_unrelated_files_become_a_replacement() {
+        let old: String = (0..5000).map(|i| format!("old {}\n", i)).collect();
+        let new: String = (0..5000).map(|i| format!("new {}\n", i)).collect();
+        let old = format!("head\n{}tail\n", old);
+        let new = format!("head\n{}tail\n", new);
+
+        let diff = unified_diff("big.txt", Some(&old), Some(&new));
+        assert!(diff
+            .starts_with("--- a/big.txt\n+++ b/big.txt\n@@ -1,5002 +1,5002 @@\n head\n-old 0\n"));
+        assert!(diff.ends_with("+new 4999\n tail\n"));
+        assert_eq!(diff.lines().filter(|l| l.starts_with('-')).count(), 5001);
+    }
+
+    #[test]
+    fn test_compare_snapshots() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join("keep.txt"), "keep\n").unwrap();
+        fs::write(root.join("edit.txt"), "old\n").unwrap();
+        fs::write(root.join("gone.txt"), "bye\n").unwrap();
+        fs::create_dir(root.join("target")).unwrap();
+        let rules = IgnoreRules::default();
+        let before = scan(root, &rules, None);
+
+        fs::write(root.join("edit.txt"), "new content\n").unwrap();
+        fs::remove_file(root.join("gone.txt")).unwrap();
+        fs::create_dir(root.join("src")).unwrap();
+        fs::write(root.join("src/new.bin"), [0u8, 1, 2]).unwrap();
+        fs::write(root.join("target/out"), "ignored").unwrap();
+        let after = scan(root, &rules, Some(&before));
+
+        let changes = compare(&before, &after);
+        assert_eq!(changes.created, vec!["src/new.bin".to_string()]);
+        assert_eq!(changes.modified, vec!["edit.txt".to_string()]);
+        assert_eq!(changes.deleted, vec!["gone.txt".to_string()]);
+        assert!(changes.diff.contains("-old\n+new content\n"));
+        assert!(changes.diff.contains("+++ /dev/null"));
+        // Binary files are reported but not diffed
+        assert!(!changes.diff.contains("new.bin"));
+    }
+
+    #[tokio::test]
+    async fn test_tracker_reports_changes() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "one\n").unwrap();
+        let tracker = ChangeTracker::new(IgnoreRules::default());
+
+        let pending = tracker.begin(dir.path()).await;
+        fs::write(dir.path().join("b.txt"), "two\n").unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+        let changes = tracker.finish(pending).await;
+        assert_eq!(changes.created, vec!["b.txt".to_string()]);
+
+        // The next request reuses the watcher
+        let pending = tracker.begin(dir.path()).await;
+        fs::write(dir.path().join("c.txt"), "three\n").unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+        let changes = tracker.finish(pending).await;
+        assert_eq!(changes.created, vec!["c.txt".to_string()]);
+        assert_eq!(tracker.watches.lock().len(), 1);
+    }
+}
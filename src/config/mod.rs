@@ -1,6 +1,10 @@
 //! Configuration module for ccgonext
 
+use crate::recording::RecordingConfig;
+use crate::sandbox::{env_pattern_matches, SandboxProfile};
+use crate::worktree::IsolationMode;
 use std::collections::HashMap;
+use std::path::{Component, PathBuf};
 
 #[derive(Debug, Clone)]
 pub struct Config {
@@ -8,6 +12,15 @@ pub struct Config {
     pub agents: HashMap<String, AgentConfig>,
     pub timeouts: TimeoutConfig,
     pub web: WebConfig,
+    pub journal: JournalConfig,
+    pub cache: CacheConfig,
+    pub usage: UsageConfig,
+    pub isolation: IsolationConfig,
+    pub change_tracking: ChangeTrackingConfig,
+    pub resources: ResourceConfig,
+    /// asciicast recording of PTY streams; off when unset
+    pub recording: Option<RecordingConfig>,
+    pub prompts: PromptsConfig,
 }
 
 impl Default for Config {
@@ -23,6 +36,14 @@ impl Default for Config {
             agents,
             timeouts: TimeoutConfig::default(),
             web: WebConfig::default(),
+            journal: JournalConfig::default(),
+            cache: CacheConfig::default(),
+            usage: UsageConfig::default(),
+            isolation: IsolationConfig::default(),
+            change_tracking: ChangeTrackingConfig::default(),
+            resources: ResourceConfig::default(),
+            recording: None,
+            prompts: PromptsConfig::default(),
         }
     }
 }
@@ -55,6 +76,10 @@ pub struct AgentConfig {
     pub done_template: String,
     pub done_regex: String,
     pub use_stability_heuristic: bool,
+    /// Run the agent inside this sandbox profile (Linux, requires bubblewrap)
+    pub sandbox: Option<SandboxProfile>,
+    /// Environment, working directory and shell for the agent process
+    pub launch: LaunchConfig,
 }
 
 impl AgentConfig {
@@ -72,6 +97,8 @@ impl AgentConfig {
             done_template: "CCGO_DONE: {id}".to_string(),
             done_regex: r"(?mi)^\s*CCGO_DONE:\s*{id}\s*$".to_string(),
             use_stability_heuristic: true,
+            sandbox: None,
+            launch: LaunchConfig::default(),
         }
     }
 
@@ -89,6 +116,8 @@ impl AgentConfig {
             done_template: "CCGO_DONE: {id}".to_string(),
             done_regex: r"(?mi)^\s*CCGO_DONE:\s*{id}\s*$".to_string(),
             use_stability_heuristic: false,
+            sandbox: None,
+            launch: LaunchConfig::default(),
         }
     }
 
@@ -106,6 +135,8 @@ impl AgentConfig {
             done_template: "CCGO_DONE: {id}".to_string(),
             done_regex: r"(?mi)^\s*CCGO_DONE:\s*{id}\s*$".to_string(),
             use_stability_heuristic: true,
+            sandbox: None,
+            launch: LaunchConfig::default(),
         }
     }
 
@@ -127,6 +158,8 @@ impl AgentConfig {
             done_template: "CCGO_DONE: {id}".to_string(),
             done_regex: r"(?mi)^\s*CCGO_DONE:\s*{id}\s*$".to_string(),
             use_stability_heuristic: true,
+            sandbox: None,
+            launch: LaunchConfig::default(),
         }
     }
 
@@ -149,6 +182,143 @@ impl AgentConfig {
         self.args.extend(args);
         self
     }
+
+    /// Runs the agent inside the given sandbox profile
+    pub fn with_sandbox(mut self, profile: SandboxProfile) -> Self {
+        self.sandbox = Some(profile);
+        self
+    }
+
+    /// Replaces the launch settings
+    pub fn with_launch(mut self, launch: LaunchConfig) -> Self {
+        self.launch = launch;
+        self
+    }
+}
+
+/// Which of our environment variables an agent inherits
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum EnvInherit {
+    #[default]
+    All,
+    None,
+    /// Only variables matching these patterns (`*` wildcards)
+    Only(Vec<String>),
+}
+
+/// `all`, `none` or `+`-separated patterns such as `PATH+HOME+*_API_KEY`
+impl std::str::FromStr for EnvInherit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "all" => Ok(Self::All),
+            "none" => Ok(Self::None),
+            "" => Err("expected all, none or a list of variables".to_string()),
+            list => Ok(Self::Only(
+                list.split('+')
+                    .map(|p| p.trim().to_string())
+                    .filter(|p| !p.is_empty())
+                    .collect(),
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for EnvInherit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::All => write!(f, "all"),
+            Self::None => write!(f, "none"),
+            Self::Only(patterns) => write!(f, "{}", patterns.join("+")),
+        }
+    }
+}
+
+/// How an agent process is started
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LaunchConfig {
+    pub inherit: EnvInherit,
+    /// Variables set for the agent, applied last
+    pub env_set: Vec<(String, String)>,
+    /// Variables removed from the agent's environment (`*` wildcards)
+    pub env_unset: Vec<String>,
+    /// Working directory relative to the project root
+    pub cwd: Option<PathBuf>,
+    /// Start the agent through this shell as a login shell (Unix)
+    pub login_shell: Option<String>,
+    pub term: Option<String>,
+    pub colorterm: Option<String>,
+}
+
+impl LaunchConfig {
+    /// Whether the agent's environment differs from ours
+    pub fn customizes_env(&self) -> bool {
+        self.inherit != EnvInherit::All
+            || !self.env_set.is_empty()
+            || !self.env_unset.is_empty()
+            || self.term.is_some()
+            || self.colorterm.is_some()
+    }
+
+    /// The agent's environment derived from `base`: inheritance first, then
+    /// TERM/COLORTERM, unset patterns and explicitly set variables
+    pub fn environment(
+        &self,
+        base: impl IntoIterator<Item = (String, String)>,
+    ) -> Vec<(String, String)> {
+        let mut env: Vec<(String, String)> = base
+            .into_iter()
+            .filter(|(key, _)| match &self.inherit {
+                EnvInherit::All => true,
+                EnvInherit::None => false,
+                EnvInherit::Only(patterns) => patterns.iter().any(|p| env_pattern_matches(p, key)),
+            })
+            .collect();
+
+        fn set(env: &mut Vec<(String, String)>, key: &str, value: &str) {
+            env.retain(|(k, _)| k != key);
+            env.push((key.to_string(), value.to_string()));
+        }
+
+        if let Some(term) = &self.term {
+            set(&mut env, "TERM", term);
+        }
+        if let Some(colorterm) = &self.colorterm {
+            set(&mut env, "COLORTERM", colorterm);
+        }
+        env.retain(|(key, _)| !self.env_unset.iter().any(|p| env_pattern_matches(p, key)));
+        for (key, value) in &self.env_set {
+            set(&mut env, key, value);
+        }
+        env
+    }
+
+    /// Validate a working directory given relative to the project root: it
+    /// may not be absolute or climb out of the project with `..`
+    pub fn project_relative_cwd(cwd: &str) -> Option<PathBuf> {
+        let cwd = PathBuf::from(cwd);
+        cwd.components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+            .then_some(cwd)
+    }
+
+    /// Wrap `command` in the login shell, if one is configured
+    pub fn command(&self, command: Vec<String>) -> Vec<String> {
+        match &self.login_shell {
+            Some(shell) => {
+                let mut wrapped = vec![
+                    shell.clone(),
+                    "-l".to_string(),
+                    "-c".to_string(),
+                    r#"exec "$0" "$@""#.to_string(),
+                ];
+                wrapped.extend(command);
+                wrapped
+            }
+            None => command,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -176,25 +346,198 @@ impl Default for TimeoutConfig {
     }
 }
 
+/// What a web token may do; each role includes the ones before it
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Read status, events, requests and terminal output
+    Viewer,
+    /// Also restart, interrupt and stop agents and cancel requests
+    Operator,
+    /// Also ask agents and type into their terminals
+    Driver,
+}
+
+impl std::str::FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "viewer" => Ok(Self::Viewer),
+            "operator" => Ok(Self::Operator),
+            "driver" => Ok(Self::Driver),
+            other => Err(format!(
+                "invalid role '{}' (expected viewer, operator or driver)",
+                other
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Viewer => write!(f, "viewer"),
+            Self::Operator => write!(f, "operator"),
+            Self::Driver => write!(f, "driver"),
+        }
+    }
+}
+
+/// Named web token; only the SHA-256 of its secret is kept
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+pub struct WebToken {
+    pub name: String,
+    /// Lowercase hex SHA-256 of the secret
+    pub sha256: String,
+    pub role: Role,
+    /// Agents the token may access; all agents when empty
+    #[serde(default)]
+    pub agents: Vec<String>,
+}
+
+/// Where the web server's HTTPS certificate comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TlsConfig {
+    /// PEM certificate chain and private key
+    Files { cert: PathBuf, key: PathBuf },
+    /// Self-signed certificate generated into `dir` on first use
+    SelfSigned { dir: PathBuf },
+}
+
 #[derive(Debug, Clone)]
 pub struct WebConfig {
     pub auth_token: Option<String>,
+    /// Named tokens with roles; the shared `auth_token` acts as a driver
+    pub tokens: Vec<WebToken>,
+    /// JSONL file recording terminal input sent through the web UI
+    pub audit_log: Option<PathBuf>,
+    /// Lifetime of browser sessions started at `/api/login`
+    pub session_ttl_secs: u64,
+    /// Origins allowed besides the local ones (`*` wildcards)
+    pub allowed_origins: Vec<String>,
+    /// Hosts requests may be addressed to besides the local ones
+    /// (`*` wildcards); any host when empty
+    pub allowed_hosts: Vec<String>,
+    /// Reverse proxies whose `X-Forwarded-*` headers are believed
+    pub trusted_proxies: Vec<ipnet::IpNet>,
+    /// Path prefix the app is served under, e.g. `/ccgonext`; empty for `/`
+    pub base_path: String,
+    /// Serve HTTPS and WSS instead of plain HTTP
+    pub tls: Option<TlsConfig>,
     pub input_enabled: bool,
     pub output_buffer_size: usize,
     pub project_root: String,
+    /// Serve Prometheus metrics at `/metrics`
+    pub metrics: bool,
 }
 
 impl Default for WebConfig {
     fn default() -> Self {
         Self {
             auth_token: None,
+            tokens: Vec::new(),
+            audit_log: None,
+            session_ttl_secs: 12 * 60 * 60,
+            allowed_origins: Vec::new(),
+            allowed_hosts: Vec::new(),
+            trusted_proxies: Vec::new(),
+            base_path: String::new(),
+            tls: None,
             input_enabled: false,
             output_buffer_size: 10 * 1024 * 1024, // 10MB
             project_root: String::new(),
+            metrics: false,
         }
     }
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct JournalConfig {
+    /// JSONL file for the request journal; in-memory only when unset
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    pub ttl_secs: u64,
+    /// Least recently used entries beyond this many are evicted
+    pub max_entries: usize,
+    /// JSONL file for persisting cached replies; in-memory only when unset
+    pub path: Option<PathBuf>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl_secs: 3600,
+            max_entries: 1000,
+            path: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct UsageConfig {
+    /// Daily token cap per agent; asks are refused once it is reached
+    pub daily_token_budget: Option<u64>,
+    /// JSON file for persisting usage totals; in-memory only when unset
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct IsolationConfig {
+    /// Run each agent in its own git worktree on a scratch branch
+    pub mode: IsolationMode,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChangeTrackingConfig {
+    /// Snapshot the working directory around each request and report changed files
+    pub enabled: bool,
+    /// Glob patterns excluded from snapshots
+    pub ignore: Vec<String>,
+}
+
+impl Default for ChangeTrackingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ignore: crate::changes::DEFAULT_IGNORE
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ResourceConfig {
+    /// Seconds between CPU/RSS samples of the agent process trees; 0 disables sampling
+    pub sample_interval_secs: u64,
+    /// Per-agent RSS limit in MiB; the agent is restarted when it is exceeded
+    pub memory_limit_mb: HashMap<String, u64>,
+}
+
+impl Default for ResourceConfig {
+    fn default() -> Self {
+        Self {
+            sample_interval_secs: 5,
+            memory_limit_mb: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PromptsConfig {
+    /// Directory of `*.json` prompt templates; built-in prompts only when unset
+    pub dir: Option<PathBuf>,
+}
+
 impl Config {
     pub fn get_agent(&self, name: &str) -> Option<&AgentConfig> {
         self.agents.get(name)
@@ -228,6 +571,18 @@ mod tests {
         assert!(!config.web.input_enabled);
     }
 
+    #[test]
+    fn test_role_parse_and_order() {
+        assert_eq!("Operator".parse::<Role>(), Ok(Role::Operator));
+        assert!("admin".parse::<Role>().is_err());
+        assert!(Role::Viewer < Role::Operator && Role::Operator < Role::Driver);
+
+        let token: WebToken =
+            serde_json::from_str(r#"{"name": "ci", "sha256": "ab", "role": "driver"}"#).unwrap();
+        assert_eq!(token.role, Role::Driver);
+        assert!(token.agents.is_empty());
+    }
+
     #[test]
     fn test_config_get_agent() {
         let config = Config::default();
@@ -363,4 +718,69 @@ mod tests {
         }
         assert_eq!(config.agents.len(), expected_agents.len());
     }
+
+    #[test]
+    fn test_launch_environment() {
+        let base = [
+            ("PATH", "/bin"),
+            ("HOME", "/home/u"),
+            ("OPENAI_API_KEY", "k"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+        let launch = LaunchConfig {
+            inherit: "PATH+HOME+*_KEY".parse().unwrap(),
+            env_set: vec![("GEMINI_ROOT".to_string(), "/tmp/g".to_string())],
+            env_unset: vec!["OPENAI_*".to_string()],
+            term: Some("xterm-256color".to_string()),
+            ..LaunchConfig::default()
+        };
+        assert!(launch.customizes_env());
+
+        let env = launch.environment(base.clone());
+        let get = |key: &str| env.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+        assert_eq!(get("PATH"), Some("/bin"));
+        assert_eq!(get("OPENAI_API_KEY"), None);
+        assert_eq!(get("TERM"), Some("xterm-256color"));
+        assert_eq!(get("GEMINI_ROOT"), Some("/tmp/g"));
+
+        let none = LaunchConfig {
+            inherit: EnvInherit::None,
+            ..LaunchConfig::default()
+        };
+        assert!(none.environment(base).is_empty());
+        assert!(!LaunchConfig::default().customizes_env());
+    }
+
+    #[test]
+    fn test_launch_project_relative_cwd() {
+        assert_eq!(
+            LaunchConfig::project_relative_cwd("backend/./api"),
+            Some(PathBuf::from("backend/./api"))
+        );
+        assert!(LaunchConfig::project_relative_cwd("/srv/app").is_none());
+        assert!(LaunchConfig::project_relative_cwd("..").is_none());
+        assert!(LaunchConfig::project_relative_cwd("web/../../etc").is_none());
+    }
+
+    #[test]
+    fn test_launch_login_shell() {
+        let cmd = vec!["codex".to_string(), "--full-auto".to_string()];
+        assert_eq!(LaunchConfig::default().command(cmd.clone()), cmd);
+
+        let launch = LaunchConfig {
+            login_shell: Some("/bin/zsh".to_string()),
+            ..LaunchConfig::default()
+        };
+        assert_eq!(
+            launch.command(cmd),
+            [
+                "/bin/zsh",
+                "-l",
+                "-c",
+                r#"exec "$0" "$@""#,
+                "codex",
+                "--full-auto"
+            ]
+        );
+    }
 }
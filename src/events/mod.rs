@@ -0,0 +1,273 @@
+//! Session event bus
+//!
+//! Every session publishes what happens to it (state transitions, the
+//! lifecycle of each request, restart attempts and errors) on a shared
+//! broadcast channel. Subscribers that fall behind lose the oldest events
+//! rather than slowing the sessions down.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashSet;
+use tokio::sync::broadcast;
+
+/// Events buffered per subscriber before the oldest are dropped
+const EVENT_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionEvent {
+    pub agent: String,
+    pub timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+    StateChanged {
+        from: String,
+        to: String,
+    },
+    RequestQueued {
+        message_id: String,
+    },
+    /// The request was written to the agent
+    RequestStarted {
+        message_id: String,
+    },
+    RequestCompleted {
+        message_id: String,
+    },
+    /// Timed out, cancelled or failed for any other reason
+    RequestFailed {
+        message_id: String,
+        error: String,
+    },
+    /// A restart (`attempt` is the session's restart count) or a retry of a
+    /// failed start (`attempt` is the retry number)
+    RestartAttempt {
+        attempt: u32,
+        reason: String,
+    },
+    Error {
+        message: String,
+    },
+}
+
+impl EventKind {
+    /// Every event type, as used in filters and as the SSE event name
+    pub const NAMES: &'static [&'static str] = &[
+        "state_changed",
+        "request_queued",
+        "request_started",
+        "request_completed",
+        "request_failed",
+        "restart_attempt",
+        "error",
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::StateChanged { .. } => "state_changed",
+            Self::RequestQueued { .. } => "request_queued",
+            Self::RequestStarted { .. } => "request_started",
+            Self::RequestCompleted { .. } => "request_completed",
+            Self::RequestFailed { .. } => "request_failed",
+            Self::RestartAttempt { .. } => "restart_attempt",
+            Self::Error { .. } => "error",
+        }
+    }
+}
+
+pub struct EventBus {
+    tx: broadcast::Sender<SessionEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(EVENT_CAPACITY);
+        Self { tx }
+    }
+
+    pub fn publish(&self, agent: &str, kind: EventKind) {
+        let _ = self.tx.send(SessionEvent {
+            agent: agent.to_string(),
+            timestamp: Utc::now(),
+            kind,
+        });
+    }
+
+    /// Receive every event published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<SessionEvent> {
+        self.tx.subscribe()
+    }
+}
+
+/// Selects events by agent and type; an empty set matches everything
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+    agents: HashSet<String>,
+    kinds: HashSet<String>,
+}
+
+impl EventFilter {
+    /// Parse comma-separated agent names and event types
+    pub fn parse(agents: Option<&str>, kinds: Option<&str>) -> Result<Self, String> {
+        let split = |list: Option<&str>| -> HashSet<String> {
+            list.unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(String::from)
+                .collect()
+        };
+        let kinds = split(kinds);
+        if let Some(unknown) = kinds
+            .iter()
+            .find(|k| !EventKind::NAMES.contains(&k.as_str()))
+        {
+            return Err(format!(
+                "Unknown event type: {} (expected one of {})",
+                unknown,
+                EventKind::NAMES.join(", ")
+            ));
+        }
+        Ok(Self {
+            agents: split(agents),
+            kinds,
+        })
+    }
+
+    pub fn matches(&self, event: &SessionEvent) -> bool {
+        (self.agents.is_empty() || self.agents.contains(&event.agent))
+            && (self.kinds.is_empty() || self.kinds.contains(event.kind.name()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(agent: &str, kind: EventKind) -> SessionEvent {
+        SessionEvent {
+            agent: agent.to_string(),
+            timestamp: Utc::now(),
+            kind,
+        }
+    }
+
+    #[test]
+    fn test_serialize_flattens_kind() {
+        let value = serde_json::to_value(event(
+            "codex",
+            EventKind::RequestFailed {
+                message_id: "m1".to_string(),
+                error: "Request timeout".to_string(),
+            },
+        ))
+        .unwrap();
+        assert_eq!(value["agent"], "codex");
+        assert_eq!(value["type"], "request_failed");
+        assert_eq!(value["message_id"], "m1");
+        assert_eq!(value["error"], "Request timeout");
+        assert!(value["timestamp"].is_string());
+    }
+
+    #[test]
+    fn test_names_match_serialized_type() {
+        let kinds = [
+            EventKind::StateChanged {
+                from: "IDLE".to_string(),
+                to: "BUSY".to_string(),
+            },
+            EventKind::RequestQueued {
+                message_id: String::new(),
+            },
+            EventKind::RequestStarted {
+                message_id: String::new(),
+            },
+            EventKind::RequestCompleted {
+                message_id: String::new(),
+            },
+            EventKind::RequestFailed {
+                message_id: String::new(),
+                error: String::new(),
+            },
+            EventKind::RestartAttempt {
+                attempt: 1,
+                reason: String::new(),
+            },
+            EventKind::Error {
+                message: String::new(),
+            },
+        ];
+        let names: Vec<&str> = kinds.iter().map(EventKind::name).collect();
+        assert_eq!(names, EventKind::NAMES);
+        for kind in kinds {
+            assert_eq!(serde_json::to_value(&kind).unwrap()["type"], kind.name());
+        }
+    }
+
+    #[test]
+    fn test_filter() {
+        let queued = event(
+            "codex",
+            EventKind::RequestQueued {
+                message_id: "m1".to_string(),
+            },
+        );
+        let error = event(
+            "gemini",
+            EventKind::Error {
+                message: "boom".to_string(),
+            },
+        );
+
+        let all = EventFilter::parse(None, Some("")).unwrap();
+        assert!(all.matches(&queued) && all.matches(&error));
+
+        let codex = EventFilter::parse(Some("codex, opencode"), None).unwrap();
+        assert!(codex.matches(&queued));
+        assert!(!codex.matches(&error));
+
+        let errors = EventFilter::parse(None, Some("error,request_failed")).unwrap();
+        assert!(!errors.matches(&queued));
+        assert!(errors.matches(&error));
+
+        let err = EventFilter::parse(None, Some("error,nope")).unwrap_err();
+        assert!(err.contains("nope"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_publish_subscribe() {
+        let bus = EventBus::new();
+        bus.publish(
+            "codex",
+            EventKind::Error {
+                message: "dropped".to_string(),
+            },
+        );
+        let mut rx = bus.subscribe();
+        bus.publish(
+            "codex",
+            EventKind::RestartAttempt {
+                attempt: 2,
+                reason: "crashed".to_string(),
+            },
+        );
+        let received = rx.recv().await.unwrap();
+        assert_eq!(
+            received.kind,
+            EventKind::RestartAttempt {
+                attempt: 2,
+                reason: "crashed".to_string(),
+            }
+        );
+    }
+}
@@ -0,0 +1,408 @@
+//! Durable request journal
+//!
+//! Every request sent to an agent is recorded with enough information to
+//! harvest its reply from the agent's log after a ccgonext restart: the
+//! message id, agent name, a hash of the prompt, the log baseline offset and
+//! the locked session file. Records are kept in memory and, when a path is
+//! configured, appended to a JSONL file (last line for an id wins) that is
+//! compacted on open and whenever it grows past twice the record bound.
+
+use crate::digest::sha256_hex;
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// Maximum number of records kept in memory; oldest finished records are evicted first.
+const MAX_RECORDS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RequestState {
+    /// Queued, not yet written to the agent
+    Pending,
+    /// Written to the agent, waiting for the reply
+    Sent,
+    Completed,
+    Failed,
+    TimedOut,
+    /// ccgonext restarted and the reply could not be recovered
+    Lost,
+}
+
+impl RequestState {
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, RequestState::Pending | RequestState::Sent)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalRecord {
+    pub message_id: String,
+    pub agent: String,
+    pub prompt_hash: String,
+    pub state: RequestState,
+    #[serde(default)]
+    pub baseline_offset: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_file: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+struct JournalInner {
+    records: HashMap<String, JournalRecord>,
+    file: Option<File>,
+    /// Lines in the file, live or superseded
+    lines: usize,
+}
+
+pub struct RequestJournal {
+    path: Option<PathBuf>,
+    inner: Mutex<JournalInner>,
+}
+
+pub fn prompt_hash(prompt: &str) -> String {
+    sha256_hex(prompt)
+}
+
+impl RequestJournal {
+    /// Journal that only lives for the lifetime of the process
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            inner: Mutex::new(JournalInner {
+                records: HashMap::new(),
+                file: None,
+                lines: 0,
+            }),
+        }
+    }
+
+    /// Open (or create) a journal file, replay existing records and compact it.
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+
+        let mut records: HashMap<String, JournalRecord> = HashMap::new();
+        if path.exists() {
+            let reader = BufReader::new(File::open(path)?);
+            for line in reader.lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<JournalRecord>(&line) {
+                    Ok(record) => {
+                        records.insert(record.message_id.clone(), record);
+                    }
+                    Err(e) => {
+                        tracing::warn!("[Journal] Skipping malformed journal line: {}", e);
+                    }
+                }
+            }
+        }
+
+        Self::evict(&mut records);
+        let file = Self::compact(path, &records)?;
+
+        tracing::info!("[Journal] Opened {:?} with {} records", path, records.len());
+
+        Ok(Self {
+            path: Some(path.to_path_buf()),
+            inner: Mutex::new(JournalInner {
+                lines: records.len(),
+                records,
+                file: Some(file),
+            }),
+        })
+    }
+
+    /// Rewrite the file with one line per record so it does not grow forever,
+    /// returning a handle that appends to the new file
+    fn compact(path: &Path, records: &HashMap<String, JournalRecord>) -> std::io::Result<File> {
+        let tmp_path = path.with_extension("jsonl.tmp");
+        {
+            let mut tmp = File::create(&tmp_path)?;
+            let mut sorted: Vec<_> = records.values().collect();
+            sorted.sort_by_key(|r| r.created_at);
+            for record in sorted {
+                writeln!(tmp, "{}", serde_json::to_string(record)?)?;
+            }
+            tmp.sync_all()?;
+        }
+        fs::rename(&tmp_path, path)?;
+        OpenOptions::new().append(true).open(path)
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    fn evict(records: &mut HashMap<String, JournalRecord>) {
+        if records.len() <= MAX_RECORDS {
+            return;
+        }
+        let mut finished: Vec<(DateTime<Utc>, String)> = records
+            .values()
+            .filter(|r| r.state.is_finished())
+            .map(|r| (r.updated_at, r.message_id.clone()))
+            .collect();
+        finished.sort();
+        let excess = records.len() - MAX_RECORDS;
+        for (_, id) in finished.into_iter().take(excess) {
+            records.remove(&id);
+        }
+    }
+
+    fn persist(&self, inner: &mut JournalInner, record: &JournalRecord) {
+        let (Some(path), Some(file)) = (self.path.as_deref(), inner.file.as_mut()) else {
+            return;
+        };
+        let line = match serde_json::to_string(record) {
+            Ok(l) => l,
+            Err(e) => {
+                tracing::warn!("[Journal] Failed to serialize record: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = writeln!(file, "{}", line).and_then(|_| file.flush()) {
+            tracing::warn!("[Journal] Failed to append record: {}", e);
+            return;
+        }
+        inner.lines += 1;
+        if inner.lines > MAX_RECORDS * 2 {
+            match Self::compact(path, &inner.records) {
+                Ok(file) => {
+                    inner.file = Some(file);
+                    inner.lines = inner.records.len();
+                }
+                Err(e) => tracing::warn!("[Journal] Failed to compact journal: {}", e),
+            }
+        }
+    }
+
+    fn update<F>(&self, message_id: &str, f: F)
+    where
+        F: FnOnce(&mut JournalRecord),
+    {
+        let mut inner = self.inner.lock();
+        let Some(record) = inner.records.get_mut(message_id) else {
+            return;
+        };
+        f(record);
+        record.updated_at = Utc::now();
+        let record = record.clone();
+        self.persist(&mut inner, &record);
+    }
+
+    /// Record a request that has been queued for an agent
+    pub fn record_queued(&self, message_id: &str, agent: &str, prompt: &str) {
+        let now = Utc::now();
+        let record = JournalRecord {
+            message_id: message_id.to_string(),
+            agent: agent.to_string(),
+            prompt_hash: prompt_hash(prompt),
+            state: RequestState::Pending,
+            baseline_offset: 0,
+            session_file: None,
+            response: None,
+            error: None,
+            created_at: now,
+            updated_at: now,
+        };
+
+        let mut inner = self.inner.lock();
+        inner
+            .records
+            .insert(record.message_id.clone(), record.clone());
+        Self::evict(&mut inner.records);
+        self.persist(&mut inner, &record);
+    }
+
+    /// Record that a request was written to the agent, with its log position
+    pub fn record_sent(
+        &self,
+        message_id: &str,
+        baseline_offset: u64,
+        session_file: Option<PathBuf>,
+    ) {
+        self.update(message_id, |r| {
+            r.state = RequestState::Sent;
+            r.baseline_offset = baseline_offset;
+            r.session_file = session_file;
+        });
+    }
+
+    pub fn record_completed(&self, message_id: &str, response: &str) {
+        self.update(message_id, |r| {
+            r.state = RequestState::Completed;
+            r.response = Some(response.to_string());
+            r.error = None;
+        });
+    }
+
+    pub fn record_failed(&self, message_id: &str, error: &str) {
+        self.update(message_id, |r| {
+            r.state = RequestState::Failed;
+            r.error = Some(error.to_string());
+        });
+    }
+
+    pub fn record_timed_out(&self, message_id: &str) {
+        self.update(message_id, |r| {
+            r.state = RequestState::TimedOut;
+            r.error = Some("Request timeout".to_string());
+        });
+    }
+
+    pub fn record_lost(&self, message_id: &str, reason: &str) {
+        self.update(message_id, |r| {
+            r.state = RequestState::Lost;
+            r.error = Some(reason.to_string());
+        });
+    }
+
+    pub fn get(&self, message_id: &str) -> Option<JournalRecord> {
+        self.inner.lock().records.get(message_id).cloned()
+    }
+
+    /// The most recent completed request for `agent`
+    pub fn latest_completed(&self, agent: &str) -> Option<JournalRecord> {
+        self.inner
+            .lock()
+            .records
+            .values()
+            .filter(|r| r.agent == agent && r.state == RequestState::Completed)
+            .max_by_key(|r| r.updated_at)
+            .cloned()
+    }
+
+    /// Records that were pending or in flight, oldest first
+    pub fn unfinished(&self) -> Vec<JournalRecord> {
+        let mut records: Vec<_> = self
+            .inner
+            .lock()
+            .records
+            .values()
+            .filter(|r| !r.state.is_finished())
+            .cloned()
+            .collect();
+        records.sort_by_key(|r| r.created_at);
+        records
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_prompt_hash_is_stable_sha256() {
+        assert_eq!(
+            prompt_hash("hello"),
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+    }
+
+    #[test]
+    fn test_in_memory_lifecycle() {
+        let journal = RequestJournal::in_memory();
+        journal.record_queued("id-1", "codex", "hello");
+        assert_eq!(journal.get("id-1").unwrap().state, RequestState::Pending);
+
+        journal.record_sent("id-1", 42, Some(PathBuf::from("/tmp/session.jsonl")));
+        let record = journal.get("id-1").unwrap();
+        assert_eq!(record.state, RequestState::Sent);
+        assert_eq!(record.baseline_offset, 42);
+        assert_eq!(journal.unfinished().len(), 1);
+
+        journal.record_completed("id-1", "world");
+        let record = journal.get("id-1").unwrap();
+        assert_eq!(record.state, RequestState::Completed);
+        assert_eq!(record.response.as_deref(), Some("world"));
+        assert!(journal.unfinished().is_empty());
+    }
+
+    #[test]
+    fn test_latest_completed() {
+        let journal = RequestJournal::in_memory();
+        assert!(journal.latest_completed("codex").is_none());
+
+        journal.record_queued("id-1", "codex", "first");
+        journal.record_completed("id-1", "one");
+        journal.record_queued("id-2", "codex", "second");
+        journal.record_completed("id-2", "two");
+        journal.record_queued("id-3", "codex", "third");
+        journal.record_failed("id-3", "boom");
+        journal.record_queued("id-4", "gemini", "other");
+        journal.record_completed("id-4", "four");
+
+        let latest = journal.latest_completed("codex").unwrap();
+        assert_eq!(latest.response.as_deref(), Some("two"));
+    }
+
+    #[test]
+    fn test_update_unknown_id_is_ignored() {
+        let journal = RequestJournal::in_memory();
+        journal.record_completed("missing", "x");
+        assert!(journal.get("missing").is_none());
+    }
+
+    #[test]
+    fn test_reopen_replays_last_state() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("journal.jsonl");
+
+        {
+            let journal = RequestJournal::open(&path).unwrap();
+            journal.record_queued("a", "codex", "one");
+            journal.record_sent("a", 7, Some(PathBuf::from("/logs/a.jsonl")));
+            journal.record_queued("b", "gemini", "two");
+            journal.record_failed("b", "boom");
+        }
+
+        let journal = RequestJournal::open(&path).unwrap();
+        let a = journal.get("a").unwrap();
+        assert_eq!(a.state, RequestState::Sent);
+        assert_eq!(a.baseline_offset, 7);
+        assert_eq!(journal.get("b").unwrap().state, RequestState::Failed);
+
+        // Compaction leaves one line per record
+        let content = fs::read_to_string(&path).unwrap();
+        assert_eq!(content.lines().count(), 2);
+    }
+
+    #[test]
+    fn test_file_is_compacted_while_running() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("journal.jsonl");
+        let line_count = || fs::read_to_string(&path).unwrap().lines().count();
+
+        let journal = RequestJournal::open(&path).unwrap();
+        for i in 0..MAX_RECORDS {
+            let id = format!("id-{}", i);
+            journal.record_queued(&id, "codex", "prompt");
+            journal.record_sent(&id, 0, None);
+            journal.record_completed(&id, "reply");
+        }
+
+        // 3 lines per request were appended, but the file was rewritten on the way
+        assert!(line_count() <= MAX_RECORDS * 2);
+        let reopened = RequestJournal::open(&path).unwrap();
+        assert_eq!(line_count(), MAX_RECORDS);
+        let last = reopened.get(&format!("id-{}", MAX_RECORDS - 1)).unwrap();
+        assert_eq!(last.state, RequestState::Completed);
+    }
+}
@@ -3,13 +3,26 @@
 //! A multi-AI collaboration bridge based on MCP protocol.
 
 pub mod agent;
+pub mod cache;
+pub mod changes;
 pub mod config;
+pub mod digest;
+pub mod events;
+pub mod journal;
 pub mod log_provider;
 pub mod mcp;
+pub mod metrics;
+pub mod procmon;
+pub mod prompts;
 pub mod pty;
+pub mod recording;
+pub mod sandbox;
 pub mod session;
 pub mod state;
+pub mod telemetry;
+pub mod usage;
 pub mod web;
+pub mod worktree;
 
 pub use config::Config;
 pub use mcp::McpServer;
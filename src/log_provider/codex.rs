@@ -1,12 +1,15 @@
 //! Codex log provider
 
-use super::{HistoryEntry, LockedSession, LogEntry, LogProvider, PathMapper};
+use super::{
+    agent_env, agent_home_path, join_command, parse_patch_files, HistoryEntry, LockedSession,
+    LogEntry, LogProvider, PathMapper, Reply, TokenUsage, ToolInvocation,
+};
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use std::collections::HashMap;
 use std::fs::{self, File};
 use std::io::{BufRead, BufReader, Seek, SeekFrom};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::sync::Mutex;
@@ -25,10 +28,10 @@ impl CodexLogProvider {
             if let Some(path) = cfg.get("path_pattern") {
                 PathMapper::normalize(path)
             } else {
-                Self::default_log_path()
+                Self::default_log_path(config)
             }
         } else {
-            Self::default_log_path()
+            Self::default_log_path(config)
         };
 
         Self {
@@ -39,8 +42,11 @@ impl CodexLogProvider {
         }
     }
 
-    fn default_log_path() -> PathBuf {
-        PathMapper::normalize("~/.codex/sessions")
+    fn default_log_path(config: Option<&HashMap<String, String>>) -> PathBuf {
+        if let Some(home) = agent_env(config, "CODEX_HOME") {
+            return PathMapper::normalize(&home).join("sessions");
+        }
+        agent_home_path(config, ".codex/sessions")
     }
 
     fn find_latest_session_file(&self) -> Option<PathBuf> {
@@ -91,7 +97,13 @@ impl CodexLogProvider {
 
     fn parse_jsonl_entry(&self, line: &str) -> Option<(String, String, DateTime<Utc>)> {
         let json: serde_json::Value = serde_json::from_str(line).ok()?;
+        self.parse_json_entry(&json)
+    }
 
+    fn parse_json_entry(
+        &self,
+        json: &serde_json::Value,
+    ) -> Option<(String, String, DateTime<Utc>)> {
         // Parse timestamp from top level
         let timestamp = json
             .get("timestamp")
@@ -213,29 +225,178 @@ impl CodexLogProvider {
         }
         Some((role, content, timestamp))
     }
-}
 
-#[async_trait]
-impl LogProvider for CodexLogProvider {
-    async fn get_latest_reply(&self, since_offset: u64) -> Option<LogEntry> {
-        tracing::debug!(
-            "[CodexLogProvider] get_latest_reply called with since_offset={}",
-            since_offset
-        );
+    /// Parse a `token_count` event: returns (cumulative session total, usage of the last turn).
+    fn parse_token_count(json: &serde_json::Value) -> Option<(u64, TokenUsage)> {
+        if json.get("type").and_then(|t| t.as_str()) != Some("event_msg") {
+            return None;
+        }
+        let payload = json.get("payload")?;
+        if payload.get("type").and_then(|t| t.as_str()) != Some("token_count") {
+            return None;
+        }
+        let info = payload.get("info")?;
+        let field = |usage: &serde_json::Value, name: &str| {
+            usage.get(name).and_then(|v| v.as_u64()).unwrap_or(0)
+        };
 
-        // Use locked session file if available, otherwise find latest
-        let session_file = {
-            let locked = self.locked_session.lock().await;
-            if let Some(ref path) = *locked {
-                tracing::debug!("[CodexLogProvider] Using locked session file: {:?}", path);
-                path.clone()
-            } else {
-                drop(locked);
-                self.find_latest_session_file()?
+        let session_total = info
+            .get("total_token_usage")
+            .map(|u| field(u, "total_tokens"))
+            .unwrap_or(0);
+        let last = info.get("last_token_usage")?;
+        let usage = TokenUsage {
+            input_tokens: field(last, "input_tokens"),
+            output_tokens: field(last, "output_tokens"),
+            cached_input_tokens: field(last, "cached_input_tokens"),
+            reasoning_tokens: field(last, "reasoning_output_tokens"),
+            total_tokens: field(last, "total_tokens"),
+        };
+        Some((session_total, usage))
+    }
+
+    /// Model name from a `turn_context` entry
+    fn parse_turn_model(json: &serde_json::Value) -> Option<String> {
+        if json.get("type").and_then(|t| t.as_str()) != Some("turn_context") {
+            return None;
+        }
+        json.get("payload")?
+            .get("model")?
+            .as_str()
+            .map(|m| m.to_string())
+    }
+
+    /// Record a tool call; returns its index in `reply.tool_calls`
+    fn push_tool_call(reply: &mut Reply, name: &str, args: &serde_json::Value) -> Option<usize> {
+        let patch = match name {
+            "apply_patch" => args
+                .get("input")
+                .and_then(|i| i.as_str())
+                .or_else(|| args.as_str()),
+            _ => None,
+        };
+        if let Some(patch) = patch {
+            for (action, path) in parse_patch_files(patch) {
+                reply.push_file_edit(path, action);
             }
+            return None;
+        }
+
+        if let Some(command) = args.get("command") {
+            // `apply_patch` is also invoked through the shell tool
+            if let Some(argv) = command.as_array() {
+                if argv.first().and_then(|a| a.as_str()) == Some("apply_patch") {
+                    let patch = argv.get(1).and_then(|p| p.as_str()).unwrap_or_default();
+                    for (action, path) in parse_patch_files(patch) {
+                        reply.push_file_edit(path, action);
+                    }
+                    return None;
+                }
+            }
+            if let Some(command) = join_command(command) {
+                reply.push_shell(command, None);
+                return Some(reply.tool_calls.len() - 1);
+            }
+        }
+
+        reply.tool_calls.push(ToolInvocation::Other {
+            name: name.to_string(),
+        });
+        None
+    }
+
+    /// Collect reasoning, tool calls and errors from one session entry
+    fn collect_reply_details(
+        json: &serde_json::Value,
+        reply: &mut Reply,
+        calls: &mut HashMap<String, usize>,
+    ) {
+        let Some(payload) = json.get("payload") else {
+            return;
         };
+        let entry_type = json
+            .get("type")
+            .and_then(|t| t.as_str())
+            .unwrap_or_default();
+        let payload_type = payload
+            .get("type")
+            .and_then(|t| t.as_str())
+            .unwrap_or_default();
+        let call_id = payload.get("call_id").and_then(|c| c.as_str());
+
+        match (entry_type, payload_type) {
+            ("response_item", "reasoning") => {
+                let summary = payload
+                    .get("summary")
+                    .and_then(|s| s.as_array())
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|s| s.get("text").and_then(|t| t.as_str()))
+                    .map(|t| t.trim())
+                    .filter(|t| !t.is_empty());
+                reply.reasoning.extend(summary.map(String::from));
+            }
+            ("response_item", "function_call") => {
+                let name = payload
+                    .get("name")
+                    .and_then(|n| n.as_str())
+                    .unwrap_or_default();
+                let args = payload
+                    .get("arguments")
+                    .and_then(|a| a.as_str())
+                    .and_then(|a| serde_json::from_str(a).ok())
+                    .unwrap_or(serde_json::Value::Null);
+                if let (Some(idx), Some(id)) = (Self::push_tool_call(reply, name, &args), call_id) {
+                    calls.insert(id.to_string(), idx);
+                }
+            }
+            ("response_item", "custom_tool_call") => {
+                let name = payload
+                    .get("name")
+                    .and_then(|n| n.as_str())
+                    .unwrap_or_default();
+                let input = payload.get("input").cloned().unwrap_or_default();
+                Self::push_tool_call(reply, name, &input);
+            }
+            ("response_item", "local_shell_call") => {
+                let command = payload
+                    .get("action")
+                    .and_then(|a| a.get("command"))
+                    .and_then(join_command);
+                if let Some(command) = command {
+                    reply.push_shell(command, None);
+                    if let Some(id) = call_id {
+                        calls.insert(id.to_string(), reply.tool_calls.len() - 1);
+                    }
+                }
+            }
+            ("response_item", "function_call_output") => {
+                let exit = payload
+                    .get("output")
+                    .and_then(|o| o.as_str())
+                    .and_then(|o| serde_json::from_str::<serde_json::Value>(o).ok())
+                    .and_then(|o| o.get("metadata")?.get("exit_code")?.as_i64());
+                let idx = call_id.and_then(|id| calls.get(id));
+                if let (Some(code), Some(&idx)) = (exit, idx) {
+                    if let Some(ToolInvocation::Shell { exit_code, .. }) =
+                        reply.tool_calls.get_mut(idx)
+                    {
+                        *exit_code = Some(code);
+                    }
+                }
+            }
+            ("event_msg", "error") | ("event_msg", "stream_error") => {
+                if let Some(message) = payload.get("message").and_then(|m| m.as_str()) {
+                    reply.errors.push(message.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
 
-        let file = match File::open(&session_file) {
+    /// Read the last assistant reply appended to `session_file` after `since_offset`.
+    fn read_reply_from_file(&self, session_file: &Path, since_offset: u64) -> Option<LogEntry> {
+        let file = match File::open(session_file) {
             Ok(f) => f,
             Err(e) => {
                 tracing::warn!(
@@ -261,6 +422,11 @@ impl LogProvider for CodexLogProvider {
         let mut last_end_offset: u64 = since_offset;
         let mut lines_read = 0u32;
         let mut entries_parsed = 0u32;
+        let mut usage: Option<TokenUsage> = None;
+        let mut last_session_total: Option<u64> = None;
+        let mut model: Option<String> = None;
+        let mut details = Reply::default();
+        let mut calls: HashMap<String, usize> = HashMap::new();
 
         loop {
             let mut line = String::new();
@@ -272,7 +438,27 @@ impl LogProvider for CodexLogProvider {
                     // Get position after reading line (end of line)
                     let end_pos = reader.stream_position().ok()?;
 
-                    if let Some((role, content, timestamp)) = self.parse_jsonl_entry(&line) {
+                    let Ok(json) = serde_json::from_str::<serde_json::Value>(&line) else {
+                        continue;
+                    };
+
+                    if let Some((session_total, turn)) = Self::parse_token_count(&json) {
+                        // Codex may repeat a token_count event without new usage
+                        if last_session_total != Some(session_total) {
+                            last_session_total = Some(session_total);
+                            usage
+                                .get_or_insert_with(TokenUsage::default)
+                                .accumulate(&turn);
+                        }
+                        continue;
+                    }
+                    if let Some(m) = Self::parse_turn_model(&json) {
+                        model = Some(m);
+                        continue;
+                    }
+                    Self::collect_reply_details(&json, &mut details, &mut calls);
+
+                    if let Some((role, content, timestamp)) = self.parse_json_entry(&json) {
                         entries_parsed += 1;
                         tracing::debug!(
                             "[CodexLogProvider] Parsed entry: role={}, content_len={}, offset={}",
@@ -288,6 +474,9 @@ impl LogProvider for CodexLogProvider {
                                 timestamp,
                                 inode: self.get_inode(),
                                 done_seen: super::might_have_done_marker(&content),
+                                usage: None,
+                                model: None,
+                                reply: None,
                             });
                             last_end_offset = end_pos;
                         }
@@ -307,12 +496,40 @@ impl LogProvider for CodexLogProvider {
             last_assistant_entry.is_some()
         );
 
-        if last_assistant_entry.is_some() {
+        if let Some(entry) = last_assistant_entry.as_mut() {
             self.current_offset.store(last_end_offset, Ordering::SeqCst);
+            entry.usage = usage;
+            entry.model = model;
+            details.text = entry.content.clone();
+            entry.reply = Some(details);
         }
 
         last_assistant_entry
     }
+}
+
+#[async_trait]
+impl LogProvider for CodexLogProvider {
+    async fn get_latest_reply(&self, since_offset: u64) -> Option<LogEntry> {
+        tracing::debug!(
+            "[CodexLogProvider] get_latest_reply called with since_offset={}",
+            since_offset
+        );
+
+        // Use locked session file if available, otherwise find latest
+        let session_file = {
+            let locked = self.locked_session.lock().await;
+            if let Some(ref path) = *locked {
+                tracing::debug!("[CodexLogProvider] Using locked session file: {:?}", path);
+                path.clone()
+            } else {
+                drop(locked);
+                self.find_latest_session_file()?
+            }
+        };
+
+        self.read_reply_from_file(&session_file, since_offset)
+    }
 
     async fn get_history(&self, _session_id: Option<&str>, count: usize) -> Vec<HistoryEntry> {
         let Some(session_file) = self.find_latest_session_file() else {
@@ -396,4 +613,80 @@ impl LogProvider for CodexLogProvider {
         }
         *locked = None;
     }
+
+    async fn harvest_reply(&self, locked: &LockedSession) -> Option<LogEntry> {
+        self.read_reply_from_file(&locked.file_path, locked.baseline_offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_reply_carries_token_usage_and_model() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("rollout.jsonl");
+        let mut file = File::create(&path).unwrap();
+        let lines = [
+            r#"{"timestamp":"2026-01-01T00:00:00Z","type":"turn_context","payload":{"cwd":"/p","model":"gpt-5-codex"}}"#,
+            r#"{"timestamp":"2026-01-01T00:00:01Z","type":"event_msg","payload":{"type":"agent_message","message":"done"}}"#,
+            r#"{"timestamp":"2026-01-01T00:00:02Z","type":"event_msg","payload":{"type":"token_count","info":{"total_token_usage":{"total_tokens":120},"last_token_usage":{"input_tokens":100,"cached_input_tokens":40,"output_tokens":20,"reasoning_output_tokens":5,"total_tokens":120}}}}"#,
+            r#"{"timestamp":"2026-01-01T00:00:02Z","type":"event_msg","payload":{"type":"token_count","info":{"total_token_usage":{"total_tokens":120},"last_token_usage":{"input_tokens":100,"cached_input_tokens":40,"output_tokens":20,"reasoning_output_tokens":5,"total_tokens":120}}}}"#,
+        ];
+        for line in lines {
+            writeln!(file, "{}", line).unwrap();
+        }
+
+        let provider = CodexLogProvider::new(None);
+        let entry = provider.read_reply_from_file(&path, 0).unwrap();
+        assert_eq!(entry.content, "done");
+        assert_eq!(entry.model.as_deref(), Some("gpt-5-codex"));
+        let usage = entry.usage.unwrap();
+        assert_eq!(usage.input_tokens, 100);
+        assert_eq!(usage.cached_input_tokens, 40);
+        assert_eq!(usage.reasoning_tokens, 5);
+        // Repeated token_count with the same session total is counted once
+        assert_eq!(usage.total_tokens, 120);
+    }
+
+    #[test]
+    fn test_reply_collects_tool_calls_reasoning_and_errors() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("rollout.jsonl");
+        let mut file = File::create(&path).unwrap();
+        let lines = [
+            r#"{"timestamp":"2026-01-01T00:00:00Z","type":"response_item","payload":{"type":"reasoning","summary":[{"type":"summary_text","text":"Check the tests"}]}}"#,
+            r#"{"timestamp":"2026-01-01T00:00:01Z","type":"response_item","payload":{"type":"function_call","name":"shell","arguments":"{\"command\":[\"bash\",\"-lc\",\"cargo test\"]}","call_id":"call_1"}}"#,
+            r#"{"timestamp":"2026-01-01T00:00:02Z","type":"response_item","payload":{"type":"function_call_output","call_id":"call_1","output":"{\"output\":\"ok\",\"metadata\":{\"exit_code\":101}}"}}"#,
+            r#"{"timestamp":"2026-01-01T00:00:03Z","type":"response_item","payload":{"type":"custom_tool_call","name":"apply_patch","input":"*** Begin Patch\n*** Update File: src/lib.rs\n@@\n-a\n+b\n*** End Patch","call_id":"call_2"}}"#,
+            r#"{"timestamp":"2026-01-01T00:00:04Z","type":"event_msg","payload":{"type":"error","message":"stream disconnected"}}"#,
+            r#"{"timestamp":"2026-01-01T00:00:05Z","type":"event_msg","payload":{"type":"agent_message","message":"fixed"}}"#,
+        ];
+        for line in lines {
+            writeln!(file, "{}", line).unwrap();
+        }
+
+        let provider = CodexLogProvider::new(None);
+        let entry = provider.read_reply_from_file(&path, 0).unwrap();
+        let reply = entry.reply.unwrap();
+        assert_eq!(reply.text, "fixed");
+        assert_eq!(reply.reasoning, vec!["Check the tests".to_string()]);
+        assert_eq!(
+            reply.tool_calls,
+            vec![
+                ToolInvocation::Shell {
+                    command: "cargo test".to_string(),
+                    exit_code: Some(101),
+                },
+                ToolInvocation::FileEdit {
+                    path: "src/lib.rs".to_string(),
+                    action: "update".to_string(),
+                },
+            ]
+        );
+        assert_eq!(reply.errors, vec!["stream disconnected".to_string()]);
+    }
 }
@@ -7,12 +7,14 @@
 //! directory when available, to avoid locking the wrong project's session file
 //! when multiple projects exist under ~/.gemini/tmp.
 
-use super::{HistoryEntry, LockedSession, LogEntry, LogProvider, PathMapper};
+use super::{
+    agent_env, agent_home_path, HistoryEntry, LockedSession, LogEntry, LogProvider, PathMapper,
+    Reply, TokenUsage, ToolInvocation,
+};
+use crate::digest::sha256_hex;
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use sha2::{Digest, Sha256};
 use std::collections::{HashMap, HashSet};
-use std::fmt::Write as _;
 use std::fs::{self, File};
 use std::io::{BufReader, Read};
 use std::path::{Path, PathBuf};
@@ -21,6 +23,48 @@ use std::sync::Arc;
 use std::time::SystemTime;
 use tokio::sync::Mutex;
 
+/// One message of a Gemini chat session file
+#[derive(Debug, Clone)]
+struct ChatMessage {
+    role: String,
+    content: String,
+    timestamp: DateTime<Utc>,
+    usage: Option<TokenUsage>,
+    model: Option<String>,
+    /// Thoughts, tool calls and errors recorded on this message
+    details: Reply,
+}
+
+impl ChatMessage {
+    /// Build the log entry for this message; `preceding` are the messages written
+    /// between the baseline and this one, whose details and token usage are
+    /// folded into the reply.
+    fn to_log_entry(&self, offset: u64, inode: Option<u64>, preceding: &[ChatMessage]) -> LogEntry {
+        let mut reply = Reply::default();
+        let mut usage: Option<TokenUsage> = None;
+        for msg in preceding.iter().chain(std::iter::once(self)) {
+            reply.merge(msg.details.clone());
+            if let Some(msg_usage) = &msg.usage {
+                usage
+                    .get_or_insert_with(TokenUsage::default)
+                    .accumulate(msg_usage);
+            }
+        }
+        reply.text = self.content.clone();
+
+        LogEntry {
+            content: self.content.clone(),
+            offset,
+            timestamp: self.timestamp,
+            inode,
+            done_seen: super::might_have_done_marker(&self.content),
+            usage,
+            model: self.model.clone(),
+            reply: Some(reply),
+        }
+    }
+}
+
 #[derive(Clone)]
 struct LockedGeminiSession {
     path: PathBuf,
@@ -28,7 +72,7 @@ struct LockedGeminiSession {
     // Cache fields to avoid re-parsing unchanged files
     last_modified: SystemTime,
     last_size: u64,
-    cached_entries: Arc<Vec<(String, String, DateTime<Utc>)>>,
+    cached_entries: Arc<Vec<ChatMessage>>,
 }
 
 pub struct GeminiLogProvider {
@@ -44,10 +88,10 @@ impl GeminiLogProvider {
             if let Some(path) = cfg.get("path_pattern") {
                 PathMapper::normalize(path)
             } else {
-                Self::default_log_path()
+                Self::default_log_path(config)
             }
         } else {
-            Self::default_log_path()
+            Self::default_log_path(config)
         };
 
         let project_dir = config
@@ -72,13 +116,11 @@ impl GeminiLogProvider {
         DateTime::<Utc>::from(SystemTime::UNIX_EPOCH)
     }
 
-    fn default_log_path() -> PathBuf {
-        if let Ok(root) = std::env::var("GEMINI_ROOT") {
-            if !root.is_empty() {
-                return PathMapper::normalize(&root);
-            }
+    fn default_log_path(config: Option<&HashMap<String, String>>) -> PathBuf {
+        if let Some(root) = agent_env(config, "GEMINI_ROOT") {
+            return PathMapper::normalize(&root);
         }
-        PathMapper::normalize("~/.gemini/tmp")
+        agent_home_path(config, ".gemini/tmp")
     }
 
     fn project_dir_for_working_dir(log_root: &Path, working_dir: &str) -> PathBuf {
@@ -131,13 +173,7 @@ impl GeminiLogProvider {
                 continue;
             }
 
-            let digest = Sha256::digest(candidate.as_bytes());
-            let mut hash = String::with_capacity(64);
-            for b in digest {
-                let _ = write!(&mut hash, "{:02x}", b);
-            }
-
-            let dir = log_root.join(hash);
+            let dir = log_root.join(sha256_hex(&candidate));
             if fallback_dir.is_none() {
                 fallback_dir = Some(dir.clone());
             }
@@ -278,7 +314,89 @@ impl GeminiLogProvider {
         latest_file
     }
 
-    fn parse_chat_json(&self, content: &str) -> Vec<(String, String, DateTime<Utc>)> {
+    fn parse_tokens(msg: &serde_json::Value) -> Option<TokenUsage> {
+        let tokens = msg.get("tokens")?;
+        let field = |name: &str| tokens.get(name).and_then(|v| v.as_u64()).unwrap_or(0);
+        Some(TokenUsage {
+            input_tokens: field("input"),
+            output_tokens: field("output"),
+            cached_input_tokens: field("cached"),
+            reasoning_tokens: field("thoughts"),
+            total_tokens: field("total"),
+        })
+    }
+
+    fn parse_details(role: &str, content: &str, msg: &serde_json::Value) -> Reply {
+        let mut reply = Reply::default();
+        if role == "error" && !content.trim().is_empty() {
+            reply.errors.push(content.trim().to_string());
+        }
+
+        for thought in msg
+            .get("thoughts")
+            .and_then(|t| t.as_array())
+            .into_iter()
+            .flatten()
+        {
+            let subject = thought
+                .get("subject")
+                .and_then(|s| s.as_str())
+                .unwrap_or("");
+            let description = thought
+                .get("description")
+                .and_then(|d| d.as_str())
+                .unwrap_or("");
+            let text = match (subject.is_empty(), description.is_empty()) {
+                (false, false) => format!("{}: {}", subject, description),
+                (false, true) => subject.to_string(),
+                _ => description.to_string(),
+            };
+            if !text.is_empty() {
+                reply.reasoning.push(text);
+            }
+        }
+
+        for call in msg
+            .get("toolCalls")
+            .and_then(|t| t.as_array())
+            .into_iter()
+            .flatten()
+        {
+            let name = call
+                .get("name")
+                .and_then(|n| n.as_str())
+                .unwrap_or_default();
+            let args = call.get("args");
+            let arg = |key: &str| args.and_then(|a| a.get(key)).and_then(|v| v.as_str());
+            match name {
+                "run_shell_command" => {
+                    reply.push_shell(arg("command").unwrap_or_default(), None);
+                }
+                "write_file" | "replace" | "edit" => {
+                    let path = arg("file_path")
+                        .or_else(|| arg("absolute_path"))
+                        .or_else(|| arg("path"))
+                        .unwrap_or_default();
+                    let action = if name == "write_file" {
+                        "write"
+                    } else {
+                        "update"
+                    };
+                    reply.push_file_edit(path, action);
+                }
+                _ => reply.tool_calls.push(ToolInvocation::Other {
+                    name: name.to_string(),
+                }),
+            }
+            if call.get("status").and_then(|s| s.as_str()) == Some("error") {
+                reply.errors.push(format!("tool {} failed", name));
+            }
+        }
+
+        reply
+    }
+
+    fn parse_chat_json(&self, content: &str) -> Vec<ChatMessage> {
         let Ok(json) = serde_json::from_str::<serde_json::Value>(content) else {
             return Vec::new();
         };
@@ -299,7 +417,15 @@ impl GeminiLogProvider {
                     .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
                     .map(|t| t.with_timezone(&Utc))
                     .unwrap_or_else(Self::default_timestamp);
-                Some((role, content, timestamp))
+                let details = Self::parse_details(&role, &content, msg);
+                Some(ChatMessage {
+                    role,
+                    content,
+                    timestamp,
+                    usage: Self::parse_tokens(msg),
+                    model: msg.get("model").and_then(|m| m.as_str()).map(String::from),
+                    details,
+                })
             })
             .collect()
     }
@@ -353,10 +479,7 @@ impl GeminiLogProvider {
 
     /// Parse file and update the locked session cache.
     /// Returns the parsed entries.
-    async fn parse_and_update_cache(
-        &self,
-        path: &PathBuf,
-    ) -> Arc<Vec<(String, String, DateTime<Utc>)>> {
+    async fn parse_and_update_cache(&self, path: &PathBuf) -> Arc<Vec<ChatMessage>> {
         let (content, metadata) = match Self::read_file_to_string_with_metadata(path) {
             Ok((c, m)) => (c, m),
             Err(e) => {
@@ -387,11 +510,7 @@ impl GeminiLogProvider {
         entries
     }
 
-    fn find_assistant_reply(
-        &self,
-        entries: &[(String, String, DateTime<Utc>)],
-        since_offset: u64,
-    ) -> Option<LogEntry> {
+    fn find_assistant_reply(&self, entries: &[ChatMessage], since_offset: u64) -> Option<LogEntry> {
         // On 32-bit systems, u64 may overflow usize. Using MAX ensures .min() clamps
         // to array length, resulting in an empty slice (no matches) - correct behavior.
         let start = usize::try_from(since_offset)
@@ -402,43 +521,32 @@ impl GeminiLogProvider {
         entries[start..]
             .iter()
             .enumerate()
-            .rfind(|(_, (role, content, _))| {
-                Self::is_assistant_role(role) && !content.trim().is_empty()
-            })
-            .map(|(local_idx, (_, content, timestamp))| {
-                let done_seen = super::might_have_done_marker(content);
-                LogEntry {
-                    content: content.clone(),
-                    offset: (start + local_idx) as u64 + 1,
-                    timestamp: *timestamp,
-                    inode: self.get_inode(),
-                    done_seen,
-                }
+            .rfind(|(_, msg)| Self::is_assistant_role(&msg.role) && !msg.content.trim().is_empty())
+            .map(|(local_idx, msg)| {
+                let preceding = &entries[start..start + local_idx];
+                msg.to_log_entry((start + local_idx) as u64 + 1, self.get_inode(), preceding)
             })
     }
 
     fn find_assistant_reply_by_timestamp(
         &self,
-        entries: &[(String, String, DateTime<Utc>)],
+        entries: &[ChatMessage],
         baseline_timestamp: DateTime<Utc>,
     ) -> Option<LogEntry> {
         entries
             .iter()
             .enumerate()
-            .rfind(|(_, (role, content, timestamp))| {
-                Self::is_assistant_role(role)
-                    && *timestamp > baseline_timestamp
-                    && !content.trim().is_empty()
+            .rfind(|(_, msg)| {
+                Self::is_assistant_role(&msg.role)
+                    && msg.timestamp > baseline_timestamp
+                    && !msg.content.trim().is_empty()
             })
-            .map(|(idx, (_, content, timestamp))| {
-                let done_seen = super::might_have_done_marker(content);
-                LogEntry {
-                    content: content.clone(),
-                    offset: idx as u64 + 1,
-                    timestamp: *timestamp,
-                    inode: self.get_inode(),
-                    done_seen,
-                }
+            .map(|(idx, msg)| {
+                let first_new = entries[..idx]
+                    .iter()
+                    .position(|m| m.timestamp > baseline_timestamp)
+                    .unwrap_or(idx);
+                msg.to_log_entry(idx as u64 + 1, self.get_inode(), &entries[first_new..idx])
             })
     }
 
@@ -620,17 +728,17 @@ impl LogProvider for GeminiLogProvider {
         let mut entries: Vec<HistoryEntry> = self
             .parse_chat_json(&content)
             .into_iter()
-            .map(|(role, content, timestamp)| {
+            .map(|msg| {
                 // Normalize role: "gemini" and "model" -> "assistant"
-                let normalized_role = if role == "model" || role == "gemini" {
+                let normalized_role = if msg.role == "model" || msg.role == "gemini" {
                     "assistant".to_string()
                 } else {
-                    role
+                    msg.role
                 };
                 HistoryEntry {
                     role: normalized_role,
-                    content,
-                    timestamp,
+                    content: msg.content,
+                    timestamp: msg.timestamp,
                 }
             })
             .collect();
@@ -687,7 +795,7 @@ impl LogProvider for GeminiLogProvider {
         let baseline_timestamp = entries
             .iter()
             .rev()
-            .map(|(_, _, ts)| *ts)
+            .map(|msg| msg.timestamp)
             .find(|ts| *ts != default_timestamp)
             .unwrap_or(default_timestamp);
 
@@ -723,6 +831,12 @@ impl LogProvider for GeminiLogProvider {
         }
         *locked = None;
     }
+
+    async fn harvest_reply(&self, locked: &LockedSession) -> Option<LogEntry> {
+        let content = Self::read_file_to_string(&locked.file_path).ok()?;
+        let entries = self.parse_chat_json(&content);
+        self.find_assistant_reply(&entries, locked.baseline_offset)
+    }
 }
 
 #[cfg(test)]
@@ -783,4 +897,106 @@ mod tests {
 
         assert_eq!(chosen, project_session);
     }
+
+    #[test]
+    fn honors_per_agent_gemini_root_and_home() {
+        let mut cfg = HashMap::new();
+        cfg.insert("env.HOME".to_string(), "/home/agent".to_string());
+        assert_eq!(
+            GeminiLogProvider::default_log_path(Some(&cfg)),
+            PathMapper::normalize("/home/agent/.gemini/tmp")
+        );
+
+        cfg.insert("env.GEMINI_ROOT".to_string(), "/srv/gemini".to_string());
+        let provider = GeminiLogProvider::new(Some(&cfg));
+        assert_eq!(provider.log_root, PathMapper::normalize("/srv/gemini"));
+    }
+
+    #[test]
+    fn parses_tokens_and_model_from_chat_json() {
+        let provider = GeminiLogProvider::new(None);
+        let content = r#"{
+  "messages": [
+    {"id": "1", "timestamp": "2026-01-01T00:00:00.000Z", "type": "user", "content": "hi"},
+    {"id": "2", "timestamp": "2026-01-01T00:00:01.000Z", "type": "gemini", "content": "hello",
+     "tokens": {"input": 10, "output": 4, "cached": 3, "thoughts": 2, "tool": 0, "total": 16},
+     "model": "gemini-2.5-pro"}
+  ]
+}"#;
+        let entries = provider.parse_chat_json(content);
+        let entry = provider.find_assistant_reply(&entries, 1).unwrap();
+        assert_eq!(entry.content, "hello");
+        assert_eq!(entry.model.as_deref(), Some("gemini-2.5-pro"));
+        let usage = entry.usage.unwrap();
+        assert_eq!(usage.input_tokens, 10);
+        assert_eq!(usage.reasoning_tokens, 2);
+        assert_eq!(usage.total_tokens, 16);
+    }
+
+    #[test]
+    fn collects_thoughts_and_tool_calls_since_baseline() {
+        let provider = GeminiLogProvider::new(None);
+        let content = r#"{
+  "messages": [
+    {"id": "1", "timestamp": "2026-01-01T00:00:00.000Z", "type": "user", "content": "fix it"},
+    {"id": "2", "timestamp": "2026-01-01T00:00:01.000Z", "type": "gemini", "content": "",
+     "thoughts": [{"subject": "Plan", "description": "Run the tests first"}],
+     "toolCalls": [
+       {"id": "a", "name": "run_shell_command", "args": {"command": "npm test"}, "status": "success"},
+       {"id": "b", "name": "replace", "args": {"file_path": "/p/src/a.ts"}, "status": "error"}
+     ]},
+    {"id": "3", "timestamp": "2026-01-01T00:00:02.000Z", "type": "gemini", "content": "Fixed."}
+  ]
+}"#;
+        let entries = provider.parse_chat_json(content);
+        let reply = provider
+            .find_assistant_reply(&entries, 1)
+            .unwrap()
+            .reply
+            .unwrap();
+        assert_eq!(reply.text, "Fixed.");
+        assert_eq!(
+            reply.reasoning,
+            vec!["Plan: Run the tests first".to_string()]
+        );
+        assert_eq!(
+            reply.tool_calls,
+            vec![
+                ToolInvocation::Shell {
+                    command: "npm test".to_string(),
+                    exit_code: None,
+                },
+                ToolInvocation::FileEdit {
+                    path: "/p/src/a.ts".to_string(),
+                    action: "update".to_string(),
+                },
+            ]
+        );
+        assert_eq!(reply.errors, vec!["tool replace failed".to_string()]);
+    }
+
+    #[test]
+    fn sums_tokens_of_tool_call_messages_into_reply() {
+        let provider = GeminiLogProvider::new(None);
+        let content = r#"{
+  "messages": [
+    {"id": "1", "timestamp": "2026-01-01T00:00:00.000Z", "type": "user", "content": "fix it"},
+    {"id": "2", "timestamp": "2026-01-01T00:00:01.000Z", "type": "gemini", "content": "",
+     "toolCalls": [{"id": "a", "name": "run_shell_command", "args": {"command": "npm test"}, "status": "success"}],
+     "tokens": {"input": 100, "output": 10, "cached": 0, "thoughts": 5, "tool": 0, "total": 115}},
+    {"id": "3", "timestamp": "2026-01-01T00:00:02.000Z", "type": "gemini", "content": "Fixed.",
+     "tokens": {"input": 150, "output": 4, "cached": 0, "thoughts": 0, "tool": 0, "total": 154}}
+  ]
+}"#;
+        let entries = provider.parse_chat_json(content);
+        let usage = provider
+            .find_assistant_reply(&entries, 1)
+            .unwrap()
+            .usage
+            .unwrap();
+        assert_eq!(usage.input_tokens, 250);
+        assert_eq!(usage.output_tokens, 14);
+        assert_eq!(usage.reasoning_tokens, 5);
+        assert_eq!(usage.total_tokens, 269);
+    }
 }
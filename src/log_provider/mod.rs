@@ -2,6 +2,7 @@
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::broadcast;
@@ -10,11 +11,13 @@ mod codex;
 mod gemini;
 mod opencode;
 mod path_mapper;
+mod reply;
 
 pub use codex::CodexLogProvider;
 pub use gemini::GeminiLogProvider;
 pub use opencode::OpenCodeLogProvider;
 pub use path_mapper::PathMapper;
+pub use reply::{join_command, parse_patch_files, Reply, ToolInvocation};
 
 /// Handle for a file watcher subscription
 pub struct WatchHandle {
@@ -102,6 +105,28 @@ pub fn create_debounced_watcher(
     Some((event_tx, WatchHandle::new(cancel_tx)))
 }
 
+/// Token usage recorded in an agent's log
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    #[serde(default)]
+    pub cached_input_tokens: u64,
+    #[serde(default)]
+    pub reasoning_tokens: u64,
+    pub total_tokens: u64,
+}
+
+impl TokenUsage {
+    pub fn accumulate(&mut self, other: &TokenUsage) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cached_input_tokens += other.cached_input_tokens;
+        self.reasoning_tokens += other.reasoning_tokens;
+        self.total_tokens += other.total_tokens;
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct LogEntry {
     pub content: String,
@@ -111,6 +136,12 @@ pub struct LogEntry {
     /// Hint that content might contain a done marker. Not authoritative -
     /// actual validation is done by Agent::is_reply_complete().
     pub done_seen: bool,
+    /// Token usage for the reply, when the agent's log records it
+    pub usage: Option<TokenUsage>,
+    /// Model that produced the reply, when the agent's log records it
+    pub model: Option<String>,
+    /// Reasoning, tool calls and errors logged since the request baseline
+    pub reply: Option<Reply>,
 }
 
 /// Heuristic to detect if the reply *might* be complete.
@@ -147,6 +178,27 @@ mod tests {
         assert!(might_have_done_marker("Response\nCcGo_DoNe: abc\n\n"));
     }
 
+    #[test]
+    fn test_token_usage_accumulate() {
+        let mut total = TokenUsage::default();
+        total.accumulate(&TokenUsage {
+            input_tokens: 10,
+            output_tokens: 5,
+            cached_input_tokens: 2,
+            reasoning_tokens: 1,
+            total_tokens: 15,
+        });
+        total.accumulate(&TokenUsage {
+            input_tokens: 1,
+            output_tokens: 1,
+            total_tokens: 2,
+            ..Default::default()
+        });
+        assert_eq!(total.input_tokens, 11);
+        assert_eq!(total.cached_input_tokens, 2);
+        assert_eq!(total.total_tokens, 17);
+    }
+
     #[test]
     fn test_might_have_done_marker_not_terminal() {
         // Marker NOT at end - should return false
@@ -157,7 +209,7 @@ mod tests {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct HistoryEntry {
     pub role: String, // "user" or "assistant"
     pub content: String,
@@ -193,6 +245,12 @@ pub trait LogProvider: Send + Sync {
     /// Call this after reply detection completes (success or timeout).
     async fn unlock_session(&self);
 
+    /// Read the reply written to a previously locked session after its baseline offset.
+    /// Used to recover replies for requests that were in flight when ccgonext restarted.
+    async fn harvest_reply(&self, _locked: &LockedSession) -> Option<LogEntry> {
+        None
+    }
+
     fn subscribe_changes(&self, debounce_ms: u64) -> Option<WatchSubscription> {
         let path = self.get_watch_path()?;
         let (sender, handle) = create_debounced_watcher(path, debounce_ms)?;
@@ -234,6 +292,36 @@ impl LogProvider for NullLogProvider {
     async fn unlock_session(&self) {}
 }
 
+/// Variables that decide where agents keep their logs. When an agent's
+/// environment differs from ours, `main` passes them as `env.<NAME>` config
+/// keys (an empty value means unset for that agent).
+pub const LOG_ENV_VARS: &[&str] = &[
+    "HOME",
+    "CODEX_HOME",
+    "GEMINI_ROOT",
+    "OPENCODE_STORAGE_ROOT",
+    "XDG_DATA_HOME",
+];
+
+/// An environment variable as the agent sees it
+fn agent_env(config: Option<&HashMap<String, String>>, name: &str) -> Option<String> {
+    match config.and_then(|cfg| cfg.get(&format!("env.{}", name))) {
+        Some(value) => Some(value.clone()),
+        None => std::env::var(name).ok(),
+    }
+    .filter(|value| !value.is_empty())
+}
+
+/// `relative` under the agent's home directory
+fn agent_home_path(config: Option<&HashMap<String, String>>, relative: &str) -> std::path::PathBuf {
+    match config.and_then(|cfg| cfg.get("env.HOME")) {
+        Some(home) if !home.is_empty() => {
+            PathMapper::normalize(&std::path::Path::new(home).join(relative).to_string_lossy())
+        }
+        _ => PathMapper::normalize(&format!("~/{}", relative)),
+    }
+}
+
 pub fn create_log_provider(
     provider_type: &str,
     config: Option<&HashMap<String, String>>,
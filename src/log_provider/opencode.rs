@@ -9,7 +9,10 @@
 //! we monitor the entire storage directory and find the most recently
 //! updated session file.
 
-use super::{HistoryEntry, LockedSession, LogEntry, LogProvider, PathMapper};
+use super::{
+    agent_env, agent_home_path, parse_patch_files, HistoryEntry, LockedSession, LogEntry,
+    LogProvider, PathMapper, Reply, TokenUsage, ToolInvocation,
+};
 use async_trait::async_trait;
 use chrono::{DateTime, TimeZone, Utc};
 use std::collections::HashMap;
@@ -19,6 +22,57 @@ use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+/// A completed assistant message with its text extracted from parts
+#[derive(Debug, Clone)]
+struct AssistantEntry {
+    content: String,
+    timestamp: DateTime<Utc>,
+    usage: Option<TokenUsage>,
+    model: Option<String>,
+    /// Reasoning, tool calls and errors of this message and of the
+    /// text-less (tool-only) messages before it
+    details: Reply,
+}
+
+impl AssistantEntry {
+    /// Build the log entry; `preceding` are earlier entries written since the baseline,
+    /// whose details and token usage are folded into the reply.
+    fn into_log_entry(
+        self,
+        offset: u64,
+        inode: Option<u64>,
+        preceding: &[AssistantEntry],
+    ) -> LogEntry {
+        let mut reply = Reply::default();
+        let mut usage: Option<TokenUsage> = None;
+        for (details, entry_usage) in preceding
+            .iter()
+            .map(|e| (e.details.clone(), e.usage.as_ref()))
+            .chain(std::iter::once((self.details, self.usage.as_ref())))
+        {
+            reply.merge(details);
+            if let Some(entry_usage) = entry_usage {
+                usage
+                    .get_or_insert_with(TokenUsage::default)
+                    .accumulate(entry_usage);
+            }
+        }
+        reply.text = self.content.clone();
+
+        let done_seen = super::might_have_done_marker(&self.content);
+        LogEntry {
+            content: self.content,
+            offset,
+            timestamp: self.timestamp,
+            inode,
+            done_seen,
+            usage,
+            model: self.model,
+            reply: Some(reply),
+        }
+    }
+}
+
 pub struct OpenCodeLogProvider {
     storage_root: PathBuf,
     current_offset: Arc<AtomicU64>,
@@ -31,10 +85,10 @@ impl OpenCodeLogProvider {
             if let Some(path) = cfg.get("path_pattern") {
                 PathMapper::normalize(path)
             } else {
-                Self::default_storage_root()
+                Self::default_storage_root(config)
             }
         } else {
-            Self::default_storage_root()
+            Self::default_storage_root(config)
         };
 
         tracing::info!(
@@ -49,11 +103,14 @@ impl OpenCodeLogProvider {
         }
     }
 
-    fn default_storage_root() -> PathBuf {
-        if let Ok(root) = std::env::var("OPENCODE_STORAGE_ROOT") {
-            if !root.is_empty() {
-                return PathMapper::normalize(&root);
-            }
+    fn default_storage_root(config: Option<&HashMap<String, String>>) -> PathBuf {
+        if let Some(root) = agent_env(config, "OPENCODE_STORAGE_ROOT") {
+            return PathMapper::normalize(&root);
+        }
+        if let Some(data_home) = agent_env(config, "XDG_DATA_HOME") {
+            return PathMapper::normalize(&data_home)
+                .join("opencode")
+                .join("storage");
         }
 
         #[cfg(windows)]
@@ -74,7 +131,7 @@ impl OpenCodeLogProvider {
             }
         }
 
-        PathMapper::normalize("~/.local/share/opencode/storage")
+        agent_home_path(config, ".local/share/opencode/storage")
     }
 
     fn session_dir(&self) -> PathBuf {
@@ -316,10 +373,99 @@ impl OpenCodeLogProvider {
         String::new()
     }
 
-    fn get_assistant_entries(
-        &self,
-        locked_session_id: Option<&str>,
-    ) -> Vec<(String, String, DateTime<Utc>)> {
+    fn extract_details(&self, msg: &serde_json::Value, parts: &[serde_json::Value]) -> Reply {
+        let mut reply = Reply::default();
+
+        for part in parts {
+            match part.get("type").and_then(|t| t.as_str()) {
+                Some("reasoning") => {
+                    if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
+                        if !text.trim().is_empty() {
+                            reply.reasoning.push(text.trim().to_string());
+                        }
+                    }
+                }
+                Some("tool") => {
+                    let tool = part
+                        .get("tool")
+                        .and_then(|t| t.as_str())
+                        .unwrap_or_default();
+                    let state = part.get("state");
+                    let input = state.and_then(|s| s.get("input"));
+                    let arg = |key: &str| input.and_then(|i| i.get(key)).and_then(|v| v.as_str());
+                    match tool {
+                        "bash" => {
+                            let exit_code = state
+                                .and_then(|s| s.get("metadata"))
+                                .and_then(|m| m.get("exit"))
+                                .and_then(|e| e.as_i64());
+                            reply.push_shell(arg("command").unwrap_or_default(), exit_code);
+                        }
+                        "edit" | "write" => {
+                            let action = if tool == "write" { "write" } else { "update" };
+                            reply.push_file_edit(arg("filePath").unwrap_or_default(), action);
+                        }
+                        "patch" => {
+                            for (action, path) in
+                                parse_patch_files(arg("patchText").unwrap_or_default())
+                            {
+                                reply.push_file_edit(path, action);
+                            }
+                        }
+                        _ => reply.tool_calls.push(ToolInvocation::Other {
+                            name: tool.to_string(),
+                        }),
+                    }
+                    if state.and_then(|s| s.get("status")).and_then(|s| s.as_str()) == Some("error")
+                    {
+                        let error = state
+                            .and_then(|s| s.get("error"))
+                            .and_then(|e| e.as_str())
+                            .map(String::from)
+                            .unwrap_or_else(|| format!("tool {} failed", tool));
+                        reply.errors.push(error);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(error) = msg.get("error") {
+            let message = error
+                .get("data")
+                .and_then(|d| d.get("message"))
+                .and_then(|m| m.as_str())
+                .or_else(|| error.get("name").and_then(|n| n.as_str()))
+                .unwrap_or("unknown error");
+            reply.errors.push(message.to_string());
+        }
+
+        reply
+    }
+
+    fn parse_tokens(msg: &serde_json::Value) -> Option<TokenUsage> {
+        let tokens = msg.get("tokens")?;
+        let field = |v: Option<&serde_json::Value>| v.and_then(|n| n.as_u64()).unwrap_or(0);
+        let input_tokens = field(tokens.get("input"));
+        let output_tokens = field(tokens.get("output"));
+        let reasoning_tokens = field(tokens.get("reasoning"));
+        let cache = tokens.get("cache");
+        let cache_read = field(cache.and_then(|c| c.get("read")));
+        let cache_write = field(cache.and_then(|c| c.get("write")));
+        Some(TokenUsage {
+            input_tokens,
+            output_tokens,
+            cached_input_tokens: cache_read,
+            reasoning_tokens,
+            total_tokens: input_tokens
+                + output_tokens
+                + reasoning_tokens
+                + cache_read
+                + cache_write,
+        })
+    }
+
+    fn get_assistant_entries(&self, locked_session_id: Option<&str>) -> Vec<AssistantEntry> {
         let (_, session) = if let Some(sid) = locked_session_id {
             // Use locked session ID to find session
             match self.get_session_by_id(sid) {
@@ -343,6 +489,9 @@ impl OpenCodeLogProvider {
 
         let messages = self.read_messages(session_id);
         let mut entries = Vec::new();
+        // Details and usage of tool-only steps, attached to the next message with text
+        let mut pending = Reply::default();
+        let mut pending_usage: Option<TokenUsage> = None;
 
         for msg in messages {
             let role = msg.get("role").and_then(|r| r.as_str()).unwrap_or_default();
@@ -364,6 +513,13 @@ impl OpenCodeLogProvider {
             }
 
             let parts = self.read_parts(message_id);
+            pending.merge(self.extract_details(&msg, &parts));
+            if let Some(usage) = Self::parse_tokens(&msg) {
+                pending_usage
+                    .get_or_insert_with(TokenUsage::default)
+                    .accumulate(&usage);
+            }
+
             let text = self.extract_text(&parts, true);
             if text.is_empty() {
                 continue;
@@ -373,7 +529,16 @@ impl OpenCodeLogProvider {
                 .and_then(|ms| Utc.timestamp_millis_opt(ms).single())
                 .unwrap_or_else(Utc::now);
 
-            entries.push(("assistant".to_string(), text, timestamp));
+            entries.push(AssistantEntry {
+                content: text,
+                timestamp,
+                usage: pending_usage.take(),
+                model: msg
+                    .get("modelID")
+                    .and_then(|m| m.as_str())
+                    .map(String::from),
+                details: std::mem::take(&mut pending),
+            });
         }
 
         entries
@@ -440,19 +605,18 @@ impl LogProvider for OpenCodeLogProvider {
 
                             // Return the latest entry from the new session
                             let total = new_session_entries.len() as u64;
-                            if let Some((_, content, timestamp)) = new_session_entries.last() {
+                            let mut new_session_entries = new_session_entries;
+                            if let Some(entry) = new_session_entries.pop() {
                                 self.current_offset.store(total, Ordering::SeqCst);
                                 tracing::info!(
                                     "[OpenCodeLogProvider] Found assistant reply in new session, offset={}",
                                     total
                                 );
-                                return Some(LogEntry {
-                                    content: content.clone(),
-                                    offset: total,
-                                    timestamp: *timestamp,
-                                    inode: self.get_inode(),
-                                    done_seen: super::might_have_done_marker(content),
-                                });
+                                return Some(entry.into_log_entry(
+                                    total,
+                                    self.get_inode(),
+                                    &new_session_entries,
+                                ));
                             }
                         }
                     }
@@ -460,19 +624,12 @@ impl LogProvider for OpenCodeLogProvider {
             }
         }
 
-        let result = new_entries
-            .into_iter()
-            .last()
-            .map(|(idx, (_, content, timestamp))| {
-                let done_seen = super::might_have_done_marker(&content);
-                LogEntry {
-                    content,
-                    offset: idx as u64 + 1,
-                    timestamp,
-                    inode: self.get_inode(),
-                    done_seen,
-                }
-            });
+        let mut new_entries: Vec<AssistantEntry> =
+            new_entries.into_iter().map(|(_, e)| e).collect();
+        let result = new_entries.pop().map(|entry| {
+            let offset = since_offset + new_entries.len() as u64 + 1;
+            entry.into_log_entry(offset, self.get_inode(), &new_entries)
+        });
 
         if result.is_some() {
             self.current_offset.store(total_messages, Ordering::SeqCst);
@@ -493,10 +650,10 @@ impl LogProvider for OpenCodeLogProvider {
 
         let mut history: Vec<HistoryEntry> = entries
             .into_iter()
-            .map(|(role, content, timestamp)| HistoryEntry {
-                role,
-                content,
-                timestamp,
+            .map(|entry| HistoryEntry {
+                role: "assistant".to_string(),
+                content: entry.content,
+                timestamp: entry.timestamp,
             })
             .collect();
 
@@ -559,4 +716,147 @@ impl LogProvider for OpenCodeLogProvider {
         }
         *locked = None;
     }
+
+    async fn harvest_reply(&self, locked: &LockedSession) -> Option<LogEntry> {
+        let session = self.load_json(&locked.file_path)?;
+        let session_id = session.get("id").and_then(|i| i.as_str())?;
+        let entries = self.get_assistant_entries(Some(session_id));
+        let start = usize::try_from(locked.baseline_offset).unwrap_or(usize::MAX);
+        let total = entries.len() as u64;
+        let mut new_entries: Vec<AssistantEntry> = entries.into_iter().skip(start).collect();
+        let entry = new_entries.pop()?;
+        Some(entry.into_log_entry(total, None, &new_entries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn write_json(path: PathBuf, value: serde_json::Value) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, value.to_string()).unwrap();
+    }
+
+    #[test]
+    fn test_assistant_entry_carries_tokens_and_model() {
+        let root = tempdir().unwrap();
+        let storage = root.path();
+        write_json(
+            storage.join("session/proj/ses_1.json"),
+            serde_json::json!({"id": "ses_1", "time": {"updated": 2}}),
+        );
+        write_json(
+            storage.join("message/ses_1/msg_1.json"),
+            serde_json::json!({
+                "id": "msg_1",
+                "sessionID": "ses_1",
+                "role": "assistant",
+                "modelID": "claude-sonnet-4",
+                "tokens": {"input": 12, "output": 5, "reasoning": 3, "cache": {"read": 7, "write": 1}},
+                "time": {"created": 1, "completed": 2}
+            }),
+        );
+        write_json(
+            storage.join("part/msg_1/prt_1.json"),
+            serde_json::json!({
+                "messageID": "msg_1",
+                "type": "text",
+                "text": "hello",
+                "time": {"start": 1}
+            }),
+        );
+
+        let mut cfg = HashMap::new();
+        cfg.insert(
+            "path_pattern".to_string(),
+            storage.to_string_lossy().to_string(),
+        );
+        let provider = OpenCodeLogProvider::new(Some(&cfg));
+        let entries = provider.get_assistant_entries(Some("ses_1"));
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].content, "hello");
+        assert_eq!(entries[0].model.as_deref(), Some("claude-sonnet-4"));
+        let usage = entries[0].usage.unwrap();
+        assert_eq!(usage.cached_input_tokens, 7);
+        assert_eq!(usage.total_tokens, 28);
+    }
+
+    #[tokio::test]
+    async fn test_reply_sums_tokens_of_tool_call_messages() {
+        let root = tempdir().unwrap();
+        let storage = root.path();
+        let session_path = storage.join("session/proj/ses_1.json");
+        write_json(
+            session_path.clone(),
+            serde_json::json!({"id": "ses_1", "time": {"updated": 3}}),
+        );
+        write_json(
+            storage.join("message/ses_1/msg_1.json"),
+            serde_json::json!({
+                "id": "msg_1",
+                "sessionID": "ses_1",
+                "role": "assistant",
+                "tokens": {"input": 100, "output": 10, "reasoning": 0, "cache": {"read": 0, "write": 0}},
+                "time": {"created": 1, "completed": 2}
+            }),
+        );
+        write_json(
+            storage.join("part/msg_1/prt_1.json"),
+            serde_json::json!({
+                "messageID": "msg_1",
+                "type": "text",
+                "text": "Running the tests",
+                "time": {"start": 1}
+            }),
+        );
+        write_json(
+            storage.join("part/msg_1/prt_2.json"),
+            serde_json::json!({
+                "messageID": "msg_1",
+                "type": "tool",
+                "tool": "bash",
+                "state": {"input": {"command": "cargo test"}, "metadata": {"exit": 0}},
+                "time": {"start": 2}
+            }),
+        );
+        write_json(
+            storage.join("message/ses_1/msg_2.json"),
+            serde_json::json!({
+                "id": "msg_2",
+                "sessionID": "ses_1",
+                "role": "assistant",
+                "tokens": {"input": 150, "output": 5, "reasoning": 0, "cache": {"read": 0, "write": 0}},
+                "time": {"created": 3, "completed": 4}
+            }),
+        );
+        write_json(
+            storage.join("part/msg_2/prt_3.json"),
+            serde_json::json!({
+                "messageID": "msg_2",
+                "type": "text",
+                "text": "All green",
+                "time": {"start": 3}
+            }),
+        );
+
+        let mut cfg = HashMap::new();
+        cfg.insert(
+            "path_pattern".to_string(),
+            storage.to_string_lossy().to_string(),
+        );
+        let provider = OpenCodeLogProvider::new(Some(&cfg));
+        let locked = LockedSession {
+            file_path: session_path,
+            baseline_offset: 0,
+        };
+        let entry = provider.harvest_reply(&locked).await.unwrap();
+        assert_eq!(entry.content, "All green");
+        assert_eq!(entry.reply.unwrap().tool_calls.len(), 1);
+        let usage = entry.usage.unwrap();
+        assert_eq!(usage.input_tokens, 250);
+        assert_eq!(usage.output_tokens, 15);
+        assert_eq!(usage.total_tokens, 265);
+    }
 }
@@ -0,0 +1,160 @@
+//! Structured reply model
+//!
+//! Besides the final text, agent logs record reasoning, the tools the agent
+//! ran and any errors it hit. Providers collect these for every log entry
+//! written after the request baseline so callers can see what an agent
+//! actually did, not just what it said.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Reply {
+    /// Final reply text (done marker stripped once delivered)
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reasoning: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolInvocation>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ToolInvocation {
+    Shell {
+        command: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        exit_code: Option<i64>,
+    },
+    FileEdit {
+        path: String,
+        /// "add", "update", "delete", "move" or "write"
+        action: String,
+    },
+    Other {
+        name: String,
+    },
+}
+
+impl Reply {
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+            && self.reasoning.is_empty()
+            && self.tool_calls.is_empty()
+            && self.errors.is_empty()
+    }
+
+    /// Append another reply's details; the other reply's text wins when non-empty
+    pub fn merge(&mut self, other: Reply) {
+        if !other.text.is_empty() {
+            self.text = other.text;
+        }
+        self.reasoning.extend(other.reasoning);
+        self.tool_calls.extend(other.tool_calls);
+        self.errors.extend(other.errors);
+    }
+
+    /// Record a shell command; `exit_code` may be filled in later
+    pub fn push_shell(&mut self, command: impl Into<String>, exit_code: Option<i64>) {
+        self.tool_calls.push(ToolInvocation::Shell {
+            command: command.into(),
+            exit_code,
+        });
+    }
+
+    pub fn push_file_edit(&mut self, path: impl Into<String>, action: impl Into<String>) {
+        self.tool_calls.push(ToolInvocation::FileEdit {
+            path: path.into(),
+            action: action.into(),
+        });
+    }
+}
+
+/// Join a command given as an argv array (as Codex logs it) into one string
+pub fn join_command(value: &serde_json::Value) -> Option<String> {
+    if let Some(s) = value.as_str() {
+        return Some(s.to_string());
+    }
+    let parts: Vec<&str> = value
+        .as_array()?
+        .iter()
+        .filter_map(|v| v.as_str())
+        .collect();
+    if parts.is_empty() {
+        return None;
+    }
+    // Unwrap `bash -lc "<script>"` so the reader sees the actual command
+    if parts.len() == 3 && (parts[1] == "-lc" || parts[1] == "-c") {
+        return Some(parts[2].to_string());
+    }
+    Some(parts.join(" "))
+}
+
+/// Extract (action, path) pairs from an `apply_patch` envelope
+pub fn parse_patch_files(patch: &str) -> Vec<(String, String)> {
+    const HEADERS: &[(&str, &str)] = &[
+        ("*** Add File: ", "add"),
+        ("*** Update File: ", "update"),
+        ("*** Delete File: ", "delete"),
+        ("*** Move to: ", "move"),
+    ];
+    patch
+        .lines()
+        .filter_map(|line| {
+            HEADERS.iter().find_map(|(prefix, action)| {
+                line.strip_prefix(prefix)
+                    .map(|path| (action.to_string(), path.trim().to_string()))
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_patch_files() {
+        let patch = "*** Begin Patch\n*** Update File: src/a.rs\n@@\n-x\n+y\n*** Add File: b.txt\n+hi\n*** Delete File: c.txt\n*** End Patch";
+        assert_eq!(
+            parse_patch_files(patch),
+            vec![
+                ("update".to_string(), "src/a.rs".to_string()),
+                ("add".to_string(), "b.txt".to_string()),
+                ("delete".to_string(), "c.txt".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_join_command_unwraps_shell() {
+        assert_eq!(
+            join_command(&json!(["bash", "-lc", "cargo test"])).as_deref(),
+            Some("cargo test")
+        );
+        assert_eq!(
+            join_command(&json!(["ls", "-la"])).as_deref(),
+            Some("ls -la")
+        );
+        assert_eq!(
+            join_command(&json!("git status")).as_deref(),
+            Some("git status")
+        );
+    }
+
+    #[test]
+    fn test_reply_serialization_tags_tool_kind() {
+        let mut reply = Reply {
+            text: "done".to_string(),
+            ..Default::default()
+        };
+        reply.push_shell("ls", Some(0));
+        reply.push_file_edit("src/lib.rs", "update");
+        let value = serde_json::to_value(&reply).unwrap();
+        assert_eq!(value["tool_calls"][0]["kind"], "shell");
+        assert_eq!(value["tool_calls"][1]["kind"], "file_edit");
+        assert!(value.get("errors").is_none());
+    }
+}
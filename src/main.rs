@@ -2,12 +2,25 @@
 
 use ccgonext::{
     agent,
-    config::{AgentConfig, Config, ServerConfig, TimeoutConfig, WebConfig},
+    cache::ResponseCache,
+    changes::{ChangeTracker, IgnoreRules},
+    config::{
+        AgentConfig, CacheConfig, ChangeTrackingConfig, Config, EnvInherit, IsolationConfig,
+        JournalConfig, LaunchConfig, PromptsConfig, ResourceConfig, ServerConfig, TimeoutConfig,
+        TlsConfig, UsageConfig, WebConfig, WebToken,
+    },
+    journal::RequestJournal,
     log_provider,
-    mcp::McpServer,
+    mcp::{McpLogging, McpServer},
+    prompts::PromptLibrary,
     pty::PtyManager,
+    recording::RecordingConfig,
+    sandbox::{ResourceLimits, Sandbox, SandboxProfile},
     session::{AgentSession, SessionManager},
-    web::{WebServer, WebServerRunOptions},
+    telemetry::{self, OtlpTarget},
+    usage::UsageTracker,
+    web::{hash_token, WebServer, WebServerRunOptions},
+    worktree::{IsolationMode, WorktreeManager},
 };
 use clap::{Parser, Subcommand};
 use std::collections::HashMap;
@@ -55,6 +68,50 @@ struct Cli {
     #[arg(long, env = "CCGONEXT_AUTH_TOKEN")]
     auth_token: Option<String>,
 
+    /// JSON file of named web tokens with roles and agent scopes, stored as SHA-256 hashes [env: CCGONEXT_TOKENS_FILE]
+    #[arg(long, env = "CCGONEXT_TOKENS_FILE")]
+    tokens_file: Option<std::path::PathBuf>,
+
+    /// Extra origins allowed to call the web server, e.g. https://*.example.com (comma-separated, `*` wildcards) [env: CCGONEXT_ALLOWED_ORIGINS]
+    #[arg(long, env = "CCGONEXT_ALLOWED_ORIGINS")]
+    allowed_origins: Option<String>,
+
+    /// Only answer requests addressed to these hosts besides localhost (comma-separated, `*` wildcards) [env: CCGONEXT_ALLOWED_HOSTS]
+    #[arg(long, env = "CCGONEXT_ALLOWED_HOSTS")]
+    allowed_hosts: Option<String>,
+
+    /// Reverse proxies whose X-Forwarded-* headers are trusted (comma-separated IPs or CIDRs) [env: CCGONEXT_TRUSTED_PROXIES]
+    #[arg(long, env = "CCGONEXT_TRUSTED_PROXIES")]
+    trusted_proxies: Option<String>,
+
+    /// Serve the web UI and API under this path, e.g. /ccgonext [env: CCGONEXT_BASE_PATH]
+    #[arg(long, env = "CCGONEXT_BASE_PATH")]
+    base_path: Option<String>,
+
+    /// Serve HTTPS with this PEM certificate chain; requires --tls-key [env: CCGONEXT_TLS_CERT]
+    #[arg(long, env = "CCGONEXT_TLS_CERT")]
+    tls_cert: Option<std::path::PathBuf>,
+
+    /// PEM private key for --tls-cert [env: CCGONEXT_TLS_KEY]
+    #[arg(long, env = "CCGONEXT_TLS_KEY")]
+    tls_key: Option<std::path::PathBuf>,
+
+    /// Serve HTTPS with a self-signed certificate generated in the config dir [env: CCGONEXT_TLS_SELF_SIGNED]
+    #[arg(long, env = "CCGONEXT_TLS_SELF_SIGNED")]
+    tls_self_signed: bool,
+
+    /// Lifetime in seconds of browser sessions started at /api/login [env: CCGONEXT_SESSION_TTL]
+    #[arg(long, default_value = "43200", env = "CCGONEXT_SESSION_TTL")]
+    session_ttl: u64,
+
+    /// Append terminal input sent through the web UI to this JSONL file [env: CCGONEXT_AUDIT_LOG]
+    #[arg(long, env = "CCGONEXT_AUDIT_LOG")]
+    audit_log: Option<std::path::PathBuf>,
+
+    /// Serve Prometheus metrics at /metrics (behind the auth token, if set) [env: CCGONEXT_METRICS]
+    #[arg(long, env = "CCGONEXT_METRICS")]
+    metrics: bool,
+
     /// Output buffer size in bytes [env: CCGONEXT_BUFFER_SIZE]
     #[arg(long, default_value = "10485760", env = "CCGONEXT_BUFFER_SIZE")]
     buffer_size: usize,
@@ -98,6 +155,118 @@ struct Cli {
     /// Log directory for rotating logs [env: CCGONEXT_LOG_DIR]
     #[arg(long, env = "CCGONEXT_LOG_DIR")]
     log_dir: Option<String>,
+
+    /// Export trace spans over OTLP/HTTP to this collector, e.g. http://localhost:4318 [env: CCGONEXT_OTLP_ENDPOINT]
+    #[arg(long, env = "CCGONEXT_OTLP_ENDPOINT", conflicts_with = "otlp_file")]
+    otlp_endpoint: Option<String>,
+
+    /// Append trace spans to this file as OTLP JSON lines [env: CCGONEXT_OTLP_FILE]
+    #[arg(long, env = "CCGONEXT_OTLP_FILE")]
+    otlp_file: Option<std::path::PathBuf>,
+
+    /// Request journal file used to recover replies after a restart [env: CCGONEXT_JOURNAL_FILE]
+    #[arg(long, env = "CCGONEXT_JOURNAL_FILE")]
+    journal_file: Option<std::path::PathBuf>,
+
+    /// Cache agent replies for identical prompts on an unchanged tree [env: CCGONEXT_CACHE]
+    #[arg(long, env = "CCGONEXT_CACHE")]
+    cache: bool,
+
+    /// Response cache TTL in seconds [env: CCGONEXT_CACHE_TTL]
+    #[arg(long, default_value = "3600", env = "CCGONEXT_CACHE_TTL")]
+    cache_ttl: u64,
+
+    /// Maximum cached replies; the least recently used are evicted [env: CCGONEXT_CACHE_MAX_ENTRIES]
+    #[arg(long, default_value = "1000", env = "CCGONEXT_CACHE_MAX_ENTRIES")]
+    cache_max_entries: usize,
+
+    /// Persist the response cache to this file [env: CCGONEXT_CACHE_FILE]
+    #[arg(long, env = "CCGONEXT_CACHE_FILE")]
+    cache_file: Option<std::path::PathBuf>,
+
+    /// Daily token budget per agent; new asks are refused once reached [env: CCGONEXT_DAILY_TOKEN_BUDGET]
+    #[arg(long, env = "CCGONEXT_DAILY_TOKEN_BUDGET")]
+    daily_token_budget: Option<u64>,
+
+    /// Persist per-agent daily token usage to this file [env: CCGONEXT_USAGE_FILE]
+    #[arg(long, env = "CCGONEXT_USAGE_FILE")]
+    usage_file: Option<std::path::PathBuf>,
+
+    /// Git worktree isolation per agent: off, session or request [env: CCGONEXT_ISOLATION]
+    #[arg(long, default_value = "off", env = "CCGONEXT_ISOLATION")]
+    isolation: IsolationMode,
+
+    /// Report files each request created, modified or deleted, with diffs [env: CCGONEXT_TRACK_CHANGES]
+    #[arg(long, env = "CCGONEXT_TRACK_CHANGES")]
+    track_changes: bool,
+
+    /// Sandbox profiles per agent, e.g. "codex=standard,gemini=reviewer+offline" (Linux, requires bwrap) [env: CCGONEXT_SANDBOX]
+    #[arg(long, env = "CCGONEXT_SANDBOX")]
+    sandbox: Option<String>,
+
+    /// Only pass these environment variables to sandboxed agents (comma-separated, `*` wildcards) [env: CCGONEXT_SANDBOX_ENV_ALLOW]
+    #[arg(long, env = "CCGONEXT_SANDBOX_ENV_ALLOW")]
+    sandbox_env_allow: Option<String>,
+
+    /// Extra environment variables withheld from sandboxed agents (comma-separated, `*` wildcards) [env: CCGONEXT_SANDBOX_ENV_DENY]
+    #[arg(long, env = "CCGONEXT_SANDBOX_ENV_DENY")]
+    sandbox_env_deny: Option<String>,
+
+    /// Extra ignore patterns for change tracking (comma-separated globs) [env: CCGONEXT_TRACK_IGNORE]
+    #[arg(long, env = "CCGONEXT_TRACK_IGNORE")]
+    track_ignore: Option<String>,
+
+    /// Set or unset an agent's environment variable: "codex:CODEX_HOME=/path" or "codex:-OPENAI_*" (repeatable) [env: CCGONEXT_AGENT_ENV, `;`-separated]
+    #[arg(long, env = "CCGONEXT_AGENT_ENV", value_delimiter = ';')]
+    agent_env: Vec<String>,
+
+    /// Environment inherited per agent: "codex=none,gemini=PATH+HOME+GOOGLE_*" (default: all) [env: CCGONEXT_AGENT_INHERIT]
+    #[arg(long, env = "CCGONEXT_AGENT_INHERIT")]
+    agent_inherit: Option<String>,
+
+    /// Working directory per agent, relative to the project root: "codex=backend,gemini=web" [env: CCGONEXT_AGENT_CWD]
+    #[arg(long, env = "CCGONEXT_AGENT_CWD")]
+    agent_cwd: Option<String>,
+
+    /// Start agents through a login shell: "codex,gemini=/bin/zsh" ($SHELL when no shell is given) [env: CCGONEXT_LOGIN_SHELL]
+    #[arg(long, env = "CCGONEXT_LOGIN_SHELL")]
+    login_shell: Option<String>,
+
+    /// Seconds between CPU/memory samples of agent process trees, 0 to disable [env: CCGONEXT_RESOURCE_INTERVAL]
+    #[arg(long, default_value = "5", env = "CCGONEXT_RESOURCE_INTERVAL")]
+    resource_interval: u64,
+
+    /// Restart agents whose process tree exceeds this RSS in MiB: "2048" or "codex=2048,gemini=1024" [env: CCGONEXT_MEMORY_LIMIT]
+    #[arg(long, env = "CCGONEXT_MEMORY_LIMIT")]
+    memory_limit: Option<String>,
+
+    /// Record every agent's PTY stream as asciicast v2 under this directory [env: CCGONEXT_RECORD_DIR]
+    #[arg(long, env = "CCGONEXT_RECORD_DIR")]
+    record_dir: Option<std::path::PathBuf>,
+
+    /// Also record input written to agents [env: CCGONEXT_RECORD_INPUT]
+    #[arg(long, env = "CCGONEXT_RECORD_INPUT")]
+    record_input: bool,
+
+    /// Start a new recording file after this many MiB, 0 to never rotate [env: CCGONEXT_RECORD_ROTATE_MB]
+    #[arg(long, default_value = "64", env = "CCGONEXT_RECORD_ROTATE_MB")]
+    record_rotate_mb: u64,
+
+    /// Delete recordings older than this many days, 0 to keep them [env: CCGONEXT_RECORD_RETENTION_DAYS]
+    #[arg(long, default_value = "14", env = "CCGONEXT_RECORD_RETENTION_DAYS")]
+    record_retention_days: u64,
+
+    /// Directory of prompt templates (default: <config dir>/ccgonext/prompts) [env: CCGONEXT_PROMPTS_DIR]
+    #[arg(long, env = "CCGONEXT_PROMPTS_DIR")]
+    prompts_dir: Option<std::path::PathBuf>,
+
+    /// TERM for agent processes (inherited when unset) [env: CCGONEXT_TERM]
+    #[arg(long, env = "CCGONEXT_TERM")]
+    term: Option<String>,
+
+    /// COLORTERM for agent processes (inherited when unset) [env: CCGONEXT_COLORTERM]
+    #[arg(long, env = "CCGONEXT_COLORTERM")]
+    colorterm: Option<String>,
 }
 
 #[derive(Subcommand)]
@@ -108,20 +277,87 @@ enum Commands {
     Web,
     /// Show current configuration
     Config,
+    /// Print the SHA-256 to put in the tokens file; generates a token if none is given
+    HashToken {
+        /// Token secret
+        token: Option<String>,
+    },
+    /// Play back an asciicast recording in this terminal
+    Replay {
+        /// Recording file (.cast)
+        file: std::path::PathBuf,
+        /// Playback speed multiplier
+        #[arg(long, default_value = "1.0")]
+        speed: f64,
+        /// Cap pauses between events at this many seconds
+        #[arg(long)]
+        max_idle: Option<f64>,
+    },
+    /// Apply resource limits and exec a command (used by sandbox profiles)
+    #[command(hide = true)]
+    SandboxExec {
+        #[arg(long)]
+        cpu: Option<u64>,
+        #[arg(long)]
+        mem: Option<u64>,
+        #[arg(long)]
+        nproc: Option<u64>,
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
 
+    // The trampoline runs inside the agent's PTY: no logging, just exec
+    if let Some(Commands::SandboxExec {
+        cpu,
+        mem,
+        nproc,
+        command,
+    }) = &cli.command
+    {
+        let limits = ResourceLimits {
+            cpu_secs: *cpu,
+            memory_mb: *mem,
+            max_processes: *nproc,
+        };
+        let err = ccgonext::sandbox::exec_with_limits(&limits, command);
+        anyhow::bail!("sandbox-exec failed to run {:?}: {}", command, err);
+    }
+
+    if let Some(Commands::HashToken { token }) = &cli.command {
+        match token {
+            Some(token) => println!("{}", hash_token(token)),
+            None => {
+                let token = format!(
+                    "{}{}",
+                    uuid::Uuid::new_v4().simple(),
+                    uuid::Uuid::new_v4().simple()
+                );
+                println!("Token:   {}", token);
+                println!("SHA-256: {}", hash_token(&token));
+            }
+        }
+        return Ok(());
+    }
+
     // Initialize tracing with optional file output
-    init_tracing(&cli);
+    let mcp_logging = init_tracing(&cli);
 
-    let config = Arc::new(build_config(&cli));
+    let config = Arc::new(build_config(&cli)?);
 
     match cli.command {
         Some(Commands::Serve) | None => {
-            run_mcp_server(config, cli.port_retry, cli.windows_enter_delay_ms).await?;
+            run_mcp_server(
+                config,
+                cli.port_retry,
+                cli.windows_enter_delay_ms,
+                mcp_logging,
+            )
+            .await?;
         }
         Some(Commands::Web) => {
             run_web_server(
@@ -135,17 +371,44 @@ async fn main() -> anyhow::Result<()> {
         Some(Commands::Config) => {
             show_config(&config);
         }
+        Some(Commands::Replay {
+            file,
+            speed,
+            max_idle,
+        }) => {
+            replay_recording(&file, speed, max_idle).await?;
+        }
+        Some(Commands::SandboxExec { .. }) | Some(Commands::HashToken { .. }) => {
+            unreachable!("handled above")
+        }
     }
 
+    telemetry::shutdown();
     Ok(())
 }
 
-fn init_tracing(cli: &Cli) {
+/// Returns the handle that forwards events to the MCP client once it asks
+/// for them with `logging/setLevel`
+fn init_tracing(cli: &Cli) -> McpLogging {
+    let mcp_logging = McpLogging::new();
     let env_filter = EnvFilter::try_from_default_env()
         .unwrap_or_else(|_| "ccgonext=debug,tower_http=debug".into());
 
     let stderr_layer = fmt::layer().with_target(false).with_writer(std::io::stderr);
 
+    let otlp_target = match (&cli.otlp_endpoint, &cli.otlp_file) {
+        (Some(endpoint), _) => Some(OtlpTarget::Endpoint(endpoint.clone())),
+        (None, Some(path)) => Some(OtlpTarget::File(path.clone())),
+        (None, None) => None,
+    };
+    let otlp_layer = otlp_target.and_then(|target| match telemetry::init(&target) {
+        Ok(layer) => Some(layer),
+        Err(e) => {
+            eprintln!("Failed to start trace export to {:?}: {}", target, e);
+            None
+        }
+    });
+
     if let Some(log_dir) = &cli.log_dir {
         // Rotating file appender (daily rotation)
         let file_appender = tracing_appender::rolling::daily(log_dir, "ccgonext.log");
@@ -157,9 +420,11 @@ fn init_tracing(cli: &Cli) {
             .with_writer(non_blocking);
 
         tracing_subscriber::registry()
+            .with(otlp_layer)
             .with(env_filter)
             .with(stderr_layer)
             .with(file_layer)
+            .with(mcp_logging.layer())
             .init();
 
         // Leak the guard to keep it alive for the program lifetime
@@ -178,20 +443,256 @@ fn init_tracing(cli: &Cli) {
             .with_writer(std::sync::Arc::new(file));
 
         tracing_subscriber::registry()
+            .with(otlp_layer)
             .with(env_filter)
             .with(stderr_layer)
             .with(file_layer)
+            .with(mcp_logging.layer())
             .init();
     } else {
         // Stderr only
         tracing_subscriber::registry()
+            .with(otlp_layer)
             .with(env_filter)
             .with(stderr_layer)
+            .with(mcp_logging.layer())
             .init();
     }
+
+    mcp_logging
+}
+
+/// Split a comma-separated option into trimmed, non-empty items
+fn split_list(value: Option<&str>) -> Vec<String> {
+    value
+        .unwrap_or_default()
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Parse `--trusted-proxies`; a bare address trusts just that address
+fn parse_trusted_proxies(cli: &Cli) -> anyhow::Result<Vec<ipnet::IpNet>> {
+    split_list(cli.trusted_proxies.as_deref())
+        .iter()
+        .map(|entry| {
+            entry
+                .parse::<ipnet::IpNet>()
+                .or_else(|_| entry.parse::<std::net::IpAddr>().map(ipnet::IpNet::from))
+                .map_err(|_| {
+                    anyhow::anyhow!("--trusted-proxies expects IPs or CIDRs, got '{}'", entry)
+                })
+        })
+        .collect()
+}
+
+/// `/ccgonext/` and `ccgonext` both become `/ccgonext`; `/` becomes empty
+fn normalize_base_path(value: Option<&str>) -> String {
+    let trimmed = value.unwrap_or_default().trim().trim_matches('/');
+    if trimmed.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", trimmed)
+    }
+}
+
+fn parse_tls(cli: &Cli) -> anyhow::Result<Option<TlsConfig>> {
+    match (&cli.tls_cert, &cli.tls_key, cli.tls_self_signed) {
+        (Some(_), Some(_), true) => {
+            anyhow::bail!("--tls-self-signed cannot be combined with --tls-cert/--tls-key")
+        }
+        (Some(cert), Some(key), false) => Ok(Some(TlsConfig::Files {
+            cert: cert.clone(),
+            key: key.clone(),
+        })),
+        (Some(_), None, _) | (None, Some(_), _) => {
+            anyhow::bail!("--tls-cert and --tls-key must be given together")
+        }
+        (None, None, true) => {
+            let dir = dirs::config_dir()
+                .map(|dir| dir.join("ccgonext").join("tls"))
+                .ok_or_else(|| {
+                    anyhow::anyhow!("No config directory to store the self-signed certificate in")
+                })?;
+            Ok(Some(TlsConfig::SelfSigned { dir }))
+        }
+        (None, None, false) => Ok(None),
+    }
+}
+
+/// Parse an `agent=value,...` option
+fn parse_agent_pairs(flag: &str, value: Option<&str>) -> anyhow::Result<Vec<(String, String)>> {
+    split_list(value)
+        .into_iter()
+        .map(|item| {
+            let (agent, value) = item
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("{} expects agent=value, got '{}'", flag, item))?;
+            Ok((agent.trim().to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+fn agent_mut<'a>(
+    agents: &'a mut HashMap<String, AgentConfig>,
+    flag: &str,
+    name: &str,
+) -> anyhow::Result<&'a mut AgentConfig> {
+    agents
+        .get_mut(name)
+        .ok_or_else(|| anyhow::anyhow!("{} names unknown or disabled agent '{}'", flag, name))
+}
+
+/// Apply `--sandbox` profiles
+fn apply_sandbox_options(
+    cli: &Cli,
+    agents: &mut HashMap<String, AgentConfig>,
+) -> anyhow::Result<()> {
+    for (name, spec) in parse_agent_pairs("--sandbox", cli.sandbox.as_deref())? {
+        let profile = spec
+            .parse::<SandboxProfile>()?
+            .with_env_allow(split_list(cli.sandbox_env_allow.as_deref()))
+            .with_env_deny(split_list(cli.sandbox_env_deny.as_deref()));
+        agent_mut(agents, "--sandbox", &name)?.sandbox = Some(profile);
+    }
+    Ok(())
 }
 
-fn build_config(cli: &Cli) -> Config {
+/// Apply the per-agent environment, cwd and shell options
+fn apply_launch_options(
+    cli: &Cli,
+    agents: &mut HashMap<String, AgentConfig>,
+) -> anyhow::Result<()> {
+    for agent in agents.values_mut() {
+        agent.launch.term = cli.term.clone();
+        agent.launch.colorterm = cli.colorterm.clone();
+    }
+
+    for item in &cli.agent_env {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+        let invalid = || {
+            anyhow::anyhow!(
+                "--agent-env expects agent:KEY=VALUE or agent:-KEY, got '{}'",
+                item
+            )
+        };
+        let (name, var) = item.split_once(':').ok_or_else(invalid)?;
+        let launch = &mut agent_mut(agents, "--agent-env", name.trim())?.launch;
+        if let Some(pattern) = var.strip_prefix('-') {
+            launch.env_unset.push(pattern.to_string());
+        } else {
+            let (key, value) = var.split_once('=').ok_or_else(invalid)?;
+            launch.env_set.push((key.to_string(), value.to_string()));
+        }
+    }
+
+    for (name, inherit) in parse_agent_pairs("--agent-inherit", cli.agent_inherit.as_deref())? {
+        let inherit = inherit
+            .parse::<EnvInherit>()
+            .map_err(|e| anyhow::anyhow!("--agent-inherit for {}: {}", name, e))?;
+        agent_mut(agents, "--agent-inherit", &name)?.launch.inherit = inherit;
+    }
+
+    for (name, cwd) in parse_agent_pairs("--agent-cwd", cli.agent_cwd.as_deref())? {
+        let Some(cwd) = LaunchConfig::project_relative_cwd(&cwd) else {
+            anyhow::bail!(
+                "--agent-cwd for {} must be relative to the project root and stay inside it",
+                name
+            );
+        };
+        agent_mut(agents, "--agent-cwd", &name)?.launch.cwd = Some(cwd);
+    }
+
+    for item in split_list(cli.login_shell.as_deref()) {
+        let (name, shell) = match item.split_once('=') {
+            Some((name, shell)) => (name.trim().to_string(), shell.trim().to_string()),
+            None => (
+                item.clone(),
+                std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string()),
+            ),
+        };
+        agent_mut(agents, "--login-shell", &name)?
+            .launch
+            .login_shell = Some(shell);
+    }
+    Ok(())
+}
+
+/// Parse `--memory-limit`: one limit for all agents or `agent=MiB` pairs
+fn parse_memory_limits(
+    cli: &Cli,
+    agents: &HashMap<String, AgentConfig>,
+) -> anyhow::Result<HashMap<String, u64>> {
+    let parse = |value: &str| {
+        value
+            .parse::<u64>()
+            .map_err(|_| anyhow::anyhow!("--memory-limit expects MiB, got '{}'", value))
+    };
+    let Some(value) = cli.memory_limit.as_deref().map(str::trim) else {
+        return Ok(HashMap::new());
+    };
+    if !value.contains('=') {
+        let limit = parse(value)?;
+        return Ok(agents.keys().map(|name| (name.clone(), limit)).collect());
+    }
+    let mut limits = HashMap::new();
+    for (name, limit) in parse_agent_pairs("--memory-limit", Some(value))? {
+        if !agents.contains_key(&name) {
+            anyhow::bail!("--memory-limit names unknown or disabled agent '{}'", name);
+        }
+        limits.insert(name, parse(&limit)?);
+    }
+    Ok(limits)
+}
+
+/// Read `--tokens-file`: a JSON array of `{name, sha256, role, agents}`
+fn load_tokens(cli: &Cli) -> anyhow::Result<Vec<WebToken>> {
+    let Some(path) = &cli.tokens_file else {
+        return Ok(Vec::new());
+    };
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Cannot read tokens file {}: {}", path.display(), e))?;
+    let tokens: Vec<WebToken> = serde_json::from_str(&content)
+        .map_err(|e| anyhow::anyhow!("Invalid tokens file {}: {}", path.display(), e))?;
+
+    let mut names = std::collections::HashSet::new();
+    for token in &tokens {
+        if !names.insert(token.name.as_str()) {
+            anyhow::bail!("Tokens file names '{}' more than once", token.name);
+        }
+        if token.sha256.len() != 64 || !token.sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+            anyhow::bail!(
+                "Token '{}' needs a hex SHA-256 (see `ccgonext hash-token`)",
+                token.name
+            );
+        }
+    }
+    Ok(tokens)
+}
+
+/// `env.<NAME>` log provider keys for variables the agent sees differently
+fn log_env_overrides(launch: &LaunchConfig) -> HashMap<String, String> {
+    let mut overrides = HashMap::new();
+    if !launch.customizes_env() {
+        return overrides;
+    }
+    let agent_env: HashMap<String, String> =
+        launch.environment(std::env::vars()).into_iter().collect();
+    for name in log_provider::LOG_ENV_VARS {
+        let ours = std::env::var(name).ok();
+        let theirs = agent_env.get(*name);
+        if ours.as_ref() != theirs {
+            overrides.insert(format!("env.{}", name), theirs.cloned().unwrap_or_default());
+        }
+    }
+    overrides
+}
+
+fn build_config(cli: &Cli) -> anyhow::Result<Config> {
     let enabled_agents: Vec<&str> = cli.agents.split(',').map(|s| s.trim()).collect();
     let project_root = if cli.show_project_root {
         std::env::current_dir()
@@ -232,7 +733,11 @@ fn build_config(cli: &Cli) -> Config {
         );
     }
 
-    Config {
+    apply_sandbox_options(cli, &mut agents)?;
+    apply_launch_options(cli, &mut agents)?;
+    let memory_limit_mb = parse_memory_limits(cli, &agents)?;
+
+    Ok(Config {
         server: ServerConfig {
             port: cli.port,
             host: cli.host.clone(),
@@ -246,17 +751,69 @@ fn build_config(cli: &Cli) -> Config {
         },
         web: WebConfig {
             auth_token: cli.auth_token.clone(),
+            tokens: load_tokens(cli)?,
+            audit_log: cli.audit_log.clone(),
+            session_ttl_secs: cli.session_ttl,
+            allowed_origins: split_list(cli.allowed_origins.as_deref()),
+            allowed_hosts: split_list(cli.allowed_hosts.as_deref()),
+            trusted_proxies: parse_trusted_proxies(cli)?,
+            base_path: normalize_base_path(cli.base_path.as_deref()),
+            tls: parse_tls(cli)?,
             input_enabled: cli.input_enabled,
             output_buffer_size: cli.buffer_size,
             project_root,
+            metrics: cli.metrics,
         },
-    }
+        journal: JournalConfig {
+            path: cli.journal_file.clone(),
+        },
+        cache: CacheConfig {
+            enabled: cli.cache,
+            ttl_secs: cli.cache_ttl,
+            max_entries: cli.cache_max_entries,
+            path: cli.cache_file.clone(),
+        },
+        usage: UsageConfig {
+            daily_token_budget: cli.daily_token_budget,
+            path: cli.usage_file.clone(),
+        },
+        isolation: IsolationConfig {
+            mode: cli.isolation,
+        },
+        change_tracking: {
+            let mut tracking = ChangeTrackingConfig {
+                enabled: cli.track_changes,
+                ..ChangeTrackingConfig::default()
+            };
+            tracking
+                .ignore
+                .extend(split_list(cli.track_ignore.as_deref()));
+            tracking
+        },
+        resources: ResourceConfig {
+            sample_interval_secs: cli.resource_interval,
+            memory_limit_mb,
+        },
+        recording: cli.record_dir.as_ref().map(|dir| RecordingConfig {
+            dir: dir.clone(),
+            record_input: cli.record_input,
+            rotate_mb: cli.record_rotate_mb,
+            retention_days: cli.record_retention_days,
+        }),
+        prompts: PromptsConfig {
+            dir: cli
+                .prompts_dir
+                .clone()
+                .or_else(|| dirs::config_dir().map(|dir| dir.join("ccgonext").join("prompts"))),
+        },
+    })
 }
 
 async fn run_mcp_server(
     config: Arc<Config>,
     port_retry: u16,
     windows_enter_delay_ms: u64,
+    mcp_logging: McpLogging,
 ) -> anyhow::Result<()> {
     let session_manager = create_session_manager(&config, windows_enter_delay_ms).await?;
 
@@ -284,7 +841,7 @@ async fn run_mcp_server(
     });
 
     // Run MCP server on stdio
-    let mcp_server = McpServer::new(session_manager.clone(), config);
+    let mcp_server = McpServer::new(session_manager.clone(), config).with_logging(mcp_logging);
     let result = mcp_server.run_stdio().await;
 
     // Ensure cleanup happens even if MCP server exits normally
@@ -309,6 +866,7 @@ async fn run_web_server(
         tracing::info!("Received shutdown signal, cleaning up...");
         shutdown_manager.shutdown_all().await;
         tracing::info!("Cleanup complete");
+        telemetry::shutdown();
         std::process::exit(0);
     });
 
@@ -348,41 +906,135 @@ async fn create_session_manager(
     config: &Config,
     windows_enter_delay_ms: u64,
 ) -> anyhow::Result<Arc<SessionManager>> {
-    let pty_manager = Arc::new(PtyManager::new_with_windows_enter_delay_ms(
+    let mut pty_manager = PtyManager::new_with_windows_enter_delay_ms(
         config.web.output_buffer_size,
         windows_enter_delay_ms,
-    ));
-    let session_manager = Arc::new(SessionManager::new(pty_manager));
+    );
+    if let Some(recording) = &config.recording {
+        pty_manager = pty_manager.with_recording(recording.clone());
+    }
+    let pty_manager = Arc::new(pty_manager);
+    let mut session_manager = SessionManager::new(pty_manager);
+    if let Some(path) = &config.journal.path {
+        let journal = RequestJournal::open(path)
+            .map_err(|e| anyhow::anyhow!("Failed to open journal {:?}: {}", path, e))?;
+        session_manager = session_manager.with_journal(Arc::new(journal));
+    }
+    if config.cache.enabled {
+        let ttl = std::time::Duration::from_secs(config.cache.ttl_secs);
+        let cache = match &config.cache.path {
+            Some(path) => ResponseCache::open(path, ttl, config.cache.max_entries)
+                .map_err(|e| anyhow::anyhow!("Failed to open cache {:?}: {}", path, e))?,
+            None => ResponseCache::in_memory(ttl, config.cache.max_entries),
+        };
+        session_manager = session_manager.with_cache(Arc::new(cache));
+    }
+    let budget = config.usage.daily_token_budget;
+    let usage = match &config.usage.path {
+        Some(path) => UsageTracker::open(path, budget)
+            .map_err(|e| anyhow::anyhow!("Failed to open usage file {:?}: {}", path, e))?,
+        None => UsageTracker::new(budget),
+    };
+    session_manager = session_manager.with_usage_tracker(Arc::new(usage));
+    session_manager =
+        session_manager.with_prompts(Arc::new(PromptLibrary::new(config.prompts.dir.clone())));
+    if config.change_tracking.enabled {
+        let rules = IgnoreRules::new(config.change_tracking.ignore.iter().cloned());
+        session_manager = session_manager.with_change_tracker(Arc::new(ChangeTracker::new(rules)));
+    }
 
     let working_dir = std::env::current_dir()?;
     tracing::info!("Working directory for agents: {:?}", working_dir);
 
+    let worktrees = if config.isolation.mode != IsolationMode::Off {
+        let manager = WorktreeManager::new(&working_dir, config.isolation.mode)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to enable worktree isolation: {}", e))?;
+        let manager = Arc::new(manager);
+        session_manager = session_manager.with_worktrees(Arc::clone(&manager));
+        Some(manager)
+    } else {
+        None
+    };
+    let session_manager = Arc::new(session_manager);
+
     // Register configured agents
     for (name, agent_config) in &config.agents {
         let adapter = agent::create_agent(name, agent_config);
 
+        let working_dir = match &worktrees {
+            Some(worktrees) => worktrees
+                .create(name)
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to create worktree for {}: {}", name, e))?,
+            None => working_dir.clone(),
+        };
+        let working_dir = match &agent_config.launch.cwd {
+            Some(cwd) => {
+                let dir = working_dir.join(cwd);
+                if !dir.is_dir() {
+                    anyhow::bail!("Working directory for {} does not exist: {:?}", name, dir);
+                }
+                dir
+            }
+            None => working_dir,
+        };
+
         // Create config with working_dir for LogProvider
         let mut log_config = std::collections::HashMap::new();
         log_config.insert(
             "working_dir".to_string(),
             working_dir.to_string_lossy().to_string(),
         );
+        log_config.extend(log_env_overrides(&agent_config.launch));
 
         let log_provider: Arc<dyn log_provider::LogProvider> = Arc::from(
             log_provider::create_log_provider(&agent_config.log_provider, Some(&log_config)),
         );
 
-        let session = AgentSession::new(
+        // The project root stays bound so worktrees can reach the shared .git
+        let sandbox = match &agent_config.sandbox {
+            Some(profile) => {
+                let sandbox = Sandbox::new(profile.clone(), vec![std::env::current_dir()?])
+                    .map_err(|e| anyhow::anyhow!("Failed to sandbox {}: {}", name, e))?;
+                // Resolve state dirs from the environment the agent will see
+                let agent_env: HashMap<String, String> = agent_config
+                    .launch
+                    .environment(sandbox.environment())
+                    .into_iter()
+                    .collect();
+                Some(sandbox.with_agent_state(name, &agent_env))
+            }
+            None => None,
+        };
+
+        let mut session = AgentSession::new(
             name.clone(),
             Arc::from(adapter),
             log_provider,
-            working_dir.clone(),
+            working_dir,
             config.timeouts.clone(),
-        );
+        )
+        .with_launch(agent_config.launch.clone());
+        if let Some(limit) = config.resources.memory_limit_mb.get(name) {
+            session = session.with_memory_limit(*limit);
+        }
+        if let Some(sandbox) = sandbox {
+            session = session.with_sandbox(sandbox);
+        }
 
         session_manager.register(session).await;
     }
 
+    // Harvest replies for requests left in flight by a previous run
+    session_manager.recover_journal().await;
+
+    if config.resources.sample_interval_secs > 0 {
+        session_manager.spawn_resource_monitor(std::time::Duration::from_secs(
+            config.resources.sample_interval_secs,
+        ));
+    }
+
     // Pre-start all agents in background (non-blocking)
     // Use tokio::task::yield_now to ensure the spawn gets a chance to start
     let sm = session_manager.clone();
@@ -396,6 +1048,38 @@ async fn create_session_manager(
     Ok(session_manager)
 }
 
+/// Write a recording's output events to stdout with their original timing
+async fn replay_recording(
+    file: &std::path::Path,
+    speed: f64,
+    max_idle: Option<f64>,
+) -> anyhow::Result<()> {
+    use std::io::Write;
+
+    if !ccgonext::recording::is_valid_speed(speed) {
+        anyhow::bail!("--speed must be a positive number, got {}", speed);
+    }
+    let (header, events) = ccgonext::recording::read(file)
+        .map_err(|e| anyhow::anyhow!("Failed to read recording {:?}: {}", file, e))?;
+    eprintln!(
+        "Replaying {} ({}x{}, {} events)",
+        header.title.as_deref().unwrap_or("recording"),
+        header.width,
+        header.height,
+        events.len()
+    );
+    let delays = ccgonext::recording::schedule(&events, speed, max_idle);
+    let mut stdout = std::io::stdout();
+    for (event, delay) in events.iter().zip(delays) {
+        tokio::time::sleep(delay).await;
+        if event.code() == "o" {
+            stdout.write_all(event.data().as_bytes())?;
+            stdout.flush()?;
+        }
+    }
+    Ok(())
+}
+
 fn show_config(config: &Config) {
     println!("CCGONEXT Configuration");
     println!("==================");
@@ -414,6 +1098,51 @@ fn show_config(config: &Config) {
             "none"
         }
     );
+    for token in &config.web.tokens {
+        println!(
+            "  Token {}: {} ({})",
+            token.name,
+            token.role,
+            if token.agents.is_empty() {
+                "all agents".to_string()
+            } else {
+                token.agents.join(", ")
+            }
+        );
+    }
+    if let Some(path) = &config.web.audit_log {
+        println!("  Audit log: {}", path.display());
+    }
+    if !config.web.allowed_origins.is_empty() {
+        println!(
+            "  Allowed origins: {}",
+            config.web.allowed_origins.join(", ")
+        );
+    }
+    if !config.web.allowed_hosts.is_empty() {
+        println!("  Allowed hosts: {}", config.web.allowed_hosts.join(", "));
+    }
+    if !config.web.trusted_proxies.is_empty() {
+        let proxies: Vec<String> = config
+            .web
+            .trusted_proxies
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        println!("  Trusted proxies: {}", proxies.join(", "));
+    }
+    if !config.web.base_path.is_empty() {
+        println!("  Base path: {}", config.web.base_path);
+    }
+    match &config.web.tls {
+        Some(TlsConfig::Files { cert, key }) => {
+            println!("  TLS: {} / {}", cert.display(), key.display());
+        }
+        Some(TlsConfig::SelfSigned { dir }) => {
+            println!("  TLS: self-signed, in {}", dir.display());
+        }
+        None => {}
+    }
     println!("  Buffer size: {} bytes", config.web.output_buffer_size);
     println!();
     println!("Timeouts:");
@@ -425,8 +1154,93 @@ fn show_config(config: &Config) {
         config.timeouts.start_retry_delay_ms
     );
     println!();
+    println!("Journal:");
+    match &config.journal.path {
+        Some(path) => println!("  File: {}", path.display()),
+        None => println!("  File: none (in-memory)"),
+    }
+    println!();
+    println!("Cache:");
+    println!("  Enabled: {}", config.cache.enabled);
+    println!("  TTL: {}s", config.cache.ttl_secs);
+    println!("  Max entries: {}", config.cache.max_entries);
+    match &config.cache.path {
+        Some(path) => println!("  File: {}", path.display()),
+        None => println!("  File: none (in-memory)"),
+    }
+    println!();
+    println!("Usage:");
+    match config.usage.daily_token_budget {
+        Some(budget) => println!("  Daily token budget: {} per agent", budget),
+        None => println!("  Daily token budget: none"),
+    }
+    match &config.usage.path {
+        Some(path) => println!("  File: {}", path.display()),
+        None => println!("  File: none (in-memory)"),
+    }
+    println!();
+    println!("Isolation:");
+    println!("  Mode: {}", config.isolation.mode);
+    println!();
+    println!("Change tracking:");
+    println!("  Enabled: {}", config.change_tracking.enabled);
+    println!("  Ignore: {}", config.change_tracking.ignore.join(", "));
+    println!();
+    println!("Resources:");
+    match config.resources.sample_interval_secs {
+        0 => println!("  Sampling: off"),
+        secs => println!("  Sampling: every {}s", secs),
+    }
+    let mut limits: Vec<_> = config.resources.memory_limit_mb.iter().collect();
+    limits.sort();
+    for (name, limit) in limits {
+        println!("  Memory limit {}: {} MiB", name, limit);
+    }
+    println!();
+    println!("Recording:");
+    match &config.recording {
+        Some(recording) => {
+            println!("  Directory: {}", recording.dir.display());
+            println!("  Input: {}", recording.record_input);
+            println!("  Rotate: {} MiB", recording.rotate_mb);
+            println!("  Retention: {} days", recording.retention_days);
+        }
+        None => println!("  Off"),
+    }
+    println!();
+    println!("Prompts:");
+    match &config.prompts.dir {
+        Some(dir) => println!("  Directory: {}", dir.display()),
+        None => println!("  Directory: none (built-in prompts only)"),
+    }
+    println!();
     println!("Agents:");
     for (name, agent_config) in &config.agents {
         println!("  - {} (command: {})", name, agent_config.command);
+        if let Some(profile) = &agent_config.sandbox {
+            println!("    Sandbox: {}", profile);
+        }
+        let launch = &agent_config.launch;
+        if let Some(cwd) = &launch.cwd {
+            println!("    Working directory: {}", cwd.display());
+        }
+        if let Some(shell) = &launch.login_shell {
+            println!("    Login shell: {}", shell);
+        }
+        if launch.customizes_env() {
+            println!("    Environment: inherit {}", launch.inherit);
+            for (key, _) in &launch.env_set {
+                println!("      set {}", key);
+            }
+            for pattern in &launch.env_unset {
+                println!("      unset {}", pattern);
+            }
+            if let Some(term) = &launch.term {
+                println!("      TERM={}", term);
+            }
+            if let Some(colorterm) = &launch.colorterm {
+                println!("      COLORTERM={}", colorterm);
+            }
+        }
     }
 }
@@ -0,0 +1,344 @@
+//! JSON-RPC message framing on stdio
+//!
+//! Clients either send one JSON document per line or LSP-style messages with
+//! a `Content-Length` header; the first message decides which. Messages over
+//! [`MAX_CONTENT_LENGTH`] are skipped without being buffered, and framing
+//! errors other than I/O failures leave the stream usable, so the server can
+//! answer them and carry on.
+
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
+/// Transport mode for MCP protocol
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportMode {
+    /// Auto-detect mode (initial state)
+    AutoDetect,
+    /// Line-delimited JSON (JSONL) - one JSON object per line
+    JsonLines,
+    /// LSP-style with Content-Length header
+    LspStyle,
+}
+
+/// Maximum allowed message size (16MB) to prevent OOM attacks
+pub const MAX_CONTENT_LENGTH: usize = 16 * 1024 * 1024;
+
+/// Longest accepted LSP header line
+const MAX_HEADER_LINE: usize = 8 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum FrameError {
+    #[error("Message of {0} bytes exceeds maximum allowed size {MAX_CONTENT_LENGTH}")]
+    TooLarge(usize),
+    #[error("Invalid header: {0}")]
+    InvalidHeader(String),
+    #[error("Missing Content-Length header in LSP message")]
+    MissingLength,
+    #[error("Message is not valid UTF-8")]
+    InvalidUtf8,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl FrameError {
+    /// I/O errors end the session; anything else only loses one message
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, FrameError::Io(_))
+    }
+}
+
+/// Read the next message in `mode`, detecting the mode on the first message
+pub async fn read_message<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    mode: &mut TransportMode,
+) -> Result<Option<String>, FrameError> {
+    match *mode {
+        TransportMode::AutoDetect => detect_and_read_message(reader, mode).await,
+        TransportMode::JsonLines => read_jsonl_message(reader).await,
+        TransportMode::LspStyle => read_lsp_message(reader).await,
+    }
+}
+
+/// Detect transport mode from first message and read it
+pub async fn detect_and_read_message<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    mode: &mut TransportMode,
+) -> Result<Option<String>, FrameError> {
+    let first_line = match read_line_limited(reader, MAX_CONTENT_LENGTH + 2).await? {
+        Some(line) => line,
+        None => return Ok(None), // EOF
+    };
+
+    let trimmed = first_line.trim();
+
+    // Check if it looks like LSP Content-Length header
+    if is_content_length(trimmed) {
+        *mode = TransportMode::LspStyle;
+        tracing::info!("Detected LSP-style transport mode");
+        read_lsp_after(reader, Some(first_line)).await
+    } else if trimmed.starts_with('{') || trimmed.starts_with('[') {
+        // Looks like JSON, use JSONL mode
+        *mode = TransportMode::JsonLines;
+        tracing::info!("Detected JSON Lines transport mode");
+        Ok(Some(first_line))
+    } else if trimmed.is_empty() {
+        // Empty line, keep auto-detecting
+        Ok(Some(String::new()))
+    } else {
+        // Unknown format, try JSONL
+        *mode = TransportMode::JsonLines;
+        tracing::warn!(
+            "Unknown format, assuming JSON Lines mode. First line: {}",
+            trimmed
+        );
+        Ok(Some(first_line))
+    }
+}
+
+/// Read a message in JSONL mode (line-delimited)
+pub async fn read_jsonl_message<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+) -> Result<Option<String>, FrameError> {
+    // Allow for the line ending on a maximum-sized message
+    read_line_limited(reader, MAX_CONTENT_LENGTH + 2).await
+}
+
+/// Read a message in LSP mode (Content-Length header)
+pub async fn read_lsp_message<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+) -> Result<Option<String>, FrameError> {
+    read_lsp_after(reader, None).await
+}
+
+/// Read the rest of an LSP message whose first header line may already have
+/// been consumed by mode detection
+async fn read_lsp_after<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    mut first_header: Option<String>,
+) -> Result<Option<String>, FrameError> {
+    let mut content_length: Option<Result<usize, FrameError>> = None;
+
+    // Read headers; errors wait for the end of the headers so the next
+    // message starts in the right place
+    loop {
+        let header_line = match first_header.take() {
+            Some(line) => line,
+            None => match read_line_limited(reader, MAX_HEADER_LINE).await {
+                Ok(Some(line)) => line,
+                Ok(None) if content_length.is_none() => return Ok(None), // EOF
+                Ok(None) => return Err(FrameError::Io(std::io::ErrorKind::UnexpectedEof.into())),
+                Err(FrameError::Io(e)) => return Err(FrameError::Io(e)),
+                Err(e) => {
+                    content_length = Some(Err(e));
+                    continue;
+                }
+            },
+        };
+
+        let trimmed = header_line.trim();
+        if trimmed.is_empty() {
+            if content_length.is_none() {
+                // Blank lines between messages
+                continue;
+            }
+            break;
+        }
+
+        if is_content_length(trimmed) {
+            content_length = Some(parse_content_length(trimmed));
+        } else if content_length.is_none() {
+            content_length = Some(Err(FrameError::MissingLength));
+        }
+        // Ignore other headers (Content-Type, etc.)
+    }
+
+    let length = match content_length {
+        Some(Ok(length)) => length,
+        Some(Err(e)) => return Err(e),
+        None => return Err(FrameError::MissingLength),
+    };
+
+    if length > MAX_CONTENT_LENGTH {
+        // Skip the body without holding it in memory
+        tokio::io::copy(
+            &mut (&mut *reader).take(length as u64),
+            &mut tokio::io::sink(),
+        )
+        .await?;
+        return Err(FrameError::TooLarge(length));
+    }
+
+    // Read the JSON content
+    let mut content = vec![0u8; length];
+    reader.read_exact(&mut content).await?;
+    String::from_utf8(content)
+        .map(Some)
+        .map_err(|_| FrameError::InvalidUtf8)
+}
+
+fn is_content_length(header: &str) -> bool {
+    header
+        .get(..15)
+        .is_some_and(|name| name.eq_ignore_ascii_case("content-length:"))
+}
+
+/// Parse Content-Length header value
+pub fn parse_content_length(header: &str) -> Result<usize, FrameError> {
+    let (_, value) = header
+        .split_once(':')
+        .ok_or_else(|| FrameError::InvalidHeader(header.to_string()))?;
+    value
+        .trim()
+        .parse()
+        .map_err(|_| FrameError::InvalidHeader(header.to_string()))
+}
+
+/// Frame a serialized message for `mode`
+pub fn encode(json: &str, mode: TransportMode) -> Vec<u8> {
+    match mode {
+        // LSP style: Content-Length header + \r\n\r\n + content
+        TransportMode::LspStyle => {
+            format!("Content-Length: {}\r\n\r\n{}", json.len(), json).into_bytes()
+        }
+        // JSONL style: JSON + newline
+        TransportMode::JsonLines | TransportMode::AutoDetect => format!("{}\n", json).into_bytes(),
+    }
+}
+
+/// Read one line (including its newline) of at most `limit` bytes. Longer
+/// lines are consumed to their end and reported as too large.
+async fn read_line_limited<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    limit: usize,
+) -> Result<Option<String>, FrameError> {
+    let mut line = Vec::new();
+    let mut total = 0usize;
+    loop {
+        let available = reader.fill_buf().await?;
+        if available.is_empty() {
+            if total == 0 {
+                return Ok(None); // EOF
+            }
+            break;
+        }
+        let (used, done) = match available.iter().position(|&b| b == b'\n') {
+            Some(i) => (i + 1, true),
+            None => (available.len(), false),
+        };
+        total = total.saturating_add(used);
+        if total <= limit {
+            line.extend_from_slice(&available[..used]);
+        } else {
+            line = Vec::new();
+        }
+        reader.consume(used);
+        if done {
+            break;
+        }
+    }
+    if total > limit {
+        return Err(FrameError::TooLarge(total));
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|_| FrameError::InvalidUtf8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn read_all(input: &[u8]) -> (TransportMode, Vec<Result<String, String>>) {
+        let mut reader = input;
+        let mut mode = TransportMode::AutoDetect;
+        let mut messages = Vec::new();
+        loop {
+            match read_message(&mut reader, &mut mode).await {
+                Ok(None) => break,
+                Ok(Some(message)) if message.trim().is_empty() => {}
+                Ok(Some(message)) => messages.push(Ok(message.trim().to_string())),
+                Err(e) if e.is_fatal() => break,
+                Err(e) => messages.push(Err(e.to_string())),
+            }
+        }
+        (mode, messages)
+    }
+
+    #[tokio::test]
+    async fn test_jsonl_messages() {
+        let (mode, messages) = read_all(b"\n{\"a\":1}\r\n[{\"b\":2}]\n{\"c\":3}").await;
+        assert_eq!(mode, TransportMode::JsonLines);
+        assert_eq!(
+            messages,
+            vec![
+                Ok("{\"a\":1}".to_string()),
+                Ok("[{\"b\":2}]".to_string()),
+                Ok("{\"c\":3}".to_string())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lsp_messages() {
+        let input = b"Content-Length: 7\r\n\r\n{\"a\":1}\
+                      content-length: 7\r\nContent-Type: application/json\r\n\r\n{\"b\":2}";
+        let (mode, messages) = read_all(input).await;
+        assert_eq!(mode, TransportMode::LspStyle);
+        assert_eq!(
+            messages,
+            vec![Ok("{\"a\":1}".to_string()), Ok("{\"b\":2}".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lsp_errors_keep_the_stream_in_sync() {
+        let oversized = MAX_CONTENT_LENGTH + 1;
+        let mut input = format!(
+            "Content-Length: 7\r\n\r\n{{\"a\":1}}Content-Length: {}\r\n\r\n",
+            oversized
+        )
+        .into_bytes();
+        input.resize(input.len() + oversized, b'x');
+        input.extend_from_slice(b"Content-Length: nope\r\n\r\n");
+        input.extend_from_slice(b"Content-Type: text/plain\r\n\r\n");
+        input.extend_from_slice(b"Content-Length: 2\r\n\r\n\xff\xfe");
+        input.extend_from_slice(b"Content-Length: 7\r\n\r\n{\"b\":2}");
+
+        let (_, messages) = read_all(&input).await;
+        assert_eq!(messages.len(), 6, "{:?}", messages);
+        assert_eq!(messages[0], Ok("{\"a\":1}".to_string()));
+        assert!(messages[1].as_ref().unwrap_err().contains("exceeds"));
+        assert!(messages[2].as_ref().unwrap_err().contains("Invalid header"));
+        assert!(messages[3].as_ref().unwrap_err().contains("Missing"));
+        assert!(messages[4].as_ref().unwrap_err().contains("UTF-8"));
+        assert_eq!(messages[5], Ok("{\"b\":2}".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_oversized_jsonl_line_is_skipped() {
+        let mut input = vec![b'{'];
+        input.resize(MAX_CONTENT_LENGTH + 10, b' ');
+        input.extend_from_slice(b"}\n{\"b\":2}\n");
+
+        let (_, messages) = read_all(&input).await;
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].as_ref().unwrap_err().contains("exceeds"));
+        assert_eq!(messages[1], Ok("{\"b\":2}".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_truncated_lsp_body_is_fatal() {
+        let mut reader: &[u8] = b"Content-Length: 10\r\n\r\n{}";
+        let mut mode = TransportMode::AutoDetect;
+        let err = read_message(&mut reader, &mut mode).await.unwrap_err();
+        assert!(err.is_fatal());
+    }
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(
+            encode("{}", TransportMode::LspStyle),
+            b"Content-Length: 2\r\n\r\n{}".to_vec()
+        );
+        assert_eq!(encode("{}", TransportMode::JsonLines), b"{}\n".to_vec());
+    }
+}
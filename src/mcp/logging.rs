@@ -0,0 +1,284 @@
+//! Forwarding of tracing events to the MCP client
+//!
+//! [`McpLogging::layer`] is installed in the tracing subscriber at startup.
+//! Nothing is forwarded until the client picks a level with
+//! `logging/setLevel`; from then on events at or above it are sent as
+//! `notifications/message`. Events that carry prompts, replies, terminal
+//! output or audited input are never forwarded, and events the client does
+//! not read fast enough are dropped and counted.
+
+use parking_lot::Mutex;
+use serde_json::{json, Map, Value};
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+/// Syslog severities used by MCP, lowest first
+pub const LOG_LEVELS: &[&str] = &[
+    "debug",
+    "info",
+    "notice",
+    "warning",
+    "error",
+    "critical",
+    "alert",
+    "emergency",
+];
+
+/// Threshold meaning "forward nothing"
+const LEVEL_OFF: u8 = u8::MAX;
+
+/// Notifications queued for the client before further events are dropped
+const CHANNEL_CAPACITY: usize = 256;
+
+/// Target for events that carry prompts, replies or terminal output; they
+/// only go to the local log
+pub const CONTENT_TARGET: &str = "ccgonext::content";
+
+/// Targets never forwarded to the client
+const PRIVATE_TARGETS: &[&str] = &[CONTENT_TARGET, "ccgonext::web::audit"];
+
+/// Shared between the tracing layer and the MCP server
+#[derive(Clone)]
+pub struct McpLogging {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    threshold: AtomicU8,
+    sender: Mutex<Option<mpsc::Sender<Value>>>,
+    /// Events dropped since the client last kept up
+    dropped: AtomicU64,
+}
+
+impl Default for McpLogging {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl McpLogging {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                threshold: AtomicU8::new(LEVEL_OFF),
+                sender: Mutex::new(None),
+                dropped: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Layer to add to the tracing subscriber
+    pub fn layer(&self) -> McpLogLayer {
+        McpLogLayer {
+            logging: self.clone(),
+        }
+    }
+
+    /// Start collecting `notifications/message` payloads for a client
+    pub fn attach(&self) -> mpsc::Receiver<Value> {
+        let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+        *self.inner.sender.lock() = Some(tx);
+        self.inner.dropped.store(0, Ordering::Relaxed);
+        rx
+    }
+
+    /// Stop forwarding and reset the level for the next client
+    pub fn detach(&self) {
+        self.inner.threshold.store(LEVEL_OFF, Ordering::Relaxed);
+        *self.inner.sender.lock() = None;
+    }
+
+    /// Set the minimum level from `logging/setLevel`; false if `level` is unknown
+    pub fn set_level(&self, level: &str) -> bool {
+        match LOG_LEVELS.iter().position(|l| *l == level) {
+            Some(severity) => {
+                self.inner
+                    .threshold
+                    .store(severity as u8, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn enabled(&self, severity: u8) -> bool {
+        severity >= self.inner.threshold.load(Ordering::Relaxed)
+    }
+
+    /// Queue `params`, first reporting how many events were dropped while
+    /// the queue was full
+    fn send(&self, params: Value) {
+        let sender = self.inner.sender.lock();
+        let Some(sender) = sender.as_ref() else {
+            return;
+        };
+        let dropped = self.inner.dropped.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            let notice = json!({
+                "level": "warning",
+                "logger": module_path!(),
+                "data": { "message": "Log messages dropped", "dropped": dropped },
+            });
+            if sender.try_send(notice).is_err() {
+                self.inner.dropped.fetch_add(dropped + 1, Ordering::Relaxed);
+                return;
+            }
+        }
+        if sender.try_send(params).is_err() {
+            self.inner.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+pub struct McpLogLayer {
+    logging: McpLogging,
+}
+
+/// Index into [`LOG_LEVELS`] for a tracing level
+fn severity(level: &Level) -> u8 {
+    match *level {
+        Level::TRACE | Level::DEBUG => 0,
+        Level::INFO => 1,
+        Level::WARN => 3,
+        Level::ERROR => 4,
+    }
+}
+
+impl<S: Subscriber> Layer<S> for McpLogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let severity = severity(event.metadata().level());
+        if !self.logging.enabled(severity) || PRIVATE_TARGETS.contains(&event.metadata().target()) {
+            return;
+        }
+
+        let mut fields = FieldVisitor::default();
+        event.record(&mut fields);
+        self.logging.send(json!({
+            "level": LOG_LEVELS[severity as usize],
+            "logger": event.metadata().target(),
+            "data": Value::Object(fields.0),
+        }));
+    }
+}
+
+#[derive(Default)]
+struct FieldVisitor(Map<String, Value>);
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), json!(format!("{:?}", value)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_forwards_events_at_or_above_level() {
+        let logging = McpLogging::new();
+        let mut rx = logging.attach();
+        let subscriber = tracing_subscriber::registry().with(logging.layer());
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::warn!("before setLevel");
+            assert!(logging.set_level("notice"));
+            tracing::info!("too quiet");
+            tracing::warn!(agent = "codex", retries = 2, "Agent slow");
+            tracing::error!("Agent failed");
+        });
+
+        let first = rx.try_recv().unwrap();
+        assert_eq!(first["level"], "warning");
+        assert_eq!(first["data"]["message"], "Agent slow");
+        assert_eq!(first["data"]["agent"], "codex");
+        assert_eq!(first["data"]["retries"], 2);
+        assert_eq!(rx.try_recv().unwrap()["level"], "error");
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_set_level_rejects_unknown_levels() {
+        let logging = McpLogging::new();
+        assert!(logging.set_level("emergency"));
+        assert!(!logging.set_level("verbose"));
+        assert!(!logging.set_level("WARNING"));
+    }
+
+    #[test]
+    fn test_detach_stops_forwarding() {
+        let logging = McpLogging::new();
+        let mut rx = logging.attach();
+        logging.set_level("debug");
+        logging.detach();
+        let subscriber = tracing_subscriber::registry().with(logging.layer());
+        tracing::subscriber::with_default(subscriber, || tracing::error!("gone"));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_private_targets_are_not_forwarded() {
+        let logging = McpLogging::new();
+        let mut rx = logging.attach();
+        logging.set_level("debug");
+        let subscriber = tracing_subscriber::registry().with(logging.layer());
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::debug!(target: CONTENT_TARGET, "PTY output: secret");
+            tracing::warn!(target: "ccgonext::web::audit", "secret");
+            tracing::debug!("kept");
+        });
+
+        assert_eq!(rx.try_recv().unwrap()["data"]["message"], "kept");
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_full_queue_drops_and_counts() {
+        let logging = McpLogging::new();
+        let mut rx = logging.attach();
+        logging.set_level("debug");
+        let subscriber = tracing_subscriber::registry().with(logging.layer());
+
+        tracing::subscriber::with_default(subscriber, || {
+            for i in 0..CHANNEL_CAPACITY + 10 {
+                tracing::info!(i, "event");
+            }
+        });
+        let mut received = 0;
+        while rx.try_recv().is_ok() {
+            received += 1;
+        }
+        assert_eq!(received, CHANNEL_CAPACITY);
+
+        let subscriber = tracing_subscriber::registry().with(logging.layer());
+        tracing::subscriber::with_default(subscriber, || tracing::info!("after"));
+        let notice = rx.try_recv().unwrap();
+        assert_eq!(notice["level"], "warning");
+        assert_eq!(notice["data"]["dropped"], 10);
+        assert_eq!(rx.try_recv().unwrap()["data"]["message"], "after");
+    }
+}
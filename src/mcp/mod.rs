@@ -1,37 +1,70 @@
 //! MCP Server implementation
 
+mod framing;
+mod logging;
 mod protocol;
+mod resources;
 mod tools;
 
+pub use framing::*;
+pub use logging::*;
 pub use protocol::*;
+pub use resources::*;
 pub use tools::*;
 
 use crate::config::Config;
-use crate::session::SessionManager;
+use crate::session::{ReplyChunk, SessionManager};
 use crossterm::terminal;
+use serde_json::{json, Value};
+use std::collections::HashMap;
 use std::io::IsTerminal;
 use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
-use tokio::sync::Mutex;
-
-/// Transport mode for MCP protocol
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum TransportMode {
-    /// Auto-detect mode (initial state)
-    AutoDetect,
-    /// Line-delimited JSON (JSONL) - one JSON object per line
-    JsonLines,
-    /// LSP-style with Content-Length header
-    LspStyle,
-}
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinSet;
+use tracing::Instrument;
 
-/// Maximum allowed Content-Length (16MB) to prevent OOM attacks
-const MAX_CONTENT_LENGTH: usize = 16 * 1024 * 1024;
+/// Logger name of the `notifications/message` carrying streamed replies
+const REPLY_LOGGER: &str = "ccgonext.reply";
 
+#[derive(Clone)]
 pub struct McpServer {
     session_manager: Arc<SessionManager>,
     #[allow(dead_code)]
     config: Arc<Config>,
+    logging: McpLogging,
+    /// Revision agreed in `initialize`
+    protocol_version: Arc<parking_lot::RwLock<&'static str>>,
+    outbox: Arc<Outbox>,
+    /// Watchers for `resources/subscribe`, by URI
+    subscriptions: Arc<parking_lot::Mutex<HashMap<String, tokio::task::JoinHandle<()>>>>,
+}
+
+/// Serializes everything written to stdout in the client's framing
+struct Outbox {
+    stdout: Mutex<tokio::io::Stdout>,
+    mode: parking_lot::Mutex<TransportMode>,
+}
+
+impl Outbox {
+    fn new() -> Self {
+        Self {
+            stdout: Mutex::new(tokio::io::stdout()),
+            mode: parking_lot::Mutex::new(TransportMode::AutoDetect),
+        }
+    }
+
+    fn set_mode(&self, mode: TransportMode) {
+        *self.mode.lock() = mode;
+    }
+
+    async fn send(&self, payload: &str) -> anyhow::Result<()> {
+        let framed = encode(payload, *self.mode.lock());
+        let mut out = self.stdout.lock().await;
+        out.write_all(&framed).await?;
+        out.flush().await?;
+        Ok(())
+    }
 }
 
 struct RawModeGuard;
@@ -42,14 +75,87 @@ impl Drop for RawModeGuard {
     }
 }
 
+/// Tool calls can run for minutes, so they are answered from their own task
+/// while later messages are read; everything else is answered in order
+fn contains_tool_call(value: &Value) -> bool {
+    match value {
+        Value::Array(items) => items.iter().any(contains_tool_call),
+        Value::Object(fields) => fields.get("method").and_then(Value::as_str) == Some("tools/call"),
+        _ => false,
+    }
+}
+
+fn invalid_request(id: Value, message: String) -> JsonRpcResponse {
+    JsonRpcResponse::error(
+        id,
+        JsonRpcError {
+            code: -32600,
+            message,
+            data: None,
+        },
+    )
+}
+
+fn invalid_params(id: Value, error: serde_json::Error) -> JsonRpcResponse {
+    JsonRpcResponse::error(
+        id,
+        JsonRpcError {
+            code: -32602,
+            message: format!("Invalid params: {}", error),
+            data: None,
+        },
+    )
+}
+
+fn resource_not_found(id: Value, error: ResourceNotFound) -> JsonRpcResponse {
+    let uri = error.0.clone();
+    JsonRpcResponse::error(
+        id,
+        JsonRpcError {
+            code: -32002,
+            message: error.to_string(),
+            data: Some(json!({"uri": uri})),
+        },
+    )
+}
+
+fn parse_error(message: String) -> JsonRpcResponse {
+    JsonRpcResponse::error(
+        Value::Null,
+        JsonRpcError {
+            code: -32700,
+            message,
+            data: None,
+        },
+    )
+}
+
 impl McpServer {
     pub fn new(session_manager: Arc<SessionManager>, config: Arc<Config>) -> Self {
         Self {
             session_manager,
             config,
+            logging: McpLogging::new(),
+            // Clients that skip `initialize` get the original feature set
+            protocol_version: Arc::new(parking_lot::RwLock::new(
+                SUPPORTED_PROTOCOL_VERSIONS[SUPPORTED_PROTOCOL_VERSIONS.len() - 1],
+            )),
+            outbox: Arc::new(Outbox::new()),
+            subscriptions: Arc::new(parking_lot::Mutex::new(HashMap::new())),
         }
     }
 
+    /// Forward tracing events collected by `logging`'s layer to the client
+    pub fn with_logging(mut self, logging: McpLogging) -> Self {
+        self.logging = logging;
+        self
+    }
+
+    fn protocol_at_least(&self, version: &str) -> bool {
+        // Revisions are dates, so they order as strings
+        *self.protocol_version.read() >= version
+    }
+
     pub async fn run_stdio(&self) -> anyhow::Result<()> {
         let _raw_mode_guard = if std::io::stdin().is_terminal() {
             match terminal::enable_raw_mode() {
@@ -64,216 +170,133 @@ impl McpServer {
         };
 
         let stdin = tokio::io::stdin();
-        let stdout = Arc::new(Mutex::new(tokio::io::stdout()));
+        let outbox = Arc::clone(&self.outbox);
         let mut reader = BufReader::new(stdin);
         let mut mode = TransportMode::AutoDetect;
+        let mut tool_calls = JoinSet::new();
+
+        let mut log_messages = self.logging.attach();
+        let log_outbox = Arc::clone(&outbox);
+        let log_forwarder = tokio::spawn(async move {
+            while let Some(params) = log_messages.recv().await {
+                let notification = json!({
+                    "jsonrpc": "2.0",
+                    "method": "notifications/message",
+                    "params": params,
+                });
+                if log_outbox.send(&notification.to_string()).await.is_err() {
+                    break;
+                }
+            }
+        });
 
         tracing::info!("MCP Server started on stdio (auto-detecting transport mode)");
 
         loop {
-            let message_result = match mode {
-                TransportMode::AutoDetect => {
-                    // Peek at first bytes to detect mode
-                    let detected = self.detect_and_read_message(&mut reader, &mut mode).await;
-                    detected
-                }
-                TransportMode::JsonLines => self.read_jsonl_message(&mut reader).await,
-                TransportMode::LspStyle => self.read_lsp_message(&mut reader).await,
-            };
+            while tool_calls.try_join_next().is_some() {}
 
-            match message_result {
+            match read_message(&mut reader, &mut mode).await {
                 Ok(None) => break, // EOF
                 Ok(Some(content)) => {
+                    outbox.set_mode(mode);
                     let content = content.trim();
                     if content.is_empty() {
                         continue;
                     }
 
-                    match serde_json::from_str::<JsonRpcMessage>(content) {
-                        Ok(message) => {
-                            if let Some(response) = self.handle_message(message).await {
-                                self.write_response(&stdout, &response, mode).await?;
-                            }
-                        }
+                    let value = match serde_json::from_str::<Value>(content) {
+                        Ok(value) => value,
                         Err(e) => {
-                            let error_response = JsonRpcResponse::error(
-                                serde_json::Value::Null,
-                                JsonRpcError {
-                                    code: -32700,
-                                    message: format!("Parse error: {}", e),
-                                    data: None,
-                                },
-                            );
-                            self.write_response(&stdout, &error_response, mode).await?;
+                            let response = parse_error(format!("Parse error: {}", e));
+                            outbox.send(&serde_json::to_string(&response)?).await?;
+                            continue;
                         }
+                    };
+
+                    if contains_tool_call(&value) {
+                        let server = self.clone();
+                        let outbox = Arc::clone(&outbox);
+                        tool_calls.spawn(async move {
+                            if let Some(payload) = server.handle_payload(value).await {
+                                if let Err(e) = outbox.send(&payload).await {
+                                    tracing::error!("Error writing response: {}", e);
+                                }
+                            }
+                        });
+                    } else if let Some(payload) = self.handle_payload(value).await {
+                        outbox.send(&payload).await?;
                     }
                 }
-                Err(e) => {
+                Err(e) if e.is_fatal() => {
                     tracing::error!("Error reading stdin: {}", e);
                     break;
                 }
-            }
-        }
-
-        Ok(())
-    }
-
-    /// Detect transport mode from first message and read it
-    async fn detect_and_read_message<R: AsyncBufReadExt + Unpin>(
-        &self,
-        reader: &mut R,
-        mode: &mut TransportMode,
-    ) -> anyhow::Result<Option<String>> {
-        let mut first_line = String::new();
-        let bytes_read = reader.read_line(&mut first_line).await?;
-
-        if bytes_read == 0 {
-            return Ok(None); // EOF
-        }
-
-        let trimmed = first_line.trim();
-
-        // Check if it looks like LSP Content-Length header
-        if trimmed.to_lowercase().starts_with("content-length:") {
-            *mode = TransportMode::LspStyle;
-            tracing::info!("Detected LSP-style transport mode");
-
-            // Parse content length
-            let content_length = self.parse_content_length(trimmed)?;
-
-            // Read until empty line (end of headers)
-            loop {
-                let mut header_line = String::new();
-                reader.read_line(&mut header_line).await?;
-                if header_line.trim().is_empty() {
-                    break;
+                Err(e) => {
+                    outbox.set_mode(mode);
+                    tracing::warn!("Dropped malformed message: {}", e);
+                    let response = match e {
+                        FrameError::TooLarge(_) => invalid_request(Value::Null, e.to_string()),
+                        _ => parse_error(format!("Parse error: {}", e)),
+                    };
+                    outbox.send(&serde_json::to_string(&response)?).await?;
                 }
-                // Could parse other headers here if needed
             }
-
-            // Read the JSON content
-            let mut content = vec![0u8; content_length];
-            reader.read_exact(&mut content).await?;
-            Ok(Some(String::from_utf8(content)?))
-        } else if trimmed.starts_with('{') {
-            // Looks like JSON, use JSONL mode
-            *mode = TransportMode::JsonLines;
-            tracing::info!("Detected JSON Lines transport mode");
-            Ok(Some(first_line))
-        } else if trimmed.is_empty() {
-            // Empty line, keep auto-detecting
-            Ok(Some(String::new()))
-        } else {
-            // Unknown format, try JSONL
-            *mode = TransportMode::JsonLines;
-            tracing::warn!(
-                "Unknown format, assuming JSON Lines mode. First line: {}",
-                trimmed
-            );
-            Ok(Some(first_line))
         }
-    }
-
-    /// Read a message in JSONL mode (line-delimited)
-    async fn read_jsonl_message<R: AsyncBufReadExt + Unpin>(
-        &self,
-        reader: &mut R,
-    ) -> anyhow::Result<Option<String>> {
-        let mut line = String::new();
-        let bytes_read = reader.read_line(&mut line).await?;
 
-        if bytes_read == 0 {
-            return Ok(None); // EOF
+        // The client closed stdin: nobody is left to read tool results
+        if !tool_calls.is_empty() {
+            tracing::info!("Cancelling {} tool call(s) still running", tool_calls.len());
+        }
+        tool_calls.shutdown().await;
+        self.logging.detach();
+        log_forwarder.abort();
+        for (_, watcher) in self.subscriptions.lock().drain() {
+            watcher.abort();
         }
 
-        Ok(Some(line))
+        Ok(())
     }
 
-    /// Read a message in LSP mode (Content-Length header)
-    async fn read_lsp_message<R: AsyncBufReadExt + AsyncReadExt + Unpin>(
-        &self,
-        reader: &mut R,
-    ) -> anyhow::Result<Option<String>> {
-        let mut content_length: Option<usize> = None;
-
-        // Read headers
-        loop {
-            let mut header_line = String::new();
-            let bytes_read = reader.read_line(&mut header_line).await?;
-
-            if bytes_read == 0 {
-                return Ok(None); // EOF
-            }
-
-            let trimmed = header_line.trim();
-            if trimmed.is_empty() {
-                // End of headers
-                break;
-            }
-
-            if trimmed.to_lowercase().starts_with("content-length:") {
-                content_length = Some(self.parse_content_length(trimmed)?);
+    /// Answer a single message or a batch; None when nothing is to be sent
+    /// back (notifications, or a batch of only notifications)
+    async fn handle_payload(&self, value: Value) -> Option<String> {
+        match value {
+            Value::Array(items) if items.is_empty() => Some(
+                serde_json::to_string(&invalid_request(
+                    Value::Null,
+                    "Invalid Request: empty batch".to_string(),
+                ))
+                .ok()?,
+            ),
+            Value::Array(items) => {
+                let responses: Vec<JsonRpcResponse> = futures::future::join_all(
+                    items.into_iter().map(|item| self.handle_value(item)),
+                )
+                .await
+                .into_iter()
+                .flatten()
+                .collect();
+                if responses.is_empty() {
+                    return None;
+                }
+                serde_json::to_string(&responses).ok()
             }
-            // Ignore other headers (Content-Type, etc.)
+            value => serde_json::to_string(&self.handle_value(value).await?).ok(),
         }
-
-        let length = content_length
-            .ok_or_else(|| anyhow::anyhow!("Missing Content-Length header in LSP message"))?;
-
-        // Read the JSON content
-        let mut content = vec![0u8; length];
-        reader.read_exact(&mut content).await?;
-
-        Ok(Some(String::from_utf8(content)?))
     }
 
-    /// Parse Content-Length header value
-    fn parse_content_length(&self, header: &str) -> anyhow::Result<usize> {
-        let parts: Vec<&str> = header.splitn(2, ':').collect();
-        if parts.len() != 2 {
-            anyhow::bail!("Invalid Content-Length header format");
-        }
-
-        let length: usize = parts[1].trim().parse()?;
-
-        // Prevent OOM attacks by limiting content length
-        if length > MAX_CONTENT_LENGTH {
-            anyhow::bail!(
-                "Content-Length {} exceeds maximum allowed size {}",
-                length,
-                MAX_CONTENT_LENGTH
-            );
+    async fn handle_value(&self, value: Value) -> Option<JsonRpcResponse> {
+        let id = value.get("id").cloned().unwrap_or(Value::Null);
+        if value.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+            return Some(invalid_request(
+                id,
+                "Invalid Request: expected a JSON-RPC 2.0 object".to_string(),
+            ));
         }
-
-        Ok(length)
-    }
-
-    /// Write response in the appropriate format
-    async fn write_response(
-        &self,
-        stdout: &Arc<Mutex<tokio::io::Stdout>>,
-        response: &JsonRpcResponse,
-        mode: TransportMode,
-    ) -> anyhow::Result<()> {
-        let response_json = serde_json::to_string(response)?;
-        let mut out = stdout.lock().await;
-
-        match mode {
-            TransportMode::LspStyle => {
-                // LSP style: Content-Length header + \r\n\r\n + content
-                let header = format!("Content-Length: {}\r\n\r\n", response_json.len());
-                out.write_all(header.as_bytes()).await?;
-                out.write_all(response_json.as_bytes()).await?;
-            }
-            TransportMode::JsonLines | TransportMode::AutoDetect => {
-                // JSONL style: JSON + newline
-                out.write_all(response_json.as_bytes()).await?;
-                out.write_all(b"\n").await?;
-            }
+        match serde_json::from_value::<JsonRpcMessage>(value) {
+            Ok(message) => self.handle_message(message).await,
+            Err(e) => Some(invalid_request(id, format!("Invalid Request: {}", e))),
         }
-
-        out.flush().await?;
-        Ok(())
     }
 
     async fn handle_message(&self, message: JsonRpcMessage) -> Option<JsonRpcResponse> {
@@ -303,7 +326,21 @@ impl McpServer {
     async fn handle_request(&self, request: JsonRpcRequest) -> JsonRpcResponse {
         match request.method.as_str() {
             "initialize" => self.handle_initialize(request).await,
+            "ping" => JsonRpcResponse::success(request.id, json!({})),
+            "logging/setLevel" => self.handle_set_level(request),
             "tools/list" => self.handle_tools_list(request).await,
+            "resources/list" => {
+                let resources = list_resources(&self.session_manager).await;
+                JsonRpcResponse::success(
+                    request.id,
+                    serde_json::to_value(ResourcesListResult { resources }).unwrap(),
+                )
+            }
+            "resources/read" => self.handle_resources_read(request).await,
+            "resources/subscribe" => self.handle_resources_subscribe(request).await,
+            "resources/unsubscribe" => self.handle_resources_unsubscribe(request),
+            "prompts/list" => self.handle_prompts_list(request),
+            "prompts/get" => self.handle_prompts_get(request),
             "tools/call" => self.handle_tools_call(request).await,
             _ => JsonRpcResponse::error(
                 request.id,
@@ -317,10 +354,33 @@ impl McpServer {
     }
 
     async fn handle_initialize(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let requested = request.params["protocolVersion"].as_str();
+        let version = negotiate_protocol_version(requested);
+        if requested != Some(version) {
+            tracing::info!(
+                "Client requested protocol {:?}, offering {}",
+                requested,
+                version
+            );
+        }
+        *self.protocol_version.write() = version;
+
         let result = InitializeResult {
-            protocol_version: "2024-11-05".to_string(),
+            protocol_version: version.to_string(),
             capabilities: ServerCapabilities {
-                tools: Some(ToolsCapability { list_changed: true }),
+                // The tool list is fixed for the life of the server
+                tools: Some(ToolsCapability {
+                    list_changed: false,
+                }),
+                resources: Some(ResourcesCapability {
+                    subscribe: true,
+                    list_changed: false,
+                }),
+                // Prompt files are re-read on every request, so there is nothing to announce
+                prompts: Some(PromptsCapability {
+                    list_changed: false,
+                }),
+                logging: Some(json!({})),
             },
             server_info: ServerInfo {
                 name: "ccgonext".to_string(),
@@ -331,8 +391,167 @@ impl McpServer {
         JsonRpcResponse::success(request.id, serde_json::to_value(result).unwrap())
     }
 
+    fn handle_set_level(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let level = serde_json::from_value::<SetLevelParams>(request.params)
+            .map(|params| params.level)
+            .unwrap_or_default();
+        if !self.logging.set_level(&level) {
+            return JsonRpcResponse::error(
+                request.id,
+                JsonRpcError {
+                    code: -32602,
+                    message: format!(
+                        "Invalid params: level must be one of {}",
+                        LOG_LEVELS.join(", ")
+                    ),
+                    data: None,
+                },
+            );
+        }
+        JsonRpcResponse::success(request.id, json!({}))
+    }
+
+    async fn handle_resources_read(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params: ResourceParams = match serde_json::from_value(request.params) {
+            Ok(p) => p,
+            Err(e) => return invalid_params(request.id, e),
+        };
+        match read_resource(&self.session_manager, &params.uri).await {
+            Ok(contents) => JsonRpcResponse::success(
+                request.id,
+                serde_json::to_value(ResourceReadResult {
+                    contents: vec![contents],
+                })
+                .unwrap(),
+            ),
+            Err(e) => resource_not_found(request.id, e),
+        }
+    }
+
+    async fn handle_resources_subscribe(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params: ResourceParams = match serde_json::from_value(request.params) {
+            Ok(p) => p,
+            Err(e) => return invalid_params(request.id, e),
+        };
+        let (session, resource) = match resolve_resource(&self.session_manager, &params.uri).await {
+            Ok(found) => found,
+            Err(e) => return resource_not_found(request.id, e),
+        };
+
+        let uri = params.uri;
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/resources/updated",
+            "params": {"uri": uri},
+        })
+        .to_string();
+        let outbox = Arc::clone(&self.outbox);
+        let watcher = tokio::spawn(watch_resource(session, resource, move || {
+            let outbox = Arc::clone(&outbox);
+            let notification = notification.clone();
+            tokio::spawn(async move {
+                let _ = outbox.send(&notification).await;
+            });
+        }));
+        if let Some(previous) = self.subscriptions.lock().insert(uri, watcher) {
+            previous.abort();
+        }
+        JsonRpcResponse::success(request.id, json!({}))
+    }
+
+    fn handle_resources_unsubscribe(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params: ResourceParams = match serde_json::from_value(request.params) {
+            Ok(p) => p,
+            Err(e) => return invalid_params(request.id, e),
+        };
+        if let Some(watcher) = self.subscriptions.lock().remove(&params.uri) {
+            watcher.abort();
+        }
+        JsonRpcResponse::success(request.id, json!({}))
+    }
+
+    fn handle_prompts_list(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let prompts = self
+            .session_manager
+            .prompts()
+            .list()
+            .into_iter()
+            .map(|template| Prompt {
+                description: format!(
+                    "{} (asks {})",
+                    template.description,
+                    template.agents.join(", ")
+                ),
+                name: template.name,
+                arguments: template.arguments,
+            })
+            .collect();
+        JsonRpcResponse::success(
+            request.id,
+            serde_json::to_value(PromptsListResult { prompts }).unwrap(),
+        )
+    }
+
+    fn handle_prompts_get(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        let params: GetPromptParams = match serde_json::from_value(request.params) {
+            Ok(p) => p,
+            Err(e) => return invalid_params(request.id, e),
+        };
+        let rendered = self
+            .session_manager
+            .prompts()
+            .get(&params.name)
+            .and_then(|template| Ok((template.render(&params.arguments)?, template)));
+        let (text, template) = match rendered {
+            Ok(rendered) => rendered,
+            Err(e) => {
+                return JsonRpcResponse::error(
+                    request.id,
+                    JsonRpcError {
+                        code: -32602,
+                        message: format!("Invalid params: {}", e),
+                        data: None,
+                    },
+                )
+            }
+        };
+
+        // The prompt is for the lead model, which passes it on to the agents
+        let text = format!(
+            "Use the ask_agents tool to send the message below to {}, one request per agent with the message exactly as written, then report what each agent said.\n\n{}",
+            template.agents.join(", "),
+            text
+        );
+        let result = GetPromptResult {
+            description: template.description,
+            messages: vec![PromptMessage {
+                role: "user".to_string(),
+                content: ToolContent::Text { text },
+            }],
+        };
+        JsonRpcResponse::success(request.id, serde_json::to_value(result).unwrap())
+    }
+
+    /// Tool definitions without the fields the negotiated revision predates
+    fn tool_definitions(&self) -> Vec<ToolDefinition> {
+        let annotations = self.protocol_at_least(ANNOTATIONS_VERSION);
+        let structured = self.protocol_at_least(STRUCTURED_OUTPUT_VERSION);
+        get_tool_definitions(self.session_manager.worktrees().is_some())
+            .into_iter()
+            .map(|mut tool| {
+                if !annotations {
+                    tool.annotations = None;
+                }
+                if !structured {
+                    tool.output_schema = None;
+                }
+                tool
+            })
+            .collect()
+    }
+
     async fn handle_tools_list(&self, request: JsonRpcRequest) -> JsonRpcResponse {
-        let tools = get_tool_definitions();
+        let tools = self.tool_definitions();
         let result = ToolsListResult { tools };
         JsonRpcResponse::success(request.id, serde_json::to_value(result).unwrap())
     }
@@ -352,12 +571,58 @@ impl McpServer {
             }
         };
 
-        let result = execute_tool(&params.name, params.arguments, &self.session_manager).await;
+        let has_output_schema = self
+            .tool_definitions()
+            .iter()
+            .any(|tool| tool.name == params.name && tool.output_schema.is_some());
+
+        // Partial replies go out as log messages ahead of the result
+        let (chunk_tx, mut chunk_rx) = mpsc::unbounded_channel::<ReplyChunk>();
+        let outbox = Arc::clone(&self.outbox);
+        let request_id = request.id.clone();
+        let chunk_forwarder = tokio::spawn(async move {
+            while let Some(chunk) = chunk_rx.recv().await {
+                let notification = json!({
+                    "jsonrpc": "2.0",
+                    "method": "notifications/message",
+                    "params": {
+                        "level": "info",
+                        "logger": REPLY_LOGGER,
+                        "data": {
+                            "request_id": request_id,
+                            "agent": chunk.agent,
+                            "message_id": chunk.message_id,
+                            "offset": chunk.offset,
+                            "text": chunk.text,
+                        },
+                    },
+                });
+                if outbox.send(&notification.to_string()).await.is_err() {
+                    break;
+                }
+            }
+        });
+        let span = tracing::info_span!("tools/call", tool = %params.name, request_id = %request.id);
+        let result = execute_tool(
+            &params.name,
+            params.arguments,
+            &self.session_manager,
+            Some(&chunk_tx),
+        )
+        .instrument(span)
+        .await;
+        drop(chunk_tx);
+        let _ = chunk_forwarder.await;
 
         match result {
             Ok(content) => {
+                // The text content stays for clients that ignore structuredContent
+                let structured_content = has_output_schema
+                    .then(|| serde_json::from_str(&content).ok())
+                    .flatten();
                 let tool_result = ToolCallResult {
                     content: vec![ToolContent::Text { text: content }],
+                    structured_content,
                     is_error: false,
                 };
                 JsonRpcResponse::success(request.id, serde_json::to_value(tool_result).unwrap())
@@ -367,6 +632,7 @@ impl McpServer {
                     content: vec![ToolContent::Text {
                         text: e.to_string(),
                     }],
+                    structured_content: None,
                     is_error: true,
                 };
                 JsonRpcResponse::success(request.id, serde_json::to_value(tool_result).unwrap())
@@ -2,6 +2,24 @@
 
 use serde::{Deserialize, Serialize};
 
+/// Protocol revisions this server speaks, newest first
+pub const SUPPORTED_PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+
+/// First revision with tool annotations
+pub const ANNOTATIONS_VERSION: &str = "2025-03-26";
+
+/// First revision with `outputSchema` and `structuredContent`
+pub const STRUCTURED_OUTPUT_VERSION: &str = "2025-06-18";
+
+/// The version to answer `initialize` with: the client's if supported,
+/// otherwise our newest and the client decides whether to continue
+pub fn negotiate_protocol_version(requested: Option<&str>) -> &'static str {
+    SUPPORTED_PROTOCOL_VERSIONS
+        .iter()
+        .find(|v| Some(**v) == requested)
+        .unwrap_or(&SUPPORTED_PROTOCOL_VERSIONS[0])
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JsonRpcRequest {
     pub jsonrpc: String,
@@ -100,6 +118,25 @@ pub struct InitializeResult {
 pub struct ServerCapabilities {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tools: Option<ToolsCapability>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ResourcesCapability>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompts: Option<PromptsCapability>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logging: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptsCapability {
+    #[serde(rename = "listChanged")]
+    pub list_changed: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourcesCapability {
+    pub subscribe: bool,
+    #[serde(rename = "listChanged")]
+    pub list_changed: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -125,6 +162,89 @@ pub struct ToolDefinition {
     pub description: String,
     #[serde(rename = "inputSchema")]
     pub input_schema: serde_json::Value,
+    #[serde(rename = "outputSchema", skip_serializing_if = "Option::is_none")]
+    pub output_schema: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<ToolAnnotations>,
+}
+
+/// Hints about a tool's behavior; clients must not rely on them for security
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolAnnotations {
+    pub read_only_hint: bool,
+    pub destructive_hint: bool,
+    pub idempotent_hint: bool,
+    pub open_world_hint: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SetLevelParams {
+    pub level: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Resource {
+    pub uri: String,
+    pub name: String,
+    pub description: String,
+    #[serde(rename = "mimeType")]
+    pub mime_type: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourcesListResult {
+    pub resources: Vec<Resource>,
+}
+
+/// Params of `resources/read`, `resources/subscribe` and `resources/unsubscribe`
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResourceParams {
+    pub uri: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourceContents {
+    pub uri: String,
+    #[serde(rename = "mimeType")]
+    pub mime_type: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourceReadResult {
+    pub contents: Vec<ResourceContents>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Prompt {
+    pub name: String,
+    pub description: String,
+    pub arguments: Vec<crate::prompts::PromptArgument>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptsListResult {
+    pub prompts: Vec<Prompt>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GetPromptParams {
+    pub name: String,
+    #[serde(default)]
+    pub arguments: std::collections::HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptMessage {
+    pub role: String,
+    pub content: ToolContent,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetPromptResult {
+    pub description: String,
+    pub messages: Vec<PromptMessage>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -137,6 +257,8 @@ pub struct ToolCallParams {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolCallResult {
     pub content: Vec<ToolContent>,
+    #[serde(rename = "structuredContent", skip_serializing_if = "Option::is_none")]
+    pub structured_content: Option<serde_json::Value>,
     #[serde(rename = "isError")]
     pub is_error: bool,
 }
@@ -238,7 +360,12 @@ mod tests {
     fn test_initialize_result() {
         let result = InitializeResult {
             protocol_version: "2024-11-05".to_string(),
-            capabilities: ServerCapabilities { tools: None },
+            capabilities: ServerCapabilities {
+                tools: None,
+                resources: None,
+                prompts: None,
+                logging: None,
+            },
             server_info: ServerInfo {
                 name: "ccgonext".to_string(),
                 version: "0.1.3".to_string(),
@@ -261,12 +388,22 @@ mod tests {
                     "agent_name": {"type": "string"}
                 }
             }),
+            output_schema: None,
+            annotations: Some(ToolAnnotations {
+                read_only_hint: true,
+                destructive_hint: false,
+                idempotent_hint: true,
+                open_world_hint: false,
+            }),
         };
 
         assert_eq!(tool.name, "ask_agent");
 
         let serialized = serde_json::to_value(&tool).unwrap();
         assert_eq!(serialized["inputSchema"]["type"], "object");
+        assert!(serialized.get("outputSchema").is_none());
+        assert_eq!(serialized["annotations"]["readOnlyHint"], true);
+        assert_eq!(serialized["annotations"]["openWorldHint"], false);
     }
 
     #[test]
@@ -290,6 +427,7 @@ mod tests {
             content: vec![ToolContent::Text {
                 text: "Response from agent".to_string(),
             }],
+            structured_content: None,
             is_error: false,
         };
 
@@ -298,6 +436,21 @@ mod tests {
 
         let serialized = serde_json::to_value(&result).unwrap();
         assert_eq!(serialized["isError"], false);
+        assert!(serialized.get("structuredContent").is_none());
+    }
+
+    #[test]
+    fn test_negotiate_protocol_version() {
+        assert_eq!(negotiate_protocol_version(Some("2024-11-05")), "2024-11-05");
+        assert_eq!(negotiate_protocol_version(Some("2025-03-26")), "2025-03-26");
+        assert_eq!(
+            negotiate_protocol_version(Some("1999-01-01")),
+            SUPPORTED_PROTOCOL_VERSIONS[0]
+        );
+        assert_eq!(
+            negotiate_protocol_version(None),
+            SUPPORTED_PROTOCOL_VERSIONS[0]
+        );
     }
 
     #[test]
@@ -0,0 +1,257 @@
+//! MCP resources for looking at an agent without prompting it
+//!
+//! Every registered agent has three resources:
+//! - `ccgonext://agent/<name>/screen`: the terminal as currently rendered
+//! - `ccgonext://agent/<name>/history`: recent turns from the agent's log
+//! - `ccgonext://agent/<name>/last-reply`: the last completed reply
+
+use super::{Resource, ResourceContents};
+use crate::log_provider::HistoryEntry;
+use crate::session::{AgentSession, SessionManager};
+use crate::state::AgentState;
+use std::future::pending;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+
+const URI_PREFIX: &str = "ccgonext://agent/";
+
+/// Turns returned by the history resource
+const HISTORY_COUNT: usize = 50;
+
+/// Screen updates are coalesced so streaming output does not flood the client
+const SCREEN_NOTIFY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Debounce for log file changes
+const LOG_DEBOUNCE_MS: u64 = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AgentResource {
+    Screen,
+    History,
+    LastReply,
+}
+
+impl AgentResource {
+    const ALL: [AgentResource; 3] = [
+        AgentResource::Screen,
+        AgentResource::History,
+        AgentResource::LastReply,
+    ];
+
+    fn path(self) -> &'static str {
+        match self {
+            AgentResource::Screen => "screen",
+            AgentResource::History => "history",
+            AgentResource::LastReply => "last-reply",
+        }
+    }
+
+    fn mime_type(self) -> &'static str {
+        match self {
+            AgentResource::History => "application/json",
+            AgentResource::Screen | AgentResource::LastReply => "text/plain",
+        }
+    }
+
+    fn description(self, agent: &str) -> String {
+        match self {
+            AgentResource::Screen => format!("{}'s terminal as currently rendered", agent),
+            AgentResource::History => format!(
+                "Last {} turns from {}'s session log, as JSON",
+                HISTORY_COUNT, agent
+            ),
+            AgentResource::LastReply => format!("{}'s last completed reply", agent),
+        }
+    }
+}
+
+pub fn resource_uri(agent: &str, resource: AgentResource) -> String {
+    format!("{}{}/{}", URI_PREFIX, agent, resource.path())
+}
+
+/// Split a resource URI into agent name and resource
+pub fn parse_resource_uri(uri: &str) -> Option<(&str, AgentResource)> {
+    let (agent, path) = uri.strip_prefix(URI_PREFIX)?.split_once('/')?;
+    let resource = AgentResource::ALL.into_iter().find(|r| r.path() == path)?;
+    (!agent.is_empty()).then_some((agent, resource))
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Resource not found: {0}")]
+pub struct ResourceNotFound(pub String);
+
+pub async fn list_resources(session_manager: &SessionManager) -> Vec<Resource> {
+    let mut agents = session_manager.list().await;
+    agents.sort();
+    agents
+        .iter()
+        .flat_map(|agent| {
+            AgentResource::ALL
+                .into_iter()
+                .map(move |resource| Resource {
+                    uri: resource_uri(agent, resource),
+                    name: format!("{} {}", agent, resource.path()),
+                    description: resource.description(agent),
+                    mime_type: resource.mime_type().to_string(),
+                })
+        })
+        .collect()
+}
+
+/// Look up the session a resource URI refers to
+pub async fn resolve_resource(
+    session_manager: &SessionManager,
+    uri: &str,
+) -> Result<(Arc<AgentSession>, AgentResource), ResourceNotFound> {
+    let (agent, resource) =
+        parse_resource_uri(uri).ok_or_else(|| ResourceNotFound(uri.to_string()))?;
+    let session = session_manager
+        .get(agent)
+        .await
+        .ok_or_else(|| ResourceNotFound(uri.to_string()))?;
+    Ok((session, resource))
+}
+
+pub async fn read_resource(
+    session_manager: &SessionManager,
+    uri: &str,
+) -> Result<ResourceContents, ResourceNotFound> {
+    let (session, resource) = resolve_resource(session_manager, uri).await?;
+    let text = match resource {
+        // An agent that has not been started has a blank screen
+        AgentResource::Screen => match session.pty.read().await.clone() {
+            Some(pty) => pty.render_screen().await,
+            None => String::new(),
+        },
+        AgentResource::History => {
+            let history: Vec<HistoryEntry> =
+                session.log_provider.get_history(None, HISTORY_COUNT).await;
+            serde_json::to_string(&history).unwrap_or_else(|_| "[]".to_string())
+        }
+        AgentResource::LastReply => session_manager
+            .journal()
+            .latest_completed(&session.name)
+            .and_then(|record| record.response)
+            .unwrap_or_default(),
+    };
+    Ok(ResourceContents {
+        uri: uri.to_string(),
+        mime_type: resource.mime_type().to_string(),
+        text,
+    })
+}
+
+/// Call `notify` whenever `resource` may have changed; runs until the
+/// session is dropped or the task is aborted
+pub async fn watch_resource<F: FnMut()>(
+    session: Arc<AgentSession>,
+    resource: AgentResource,
+    mut notify: F,
+) {
+    match resource {
+        AgentResource::Screen => watch_screen(session, notify).await,
+        AgentResource::History => {
+            let mut states = session.subscribe_state();
+            let mut log = session.log_provider.subscribe_changes(LOG_DEBOUNCE_MS);
+            loop {
+                tokio::select! {
+                    changed = async {
+                        match log.as_mut() {
+                            Some(subscription) => subscription.receiver.recv().await,
+                            None => pending().await,
+                        }
+                    } => match changed {
+                        Ok(_) | Err(RecvError::Lagged(_)) => notify(),
+                        Err(RecvError::Closed) => log = None,
+                    },
+                    state = states.recv() => match state {
+                        Ok(AgentState::Idle) => notify(),
+                        Ok(_) | Err(RecvError::Lagged(_)) => {}
+                        Err(RecvError::Closed) => return,
+                    },
+                }
+            }
+        }
+        // Replies are recorded before the session goes back to idle
+        AgentResource::LastReply => {
+            let mut states = session.subscribe_state();
+            loop {
+                match states.recv().await {
+                    Ok(AgentState::Idle) => notify(),
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => return,
+                }
+            }
+        }
+    }
+}
+
+async fn watch_screen<F: FnMut()>(session: Arc<AgentSession>, mut notify: F) {
+    let mut states = session.subscribe_state();
+    loop {
+        let pty = session.pty.read().await.clone();
+        let mut output = pty.as_ref().map(|pty| pty.subscribe_output());
+        loop {
+            tokio::select! {
+                received = async {
+                    match output.as_mut() {
+                        Some(output) => output.recv().await,
+                        None => pending().await,
+                    }
+                } => match received {
+                    Ok(_) | Err(RecvError::Lagged(_)) => {
+                        tokio::time::sleep(SCREEN_NOTIFY_INTERVAL).await;
+                        if let Some(output) = output.as_mut() {
+                            while output.try_recv().is_ok() {}
+                        }
+                        notify();
+                    }
+                    // The agent exited; the last screen stays readable
+                    Err(RecvError::Closed) => output = None,
+                },
+                state = states.recv() => match state {
+                    Err(RecvError::Closed) => return,
+                    Err(RecvError::Lagged(_)) | Ok(_) => {
+                        // A (re)start replaces the PTY
+                        let current = session.pty.read().await.clone();
+                        let replaced = match (&pty, &current) {
+                            (Some(old), Some(new)) => !Arc::ptr_eq(old, new),
+                            (None, None) => false,
+                            _ => true,
+                        };
+                        if replaced {
+                            notify();
+                            break;
+                        }
+                    }
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resource_uris() {
+        assert_eq!(
+            resource_uri("codex", AgentResource::Screen),
+            "ccgonext://agent/codex/screen"
+        );
+        for resource in AgentResource::ALL {
+            let uri = resource_uri("gemini", resource);
+            assert_eq!(parse_resource_uri(&uri), Some(("gemini", resource)));
+        }
+        assert_eq!(
+            parse_resource_uri("ccgonext://agent/opencode/last-reply"),
+            Some(("opencode", AgentResource::LastReply))
+        );
+        assert_eq!(parse_resource_uri("ccgonext://agent/codex/logs"), None);
+        assert_eq!(parse_resource_uri("ccgonext://agent//screen"), None);
+        assert_eq!(parse_resource_uri("ccgonext://agent/codex"), None);
+        assert_eq!(parse_resource_uri("file:///codex/screen"), None);
+    }
+}
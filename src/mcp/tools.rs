@@ -1,7 +1,13 @@
 //! MCP Tool implementations
 
-use super::ToolDefinition;
-use crate::session::SessionManager;
+use super::{ToolAnnotations, ToolDefinition};
+use crate::cache::{self, CacheMode};
+use crate::changes::FileChanges;
+use crate::log_provider::{Reply, TokenUsage};
+use crate::metrics::Metrics;
+use crate::prompts::PromptLibrary;
+use crate::session::{ReplyChunk, SessionManager};
+use crate::worktree::WorktreeChange;
 use futures::FutureExt;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
@@ -10,45 +16,158 @@ use std::collections::HashSet;
 use std::panic::AssertUnwindSafe;
 use std::sync::Arc;
 use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::{mpsc, oneshot};
 use tokio::task::JoinSet;
+use tracing::Instrument;
+use utoipa::ToSchema;
 
 const VALID_AGENTS: &[&str] = &["codex", "gemini", "opencode", "claudecode"];
 const DEFAULT_TIMEOUT: u64 = 600;
 const MAX_TIMEOUT: u64 = 1800;
 const MAX_REQUESTS: usize = 4;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct AskAgentsArgs {
+    /// Agent requests (1-4 items)
+    #[serde(default)]
     pub requests: Vec<AgentRequest>,
+    /// Prompt library template to send to its agents instead of `requests`
+    #[serde(default)]
+    pub template: Option<String>,
+    /// Arguments for the template
+    #[serde(default)]
+    pub args: HashMap<String, String>,
+    /// Timeout in seconds (max 1800)
     #[serde(default = "default_timeout")]
+    #[schema(default = 600)]
     pub timeout: u64,
+    #[serde(default)]
+    pub detail: ReplyDetail,
+    /// Send reply text as `notifications/message` while agents are writing
+    #[serde(default)]
+    pub stream: bool,
 }
 
-#[derive(Debug, Deserialize)]
+/// How much of each reply `ask_agents` returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplyDetail {
+    /// Final text only
+    #[default]
+    Summary,
+    /// Final text plus the structured reply (reasoning, tool calls, errors)
+    Full,
+}
+
+#[derive(Debug, Deserialize, Default, ToSchema)]
 pub struct AgentRequest {
+    /// codex, gemini, opencode or claudecode
     pub agent: String,
     pub message: String,
+    /// Files (relative to the project root) the prompt depends on; their contents are part of the cache key
+    #[serde(default)]
+    pub attachments: Vec<String>,
+    #[serde(default)]
+    pub cache: CacheMode,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct AskAgentsResponse {
     pub results: Vec<AgentResult>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Deserialize)]
+pub struct GetResultArgs {
+    pub message_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangeArgs {
+    pub change_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, ToSchema)]
 pub struct AgentResult {
     pub agent: String,
+    /// Journal id of the request; pass to `get_result` to fetch the reply later
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<String>,
     pub success: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub response: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
+    /// True when the response was served from the response cache
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cached: bool,
+    /// Token usage reported in the agent's log for this reply
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<TokenUsage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Structured reply, only with `detail: "full"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub reply: Option<Reply>,
+    /// Edits the agent made in its worktree; resolve with apply_change, merge_change or discard_change
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub change: Option<WorktreeChange>,
+    /// Files the agent created, modified or deleted, with diffs (requires --track-changes)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub files: Option<FileChanges>,
+}
+
+/// Reply from a single agent, possibly served from the cache
+struct SingleAgentReply {
+    response: String,
+    cached: bool,
+    usage: Option<TokenUsage>,
+    model: Option<String>,
+    reply: Option<Reply>,
+    change: Option<WorktreeChange>,
+    files: Option<FileChanges>,
 }
 
 fn default_timeout() -> u64 {
     DEFAULT_TIMEOUT
 }
 
+impl AskAgentsArgs {
+    /// Expand the template, if any, and check the requests
+    pub fn prepare(&mut self, prompts: &PromptLibrary) -> Result<(), anyhow::Error> {
+        expand_template(self, prompts)?;
+        validate_args(self)
+    }
+}
+
+/// Turn `template` and `args` into one request per agent of the template
+fn expand_template(args: &mut AskAgentsArgs, prompts: &PromptLibrary) -> Result<(), anyhow::Error> {
+    let Some(name) = &args.template else {
+        if !args.args.is_empty() {
+            anyhow::bail!("args requires a template");
+        }
+        return Ok(());
+    };
+    if !args.requests.is_empty() {
+        anyhow::bail!("use either requests or template, not both");
+    }
+    let template = prompts.get(name)?;
+    let message = template.render(&args.args)?;
+    args.requests = template
+        .agents
+        .iter()
+        .map(|agent| AgentRequest {
+            agent: agent.clone(),
+            message: message.clone(),
+            ..Default::default()
+        })
+        .collect();
+    Ok(())
+}
+
 fn validate_args(args: &AskAgentsArgs) -> Result<(), anyhow::Error> {
     if args.requests.is_empty() || args.requests.len() > MAX_REQUESTS {
         anyhow::bail!("requests must have 1-{} items", MAX_REQUESTS);
@@ -77,8 +196,93 @@ fn validate_args(args: &AskAgentsArgs) -> Result<(), anyhow::Error> {
     Ok(())
 }
 
-pub fn get_tool_definitions() -> Vec<ToolDefinition> {
-    vec![ToolDefinition {
+/// Tools to advertise; the change tools only exist with worktree isolation
+pub fn get_tool_definitions(isolation: bool) -> Vec<ToolDefinition> {
+    let mut tools = vec![ask_agents_tool_definition(), get_result_tool_definition()];
+    if !isolation {
+        return tools;
+    }
+    tools.extend([
+        change_tool_definition(
+            "apply_change",
+            "Apply a change captured from an agent's worktree to the project working tree, without committing.",
+            annotations(false, false, false, false),
+        ),
+        change_tool_definition(
+            "merge_change",
+            "Merge the commit of a change captured from an agent's worktree into the project's current branch.",
+            annotations(false, false, false, false),
+        ),
+        change_tool_definition(
+            "discard_change",
+            "Discard a change captured from an agent's worktree, resetting the worktree if it is the latest change.",
+            annotations(false, true, true, false),
+        ),
+    ]);
+    tools
+}
+
+fn annotations(
+    read_only: bool,
+    destructive: bool,
+    idempotent: bool,
+    open_world: bool,
+) -> Option<ToolAnnotations> {
+    Some(ToolAnnotations {
+        read_only_hint: read_only,
+        destructive_hint: destructive,
+        idempotent_hint: idempotent,
+        open_world_hint: open_world,
+    })
+}
+
+fn change_tool_definition(
+    name: &str,
+    description: &str,
+    annotations: Option<ToolAnnotations>,
+) -> ToolDefinition {
+    ToolDefinition {
+        name: name.to_string(),
+        description: format!(
+            "{} Requires worktree isolation (--isolation session|request).",
+            description
+        ),
+        input_schema: json!({
+            "type": "object",
+            "properties": {
+                "change_id": {
+                    "type": "string",
+                    "description": "change.id from an ask_agents result"
+                }
+            },
+            "required": ["change_id"]
+        }),
+        output_schema: None,
+        annotations,
+    }
+}
+
+fn get_result_tool_definition() -> ToolDefinition {
+    ToolDefinition {
+        name: "get_result".to_string(),
+        description: "Get the state and reply of a previous ask_agents request by its message_id, including requests recovered after a restart.".to_string(),
+        input_schema: json!({
+            "type": "object",
+            "properties": {
+                "message_id": {
+                    "type": "string",
+                    "description": "message_id returned in an ask_agents result"
+                }
+            },
+            "required": ["message_id"]
+        }),
+        output_schema: None,
+        annotations: annotations(true, false, true, false),
+    }
+}
+
+fn ask_agents_tool_definition() -> ToolDefinition {
+    ToolDefinition {
         name: "ask_agents".to_string(),
         description: "Send messages to AI agents in parallel and wait for responses. Agents are auto-started if not running.".to_string(),
         input_schema: json!({
@@ -100,42 +304,141 @@ pub fn get_tool_definitions() -> Vec<ToolDefinition> {
                             "message": {
                                 "type": "string",
                                 "description": "Message to send to the agent"
+                            },
+                            "attachments": {
+                                "type": "array",
+                                "items": {"type": "string"},
+                                "description": "Files (relative to the project root) the message depends on; used for response cache keying"
+                            },
+                            "cache": {
+                                "type": "string",
+                                "enum": ["bypass", "prefer", "only"],
+                                "description": "Response cache control (default: prefer). Ignored unless the cache is enabled, except 'only' which fails without a cached reply"
                             }
                         },
                         "required": ["agent", "message"]
                     }
                 },
+                "template": {
+                    "type": "string",
+                    "description": "Name of a prompt from prompts/list to send to its agents, instead of requests"
+                },
+                "args": {
+                    "type": "object",
+                    "additionalProperties": {"type": "string"},
+                    "description": "Arguments for the template"
+                },
                 "timeout": {
                     "type": "integer",
                     "description": "Timeout in seconds (default: 600, max: 1800)"
+                },
+                "detail": {
+                    "type": "string",
+                    "enum": ["summary", "full"],
+                    "description": "'full' also returns each agent's reasoning, tool invocations (shell commands, file edits) and errors from its log (default: summary)"
+                },
+                "stream": {
+                    "type": "boolean",
+                    "description": "Send each agent's reply text as notifications/message (logger 'ccgonext.reply') while it is being written; the result still carries the full replies (default: false)"
                 }
             },
-            "required": ["requests"]
+            "oneOf": [{"required": ["requests"]}, {"required": ["template"]}]
         }),
-    }]
+        output_schema: Some(ask_agents_output_schema()),
+        // Agents run tools of their own and may edit files in the project
+        annotations: annotations(false, true, false, true),
+    }
+}
+
+/// Schema of [`AskAgentsResponse`], returned as `structuredContent`
+fn ask_agents_output_schema() -> serde_json::Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "results": {
+                "type": "array",
+                "description": "One result per request, in request order",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "agent": {"type": "string"},
+                        "message_id": {
+                            "type": "string",
+                            "description": "Pass to get_result to fetch the reply later; absent for cache hits"
+                        },
+                        "success": {"type": "boolean"},
+                        "response": {"type": "string", "description": "Reply text when success is true"},
+                        "error": {"type": "string", "description": "Failure reason when success is false"},
+                        "cached": {"type": "boolean"},
+                        "usage": {"type": "object", "description": "Token usage reported in the agent's log"},
+                        "model": {"type": "string"},
+                        "reply": {"type": "object", "description": "Structured reply, only with detail 'full'"},
+                        "change": {"type": "object", "description": "Worktree change to apply, merge or discard"},
+                        "files": {"type": "object", "description": "Files the agent changed, with diffs"}
+                    },
+                    "required": ["agent", "success"]
+                }
+            }
+        },
+        "required": ["results"]
+    })
 }
 
+/// Run a tool; `chunks` receives partial replies of `ask_agents` calls
+/// that ask for streaming
 pub async fn execute_tool(
     name: &str,
     args: serde_json::Value,
     session_manager: &Arc<SessionManager>,
+    chunks: Option<&mpsc::UnboundedSender<ReplyChunk>>,
 ) -> Result<String, anyhow::Error> {
     match name {
         "ask_agents" => {
             let args: AskAgentsArgs = serde_json::from_value(args)?;
-            execute_ask_agents(args, session_manager).await
+            let chunks = args.stream.then_some(chunks).flatten();
+            execute_ask_agents(args, session_manager, chunks).await
+        }
+        "get_result" => {
+            let args: GetResultArgs = serde_json::from_value(args)?;
+            execute_get_result(args, session_manager)
+        }
+        "apply_change" | "merge_change" | "discard_change" => {
+            let args: ChangeArgs = serde_json::from_value(args)?;
+            execute_change_tool(name, args, session_manager).await
         }
         _ => Err(anyhow::anyhow!("Unknown tool: {}", name)),
     }
 }
 
 async fn execute_ask_agents(
-    args: AskAgentsArgs,
+    mut args: AskAgentsArgs,
     session_manager: &Arc<SessionManager>,
+    chunks: Option<&mpsc::UnboundedSender<ReplyChunk>>,
 ) -> Result<String, anyhow::Error> {
-    validate_args(&args)?;
+    args.prepare(session_manager.prompts())?;
+    let message_ids = new_message_ids(&args);
+    let response = ask_agents(args, message_ids, session_manager, chunks).await;
+    Ok(serde_json::to_string(&response)?)
+}
+
+/// One fresh message id per request of prepared `args`
+pub fn new_message_ids(args: &AskAgentsArgs) -> Vec<String> {
+    args.requests
+        .iter()
+        .map(|_| uuid::Uuid::new_v4().to_string())
+        .collect()
+}
 
+/// Ask the agents of prepared `args` in parallel, request `i` under
+/// `message_ids[i]`; failures are reported per agent
+pub async fn ask_agents(
+    args: AskAgentsArgs,
+    message_ids: Vec<String>,
+    session_manager: &Arc<SessionManager>,
+    chunks: Option<&mpsc::UnboundedSender<ReplyChunk>>,
+) -> AskAgentsResponse {
     let timeout_duration = Duration::from_secs(args.timeout);
+    let full_detail = args.detail == ReplyDetail::Full;
     let request_count = args.requests.len();
 
     // Pre-collect agent names for error fallback
@@ -148,48 +451,69 @@ async fn execute_ask_agents(
     for (idx, req) in args.requests.into_iter().enumerate() {
         let sm = session_manager.clone();
         let agent = req.agent.clone();
-        let message = req.message.clone();
+        let message_id = message_ids[idx].clone();
+        let chunks = chunks.cloned();
 
-        let handle = join_set.spawn(async move {
-            let result = AssertUnwindSafe(async {
-                // Pass timeout to ask_single_agent to ensure ReplyDetection uses it
-                // This prevents ReplyDetection from continuing beyond the MCP timeout
-                ask_single_agent(&agent, &message, Some(timeout_duration), &sm).await
-            })
-            .catch_unwind()
-            .await;
+        let handle = join_set.spawn(
+            async move {
+                let result = AssertUnwindSafe(async {
+                    // Pass timeout to ask_single_agent to ensure ReplyDetection uses it
+                    // This prevents ReplyDetection from continuing beyond the MCP timeout
+                    ask_single_agent(
+                        &req,
+                        &message_id,
+                        Some(timeout_duration),
+                        &sm,
+                        chunks.as_ref(),
+                    )
+                    .await
+                })
+                .catch_unwind()
+                .await;
 
-            let agent_result = match result {
-                Ok(Ok(response)) => AgentResult {
-                    agent: agent.clone(),
-                    success: true,
-                    response: Some(response),
-                    error: None,
-                },
-                Ok(Err(e)) => AgentResult {
-                    agent: agent.clone(),
-                    success: false,
-                    response: None,
-                    error: Some(e.to_string()),
-                },
-                Err(panic_err) => {
-                    let panic_msg = if let Some(s) = panic_err.downcast_ref::<&str>() {
-                        s.to_string()
-                    } else if let Some(s) = panic_err.downcast_ref::<String>() {
-                        s.clone()
-                    } else {
-                        "unknown panic".to_string()
-                    };
-                    AgentResult {
+                let agent_result = match result {
+                    Ok(Ok(reply)) => AgentResult {
                         agent: agent.clone(),
+                        // Cache hits never reach the agent, so they have no journal entry
+                        message_id: (!reply.cached).then(|| message_id.clone()),
+                        success: true,
+                        response: Some(reply.response),
+                        cached: reply.cached,
+                        usage: reply.usage,
+                        model: reply.model,
+                        reply: if full_detail { reply.reply } else { None },
+                        change: reply.change,
+                        files: reply.files,
+                        ..Default::default()
+                    },
+                    Ok(Err(e)) => AgentResult {
+                        agent: agent.clone(),
+                        message_id: Some(message_id.clone()),
                         success: false,
-                        response: None,
-                        error: Some(format!("task panicked: {}", panic_msg)),
+                        error: Some(e.to_string()),
+                        ..Default::default()
+                    },
+                    Err(panic_err) => {
+                        let panic_msg = if let Some(s) = panic_err.downcast_ref::<&str>() {
+                            s.to_string()
+                        } else if let Some(s) = panic_err.downcast_ref::<String>() {
+                            s.clone()
+                        } else {
+                            "unknown panic".to_string()
+                        };
+                        AgentResult {
+                            agent: agent.clone(),
+                            message_id: Some(message_id.clone()),
+                            success: false,
+                            error: Some(format!("task panicked: {}", panic_msg)),
+                            ..Default::default()
+                        }
                     }
-                }
-            };
-            (idx, agent_result)
-        });
+                };
+                (idx, agent_result)
+            }
+            .in_current_span(),
+        );
         task_id_to_idx.insert(handle.id(), idx);
     }
 
@@ -206,9 +530,10 @@ async fn execute_ask_agents(
                 if let Some(&idx) = task_id_to_idx.get(&task_id) {
                     results[idx] = Some(AgentResult {
                         agent: agent_names[idx].clone(),
+                        message_id: Some(message_ids[idx].clone()),
                         success: false,
-                        response: None,
                         error: Some(format!("task cancelled: {}", join_error)),
+                        ..Default::default()
                     });
                 } else {
                     tracing::error!("Unknown task ID in JoinError: {:?}", task_id);
@@ -226,35 +551,178 @@ async fn execute_ask_agents(
                 tracing::error!("Result slot {} was not filled", idx);
                 AgentResult {
                     agent: agent_names[idx].clone(),
+                    message_id: Some(message_ids[idx].clone()),
                     success: false,
-                    response: None,
                     error: Some("internal error: result not collected".to_string()),
+                    ..Default::default()
                 }
             })
         })
         .collect();
 
-    let response = AskAgentsResponse { results };
-    Ok(serde_json::to_string(&response)?)
+    AskAgentsResponse { results }
 }
 
 async fn ask_single_agent(
-    agent_name: &str,
-    message: &str,
+    req: &AgentRequest,
+    message_id: &str,
     timeout: Option<Duration>,
     session_manager: &Arc<SessionManager>,
-) -> Result<String, anyhow::Error> {
+    chunks: Option<&mpsc::UnboundedSender<ReplyChunk>>,
+) -> Result<SingleAgentReply, anyhow::Error> {
+    let agent_name = req.agent.as_str();
+    if req.cache == CacheMode::Only && session_manager.cache().is_none() {
+        anyhow::bail!("cache mode 'only' requested but the response cache is disabled");
+    }
+
     let session = session_manager
         .get(agent_name)
         .await
         .ok_or_else(|| anyhow::anyhow!("Agent not found: {}", agent_name))?;
 
+    let cache_key = match session_manager.cache() {
+        Some(_) => {
+            let attachments =
+                cache::attachments_hash(&session.working_dir, &req.attachments).await?;
+            let workspace = cache::workspace_state(&session.working_dir).await;
+            Some(cache::cache_key(
+                agent_name,
+                &req.message,
+                &attachments,
+                &workspace,
+            ))
+        }
+        None => None,
+    };
+
+    if let (Some(cache), Some(key)) = (session_manager.cache(), &cache_key) {
+        if req.cache != CacheMode::Bypass {
+            if let Some(entry) = cache.get(key) {
+                tracing::info!("[Cache] Hit for {}", agent_name);
+                return Ok(SingleAgentReply {
+                    response: entry.response,
+                    cached: true,
+                    usage: None,
+                    model: None,
+                    reply: None,
+                    change: None,
+                    files: None,
+                });
+            }
+            if req.cache == CacheMode::Only {
+                anyhow::bail!("no cached response for agent: {}", agent_name);
+            }
+        }
+    }
+
+    // Subscribe before asking so the first chunk is not missed
+    let forwarder = chunks.map(|chunks| {
+        let received = session.subscribe_reply_chunks();
+        let (done_tx, done_rx) = oneshot::channel();
+        let task = tokio::spawn(forward_chunks(
+            received,
+            chunks.clone(),
+            message_id.to_string(),
+            done_rx,
+            Arc::clone(session_manager.metrics()),
+        ));
+        (done_tx, task)
+    });
+
     let pty_manager = session_manager.pty_manager();
-    let response = session
-        .ask(message.to_string(), timeout, pty_manager)
-        .await?;
+    let reply = session
+        .ask_with_id(
+            message_id.to_string(),
+            req.message.clone(),
+            timeout,
+            pty_manager,
+        )
+        .await;
+    if let Some((done_tx, task)) = forwarder {
+        let _ = done_tx.send(());
+        let _ = task.await;
+    }
+    let reply = reply?;
+
+    if let (Some(cache), Some(key)) = (session_manager.cache(), cache_key) {
+        cache.insert(key, agent_name, &reply.content);
+    }
+
+    Ok(SingleAgentReply {
+        response: reply.content,
+        cached: false,
+        usage: reply.usage,
+        model: reply.model,
+        reply: reply.reply,
+        change: reply.change,
+        files: reply.files,
+    })
+}
+
+/// Pass on the chunks of `message_id` until `done` fires, then those
+/// already queued, which were sent before the reply was delivered
+async fn forward_chunks(
+    mut received: broadcast::Receiver<ReplyChunk>,
+    chunks: mpsc::UnboundedSender<ReplyChunk>,
+    message_id: String,
+    mut done: oneshot::Receiver<()>,
+    metrics: Arc<Metrics>,
+) {
+    loop {
+        tokio::select! {
+            biased;
+            chunk = received.recv() => match chunk {
+                Ok(chunk) if chunk.message_id == message_id => {
+                    if chunks.send(chunk).is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(missed)) => metrics.record_lag("reply_chunks", missed),
+                Err(RecvError::Closed) => return,
+            },
+            _ = &mut done => break,
+        }
+    }
+    while let Ok(chunk) = received.try_recv() {
+        if chunk.message_id == message_id && chunks.send(chunk).is_err() {
+            return;
+        }
+    }
+}
+
+fn execute_get_result(
+    args: GetResultArgs,
+    session_manager: &Arc<SessionManager>,
+) -> Result<String, anyhow::Error> {
+    let record = session_manager
+        .journal()
+        .get(&args.message_id)
+        .ok_or_else(|| anyhow::anyhow!("Unknown message_id: {}", args.message_id))?;
+    Ok(serde_json::to_string(&record)?)
+}
 
-    Ok(response)
+async fn execute_change_tool(
+    name: &str,
+    args: ChangeArgs,
+    session_manager: &Arc<SessionManager>,
+) -> Result<String, anyhow::Error> {
+    let worktrees = session_manager
+        .worktrees()
+        .ok_or_else(|| anyhow::anyhow!("worktree isolation is disabled"))?;
+    let change = match name {
+        "apply_change" => worktrees.apply(&args.change_id).await?,
+        "merge_change" => worktrees.merge(&args.change_id).await?,
+        _ => worktrees.discard(&args.change_id).await?,
+    };
+    // The diff was already returned with the ask_agents result
+    Ok(serde_json::to_string(&json!({
+        "id": change.id,
+        "agent": change.agent,
+        "status": change.status,
+        "commit": change.commit,
+        "files": change.files,
+    }))?)
 }
 
 #[cfg(test)]
@@ -284,6 +752,18 @@ mod tests {
 
         let args: AskAgentsArgs = serde_json::from_value(json).unwrap();
         assert_eq!(args.timeout, DEFAULT_TIMEOUT);
+        assert_eq!(args.detail, ReplyDetail::Summary);
+    }
+
+    #[test]
+    fn test_ask_agents_args_full_detail() {
+        let json = json!({
+            "requests": [{"agent": "codex", "message": "hello"}],
+            "detail": "full"
+        });
+
+        let args: AskAgentsArgs = serde_json::from_value(json).unwrap();
+        assert_eq!(args.detail, ReplyDetail::Full);
     }
 
     #[test]
@@ -291,6 +771,10 @@ mod tests {
         let args = AskAgentsArgs {
             requests: vec![],
             timeout: 600,
+            detail: ReplyDetail::Summary,
+            stream: false,
+            template: None,
+            args: HashMap::new(),
         };
         assert!(validate_args(&args).is_err());
     }
@@ -302,25 +786,34 @@ mod tests {
                 AgentRequest {
                     agent: "codex".to_string(),
                     message: "a".to_string(),
+                    ..Default::default()
                 },
                 AgentRequest {
                     agent: "gemini".to_string(),
                     message: "b".to_string(),
+                    ..Default::default()
                 },
                 AgentRequest {
                     agent: "opencode".to_string(),
                     message: "c".to_string(),
+                    ..Default::default()
                 },
                 AgentRequest {
                     agent: "claudecode".to_string(),
                     message: "d".to_string(),
+                    ..Default::default()
                 },
                 AgentRequest {
                     agent: "extra".to_string(),
                     message: "e".to_string(),
+                    ..Default::default()
                 },
             ],
             timeout: 600,
+            detail: ReplyDetail::Summary,
+            stream: false,
+            template: None,
+            args: HashMap::new(),
         };
         assert!(validate_args(&args).is_err());
     }
@@ -332,13 +825,19 @@ mod tests {
                 AgentRequest {
                     agent: "codex".to_string(),
                     message: "a".to_string(),
+                    ..Default::default()
                 },
                 AgentRequest {
                     agent: "codex".to_string(),
                     message: "b".to_string(),
+                    ..Default::default()
                 },
             ],
             timeout: 600,
+            detail: ReplyDetail::Summary,
+            stream: false,
+            template: None,
+            args: HashMap::new(),
         };
         let err = validate_args(&args).unwrap_err();
         assert!(err.to_string().contains("duplicate"));
@@ -350,8 +849,13 @@ mod tests {
             requests: vec![AgentRequest {
                 agent: "invalid_agent".to_string(),
                 message: "hello".to_string(),
+                ..Default::default()
             }],
             timeout: 600,
+            detail: ReplyDetail::Summary,
+            stream: false,
+            template: None,
+            args: HashMap::new(),
         };
         let err = validate_args(&args).unwrap_err();
         assert!(err.to_string().contains("invalid agent"));
@@ -363,8 +867,13 @@ mod tests {
             requests: vec![AgentRequest {
                 agent: "codex".to_string(),
                 message: "   ".to_string(),
+                ..Default::default()
             }],
             timeout: 600,
+            detail: ReplyDetail::Summary,
+            stream: false,
+            template: None,
+            args: HashMap::new(),
         };
         let err = validate_args(&args).unwrap_err();
         assert!(err.to_string().contains("empty"));
@@ -376,8 +885,13 @@ mod tests {
             requests: vec![AgentRequest {
                 agent: "codex".to_string(),
                 message: "hello".to_string(),
+                ..Default::default()
             }],
             timeout: 0,
+            detail: ReplyDetail::Summary,
+            stream: false,
+            template: None,
+            args: HashMap::new(),
         };
         assert!(validate_args(&args).is_err());
 
@@ -385,12 +899,43 @@ mod tests {
             requests: vec![AgentRequest {
                 agent: "codex".to_string(),
                 message: "hello".to_string(),
+                ..Default::default()
             }],
             timeout: MAX_TIMEOUT + 1,
+            detail: ReplyDetail::Summary,
+            stream: false,
+            template: None,
+            args: HashMap::new(),
         };
         assert!(validate_args(&args2).is_err());
     }
 
+    #[test]
+    fn test_expand_template() {
+        let prompts = PromptLibrary::new(None);
+        let mut args: AskAgentsArgs = serde_json::from_value(json!({
+            "template": "second-opinion",
+            "args": {"diff": "--- a\n+++ b"}
+        }))
+        .unwrap();
+        expand_template(&mut args, &prompts).unwrap();
+        let agents: Vec<&str> = args.requests.iter().map(|r| r.agent.as_str()).collect();
+        assert_eq!(agents, ["codex", "gemini"]);
+        assert!(args.requests[0].message.ends_with("--- a\n+++ b"));
+        assert!(validate_args(&args).is_ok());
+
+        for invalid in [
+            json!({"template": "second-opinion"}),
+            json!({"template": "no-such-prompt"}),
+            json!({"args": {"diff": "x"}, "requests": [{"agent": "codex", "message": "hi"}]}),
+            json!({"template": "second-opinion", "args": {"diff": "x"},
+                   "requests": [{"agent": "codex", "message": "hi"}]}),
+        ] {
+            let mut args: AskAgentsArgs = serde_json::from_value(invalid.clone()).unwrap();
+            assert!(expand_template(&mut args, &prompts).is_err(), "{}", invalid);
+        }
+    }
+
     #[test]
     fn test_validate_args_valid() {
         let args = AskAgentsArgs {
@@ -398,13 +943,19 @@ mod tests {
                 AgentRequest {
                     agent: "codex".to_string(),
                     message: "hello".to_string(),
+                    ..Default::default()
                 },
                 AgentRequest {
                     agent: "gemini".to_string(),
                     message: "world".to_string(),
+                    ..Default::default()
                 },
             ],
             timeout: 600,
+            detail: ReplyDetail::Summary,
+            stream: false,
+            template: None,
+            args: HashMap::new(),
         };
         assert!(validate_args(&args).is_ok());
     }
@@ -415,7 +966,7 @@ mod tests {
             agent: "codex".to_string(),
             success: true,
             response: Some("hello".to_string()),
-            error: None,
+            ..Default::default()
         };
         let json = serde_json::to_value(&result).unwrap();
         assert_eq!(json["agent"], "codex");
@@ -429,8 +980,8 @@ mod tests {
         let result = AgentResult {
             agent: "gemini".to_string(),
             success: false,
-            response: None,
             error: Some("timeout".to_string()),
+            ..Default::default()
         };
         let json = serde_json::to_value(&result).unwrap();
         assert_eq!(json["success"], false);
@@ -446,13 +997,13 @@ mod tests {
                     agent: "codex".to_string(),
                     success: true,
                     response: Some("ok".to_string()),
-                    error: None,
+                    ..Default::default()
                 },
                 AgentResult {
                     agent: "gemini".to_string(),
                     success: false,
-                    response: None,
                     error: Some("error".to_string()),
+                    ..Default::default()
                 },
             ],
         };
@@ -460,4 +1011,150 @@ mod tests {
         assert!(json.contains("codex"));
         assert!(json.contains("gemini"));
     }
+
+    #[test]
+    fn test_tool_definitions_include_get_result() {
+        let tools = get_tool_definitions(false);
+        assert!(tools.iter().any(|t| t.name == "ask_agents"));
+        assert!(tools.iter().any(|t| t.name == "get_result"));
+    }
+
+    #[test]
+    fn test_tool_annotations() {
+        let tools = get_tool_definitions(true);
+        assert!(tools.iter().all(|t| t.annotations.is_some()));
+        let get_result = tools.iter().find(|t| t.name == "get_result").unwrap();
+        assert!(get_result.annotations.as_ref().unwrap().read_only_hint);
+        let discard = tools.iter().find(|t| t.name == "discard_change").unwrap();
+        assert!(discard.annotations.as_ref().unwrap().destructive_hint);
+    }
+
+    #[test]
+    fn test_ask_agents_output_schema_covers_result_fields() {
+        let schema = ask_agents_output_schema();
+        let properties = schema["properties"]["results"]["items"]["properties"]
+            .as_object()
+            .unwrap();
+        let result = AgentResult {
+            agent: "codex".to_string(),
+            message_id: Some("id".to_string()),
+            success: true,
+            response: Some("hello".to_string()),
+            error: Some("none".to_string()),
+            cached: true,
+            usage: Some(TokenUsage::default()),
+            model: Some("gpt".to_string()),
+            ..Default::default()
+        };
+        let json = serde_json::to_value(&result).unwrap();
+        for field in json.as_object().unwrap().keys() {
+            assert!(
+                properties.contains_key(field),
+                "{} missing from schema",
+                field
+            );
+        }
+        for field in ["reply", "change", "files"] {
+            assert!(properties.contains_key(field));
+        }
+    }
+
+    #[test]
+    fn test_agent_request_cache_fields() {
+        let req: AgentRequest = serde_json::from_value(json!({
+            "agent": "codex",
+            "message": "hi",
+            "attachments": ["src/lib.rs"],
+            "cache": "bypass"
+        }))
+        .unwrap();
+        assert_eq!(req.cache, CacheMode::Bypass);
+        assert_eq!(req.attachments, vec!["src/lib.rs".to_string()]);
+
+        let result = AgentResult {
+            agent: "codex".to_string(),
+            success: true,
+            cached: true,
+            ..Default::default()
+        };
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["cached"], true);
+        assert!(json.get("usage").is_none());
+    }
+
+    #[test]
+    fn test_agent_result_usage_serialization() {
+        let result = AgentResult {
+            agent: "codex".to_string(),
+            success: true,
+            usage: Some(TokenUsage {
+                input_tokens: 3,
+                output_tokens: 2,
+                total_tokens: 5,
+                ..Default::default()
+            }),
+            model: Some("gpt-5-codex".to_string()),
+            ..Default::default()
+        };
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["usage"]["total_tokens"], 5);
+        assert_eq!(json["model"], "gpt-5-codex");
+    }
+
+    #[tokio::test]
+    async fn test_change_tools_require_isolation() {
+        let pty_manager = Arc::new(crate::pty::PtyManager::new(1024));
+        let sm = Arc::new(SessionManager::new(pty_manager));
+        let err = execute_tool("apply_change", json!({"change_id": "m1"}), &sm, None)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("isolation is disabled"));
+        assert!(!get_tool_definitions(false)
+            .iter()
+            .any(|t| t.name.ends_with("_change")));
+        assert!(get_tool_definitions(true)
+            .iter()
+            .any(|t| t.name == "discard_change"));
+    }
+
+    #[tokio::test]
+    async fn test_cache_only_without_cache_fails() {
+        let pty_manager = Arc::new(crate::pty::PtyManager::new(1024));
+        let sm = Arc::new(SessionManager::new(pty_manager));
+        let out = execute_tool(
+            "ask_agents",
+            json!({"requests": [{"agent": "codex", "message": "hi", "cache": "only"}]}),
+            &sm,
+            None,
+        )
+        .await
+        .unwrap();
+        let value: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(value["results"][0]["success"], false);
+        assert!(value["results"][0]["error"]
+            .as_str()
+            .unwrap()
+            .contains("cache is disabled"));
+    }
+
+    #[tokio::test]
+    async fn test_get_result_reads_journal() {
+        let pty_manager = Arc::new(crate::pty::PtyManager::new(1024));
+        let sm = Arc::new(SessionManager::new(pty_manager));
+        sm.journal().record_queued("msg-1", "codex", "hi");
+        sm.journal().record_completed("msg-1", "hello");
+
+        let out = execute_tool("get_result", json!({"message_id": "msg-1"}), &sm, None)
+            .await
+            .unwrap();
+        let value: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(value["state"], "completed");
+        assert_eq!(value["response"], "hello");
+
+        assert!(
+            execute_tool("get_result", json!({"message_id": "nope"}), &sm, None)
+                .await
+                .is_err()
+        );
+    }
 }
@@ -0,0 +1,463 @@
+//! Prometheus metrics
+//!
+//! Sessions record into a shared [`Metrics`] as they go; values that are
+//! cheap to read at any time (queue depth, restarts, PTY traffic) are passed
+//! in by the caller when rendering instead. The output is the Prometheus text
+//! exposition format, served at `/metrics` when enabled.
+
+use parking_lot::Mutex;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Upper bounds in seconds of the request latency buckets; agent replies take
+/// from seconds to many minutes
+const LATENCY_BUCKETS: &[f64] = &[1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0];
+
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    /// Per bucket, not cumulative; the last entry counts values above all bounds
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if self.buckets.is_empty() {
+            self.buckets = vec![0; LATENCY_BUCKETS.len() + 1];
+        }
+        let index = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[index] += 1;
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    /// (agent, outcome)
+    requests: BTreeMap<(String, &'static str), Histogram>,
+    /// (agent, state) -> seconds spent in finished stays
+    state_seconds: BTreeMap<(String, String), f64>,
+    /// agent -> (state, entered at)
+    current_state: HashMap<String, (String, Instant)>,
+    /// (agent, trigger, completion)
+    reply_detections: BTreeMap<(String, &'static str, &'static str), u64>,
+    /// channel -> (lag events, messages missed)
+    lagged: BTreeMap<&'static str, (u64, u64)>,
+    websocket_subscribers: BTreeMap<String, u64>,
+}
+
+/// Values read from the sessions when rendering
+#[derive(Debug, Clone, Default)]
+pub struct AgentGauges {
+    pub agent: String,
+    pub queue_depth: usize,
+    pub restarts: u32,
+    /// Bytes written to the agent's terminal
+    pub pty_bytes_in: u64,
+    /// Bytes read from the agent's terminal
+    pub pty_bytes_out: u64,
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    inner: Mutex<Inner>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A finished request; `outcome` is `success`, `timeout`, `crash`,
+    /// `cancelled` or `error`
+    pub fn record_request(&self, agent: &str, outcome: &'static str, latency: Duration) {
+        self.inner
+            .lock()
+            .requests
+            .entry((agent.to_string(), outcome))
+            .or_default()
+            .observe(latency.as_secs_f64());
+    }
+
+    /// The agent left `from` for `to`. Time in `from` is only counted when
+    /// its start was seen
+    pub fn record_state(&self, agent: &str, from: &str, to: &str) {
+        let now = Instant::now();
+        let mut inner = self.inner.lock();
+        if let Some((state, since)) = inner
+            .current_state
+            .insert(agent.to_string(), (to.to_string(), now))
+        {
+            if state == from {
+                *inner
+                    .state_seconds
+                    .entry((agent.to_string(), state))
+                    .or_default() += now.duration_since(since).as_secs_f64();
+            }
+        }
+    }
+
+    /// How a reply was found (`trigger`: `initial`, `event`, `poll` or
+    /// `pty`) and how it was judged complete (`completion`: `done_marker`,
+    /// `stability` or `deadline`)
+    pub fn record_reply_detection(
+        &self,
+        agent: &str,
+        trigger: &'static str,
+        completion: &'static str,
+    ) {
+        *self
+            .inner
+            .lock()
+            .reply_detections
+            .entry((agent.to_string(), trigger, completion))
+            .or_default() += 1;
+    }
+
+    /// A broadcast receiver on `channel` fell behind and missed `missed` messages
+    pub fn record_lag(&self, channel: &'static str, missed: u64) {
+        let mut inner = self.inner.lock();
+        let entry = inner.lagged.entry(channel).or_default();
+        entry.0 += 1;
+        entry.1 += missed;
+    }
+
+    /// Count a WebSocket terminal subscriber of `agent` until the guard is dropped
+    pub fn track_websocket(self: &Arc<Self>, agent: &str) -> WebSocketGuard {
+        *self
+            .inner
+            .lock()
+            .websocket_subscribers
+            .entry(agent.to_string())
+            .or_default() += 1;
+        WebSocketGuard {
+            metrics: Arc::clone(self),
+            agent: agent.to_string(),
+        }
+    }
+
+    /// All metrics in the Prometheus text format
+    pub fn render(&self, agents: &[AgentGauges]) -> String {
+        let now = Instant::now();
+        let inner = self.inner.lock();
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "ccgonext_requests_total",
+            "counter",
+            "Finished requests by agent and outcome",
+        );
+        for ((agent, outcome), histogram) in &inner.requests {
+            let _ = writeln!(
+                out,
+                "ccgonext_requests_total{{agent=\"{}\",outcome=\"{}\"}} {}",
+                escape(agent),
+                outcome,
+                histogram.count
+            );
+        }
+
+        header(
+            &mut out,
+            "ccgonext_request_duration_seconds",
+            "histogram",
+            "Time from queueing a request to its result",
+        );
+        for ((agent, outcome), histogram) in &inner.requests {
+            let labels = format!("agent=\"{}\",outcome=\"{}\"", escape(agent), outcome);
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(&histogram.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "ccgonext_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, bound, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "ccgonext_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "ccgonext_request_duration_seconds_sum{{{}}} {}",
+                labels, histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "ccgonext_request_duration_seconds_count{{{}}} {}",
+                labels, histogram.count
+            );
+        }
+
+        header(
+            &mut out,
+            "ccgonext_agent_state_seconds_total",
+            "counter",
+            "Time agents spent in each state",
+        );
+        let mut state_seconds = inner.state_seconds.clone();
+        for (agent, (state, since)) in &inner.current_state {
+            *state_seconds
+                .entry((agent.clone(), state.clone()))
+                .or_default() += now.duration_since(*since).as_secs_f64();
+        }
+        for ((agent, state), seconds) in &state_seconds {
+            let _ = writeln!(
+                out,
+                "ccgonext_agent_state_seconds_total{{agent=\"{}\",state=\"{}\"}} {:.3}",
+                escape(agent),
+                state,
+                seconds
+            );
+        }
+
+        header(
+            &mut out,
+            "ccgonext_queue_depth",
+            "gauge",
+            "Requests waiting for their agent",
+        );
+        for gauges in agents {
+            let _ = writeln!(
+                out,
+                "ccgonext_queue_depth{{agent=\"{}\"}} {}",
+                escape(&gauges.agent),
+                gauges.queue_depth
+            );
+        }
+
+        header(
+            &mut out,
+            "ccgonext_restarts_total",
+            "counter",
+            "Agent restarts",
+        );
+        for gauges in agents {
+            let _ = writeln!(
+                out,
+                "ccgonext_restarts_total{{agent=\"{}\"}} {}",
+                escape(&gauges.agent),
+                gauges.restarts
+            );
+        }
+
+        header(
+            &mut out,
+            "ccgonext_reply_detections_total",
+            "counter",
+            "Detected replies by how they were found and judged complete",
+        );
+        for ((agent, trigger, completion), count) in &inner.reply_detections {
+            let _ = writeln!(
+                out,
+                "ccgonext_reply_detections_total{{agent=\"{}\",trigger=\"{}\",completion=\"{}\"}} {}",
+                escape(agent),
+                trigger,
+                completion,
+                count
+            );
+        }
+
+        header(
+            &mut out,
+            "ccgonext_pty_bytes_total",
+            "counter",
+            "Bytes written to (in) and read from (out) agent terminals",
+        );
+        for gauges in agents {
+            for (direction, bytes) in [("in", gauges.pty_bytes_in), ("out", gauges.pty_bytes_out)] {
+                let _ = writeln!(
+                    out,
+                    "ccgonext_pty_bytes_total{{agent=\"{}\",direction=\"{}\"}} {}",
+                    escape(&gauges.agent),
+                    direction,
+                    bytes
+                );
+            }
+        }
+
+        header(
+            &mut out,
+            "ccgonext_websocket_subscribers",
+            "gauge",
+            "Open WebSocket terminals",
+        );
+        for (agent, count) in &inner.websocket_subscribers {
+            let _ = writeln!(
+                out,
+                "ccgonext_websocket_subscribers{{agent=\"{}\"}} {}",
+                escape(agent),
+                count
+            );
+        }
+
+        header(
+            &mut out,
+            "ccgonext_broadcast_lag_events_total",
+            "counter",
+            "Times a broadcast receiver fell behind",
+        );
+        for (channel, (events, _)) in &inner.lagged {
+            let _ = writeln!(
+                out,
+                "ccgonext_broadcast_lag_events_total{{channel=\"{}\"}} {}",
+                channel, events
+            );
+        }
+        header(
+            &mut out,
+            "ccgonext_broadcast_lagged_messages_total",
+            "counter",
+            "Messages missed by lagging broadcast receivers",
+        );
+        for (channel, (_, missed)) in &inner.lagged {
+            let _ = writeln!(
+                out,
+                "ccgonext_broadcast_lagged_messages_total{{channel=\"{}\"}} {}",
+                channel, missed
+            );
+        }
+
+        out
+    }
+}
+
+pub struct WebSocketGuard {
+    metrics: Arc<Metrics>,
+    agent: String,
+}
+
+impl Drop for WebSocketGuard {
+    fn drop(&mut self) {
+        if let Some(count) = self
+            .metrics
+            .inner
+            .lock()
+            .websocket_subscribers
+            .get_mut(&self.agent)
+        {
+            *count = count.saturating_sub(1);
+        }
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Escape a label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<&str> {
+        text.lines().filter(|l| !l.starts_with('#')).collect()
+    }
+
+    #[test]
+    fn test_request_histogram() {
+        let metrics = Metrics::new();
+        metrics.record_request("codex", "success", Duration::from_secs(3));
+        metrics.record_request("codex", "success", Duration::from_millis(500));
+        metrics.record_request("codex", "timeout", Duration::from_secs(4000));
+
+        let text = metrics.render(&[]);
+        let lines = lines(&text);
+        assert!(lines.contains(&"ccgonext_requests_total{agent=\"codex\",outcome=\"success\"} 2"));
+        assert!(lines.contains(&"ccgonext_requests_total{agent=\"codex\",outcome=\"timeout\"} 1"));
+        assert!(lines.contains(
+            &"ccgonext_request_duration_seconds_bucket{agent=\"codex\",outcome=\"success\",le=\"1\"} 1"
+        ));
+        assert!(lines.contains(
+            &"ccgonext_request_duration_seconds_bucket{agent=\"codex\",outcome=\"success\",le=\"5\"} 2"
+        ));
+        assert!(lines.contains(
+            &"ccgonext_request_duration_seconds_bucket{agent=\"codex\",outcome=\"timeout\",le=\"1800\"} 0"
+        ));
+        assert!(lines.contains(
+            &"ccgonext_request_duration_seconds_bucket{agent=\"codex\",outcome=\"timeout\",le=\"+Inf\"} 1"
+        ));
+        assert!(lines.contains(
+            &"ccgonext_request_duration_seconds_sum{agent=\"codex\",outcome=\"success\"} 3.5"
+        ));
+    }
+
+    #[test]
+    fn test_state_time() {
+        let metrics = Metrics::new();
+        metrics.record_state("codex", "STOPPED", "STARTING");
+        std::thread::sleep(Duration::from_millis(20));
+        metrics.record_state("codex", "STARTING", "IDLE");
+
+        let text = metrics.render(&[]);
+        let starting = lines(&text)
+            .into_iter()
+            .find(|l| l.contains("state=\"STARTING\""))
+            .unwrap();
+        let seconds: f64 = starting.rsplit(' ').next().unwrap().parse().unwrap();
+        assert!(seconds >= 0.02, "{}", starting);
+        // Entered before the first transition seen, so not counted
+        assert!(!text.contains("state=\"STOPPED\""));
+        // The current state counts up to now
+        assert!(text.contains("ccgonext_agent_state_seconds_total{agent=\"codex\",state=\"IDLE\"}"));
+    }
+
+    #[test]
+    fn test_gauges_and_counters() {
+        let metrics = Arc::new(Metrics::new());
+        metrics.record_reply_detection("gemini", "event", "stability");
+        metrics.record_lag("pty_output", 7);
+        metrics.record_lag("pty_output", 3);
+        let first = metrics.track_websocket("codex");
+        let second = metrics.track_websocket("codex");
+        drop(first);
+
+        let text = metrics.render(&[AgentGauges {
+            agent: "codex".to_string(),
+            queue_depth: 2,
+            restarts: 1,
+            pty_bytes_in: 10,
+            pty_bytes_out: 2048,
+        }]);
+        let lines = lines(&text);
+        for expected in [
+            "ccgonext_queue_depth{agent=\"codex\"} 2",
+            "ccgonext_restarts_total{agent=\"codex\"} 1",
+            "ccgonext_pty_bytes_total{agent=\"codex\",direction=\"in\"} 10",
+            "ccgonext_pty_bytes_total{agent=\"codex\",direction=\"out\"} 2048",
+            "ccgonext_reply_detections_total{agent=\"gemini\",trigger=\"event\",completion=\"stability\"} 1",
+            "ccgonext_broadcast_lag_events_total{channel=\"pty_output\"} 2",
+            "ccgonext_broadcast_lagged_messages_total{channel=\"pty_output\"} 10",
+            "ccgonext_websocket_subscribers{agent=\"codex\"} 1",
+        ] {
+            assert!(lines.contains(&expected), "missing {}", expected);
+        }
+        drop(second);
+        assert!(metrics
+            .render(&[])
+            .contains("ccgonext_websocket_subscribers{agent=\"codex\"} 0"));
+    }
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
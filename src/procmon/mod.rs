@@ -0,0 +1,402 @@
+//! Process trees and resource sampling for agent processes
+//!
+//! portable-pty starts every agent as a session leader, so the agent's process
+//! group normally covers everything it spawns. Helpers that start their own
+//! session (some MCP and language servers do) are still found through their
+//! parent links in /proc. The agent and its members are remembered by pid and
+//! start time, and the agent's pid is forgotten once it has been reaped, so a
+//! recycled pid is never signalled.
+
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// One line of /proc/<pid>/stat, reduced to what we use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ProcStat {
+    pid: u32,
+    state: char,
+    ppid: u32,
+    pgrp: u32,
+    session: u32,
+    /// utime + stime in clock ticks
+    cpu_ticks: u64,
+    start_time: u64,
+    rss_pages: u64,
+}
+
+impl ProcStat {
+    /// Parse `/proc/<pid>/stat`; the command name may contain spaces and parens
+    fn parse(line: &str) -> Option<Self> {
+        let (head, rest) = line.rsplit_once(')')?;
+        let pid = head.split_whitespace().next()?.parse().ok()?;
+        // Fields after the command name, starting with field 3 (state)
+        let fields: Vec<&str> = rest.split_whitespace().collect();
+        let field = |n: usize| -> Option<u64> { fields.get(n - 3)?.parse().ok() };
+        Some(Self {
+            pid,
+            state: fields.first()?.chars().next()?,
+            ppid: field(4)? as u32,
+            pgrp: field(5)? as u32,
+            session: field(6)? as u32,
+            cpu_ticks: field(14)? + field(15)?,
+            start_time: field(22)?,
+            rss_pages: field(24)?,
+        })
+    }
+}
+
+/// A tree's root process: its pid and, where /proc exists, its start time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessRoot {
+    pub pid: u32,
+    start_time: Option<u64>,
+}
+
+impl ProcessRoot {
+    /// Identify the running process `pid`; None when /proc has no such process
+    pub fn of(pid: u32) -> Option<Self> {
+        #[cfg(target_os = "linux")]
+        {
+            let line = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+            let stat = ProcStat::parse(&line).filter(|stat| stat.pid == pid)?;
+            Some(Self {
+                pid,
+                start_time: Some(stat.start_time),
+            })
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            Some(Self {
+                pid,
+                start_time: None,
+            })
+        }
+    }
+
+    /// Whether `table` still holds this process rather than a recycled pid.
+    /// Without a start time only the caller's bookkeeping vouches for the pid.
+    fn is_in(&self, table: &ProcessTable) -> bool {
+        match self.start_time {
+            Some(start_time) => table
+                .entries
+                .iter()
+                .any(|p| p.pid == self.pid && p.start_time == start_time),
+            None => true,
+        }
+    }
+}
+
+/// All processes visible in /proc (empty on other platforms)
+#[derive(Debug, Clone, Default)]
+pub struct ProcessTable {
+    entries: Vec<ProcStat>,
+}
+
+impl ProcessTable {
+    pub fn read() -> Self {
+        #[cfg(target_os = "linux")]
+        {
+            let entries = std::fs::read_dir("/proc")
+                .into_iter()
+                .flatten()
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_name().to_string_lossy().parse::<u32>().is_ok())
+                .filter_map(|entry| std::fs::read_to_string(entry.path().join("stat")).ok())
+                .filter_map(|line| ProcStat::parse(&line))
+                .collect();
+            Self { entries }
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            Self::default()
+        }
+    }
+
+    /// `root`, its process group and session, and all their descendants;
+    /// zombies are left out. Empty when the root's pid has been recycled.
+    pub fn tree(&self, root: ProcessRoot) -> ProcessTree {
+        if !root.is_in(self) {
+            return ProcessTree {
+                root,
+                members: Vec::new(),
+            };
+        }
+        let pid = root.pid;
+        let mut members: Vec<ProcStat> = self
+            .entries
+            .iter()
+            .filter(|p| p.state != 'Z')
+            .filter(|p| p.pid == pid || p.pgrp == pid || p.session == pid)
+            .copied()
+            .collect();
+        let mut i = 0;
+        while i < members.len() {
+            let parent = members[i].pid;
+            for child in self
+                .entries
+                .iter()
+                .filter(|p| p.ppid == parent && p.state != 'Z')
+            {
+                if !members.iter().any(|m| m.pid == child.pid) {
+                    members.push(*child);
+                }
+            }
+            i += 1;
+        }
+        ProcessTree { root, members }
+    }
+}
+
+/// Snapshot of an agent's process tree
+#[derive(Debug, Clone)]
+pub struct ProcessTree {
+    root: ProcessRoot,
+    members: Vec<ProcStat>,
+}
+
+impl ProcessTree {
+    pub fn collect(root: ProcessRoot) -> Self {
+        ProcessTable::read().tree(root)
+    }
+
+    pub fn pids(&self) -> Vec<u32> {
+        self.members.iter().map(|m| m.pid).collect()
+    }
+
+    fn cpu_ticks(&self) -> u64 {
+        self.members.iter().map(|m| m.cpu_ticks).sum()
+    }
+
+    fn rss_bytes(&self) -> u64 {
+        self.members.iter().map(|m| m.rss_pages).sum::<u64>() * page_size()
+    }
+
+    /// Members that are still running (same pid and start time, not zombies)
+    fn alive(&self) -> Vec<u32> {
+        let table = ProcessTable::read();
+        self.members
+            .iter()
+            .filter(|m| {
+                table
+                    .entries
+                    .iter()
+                    .any(|p| p.pid == m.pid && p.start_time == m.start_time && p.state != 'Z')
+            })
+            .map(|m| m.pid)
+            .collect()
+    }
+
+    /// Send `signal` to the root's process group, while the root is still
+    /// the process the tree was collected from, and every member still running
+    #[cfg(unix)]
+    pub fn signal(&self, signal: i32) {
+        // SAFETY: kill/killpg only take plain integers; failures (already
+        // exited, not permitted) are expected and ignored
+        if self.root.is_in(&ProcessTable::read()) {
+            unsafe {
+                libc::killpg(self.root.pid as libc::pid_t, signal);
+            }
+        }
+        for pid in self.alive() {
+            unsafe {
+                libc::kill(pid as libc::pid_t, signal);
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn signal(&self, _signal: i32) {}
+
+    /// Kill the whole tree immediately
+    pub fn kill(&self) {
+        #[cfg(unix)]
+        self.signal(libc::SIGKILL);
+    }
+
+    /// SIGTERM the tree, give it `grace` to exit, then SIGKILL what is left
+    pub async fn terminate(&self, grace: Duration) {
+        #[cfg(unix)]
+        {
+            self.signal(libc::SIGTERM);
+            let deadline = Instant::now() + grace;
+            while Instant::now() < deadline {
+                if self.alive().is_empty() {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            self.kill();
+        }
+        #[cfg(not(unix))]
+        let _ = grace;
+    }
+}
+
+/// Resource usage of an agent's process tree
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ProcessStats {
+    pub pid: u32,
+    pub processes: usize,
+    /// CPU used since the previous sample; 100 is one full core
+    pub cpu_percent: f64,
+    pub rss_bytes: u64,
+}
+
+impl ProcessStats {
+    pub fn rss_mb(&self) -> u64 {
+        self.rss_bytes / (1024 * 1024)
+    }
+}
+
+/// Turns successive tree snapshots into CPU percentages
+#[derive(Debug, Default)]
+pub struct ResourceSampler {
+    last: Option<(u32, Instant, u64)>,
+}
+
+impl ResourceSampler {
+    /// Stats for `tree`, or None when nothing of it is visible
+    pub fn sample(&mut self, tree: &ProcessTree) -> Option<ProcessStats> {
+        if tree.members.is_empty() {
+            self.last = None;
+            return None;
+        }
+        let now = Instant::now();
+        let ticks = tree.cpu_ticks();
+        let cpu_percent = match self.last {
+            Some((root, at, previous)) if root == tree.root.pid && ticks >= previous => {
+                let elapsed = now.duration_since(at).as_secs_f64();
+                if elapsed > 0.0 {
+                    (ticks - previous) as f64 / clock_ticks_per_sec() / elapsed * 100.0
+                } else {
+                    0.0
+                }
+            }
+            _ => 0.0,
+        };
+        self.last = Some((tree.root.pid, now, ticks));
+        Some(ProcessStats {
+            pid: tree.root.pid,
+            processes: tree.members.len(),
+            cpu_percent,
+            rss_bytes: tree.rss_bytes(),
+        })
+    }
+}
+
+fn page_size() -> u64 {
+    #[cfg(unix)]
+    {
+        // SAFETY: sysconf has no preconditions
+        let size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+        if size > 0 {
+            return size as u64;
+        }
+    }
+    4096
+}
+
+fn clock_ticks_per_sec() -> f64 {
+    #[cfg(unix)]
+    {
+        // SAFETY: sysconf has no preconditions
+        let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+        if ticks > 0 {
+            return ticks as f64;
+        }
+    }
+    100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stat(pid: u32, ppid: u32, pgrp: u32, session: u32) -> ProcStat {
+        ProcStat {
+            pid,
+            state: 'S',
+            ppid,
+            pgrp,
+            session,
+            cpu_ticks: 10,
+            start_time: pid as u64,
+            rss_pages: 1,
+        }
+    }
+
+    #[test]
+    fn test_parse_stat_line() {
+        let line = "4242 (node (mcp) x) S 4200 4242 4242 34816 4242 4194304 1 0 0 0 \
+                    120 30 0 0 20 0 11 0 987654 1234567 2500 18446744073709551615";
+        let stat = ProcStat::parse(line).unwrap();
+        assert_eq!(stat.pid, 4242);
+        assert_eq!(stat.state, 'S');
+        assert_eq!(stat.ppid, 4200);
+        assert_eq!(stat.pgrp, 4242);
+        assert_eq!(stat.cpu_ticks, 150);
+        assert_eq!(stat.start_time, 987654);
+        assert_eq!(stat.rss_pages, 2500);
+    }
+
+    #[test]
+    fn test_tree_follows_group_and_parents() {
+        let table = ProcessTable {
+            entries: vec![
+                stat(1, 0, 1, 1),
+                stat(100, 1, 100, 100), // agent
+                stat(101, 100, 100, 100),
+                stat(102, 101, 102, 102), // helper in its own session
+                stat(103, 102, 102, 102),
+                stat(200, 1, 200, 200), // unrelated
+            ],
+        };
+        let root = |pid: u32| ProcessRoot {
+            pid,
+            start_time: Some(pid as u64),
+        };
+        let mut pids = table.tree(root(100)).pids();
+        pids.sort();
+        assert_eq!(pids, vec![100, 101, 102, 103]);
+
+        let mut sampler = ResourceSampler::default();
+        let stats = sampler.sample(&table.tree(root(100))).unwrap();
+        assert_eq!(stats.processes, 4);
+        assert_eq!(stats.rss_bytes, 4 * page_size());
+        assert!(sampler.sample(&table.tree(root(999))).is_none());
+
+        // The same pid started later is another process
+        let recycled = ProcessRoot {
+            pid: 100,
+            start_time: Some(1),
+        };
+        assert!(table.tree(recycled).pids().is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_terminate_kills_detached_children() {
+        use std::os::unix::process::CommandExt;
+
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", "sleep 30 & setsid sleep 30 & wait"])
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let root = ProcessRoot::of(child.id()).unwrap();
+
+        let mut tree = ProcessTree::collect(root);
+        for _ in 0..50 {
+            if tree.pids().len() >= 3 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            tree = ProcessTree::collect(root);
+        }
+        assert!(tree.pids().len() >= 3, "tree: {:?}", tree.pids());
+
+        tree.terminate(Duration::from_secs(2)).await;
+        let _ = child.wait();
+        assert!(tree.alive().is_empty());
+    }
+}
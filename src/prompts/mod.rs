@@ -0,0 +1,357 @@
+//! Prompt library
+//!
+//! Templated prompts for common multi-agent workflows, served as MCP prompts
+//! and usable from `ask_agents` by name. A few are built in; more are read
+//! from `*.json` files in the prompts directory on every lookup, so edits
+//! apply without a restart. A file with the same name as a built-in replaces
+//! it. Each file looks like:
+//!
+//! ```json
+//! {
+//!   "description": "Generate tests for a file",
+//!   "agents": ["codex"],
+//!   "arguments": [{"name": "path", "description": "File to test", "required": true}],
+//!   "template": "Write unit tests for {path}."
+//! }
+//! ```
+//!
+//! `{name}` is replaced by the argument's value (empty for a missing optional
+//! argument); other braces are left alone.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PromptArgument {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub required: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PromptTemplate {
+    /// File stem, or the built-in's name
+    #[serde(skip)]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Agents the prompt is sent to
+    pub agents: Vec<String>,
+    #[serde(default)]
+    pub arguments: Vec<PromptArgument>,
+    pub template: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PromptError {
+    #[error("Unknown prompt: {0}")]
+    NotFound(String),
+    #[error("Prompt {prompt} requires argument: {argument}")]
+    MissingArgument { prompt: String, argument: String },
+    #[error("Prompt {prompt} has no argument: {argument}")]
+    UnknownArgument { prompt: String, argument: String },
+}
+
+impl PromptTemplate {
+    /// Fill in the template; every required argument must be given and
+    /// every given argument must be declared
+    pub fn render(&self, args: &HashMap<String, String>) -> Result<String, PromptError> {
+        for name in args.keys() {
+            if !self.arguments.iter().any(|a| &a.name == name) {
+                return Err(PromptError::UnknownArgument {
+                    prompt: self.name.clone(),
+                    argument: name.clone(),
+                });
+            }
+        }
+
+        if let Some(missing) = self
+            .arguments
+            .iter()
+            .find(|a| a.required && !args.contains_key(&a.name))
+        {
+            return Err(PromptError::MissingArgument {
+                prompt: self.name.clone(),
+                argument: missing.name.clone(),
+            });
+        }
+
+        // One pass over the template, so placeholders inside values stay as given
+        let mut text = String::with_capacity(self.template.len());
+        let mut rest = self.template.as_str();
+        while let Some(start) = rest.find('{') {
+            text.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let argument = rest
+                .find('}')
+                .map(|end| &rest[1..end])
+                .filter(|name| self.arguments.iter().any(|a| a.name == *name));
+            match argument {
+                Some(name) => {
+                    text.push_str(args.get(name).map(String::as_str).unwrap_or(""));
+                    rest = &rest[name.len() + 2..];
+                }
+                None => {
+                    text.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        text.push_str(rest);
+        Ok(text.trim().to_string())
+    }
+}
+
+/// Built-in prompts as (name, JSON definition)
+const BUILTIN: &[(&str, &str)] = &[
+    (
+        "second-opinion",
+        r#"{
+            "description": "Second-opinion review of a diff by Codex and Gemini",
+            "agents": ["codex", "gemini"],
+            "arguments": [
+                {"name": "diff", "description": "Unified diff to review", "required": true},
+                {"name": "focus", "description": "What to pay most attention to"}
+            ],
+            "template": "Review the following change as a second reviewer. Point out bugs, risky assumptions and missing tests, most important first, and say explicitly if you find nothing of substance. {focus}\n\n{diff}"
+        }"#,
+    ),
+    (
+        "generate-tests",
+        r#"{
+            "description": "Generate tests for a file",
+            "agents": ["codex"],
+            "arguments": [
+                {"name": "path", "description": "File to write tests for", "required": true},
+                {"name": "notes", "description": "Framework, style or cases to cover"}
+            ],
+            "template": "Write tests for {path}, following the test layout and conventions already used in this project. Cover edge cases and error paths, and run the tests before you finish. {notes}"
+        }"#,
+    ),
+    (
+        "compare-approaches",
+        r#"{
+            "description": "Ask Codex and Gemini independently how to solve a problem",
+            "agents": ["codex", "gemini"],
+            "arguments": [
+                {"name": "problem", "description": "Problem to solve", "required": true}
+            ],
+            "template": "Propose how to solve the following problem in this project. Do not change any files. Describe the approach, the files involved and the main trade-offs in a few paragraphs.\n\n{problem}"
+        }"#,
+    ),
+];
+
+pub struct PromptLibrary {
+    dir: Option<PathBuf>,
+}
+
+impl PromptLibrary {
+    /// Built-in prompts plus those in `dir`, if given
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self { dir }
+    }
+
+    /// All prompts, sorted by name
+    pub fn list(&self) -> Vec<PromptTemplate> {
+        let mut prompts = BTreeMap::new();
+        for (name, json) in BUILTIN {
+            match parse_template(name, json) {
+                Ok(template) => {
+                    prompts.insert(name.to_string(), template);
+                }
+                Err(e) => tracing::error!("[Prompts] Invalid built-in prompt {}: {}", name, e),
+            }
+        }
+        if let Some(dir) = &self.dir {
+            for template in read_dir(dir) {
+                prompts.insert(template.name.clone(), template);
+            }
+        }
+        prompts.into_values().collect()
+    }
+
+    pub fn get(&self, name: &str) -> Result<PromptTemplate, PromptError> {
+        self.list()
+            .into_iter()
+            .find(|t| t.name == name)
+            .ok_or_else(|| PromptError::NotFound(name.to_string()))
+    }
+}
+
+fn parse_template(name: &str, json: &str) -> Result<PromptTemplate, serde_json::Error> {
+    let mut template: PromptTemplate = serde_json::from_str(json)?;
+    template.name = name.to_string();
+    Ok(template)
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn read_dir(dir: &Path) -> Vec<PromptTemplate> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!("[Prompts] Cannot read {:?}: {}", dir, e);
+            }
+            return Vec::new();
+        }
+    };
+
+    let mut templates = Vec::new();
+    for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        if !is_valid_name(name) {
+            tracing::warn!(
+                "[Prompts] Skipping {:?}: names may only use letters, digits, '-' and '_'",
+                path
+            );
+            continue;
+        }
+        let parsed = fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|json| parse_template(name, &json).map_err(|e| e.to_string()));
+        match parsed {
+            Ok(template) => templates.push(template),
+            Err(e) => tracing::warn!("[Prompts] Skipping {:?}: {}", path, e),
+        }
+    }
+    templates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_builtins_parse() {
+        let prompts = PromptLibrary::new(None).list();
+        let names: Vec<&str> = prompts.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["compare-approaches", "generate-tests", "second-opinion"]
+        );
+        assert!(prompts.iter().all(|p| !p.agents.is_empty()));
+    }
+
+    #[test]
+    fn test_render() {
+        let template = PromptLibrary::new(None).get("generate-tests").unwrap();
+        let text = template.render(&args(&[("path", "src/lib.rs")])).unwrap();
+        assert!(text.starts_with("Write tests for src/lib.rs,"));
+        assert!(!text.contains("{notes}"));
+
+        assert!(matches!(
+            template.render(&HashMap::new()),
+            Err(PromptError::MissingArgument { .. })
+        ));
+        assert!(matches!(
+            template.render(&args(&[("path", "a"), ("pth", "b")])),
+            Err(PromptError::UnknownArgument { .. })
+        ));
+    }
+
+    #[test]
+    fn test_render_leaves_other_braces() {
+        let template = PromptTemplate {
+            name: "t".to_string(),
+            description: String::new(),
+            agents: vec!["codex".to_string()],
+            arguments: vec![PromptArgument {
+                name: "x".to_string(),
+                description: String::new(),
+                required: true,
+            }],
+            template: "fn f() { {x} } {y}".to_string(),
+        };
+        assert_eq!(
+            template.render(&args(&[("x", "1")])).unwrap(),
+            "fn f() { 1 } {y}"
+        );
+    }
+
+    #[test]
+    fn test_render_keeps_placeholders_in_values() {
+        let template = PromptLibrary::new(None).get("second-opinion").unwrap();
+        let text = template
+            .render(&args(&[
+                ("diff", "+ let s = \"{focus}\";"),
+                ("focus", "{diff}"),
+            ]))
+            .unwrap();
+        assert!(text.contains("say explicitly if you find nothing of substance. {diff}"));
+        assert!(text.ends_with("+ let s = \"{focus}\";"));
+    }
+
+    #[test]
+    fn test_directory_overrides_and_extends_builtins() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("second-opinion.json"),
+            r#"{"agents": ["opencode"], "template": "Review"}"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("explain.json"),
+            r#"{"description": "Explain", "agents": ["gemini"],
+                "arguments": [{"name": "path", "required": true}],
+                "template": "Explain {path}"}"#,
+        )
+        .unwrap();
+        fs::write(dir.path().join("broken.json"), "{").unwrap();
+        fs::write(
+            dir.path().join("bad name.json"),
+            r#"{"agents": [], "template": ""}"#,
+        )
+        .unwrap();
+        fs::write(dir.path().join("notes.txt"), "ignored").unwrap();
+
+        let library = PromptLibrary::new(Some(dir.path().to_path_buf()));
+        let names: Vec<String> = library.list().into_iter().map(|p| p.name).collect();
+        assert_eq!(
+            names,
+            [
+                "compare-approaches",
+                "explain",
+                "generate-tests",
+                "second-opinion"
+            ]
+        );
+        assert_eq!(library.get("second-opinion").unwrap().agents, ["opencode"]);
+        let explain = library.get("explain").unwrap();
+        assert_eq!(
+            explain.render(&args(&[("path", "a.rs")])).unwrap(),
+            "Explain a.rs"
+        );
+        assert!(matches!(
+            library.get("broken"),
+            Err(PromptError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_missing_directory_gives_builtins() {
+        let library = PromptLibrary::new(Some(PathBuf::from("/nonexistent/ccgonext/prompts")));
+        assert_eq!(library.list().len(), BUILTIN.len());
+    }
+}
@@ -1,11 +1,13 @@
 //! PTY management layer
 
+use crate::procmon::{ProcessRoot, ProcessTree};
+use crate::recording::{Recorder, RecordingConfig};
 use anyhow::Result;
 use bytes::BytesMut;
-use portable_pty::{native_pty_system, Child, CommandBuilder, PtySize};
+use portable_pty::{native_pty_system, Child, ChildKiller, CommandBuilder, ExitStatus, PtySize};
 use std::io::{Read, Write};
 use std::path::Path;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::sync::{broadcast, mpsc, oneshot, Mutex};
 
@@ -15,6 +17,23 @@ const DEFAULT_WINDOWS_ENTER_DELAY_MS: u64 = 200;
 
 const TERMINAL_QUERY_TAIL_BYTES: usize = 3;
 
+/// Bytes written to and read from an agent's terminals, kept across restarts
+#[derive(Debug, Default)]
+pub struct PtyTraffic {
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+}
+
+impl PtyTraffic {
+    pub fn bytes_in(&self) -> u64 {
+        self.bytes_in.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes_out(&self) -> u64 {
+        self.bytes_out.load(Ordering::Relaxed)
+    }
+}
+
 fn query_sequence_present(data: &[u8], prefix_len: usize, needle: &[u8]) -> bool {
     data.windows(needle.len())
         .enumerate()
@@ -197,7 +216,12 @@ pub struct PtyHandle {
     output_tx: broadcast::Sender<Vec<u8>>,
     buffer: Arc<Mutex<PtyBuffer>>,
     child: Arc<Mutex<Box<dyn Child + Send + Sync>>>,
+    /// The child, which leads its own process group; cleared once reaped so
+    /// its pid is never signalled after it may have been reused
+    root: parking_lot::Mutex<Option<ProcessRoot>>,
     shutdown: Arc<AtomicBool>,
+    /// Terminal size as (cols, rows), kept in step with resize()
+    size: parking_lot::Mutex<(u16, u16)>,
     #[allow(dead_code)] // Used only on Windows in send_enter()
     windows_enter_delay: std::time::Duration,
 }
@@ -234,6 +258,29 @@ impl PtyHandle {
         working_dir: &Path,
         buffer_limit: usize,
         windows_enter_delay_ms: u64,
+    ) -> Result<Self> {
+        Self::spawn_command_with_env(
+            command,
+            working_dir,
+            buffer_limit,
+            windows_enter_delay_ms,
+            None,
+            None,
+            Arc::default(),
+        )
+    }
+
+    /// Spawn with an explicit environment (`None` inherits ours), an
+    /// optional recorder that receives every output, input and resize, and
+    /// the counters the terminal's traffic is added to
+    pub fn spawn_command_with_env(
+        command: &[String],
+        working_dir: &Path,
+        buffer_limit: usize,
+        windows_enter_delay_ms: u64,
+        env: Option<&[(String, String)]>,
+        recorder: Option<Recorder>,
+        traffic: Arc<PtyTraffic>,
     ) -> Result<Self> {
         if command.is_empty() {
             anyhow::bail!("Empty command");
@@ -255,9 +302,19 @@ impl PtyHandle {
             cmd.arg(arg);
         }
 
-        // Inherit environment variables from current process
-        for (key, value) in std::env::vars() {
-            cmd.env(key, value);
+        match env {
+            Some(vars) => {
+                cmd.env_clear();
+                for (key, value) in vars {
+                    cmd.env(key, value);
+                }
+            }
+            None => {
+                // Inherit environment variables from current process
+                for (key, value) in std::env::vars() {
+                    cmd.env(key, value);
+                }
+            }
         }
 
         if working_dir.exists() {
@@ -266,6 +323,7 @@ impl PtyHandle {
 
         // Spawn command and save child handle
         let child = pair.slave.spawn_command(cmd)?;
+        let root = child.process_id().and_then(ProcessRoot::of);
         let child: Arc<Mutex<Box<dyn Child + Send + Sync>>> = Arc::new(Mutex::new(child));
 
         // Get reader BEFORE moving master
@@ -290,6 +348,11 @@ impl PtyHandle {
         let shutdown_writer = shutdown.clone();
         let shutdown_reader = shutdown.clone();
 
+        let recorder = recorder.map(|r| Arc::new(std::sync::Mutex::new(r)));
+        let recorder_for_commands = recorder.clone();
+        let recorder_for_output = recorder;
+        let traffic_for_output = traffic.clone();
+
         // Spawn write handler thread
         let writer_for_commands = writer.clone();
         std::thread::spawn(move || {
@@ -302,6 +365,14 @@ impl PtyHandle {
                                 .and_then(|_| w.flush())
                                 .map_err(|e| anyhow::anyhow!("{}", e))
                         };
+                        if result.is_ok() {
+                            traffic
+                                .bytes_in
+                                .fetch_add(data.len() as u64, Ordering::Relaxed);
+                        }
+                        if let (Ok(()), Some(recorder)) = (&result, &recorder_for_commands) {
+                            recorder.lock().unwrap().input(&data);
+                        }
                         let _ = response.send(result);
                     }
                     PtyCommand::Resize {
@@ -317,6 +388,9 @@ impl PtyHandle {
                                 pixel_height: 0,
                             })
                             .map_err(|e| anyhow::anyhow!("{}", e));
+                        if let (Ok(()), Some(recorder)) = (&result, &recorder_for_commands) {
+                            recorder.lock().unwrap().resize(cols, rows);
+                        }
                         let _ = response.send(result);
                     }
                     PtyCommand::Shutdown => {
@@ -342,6 +416,9 @@ impl PtyHandle {
                     Ok(0) => break, // EOF
                     Ok(n) => {
                         let data = buf[..n].to_vec();
+                        traffic_for_output
+                            .bytes_out
+                            .fetch_add(n as u64, Ordering::Relaxed);
 
                         // Check for terminal query sequences and respond automatically
                         // This must happen BEFORE broadcasting to avoid race conditions
@@ -361,6 +438,10 @@ impl PtyHandle {
                             }
                         }
 
+                        if let Some(recorder) = &recorder_for_output {
+                            recorder.lock().unwrap().output(&data);
+                        }
+
                         // Broadcast to WebSocket subscribers
                         let _ = output_tx_clone.send(data.clone());
 
@@ -378,11 +459,57 @@ impl PtyHandle {
             output_tx,
             buffer,
             child,
+            root: parking_lot::Mutex::new(root),
             shutdown,
+            size: parking_lot::Mutex::new((DEFAULT_COLS, DEFAULT_ROWS)),
             windows_enter_delay: std::time::Duration::from_millis(windows_enter_delay_ms),
         })
     }
 
+    /// A handle with no process behind it: output comes from the returned
+    /// [`PtyDriver`], and whatever is written to the handle is passed to it.
+    /// Used to replay recorded sessions; must be called within a tokio runtime.
+    pub fn detached(buffer_limit: usize) -> (Self, PtyDriver) {
+        let (output_tx, _) = broadcast::channel(1024);
+        let buffer = Arc::new(Mutex::new(PtyBuffer::new(buffer_limit)));
+        let (write_tx, mut write_rx) = mpsc::channel::<PtyCommand>(256);
+        let (input_tx, input_rx) = mpsc::unbounded_channel();
+        let child = DetachedChild::default();
+
+        tokio::spawn(async move {
+            while let Some(cmd) = write_rx.recv().await {
+                match cmd {
+                    PtyCommand::Write { data, response } => {
+                        let _ = input_tx.send(data);
+                        let _ = response.send(Ok(()));
+                    }
+                    PtyCommand::Resize { response, .. } => {
+                        let _ = response.send(Ok(()));
+                    }
+                    PtyCommand::Shutdown => break,
+                }
+            }
+        });
+
+        let driver = PtyDriver {
+            output_tx: output_tx.clone(),
+            buffer: buffer.clone(),
+            input_rx,
+            child: child.clone(),
+        };
+        let handle = Self {
+            write_tx,
+            output_tx,
+            buffer,
+            child: Arc::new(Mutex::new(Box::new(child))),
+            root: parking_lot::Mutex::new(None),
+            shutdown: Arc::new(AtomicBool::new(false)),
+            size: parking_lot::Mutex::new((DEFAULT_COLS, DEFAULT_ROWS)),
+            windows_enter_delay: std::time::Duration::from_millis(DEFAULT_WINDOWS_ENTER_DELAY_MS),
+        };
+        (handle, driver)
+    }
+
     pub async fn write(&self, data: &[u8]) -> Result<()> {
         let (tx, rx) = oneshot::channel();
         self.write_tx
@@ -495,25 +622,82 @@ impl PtyHandle {
             .await
             .map_err(|_| anyhow::anyhow!("PTY channel closed"))?;
         rx.await
-            .map_err(|_| anyhow::anyhow!("Response channel closed"))?
+            .map_err(|_| anyhow::anyhow!("Response channel closed"))??;
+        *self.size.lock() = (cols, rows);
+        Ok(())
+    }
+
+    /// Terminal size as (cols, rows)
+    pub fn size(&self) -> (u16, u16) {
+        *self.size.lock()
+    }
+
+    /// The screen as a terminal of the current size would show it, as plain
+    /// text. Rendered from the scrollback buffer, so output trimmed from the
+    /// front of a full buffer can leave the first lines slightly off.
+    pub async fn render_screen(&self) -> String {
+        let (cols, rows) = self.size();
+        let mut parser = vt100::Parser::new(rows, cols, 0);
+        parser.process(self.buffer.lock().await.as_slice());
+        parser.screen().contents()
+    }
+
+    pub fn pid(&self) -> Option<u32> {
+        self.process_root().map(|root| root.pid)
     }
 
-    /// Kill the child process
+    /// The child process, until it has been reaped
+    pub fn process_root(&self) -> Option<ProcessRoot> {
+        *self.root.lock()
+    }
+
+    /// The child and everything it spawned
+    pub fn process_tree(&self) -> Option<ProcessTree> {
+        self.process_root().map(ProcessTree::collect)
+    }
+
+    /// Forget the child's pid once it has been reaped
+    fn reaped(&self) {
+        *self.root.lock() = None;
+    }
+
+    /// Kill the child process and its whole process tree
     pub async fn kill(&self) -> Result<()> {
+        if let Some(tree) = self.process_tree() {
+            tree.kill();
+        }
         let mut child = self.child.lock().await;
         child.kill().map_err(|e| anyhow::anyhow!("{}", e))
     }
 
+    /// SIGTERM the process tree, then SIGKILL whatever survives `grace`
+    pub async fn terminate(&self, grace: std::time::Duration) {
+        if let Some(tree) = self.process_tree() {
+            tree.terminate(grace).await;
+        }
+        let mut child = self.child.lock().await;
+        let _ = child.kill();
+        if let Ok(Some(_)) = child.try_wait() {
+            self.reaped();
+        }
+    }
+
     /// Wait for child process to exit
     pub async fn wait(&self) -> Result<portable_pty::ExitStatus> {
         let mut child = self.child.lock().await;
-        child.wait().map_err(|e| anyhow::anyhow!("{}", e))
+        let status = child.wait().map_err(|e| anyhow::anyhow!("{}", e))?;
+        self.reaped();
+        Ok(status)
     }
 
     /// Check if child process is still running
     pub async fn try_wait(&self) -> Result<Option<portable_pty::ExitStatus>> {
         let mut child = self.child.lock().await;
-        child.try_wait().map_err(|e| anyhow::anyhow!("{}", e))
+        let status = child.try_wait().map_err(|e| anyhow::anyhow!("{}", e))?;
+        if status.is_some() {
+            self.reaped();
+        }
+        Ok(status)
     }
 
     /// Wait for process exit with timeout.
@@ -543,6 +727,9 @@ impl PtyHandle {
 
     /// Graceful shutdown: send /quit command, wait for agent to exit, then force kill if needed
     pub async fn shutdown(&self) {
+        // Helpers are reparented once the agent exits, so remember them now
+        let tree = self.process_tree();
+
         // Step 1: Send /quit command with timeout to prevent blocking on stuck PTY I/O
         tracing::info!("Sending /quit command to agent...");
         let quit_result =
@@ -575,6 +762,11 @@ impl PtyHandle {
             }
         }
 
+        // Step 4: Kill helpers that outlived the agent
+        if let Some(tree) = tree {
+            tree.kill();
+        }
+
         // Signal shutdown to worker threads AFTER process termination.
         // This ordering ensures I/O loops remain active to capture any final
         // output from the dying process before we tear down the channels.
@@ -596,6 +788,9 @@ impl Drop for PtyHandle {
 
         // Try to kill child process synchronously
         // Note: We can't use async here, so we use try_lock
+        if let Some(tree) = self.process_tree() {
+            tree.kill();
+        }
         if let Ok(mut child) = self.child.try_lock() {
             if let Err(e) = child.kill() {
                 tracing::debug!("Failed to kill child on drop: {}", e);
@@ -604,10 +799,94 @@ impl Drop for PtyHandle {
     }
 }
 
+/// The other end of a [`PtyHandle::detached`] handle
+pub struct PtyDriver {
+    output_tx: broadcast::Sender<Vec<u8>>,
+    buffer: Arc<Mutex<PtyBuffer>>,
+    input_rx: mpsc::UnboundedReceiver<Vec<u8>>,
+    child: DetachedChild,
+}
+
+impl PtyDriver {
+    /// Emit `data` as if the process had printed it
+    pub async fn output(&self, data: &[u8]) {
+        let _ = self.output_tx.send(data.to_vec());
+        self.buffer.lock().await.write(data);
+    }
+
+    /// Next chunk written to the handle, None once the handle is dropped
+    pub async fn input(&mut self) -> Option<Vec<u8>> {
+        self.input_rx.recv().await
+    }
+
+    /// Everything written to the handle so far
+    pub fn drain_input(&mut self) -> Vec<u8> {
+        let mut data = Vec::new();
+        while let Ok(chunk) = self.input_rx.try_recv() {
+            data.extend(chunk);
+        }
+        data
+    }
+
+    /// Make the process appear to have exited with `code`
+    pub fn exit(&self, code: u32) {
+        self.child.finish(ExitStatus::with_exit_code(code));
+    }
+}
+
+/// Stand-in child of a detached handle; "exits" when killed or told to
+#[derive(Debug, Clone, Default)]
+struct DetachedChild {
+    status: Arc<std::sync::Mutex<Option<ExitStatus>>>,
+}
+
+impl DetachedChild {
+    fn finish(&self, status: ExitStatus) {
+        self.status.lock().unwrap().get_or_insert(status);
+    }
+}
+
+impl ChildKiller for DetachedChild {
+    fn kill(&mut self) -> std::io::Result<()> {
+        self.finish(ExitStatus::with_signal("Killed"));
+        Ok(())
+    }
+
+    fn clone_killer(&self) -> Box<dyn ChildKiller + Send + Sync> {
+        Box::new(self.clone())
+    }
+}
+
+impl Child for DetachedChild {
+    fn try_wait(&mut self) -> std::io::Result<Option<ExitStatus>> {
+        Ok(self.status.lock().unwrap().clone())
+    }
+
+    fn wait(&mut self) -> std::io::Result<ExitStatus> {
+        loop {
+            if let Some(status) = self.status.lock().unwrap().clone() {
+                return Ok(status);
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+    }
+
+    fn process_id(&self) -> Option<u32> {
+        None
+    }
+
+    #[cfg(windows)]
+    fn as_raw_handle(&self) -> Option<std::os::windows::io::RawHandle> {
+        None
+    }
+}
+
 pub struct PtyManager {
     handles: Arc<Mutex<std::collections::HashMap<String, Arc<PtyHandle>>>>,
     buffer_limit: usize,
     windows_enter_delay_ms: u64,
+    recording: Option<RecordingConfig>,
+    traffic: parking_lot::Mutex<std::collections::HashMap<String, Arc<PtyTraffic>>>,
 }
 
 impl PtyManager {
@@ -623,20 +902,52 @@ impl PtyManager {
             handles: Arc::new(Mutex::new(std::collections::HashMap::new())),
             buffer_limit,
             windows_enter_delay_ms,
+            recording: None,
+            traffic: parking_lot::Mutex::new(std::collections::HashMap::new()),
         }
     }
 
+    /// Record every PTY created from now on in asciicast format
+    pub fn with_recording(mut self, config: RecordingConfig) -> Self {
+        self.recording = Some(config);
+        self
+    }
+
+    pub fn recording(&self) -> Option<&RecordingConfig> {
+        self.recording.as_ref()
+    }
+
     pub async fn create(
         &self,
         agent_name: &str,
         command: &[String],
         working_dir: &Path,
     ) -> Result<Arc<PtyHandle>> {
-        let handle = Arc::new(PtyHandle::spawn_command_with_windows_enter_delay_ms(
+        self.create_with_env(agent_name, command, working_dir, None)
+            .await
+    }
+
+    /// Like `create`, with an explicit environment for the child
+    pub async fn create_with_env(
+        &self,
+        agent_name: &str,
+        command: &[String],
+        working_dir: &Path,
+        env: Option<&[(String, String)]>,
+    ) -> Result<Arc<PtyHandle>> {
+        let recorder = self.recording.as_ref().and_then(|config| {
+            Recorder::create(config, agent_name, DEFAULT_COLS, DEFAULT_ROWS)
+                .map_err(|e| tracing::warn!("[Recording] Cannot record {}: {}", agent_name, e))
+                .ok()
+        });
+        let handle = Arc::new(PtyHandle::spawn_command_with_env(
             command,
             working_dir,
             self.buffer_limit,
             self.windows_enter_delay_ms,
+            env,
+            recorder,
+            self.traffic(agent_name),
         )?);
         self.handles
             .lock()
@@ -657,6 +968,15 @@ impl PtyManager {
         self.handles.lock().await.keys().cloned().collect()
     }
 
+    /// Traffic of every terminal created for `agent_name`
+    pub fn traffic(&self, agent_name: &str) -> Arc<PtyTraffic> {
+        self.traffic
+            .lock()
+            .entry(agent_name.to_string())
+            .or_default()
+            .clone()
+    }
+
     pub fn buffer_limit(&self) -> usize {
         self.buffer_limit
     }
@@ -676,6 +996,9 @@ impl Drop for PtyManager {
         // Try to kill all child processes synchronously
         if let Ok(handles) = self.handles.try_lock() {
             for (name, handle) in handles.iter() {
+                if let Some(tree) = handle.process_tree() {
+                    tree.kill();
+                }
                 if let Ok(mut child) = handle.child.try_lock() {
                     if let Err(e) = child.kill() {
                         tracing::debug!("Failed to kill {} on manager drop: {}", name, e);
@@ -837,6 +1160,24 @@ mod tests {
         }
     }
 
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_reaped_child_is_forgotten() {
+        let manager = PtyManager::new(1024);
+        let handle = manager
+            .create("exit", &["true".to_string()], Path::new("."))
+            .await
+            .unwrap();
+        assert!(handle.process_root().is_some());
+
+        assert!(handle
+            .wait_for_exit(std::time::Duration::from_secs(5))
+            .await
+            .is_some());
+        assert!(handle.pid().is_none());
+        assert!(handle.process_tree().is_none());
+    }
+
     #[tokio::test]
     async fn test_pty_manager_buffer_limit() {
         let manager = PtyManager::new(512);
@@ -914,4 +1255,34 @@ mod tests {
             assert_eq!(adapted, plain_cmd);
         }
     }
+
+    #[tokio::test]
+    async fn test_detached_handle() {
+        let (pty, mut driver) = PtyHandle::detached(1024);
+        let mut rx = pty.subscribe_output();
+
+        driver.output(b"> ").await;
+        assert_eq!(rx.recv().await.unwrap(), b"> ");
+        assert_eq!(pty.get_current_offset().await, 2);
+
+        pty.write(b"hi").await.unwrap();
+        assert_eq!(driver.input().await.unwrap(), b"hi");
+
+        assert!(pty.try_wait().await.unwrap().is_none());
+        driver.exit(3);
+        assert_eq!(pty.try_wait().await.unwrap().unwrap().exit_code(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_render_screen() {
+        let (pty, driver) = PtyHandle::detached(1024);
+        pty.resize(20, 3).await.unwrap();
+        assert_eq!(pty.size(), (20, 3));
+
+        // A redrawn status line and a screen that scrolled past three rows
+        driver
+            .output(b"one\r\ntwo\r\nWorking...\r\x1b[2KDone\r\n\x1b[1;32mfour\x1b[0m")
+            .await;
+        assert_eq!(pty.render_screen().await, "two\nDone\nfour");
+    }
 }
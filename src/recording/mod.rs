@@ -0,0 +1,494 @@
+//! PTY session recording in asciicast v2 format
+//!
+//! Every agent's PTY stream is written to `<dir>/<agent>/<agent>-<time>.cast`:
+//! a JSON header line followed by `[seconds, code, data]` events, where the
+//! code is `o` (output), `i` (input) or `r` (resize, data `COLSxROWS`).
+//! Files are rotated by size and pruned by age. Recordings can be played back
+//! with `ccgonext replay` or streamed into the web UI at `/replay/<agent>/<file>`.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+const EXTENSION: &str = "cast";
+/// Longest pause between two replayed events
+const MAX_REPLAY_DELAY: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Clone)]
+pub struct RecordingConfig {
+    /// Root directory; each agent records into its own subdirectory
+    pub dir: PathBuf,
+    /// Also record what is written to the agent (prompts, web UI keystrokes)
+    pub record_input: bool,
+    /// Start a new file once the current one reaches this size
+    pub rotate_mb: u64,
+    /// Delete recordings older than this many days; 0 keeps them forever
+    pub retention_days: u64,
+}
+
+impl RecordingConfig {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            record_input: false,
+            rotate_mb: 64,
+            retention_days: 14,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Header {
+    pub version: u32,
+    pub width: u16,
+    pub height: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event(pub f64, pub String, pub String);
+
+impl Event {
+    pub fn time(&self) -> f64 {
+        self.0
+    }
+
+    pub fn code(&self) -> &str {
+        &self.1
+    }
+
+    pub fn data(&self) -> &str {
+        &self.2
+    }
+
+    /// `(cols, rows)` of a resize event
+    pub fn size(&self) -> Option<(u16, u16)> {
+        if self.code() != "r" {
+            return None;
+        }
+        let (cols, rows) = self.data().split_once('x')?;
+        Some((cols.parse().ok()?, rows.parse().ok()?))
+    }
+}
+
+/// Writes one agent's PTY stream to disk
+pub struct Recorder {
+    config: RecordingConfig,
+    agent: String,
+    agent_dir: PathBuf,
+    file: File,
+    path: PathBuf,
+    written: u64,
+    started: Instant,
+    width: u16,
+    height: u16,
+    /// Incomplete UTF-8 sequences carried to the next chunk
+    output_carry: Vec<u8>,
+    input_carry: Vec<u8>,
+    failed: bool,
+}
+
+impl Recorder {
+    pub fn create(
+        config: &RecordingConfig,
+        agent: &str,
+        width: u16,
+        height: u16,
+    ) -> io::Result<Self> {
+        let agent_dir = config.dir.join(agent);
+        fs::create_dir_all(&agent_dir)?;
+        prune(&agent_dir, config.retention_days);
+        let (file, path) = open_new(&agent_dir, agent)?;
+        let mut recorder = Self {
+            config: config.clone(),
+            agent: agent.to_string(),
+            agent_dir,
+            file,
+            path,
+            written: 0,
+            started: Instant::now(),
+            width,
+            height,
+            output_carry: Vec::new(),
+            input_carry: Vec::new(),
+            failed: false,
+        };
+        recorder.write_header()?;
+        tracing::info!("[Recording] {} -> {:?}", agent, recorder.path);
+        Ok(recorder)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn output(&mut self, data: &[u8]) {
+        let text = decode_utf8(&mut self.output_carry, data);
+        if !text.is_empty() {
+            self.event("o", &text);
+        }
+    }
+
+    /// Records input when `record_input` is enabled
+    pub fn input(&mut self, data: &[u8]) {
+        if !self.config.record_input {
+            return;
+        }
+        let text = decode_utf8(&mut self.input_carry, data);
+        if !text.is_empty() {
+            self.event("i", &text);
+        }
+    }
+
+    pub fn resize(&mut self, cols: u16, rows: u16) {
+        self.width = cols;
+        self.height = rows;
+        self.event("r", &format!("{}x{}", cols, rows));
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let mut env = HashMap::new();
+        for key in ["TERM", "SHELL"] {
+            if let Ok(value) = std::env::var(key) {
+                env.insert(key.to_string(), value);
+            }
+        }
+        let header = Header {
+            version: 2,
+            width: self.width,
+            height: self.height,
+            timestamp: Some(Utc::now().timestamp()),
+            title: Some(format!("ccgonext {}", self.agent)),
+            env,
+        };
+        self.write_line(&serde_json::to_string(&header).map_err(io::Error::other)?)
+    }
+
+    fn event(&mut self, code: &str, data: &str) {
+        if self.failed {
+            return;
+        }
+        let rotate_bytes = self.config.rotate_mb.saturating_mul(1024 * 1024);
+        let result = (|| {
+            if rotate_bytes > 0 && self.written >= rotate_bytes {
+                self.rotate()?;
+            }
+            let time = self.started.elapsed().as_secs_f64();
+            let event = Event(
+                (time * 1e6).round() / 1e6,
+                code.to_string(),
+                data.to_string(),
+            );
+            self.write_line(&serde_json::to_string(&event).map_err(io::Error::other)?)
+        })();
+        if let Err(e) = result {
+            tracing::warn!("[Recording] Stopped recording {}: {}", self.agent, e);
+            self.failed = true;
+        }
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        self.file.write_all(format!("{}\n", line).as_bytes())?;
+        self.written += line.len() as u64 + 1;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let (file, path) = open_new(&self.agent_dir, &self.agent)?;
+        self.file = file;
+        self.path = path;
+        self.written = 0;
+        self.started = Instant::now();
+        self.write_header()?;
+        prune(&self.agent_dir, self.config.retention_days);
+        tracing::info!("[Recording] {} rotated to {:?}", self.agent, self.path);
+        Ok(())
+    }
+}
+
+fn open_new(agent_dir: &Path, agent: &str) -> io::Result<(File, PathBuf)> {
+    let stamp = Utc::now().format("%Y%m%d-%H%M%S%.3f");
+    let path = agent_dir.join(format!("{}-{}.{}", agent, stamp, EXTENSION));
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    // Recordings may contain prompts and anything the agent printed
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let file = options.open(&path)?;
+    Ok((file, path))
+}
+
+/// Decode `data` after the carried bytes, keeping a trailing incomplete
+/// UTF-8 sequence for the next call
+fn decode_utf8(carry: &mut Vec<u8>, data: &[u8]) -> String {
+    carry.extend_from_slice(data);
+    let keep = incomplete_utf8_tail(carry);
+    let tail = carry.split_off(carry.len() - keep);
+    let text = String::from_utf8_lossy(carry).into_owned();
+    *carry = tail;
+    text
+}
+
+fn incomplete_utf8_tail(bytes: &[u8]) -> usize {
+    for back in 1..=bytes.len().min(3) {
+        let byte = bytes[bytes.len() - back];
+        if byte & 0xC0 == 0x80 {
+            continue; // continuation byte
+        }
+        let needed = match byte {
+            0xF0..=0xF7 => 4,
+            0xE0..=0xEF => 3,
+            0xC0..=0xDF => 2,
+            _ => 1,
+        };
+        return if needed > back { back } else { 0 };
+    }
+    0
+}
+
+/// Delete recordings in `agent_dir` older than `retention_days`
+pub fn prune(agent_dir: &Path, retention_days: u64) {
+    if retention_days == 0 {
+        return;
+    }
+    let max_age = Duration::from_secs(retention_days * 24 * 60 * 60);
+    let Ok(entries) = fs::read_dir(agent_dir) else {
+        return;
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if path.extension().is_none_or(|ext| ext != EXTENSION) {
+            continue;
+        }
+        let expired = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_some_and(|age| age > max_age);
+        if expired {
+            match fs::remove_file(&path) {
+                Ok(()) => tracing::info!("[Recording] Pruned {:?}", path),
+                Err(e) => tracing::warn!("[Recording] Failed to prune {:?}: {}", path, e),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordingInfo {
+    pub agent: String,
+    pub file: String,
+    pub size: u64,
+    pub modified: chrono::DateTime<Utc>,
+}
+
+/// All recordings under `dir`, newest first
+pub fn list(dir: &Path) -> Vec<RecordingInfo> {
+    let mut recordings = Vec::new();
+    for agent_entry in fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+    {
+        if !agent_entry.path().is_dir() {
+            continue;
+        }
+        let agent = agent_entry.file_name().to_string_lossy().to_string();
+        for entry in fs::read_dir(agent_entry.path())
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.ok())
+        {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != EXTENSION) {
+                continue;
+            }
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            recordings.push(RecordingInfo {
+                agent: agent.clone(),
+                file: entry.file_name().to_string_lossy().to_string(),
+                size: metadata.len(),
+                modified: metadata
+                    .modified()
+                    .map(chrono::DateTime::<Utc>::from)
+                    .unwrap_or_default(),
+            });
+        }
+    }
+    recordings.sort_by_key(|r| std::cmp::Reverse(r.modified));
+    recordings
+}
+
+/// Path of a recording by agent and file name, rejecting anything that
+/// could leave the recording directory
+pub fn resolve(dir: &Path, agent: &str, file: &str) -> Option<PathBuf> {
+    let plain = |s: &str| {
+        !s.is_empty() && !s.starts_with('.') && !s.contains(['/', '\\']) && !s.contains("..")
+    };
+    if !plain(agent) || !plain(file) || !file.ends_with(&format!(".{}", EXTENSION)) {
+        return None;
+    }
+    let path = dir.join(agent).join(file);
+    path.is_file().then_some(path)
+}
+
+/// Read a recording; malformed event lines are skipped
+pub fn read(path: &Path) -> io::Result<(Header, Vec<Event>)> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    let header_line = lines
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "empty recording"))??;
+    let header: Header = serde_json::from_str(&header_line)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let events = lines
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str(&line).ok())
+        .collect();
+    Ok((header, events))
+}
+
+/// Whether `speed` can be used as a playback speed multiplier
+pub fn is_valid_speed(speed: f64) -> bool {
+    speed.is_finite() && speed > 0.0
+}
+
+/// Delays before each event when played at `speed` (1 if invalid), with idle
+/// gaps capped at `max_idle` seconds and every delay at an hour
+pub fn schedule(events: &[Event], speed: f64, max_idle: Option<f64>) -> Vec<Duration> {
+    let speed = if is_valid_speed(speed) { speed } else { 1.0 };
+    let max_idle = max_idle.map(|max_idle| max_idle.max(0.0));
+    let mut previous = 0.0;
+    events
+        .iter()
+        .map(|event| {
+            let mut gap = (event.time() - previous).max(0.0);
+            previous = event.time();
+            if let Some(max_idle) = max_idle {
+                gap = gap.min(max_idle);
+            }
+            Duration::try_from_secs_f64(gap / speed)
+                .unwrap_or(MAX_REPLAY_DELAY)
+                .min(MAX_REPLAY_DELAY)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_utf8_across_chunks() {
+        let bytes = "héllo ✓".as_bytes();
+        let mut carry = Vec::new();
+        let mut text = String::new();
+        for chunk in bytes.chunks(2) {
+            text.push_str(&decode_utf8(&mut carry, chunk));
+        }
+        assert_eq!(text, "héllo ✓");
+        assert!(carry.is_empty());
+    }
+
+    #[test]
+    fn test_record_and_read_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = RecordingConfig::new(dir.path());
+        config.record_input = true;
+
+        let mut recorder = Recorder::create(&config, "codex", 120, 40).unwrap();
+        recorder.output(b"\x1b[1mhello\x1b[0m\r\n");
+        recorder.input(b"ask\r");
+        recorder.resize(100, 30);
+        let path = recorder.path().to_path_buf();
+        drop(recorder);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let (header, events) = read(&path).unwrap();
+        assert_eq!(header.version, 2);
+        assert_eq!((header.width, header.height), (120, 40));
+        let codes: Vec<&str> = events.iter().map(|e| e.code()).collect();
+        assert_eq!(codes, vec!["o", "i", "r"]);
+        assert_eq!(events[0].data(), "\x1b[1mhello\x1b[0m\r\n");
+        assert_eq!(events[2].size(), Some((100, 30)));
+
+        let listed = list(dir.path());
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].agent, "codex");
+        assert_eq!(resolve(dir.path(), "codex", &listed[0].file), Some(path));
+        assert_eq!(resolve(dir.path(), "..", &listed[0].file), None);
+        assert_eq!(resolve(dir.path(), "codex", "../x.cast"), None);
+    }
+
+    #[test]
+    fn test_rotation_and_input_opt_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = RecordingConfig::new(dir.path());
+        config.rotate_mb = 0;
+        let mut recorder = Recorder::create(&config, "gemini", 80, 24).unwrap();
+        recorder.input(b"secret");
+        recorder.output(b"x");
+        let (_, events) = read(recorder.path()).unwrap();
+        assert_eq!(events.len(), 1);
+
+        // Force a rotation by pretending the file is full
+        recorder.config.rotate_mb = 1;
+        recorder.written = 1024 * 1024;
+        let first = recorder.path().to_path_buf();
+        std::thread::sleep(Duration::from_millis(5));
+        recorder.output(b"y");
+        assert_ne!(recorder.path(), first);
+        let (header, events) = read(recorder.path()).unwrap();
+        assert_eq!((header.width, header.height), (80, 24));
+        assert_eq!(events.len(), 1);
+        assert_eq!(list(dir.path()).len(), 2);
+    }
+
+    #[test]
+    fn test_schedule_caps_idle_time() {
+        let events = vec![
+            Event(0.5, "o".into(), "a".into()),
+            Event(10.5, "o".into(), "b".into()),
+            Event(11.0, "o".into(), "c".into()),
+        ];
+        let delays = schedule(&events, 2.0, Some(2.0));
+        assert_eq!(
+            delays,
+            vec![
+                Duration::from_millis(250),
+                Duration::from_secs(1),
+                Duration::from_millis(250)
+            ]
+        );
+
+        // Out-of-range arguments never panic
+        assert_eq!(schedule(&events, 2.0, Some(-1.0)), vec![Duration::ZERO; 3]);
+        assert_eq!(
+            schedule(&events, f64::NAN, None)[1],
+            Duration::from_secs(10)
+        );
+        assert_eq!(schedule(&events, 1e-300, None)[1], MAX_REPLAY_DELAY);
+        assert!(!is_valid_speed(0.0));
+        assert!(!is_valid_speed(-2.0));
+        assert!(!is_valid_speed(f64::INFINITY));
+    }
+}
@@ -0,0 +1,620 @@
+//! Sandboxed agent processes (Linux)
+//!
+//! A sandbox profile wraps an agent's startup command with bubblewrap: the
+//! whole filesystem is mounted read-only except the project (read-only too for
+//! reviewers), the agent's own state, config and cache directories, and a
+//! private /tmp. The network namespace can be unshared, the environment is
+//! filtered through allow/deny lists, and rlimits are applied by re-executing
+//! ccgonext as a small `sandbox-exec` trampoline.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Variables passed through even with an allow-list, unless denied
+const ALWAYS_PASS: &[&str] = &[
+    "PATH",
+    "HOME",
+    "USER",
+    "LOGNAME",
+    "SHELL",
+    "TERM",
+    "COLORTERM",
+    "LANG",
+    "LC_*",
+    "TZ",
+];
+
+/// Variables removed by default: ccgonext's own secrets and ambient credentials
+pub const DEFAULT_ENV_DENY: &[&str] = &[
+    "CCGONEXT_*",
+    "SSH_AUTH_SOCK",
+    "GPG_AGENT_INFO",
+    "AWS_*",
+    "GITHUB_TOKEN",
+    "GH_TOKEN",
+];
+
+/// Paths an agent CLI writes outside the project: its sessions and logs (read
+/// by the log providers), config and caches. Resolved from the agent's
+/// environment the same way the log providers resolve their roots; `home` is
+/// the fallback when the environment has no HOME. They stay writable under
+/// every profile so the agent can start and log its replies.
+pub fn agent_state_dirs(
+    agent: &str,
+    env: &HashMap<String, String>,
+    home: Option<&Path>,
+) -> Vec<PathBuf> {
+    let var = |name: &str| {
+        env.get(name)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    };
+    let Some(home) = var("HOME").or_else(|| home.map(Path::to_path_buf)) else {
+        return Vec::new();
+    };
+    let xdg = |name: &str, default: &str| var(name).unwrap_or_else(|| home.join(default));
+    let npm_cache = var("npm_config_cache").unwrap_or_else(|| home.join(".npm"));
+
+    let mut dirs = match agent {
+        "codex" => vec![
+            var("CODEX_HOME").unwrap_or_else(|| home.join(".codex")),
+            npm_cache,
+        ],
+        "gemini" => {
+            let mut dirs = vec![home.join(".gemini"), npm_cache];
+            dirs.extend(var("GEMINI_ROOT"));
+            dirs
+        }
+        "opencode" => {
+            let mut dirs: Vec<PathBuf> = [
+                ("XDG_DATA_HOME", ".local/share"),
+                ("XDG_STATE_HOME", ".local/state"),
+                ("XDG_CONFIG_HOME", ".config"),
+                ("XDG_CACHE_HOME", ".cache"),
+            ]
+            .iter()
+            .map(|(name, default)| xdg(name, default).join("opencode"))
+            .collect();
+            dirs.extend(var("OPENCODE_STORAGE_ROOT"));
+            dirs
+        }
+        "claudecode" => match var("CLAUDE_CONFIG_DIR") {
+            Some(dir) => vec![dir, npm_cache],
+            None => vec![home.join(".claude"), home.join(".claude.json"), npm_cache],
+        },
+        _ => Vec::new(),
+    };
+    // Parents before children so a nested mount isn't hidden by its parent
+    dirs.sort();
+    dirs.dedup();
+    dirs
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResourceLimits {
+    /// RLIMIT_CPU in seconds
+    pub cpu_secs: Option<u64>,
+    /// RLIMIT_DATA in MiB
+    pub memory_mb: Option<u64>,
+    /// RLIMIT_NPROC (counted per user, not per agent)
+    pub max_processes: Option<u64>,
+}
+
+impl ResourceLimits {
+    pub fn is_empty(&self) -> bool {
+        self.cpu_secs.is_none() && self.memory_mb.is_none() && self.max_processes.is_none()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SandboxProfile {
+    pub name: String,
+    /// Whether the agent may write to the project directory
+    pub project_writable: bool,
+    pub private_tmp: bool,
+    pub network: bool,
+    /// When non-empty, only these variables (plus the basics) are passed on
+    pub env_allow: Vec<String>,
+    pub env_deny: Vec<String>,
+    pub limits: ResourceLimits,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SandboxError {
+    #[error("invalid sandbox profile '{0}': {1}")]
+    InvalidProfile(String, String),
+    #[error("sandbox profiles are only supported on Linux")]
+    Unsupported,
+    #[error("bubblewrap (bwrap) was not found on PATH; it is required for sandbox profiles")]
+    BwrapNotFound,
+}
+
+impl SandboxProfile {
+    /// Project read-write, rest of the filesystem read-only, private /tmp
+    pub fn standard() -> Self {
+        Self {
+            name: "standard".to_string(),
+            project_writable: true,
+            private_tmp: true,
+            network: true,
+            env_allow: Vec::new(),
+            env_deny: DEFAULT_ENV_DENY.iter().map(|s| s.to_string()).collect(),
+            limits: ResourceLimits::default(),
+        }
+    }
+
+    /// Like `standard`, but the project is read-only too
+    pub fn reviewer() -> Self {
+        Self {
+            name: "reviewer".to_string(),
+            project_writable: false,
+            ..Self::standard()
+        }
+    }
+
+    pub fn with_env_allow(mut self, allow: impl IntoIterator<Item = String>) -> Self {
+        self.env_allow.extend(allow);
+        self
+    }
+
+    pub fn with_env_deny(mut self, deny: impl IntoIterator<Item = String>) -> Self {
+        self.env_deny.extend(deny);
+        self
+    }
+
+    /// Filter an environment through the allow and deny lists
+    pub fn filter_env(
+        &self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Vec<(String, String)> {
+        vars.into_iter()
+            .filter(|(key, _)| {
+                let allowed = self.env_allow.is_empty()
+                    || ALWAYS_PASS.iter().any(|p| env_pattern_matches(p, key))
+                    || self.env_allow.iter().any(|p| env_pattern_matches(p, key));
+                allowed && !self.env_deny.iter().any(|p| env_pattern_matches(p, key))
+            })
+            .collect()
+    }
+}
+
+/// Profile spec: `<standard|reviewer>[+offline][+shared-tmp][+cpu=SECS][+mem=MIB][+nproc=N]`
+impl std::str::FromStr for SandboxProfile {
+    type Err = SandboxError;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let invalid = |msg: String| SandboxError::InvalidProfile(spec.to_string(), msg);
+        let mut parts = spec.split('+').map(|p| p.trim());
+        let mut profile = match parts.next().unwrap_or_default() {
+            "standard" => Self::standard(),
+            "reviewer" => Self::reviewer(),
+            other => return Err(invalid(format!("unknown base profile '{}'", other))),
+        };
+
+        for option in parts {
+            let (key, value) = match option.split_once('=') {
+                Some((k, v)) => (k, Some(v)),
+                None => (option, None),
+            };
+            let number = || -> Result<u64, SandboxError> {
+                value
+                    .and_then(|v| v.parse().ok())
+                    .ok_or_else(|| invalid(format!("'{}' needs a numeric value", key)))
+            };
+            match key {
+                "offline" => profile.network = false,
+                "shared-tmp" => profile.private_tmp = false,
+                "cpu" => profile.limits.cpu_secs = Some(number()?),
+                "mem" => profile.limits.memory_mb = Some(number()?),
+                "nproc" => profile.limits.max_processes = Some(number()?),
+                other => return Err(invalid(format!("unknown option '{}'", other))),
+            }
+        }
+        Ok(profile)
+    }
+}
+
+impl std::fmt::Display for SandboxProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        if !self.network {
+            write!(f, "+offline")?;
+        }
+        if !self.private_tmp {
+            write!(f, "+shared-tmp")?;
+        }
+        if let Some(cpu) = self.limits.cpu_secs {
+            write!(f, "+cpu={}", cpu)?;
+        }
+        if let Some(mem) = self.limits.memory_mb {
+            write!(f, "+mem={}", mem)?;
+        }
+        if let Some(nproc) = self.limits.max_processes {
+            write!(f, "+nproc={}", nproc)?;
+        }
+        Ok(())
+    }
+}
+
+/// `*` matches any run of characters
+pub(crate) fn env_pattern_matches(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => {
+            let Some(tail) = name.strip_prefix(prefix) else {
+                return false;
+            };
+            (0..=tail.len())
+                .filter(|&i| tail.is_char_boundary(i))
+                .any(|i| env_pattern_matches(rest, &tail[i..]))
+        }
+    }
+}
+
+fn find_in_path(program: &str) -> Option<PathBuf> {
+    std::env::var_os("PATH").and_then(|paths| {
+        std::env::split_paths(&paths)
+            .map(|dir| dir.join(program))
+            .find(|candidate| candidate.is_file())
+    })
+}
+
+/// A profile resolved for one agent: where bwrap lives and which project
+/// directories it guards
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    profile: SandboxProfile,
+    bwrap: PathBuf,
+    /// Executable used for the rlimit trampoline
+    exe: PathBuf,
+    home: Option<PathBuf>,
+    project_dirs: Vec<PathBuf>,
+    /// Agent state directories, always mounted writable
+    state_dirs: Vec<PathBuf>,
+}
+
+impl Sandbox {
+    pub fn new(profile: SandboxProfile, project_dirs: Vec<PathBuf>) -> Result<Self, SandboxError> {
+        if !cfg!(target_os = "linux") {
+            return Err(SandboxError::Unsupported);
+        }
+        let bwrap = find_in_path("bwrap").ok_or(SandboxError::BwrapNotFound)?;
+        let exe = std::env::current_exe().unwrap_or_else(|_| PathBuf::from("ccgonext"));
+        Ok(Self::with_paths(
+            profile,
+            project_dirs,
+            bwrap,
+            exe,
+            dirs::home_dir(),
+        ))
+    }
+
+    /// Construct with explicit tool paths (used by tests)
+    pub fn with_paths(
+        profile: SandboxProfile,
+        project_dirs: Vec<PathBuf>,
+        bwrap: PathBuf,
+        exe: PathBuf,
+        home: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            profile,
+            bwrap,
+            exe,
+            home,
+            project_dirs,
+            state_dirs: Vec::new(),
+        }
+    }
+
+    /// Mount the state directories of `agent` as resolved from `env`, the
+    /// environment it will run with (see [`agent_state_dirs`])
+    pub fn with_agent_state(mut self, agent: &str, env: &HashMap<String, String>) -> Self {
+        self.state_dirs = agent_state_dirs(agent, env, self.home.as_deref());
+        self
+    }
+
+    pub fn profile(&self) -> &SandboxProfile {
+        &self.profile
+    }
+
+    /// Wrap `command` so it runs inside the sandbox with `working_dir` as cwd
+    pub fn wrap(&self, command: &[String], working_dir: &Path) -> Vec<String> {
+        let path = |p: &Path| p.to_string_lossy().to_string();
+        let mut argv: Vec<String> = Vec::new();
+
+        let limits = &self.profile.limits;
+        if !limits.is_empty() {
+            argv.push(path(&self.exe));
+            argv.push("sandbox-exec".to_string());
+            for (flag, value) in [
+                ("--cpu", limits.cpu_secs),
+                ("--mem", limits.memory_mb),
+                ("--nproc", limits.max_processes),
+            ] {
+                if let Some(value) = value {
+                    argv.push(flag.to_string());
+                    argv.push(value.to_string());
+                }
+            }
+            argv.push("--".to_string());
+        }
+
+        argv.push(path(&self.bwrap));
+        argv.extend(
+            [
+                "--die-with-parent",
+                "--ro-bind",
+                "/",
+                "/",
+                "--dev-bind",
+                "/dev",
+                "/dev",
+            ]
+            .map(String::from),
+        );
+        if self.profile.private_tmp {
+            argv.extend(["--tmpfs", "/tmp"].map(String::from));
+        }
+        for dir in &self.state_dirs {
+            let dir = path(dir);
+            argv.extend(["--bind-try".to_string(), dir.clone(), dir]);
+        }
+
+        // Project mounts come last so they win over /tmp and $HOME mounts
+        let bind = if self.profile.project_writable {
+            "--bind"
+        } else {
+            "--ro-bind"
+        };
+        let mut project_dirs: Vec<&Path> = self.project_dirs.iter().map(|p| p.as_path()).collect();
+        if !project_dirs.contains(&working_dir) {
+            project_dirs.push(working_dir);
+        }
+        for dir in project_dirs {
+            argv.extend([bind.to_string(), path(dir), path(dir)]);
+        }
+
+        if !self.profile.network {
+            argv.push("--unshare-net".to_string());
+        }
+        argv.extend(["--chdir".to_string(), path(working_dir), "--".to_string()]);
+        argv.extend(command.iter().cloned());
+        argv
+    }
+
+    /// Environment for the sandboxed process, derived from ours
+    pub fn environment(&self) -> Vec<(String, String)> {
+        self.profile.filter_env(std::env::vars())
+    }
+}
+
+/// Apply `limits` and exec `command`; only returns on failure.
+/// Entry point of the hidden `ccgonext sandbox-exec` subcommand.
+#[cfg(unix)]
+pub fn exec_with_limits(limits: &ResourceLimits, command: &[String]) -> std::io::Error {
+    use std::os::unix::process::CommandExt;
+
+    let Some((program, args)) = command.split_first() else {
+        return std::io::Error::new(std::io::ErrorKind::InvalidInput, "empty command");
+    };
+
+    let set = |resource, value: u64| -> std::io::Result<()> {
+        let limit = libc::rlimit {
+            rlim_cur: value as libc::rlim_t,
+            rlim_max: value as libc::rlim_t,
+        };
+        // SAFETY: setrlimit only reads the provided struct
+        if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    };
+    let result = (|| {
+        if let Some(secs) = limits.cpu_secs {
+            set(libc::RLIMIT_CPU, secs)?;
+        }
+        if let Some(mb) = limits.memory_mb {
+            set(libc::RLIMIT_DATA, mb.saturating_mul(1024 * 1024))?;
+        }
+        if let Some(n) = limits.max_processes {
+            set(libc::RLIMIT_NPROC, n)?;
+        }
+        Ok(())
+    })();
+    if let Err(e) = result {
+        return e;
+    }
+
+    std::process::Command::new(program).args(args).exec()
+}
+
+#[cfg(not(unix))]
+pub fn exec_with_limits(_limits: &ResourceLimits, _command: &[String]) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "resource limits are only supported on Unix",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sandbox(spec: &str) -> Sandbox {
+        Sandbox::with_paths(
+            spec.parse().unwrap(),
+            vec![PathBuf::from("/work/repo")],
+            PathBuf::from("/usr/bin/bwrap"),
+            PathBuf::from("/usr/bin/ccgonext"),
+            Some(PathBuf::from("/home/u")),
+        )
+        .with_agent_state("gemini", &HashMap::new())
+    }
+
+    fn window(argv: &[String], items: &[&str]) -> bool {
+        argv.windows(items.len())
+            .any(|w| w.iter().zip(items).all(|(a, b)| a == b))
+    }
+
+    #[test]
+    fn test_profile_parse() {
+        let profile: SandboxProfile = "reviewer+offline+mem=2048".parse().unwrap();
+        assert!(!profile.project_writable);
+        assert!(!profile.network);
+        assert_eq!(profile.limits.memory_mb, Some(2048));
+        assert_eq!(profile.to_string(), "reviewer+offline+mem=2048");
+        assert!("standard+cpu".parse::<SandboxProfile>().is_err());
+        assert!("root".parse::<SandboxProfile>().is_err());
+    }
+
+    #[test]
+    fn test_env_filter() {
+        let vars = [
+            ("PATH", "/bin"),
+            ("OPENAI_API_KEY", "k"),
+            ("CCGONEXT_AUTH_TOKEN", "t"),
+            ("SECRET", "s"),
+            ("LC_ALL", "C"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+
+        let keys = |env: Vec<(String, String)>| env.into_iter().map(|(k, _)| k).collect::<Vec<_>>();
+        let standard = SandboxProfile::standard();
+        assert_eq!(
+            keys(standard.filter_env(vars.clone())),
+            vec!["PATH", "OPENAI_API_KEY", "SECRET", "LC_ALL"]
+        );
+
+        let allow = SandboxProfile::standard().with_env_allow(["*_API_KEY".to_string()]);
+        assert_eq!(
+            keys(allow.filter_env(vars)),
+            vec!["PATH", "OPENAI_API_KEY", "LC_ALL"]
+        );
+    }
+
+    #[test]
+    fn test_reviewer_wrap_mounts_project_read_only() {
+        let command = vec!["gemini".to_string()];
+        let argv = sandbox("reviewer+offline").wrap(&command, Path::new("/work/repo"));
+        assert_eq!(argv[0], "/usr/bin/bwrap");
+        assert!(window(&argv, &["--ro-bind", "/work/repo", "/work/repo"]));
+        assert!(!window(&argv, &["--bind", "/work/repo", "/work/repo"]));
+        assert!(window(&argv, &["--tmpfs", "/tmp"]));
+        assert!(window(
+            &argv,
+            &["--bind-try", "/home/u/.gemini", "/home/u/.gemini"]
+        ));
+        assert!(window(
+            &argv,
+            &["--bind-try", "/home/u/.npm", "/home/u/.npm"]
+        ));
+        assert!(!argv.contains(&"--ro-bind-try".to_string()));
+        assert!(!argv
+            .iter()
+            .any(|arg| arg.contains(".codex") || arg.contains(".config")));
+        assert!(argv.contains(&"--unshare-net".to_string()));
+        assert!(argv.ends_with(&["--".to_string(), "gemini".to_string()]));
+    }
+
+    #[test]
+    fn test_limits_use_trampoline() {
+        let command = vec!["codex".to_string()];
+        let argv = sandbox("standard+cpu=60+nproc=256").wrap(&command, Path::new("/work/repo/sub"));
+        assert!(window(
+            &argv,
+            &[
+                "/usr/bin/ccgonext",
+                "sandbox-exec",
+                "--cpu",
+                "60",
+                "--nproc",
+                "256",
+                "--",
+                "/usr/bin/bwrap"
+            ]
+        ));
+        assert!(window(&argv, &["--bind", "/work/repo", "/work/repo"]));
+        assert!(window(
+            &argv,
+            &["--bind", "/work/repo/sub", "/work/repo/sub"]
+        ));
+        assert!(window(
+            &argv,
+            &["--bind-try", "/home/u/.gemini", "/home/u/.gemini"]
+        ));
+        assert!(window(&argv, &["--chdir", "/work/repo/sub", "--"]));
+        assert!(!argv.contains(&"--unshare-net".to_string()));
+    }
+
+    #[test]
+    fn test_state_dirs_include_config_and_cache() {
+        let home = Path::new("/home/u");
+        let env = HashMap::new();
+        let dirs = agent_state_dirs("opencode", &env, Some(home));
+        for dir in [
+            "/home/u/.local/share/opencode",
+            "/home/u/.local/state/opencode",
+            "/home/u/.config/opencode",
+            "/home/u/.cache/opencode",
+        ] {
+            assert!(dirs.contains(&PathBuf::from(dir)), "missing {}", dir);
+        }
+        assert!(agent_state_dirs("gemini", &env, Some(home)).contains(&home.join(".npm")));
+
+        let sandbox = Sandbox::with_paths(
+            "reviewer".parse().unwrap(),
+            vec![PathBuf::from("/work/repo")],
+            PathBuf::from("/usr/bin/bwrap"),
+            PathBuf::from("/usr/bin/ccgonext"),
+            Some(home.to_path_buf()),
+        )
+        .with_agent_state("opencode", &env);
+        let argv = sandbox.wrap(&["opencode".to_string()], Path::new("/work/repo"));
+        assert!(window(
+            &argv,
+            &[
+                "--bind-try",
+                "/home/u/.cache/opencode",
+                "/home/u/.cache/opencode"
+            ]
+        ));
+        assert!(window(
+            &argv,
+            &[
+                "--bind-try",
+                "/home/u/.config/opencode",
+                "/home/u/.config/opencode"
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_state_dirs_follow_agent_environment() {
+        let env: HashMap<String, String> = [
+            ("HOME", "/home/agent"),
+            ("CODEX_HOME", "/srv/codex"),
+            ("XDG_CACHE_HOME", "/var/cache/agent"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .into_iter()
+        .collect();
+        let home = Path::new("/home/u");
+
+        assert_eq!(
+            agent_state_dirs("codex", &env, Some(home)),
+            vec![
+                PathBuf::from("/home/agent/.npm"),
+                PathBuf::from("/srv/codex")
+            ]
+        );
+        let opencode = agent_state_dirs("opencode", &env, Some(home));
+        assert!(opencode.contains(&PathBuf::from("/var/cache/agent/opencode")));
+        assert!(opencode.contains(&PathBuf::from("/home/agent/.config/opencode")));
+
+        let mut gemini_env = HashMap::new();
+        gemini_env.insert("GEMINI_ROOT".to_string(), "/srv/gemini".to_string());
+        assert!(agent_state_dirs("gemini", &gemini_env, Some(home))
+            .contains(&PathBuf::from("/srv/gemini")));
+    }
+}
@@ -1,31 +1,130 @@
 //! Session management layer
 
 use crate::agent::{Agent, ClaudeCodeAgent};
-use crate::config::TimeoutConfig;
-use crate::log_provider::LogProvider;
+use crate::cache::ResponseCache;
+use crate::changes::{ChangeTracker, FileChanges, PendingSnapshot};
+use crate::config::{LaunchConfig, TimeoutConfig};
+use crate::events::{EventBus, EventKind};
+use crate::journal::RequestJournal;
+use crate::log_provider::{LogProvider, Reply, TokenUsage};
+use crate::mcp::CONTENT_TARGET;
+use crate::metrics::{AgentGauges, Metrics};
+use crate::procmon::{ProcessStats, ProcessTable, ResourceSampler};
+use crate::prompts::PromptLibrary;
 use crate::pty::PtyHandle;
+use crate::sandbox::Sandbox;
 use crate::state::{AgentState, SideEffect, StateMachine, StateTransition, TransitionResult};
+use crate::usage::UsageTracker;
+use crate::worktree::{WorktreeChange, WorktreeManager};
+use serde::Serialize;
 use std::collections::VecDeque;
 use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 use tokio::sync::{broadcast, oneshot, Mutex, RwLock};
+// tokio's clock, so ready and reply detection follow a paused test runtime
+use tokio::time::Instant;
+use tracing::Instrument;
 use uuid::Uuid;
 
+/// Time the agent's process tree gets to exit after SIGTERM on stop
+const STOP_GRACE: Duration = Duration::from_secs(2);
+
+/// Reply chunks kept for slow subscribers
+const REPLY_CHUNK_CAPACITY: usize = 256;
+
+/// Reply delivered to the caller of `ask`
+#[derive(Debug, Clone, Default)]
+pub struct AgentReply {
+    pub content: String,
+    pub usage: Option<TokenUsage>,
+    pub model: Option<String>,
+    /// Structured reply (reasoning, tool calls, errors) when the log provider supplies it
+    pub reply: Option<Reply>,
+    /// Edits captured from the agent's worktree when isolation is enabled
+    pub change: Option<WorktreeChange>,
+    /// Files created, modified or deleted in the working directory during the request
+    pub files: Option<FileChanges>,
+}
+
+/// Reply text that appeared while the agent was still writing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReplyChunk {
+    pub agent: String,
+    pub message_id: String,
+    /// Characters of the reply before `text`. Usually everything streamed so
+    /// far; less when the agent rewrote earlier text, which `text` replaces
+    pub offset: usize,
+    pub text: String,
+}
+
+/// Turns successive reads of a growing reply into chunks, so re-reads of
+/// the same content (Gemini rewrites the whole message on every update)
+/// only send what changed
+struct ReplyStream {
+    tx: broadcast::Sender<ReplyChunk>,
+    agent: String,
+    message_id: String,
+    sent: String,
+}
+
+impl ReplyStream {
+    fn new(session: &AgentSession, message_id: &str) -> Self {
+        Self {
+            tx: session.reply_tx.clone(),
+            agent: session.name.clone(),
+            message_id: message_id.to_string(),
+            sent: String::new(),
+        }
+    }
+
+    fn update(&mut self, adapter: &Arc<dyn Agent>, content: &str) {
+        let mut text = adapter.strip_done_marker(content, &self.message_id);
+        // Hold back a last line that may be the start of the done marker
+        let marker = adapter.done_marker(&self.message_id);
+        let start = text.rfind('\n').map_or(0, |i| i + 1);
+        let last = text[start..].trim();
+        if !last.is_empty() && marker.starts_with(last) {
+            text.truncate(start);
+            text.truncate(text.trim_end().len());
+        }
+        let offset = self
+            .sent
+            .chars()
+            .zip(text.chars())
+            .take_while(|(a, b)| a == b)
+            .count();
+        // Nothing new; a shorter read is treated as transient
+        if offset == text.chars().count() {
+            return;
+        }
+        let chunk = ReplyChunk {
+            agent: self.agent.clone(),
+            message_id: self.message_id.clone(),
+            offset,
+            text: text.chars().skip(offset).collect(),
+        };
+        self.sent = text;
+        let _ = self.tx.send(chunk);
+    }
+}
+
 #[derive(Debug)]
 pub struct Request {
     pub id: String,
     pub message: String,
     pub timeout: Duration,
     pub created_at: Instant,
-    pub response_tx: oneshot::Sender<Result<String, SessionError>>,
+    pub response_tx: oneshot::Sender<Result<AgentReply, SessionError>>,
+    /// Span of the caller's ask; sending and reply detection are traced under it
+    pub span: tracing::Span,
 }
 
 impl Request {
     pub fn new(
         message: String,
         timeout: Duration,
-        response_tx: oneshot::Sender<Result<String, SessionError>>,
+        response_tx: oneshot::Sender<Result<AgentReply, SessionError>>,
     ) -> Self {
         Self {
             id: Uuid::new_v4().to_string(),
@@ -33,6 +132,24 @@ impl Request {
             timeout,
             created_at: Instant::now(),
             response_tx,
+            span: tracing::Span::current(),
+        }
+    }
+
+    /// Create a request with a caller-supplied id (used to look up results later)
+    pub fn with_id(
+        id: String,
+        message: String,
+        timeout: Duration,
+        response_tx: oneshot::Sender<Result<AgentReply, SessionError>>,
+    ) -> Self {
+        Self {
+            id,
+            message,
+            timeout,
+            created_at: Instant::now(),
+            response_tx,
+            span: tracing::Span::current(),
         }
     }
 }
@@ -55,11 +172,35 @@ pub enum SessionError {
     InvalidTransition(String),
     #[error("PTY error: {0}")]
     PtyError(String),
+    #[error("Worktree error: {0}")]
+    Worktree(String),
+    #[error("Daily token budget exceeded: {used} of {budget} tokens used")]
+    BudgetExceeded { used: u64, budget: u64 },
+    #[error("Request cancelled")]
+    Cancelled,
+}
+
+/// Outcome label of a finished request in the metrics
+fn request_outcome(result: &Result<AgentReply, SessionError>) -> &'static str {
+    match result {
+        Ok(_) => "success",
+        Err(SessionError::RequestTimeout | SessionError::QueueTimeout) => "timeout",
+        Err(
+            SessionError::Crashed(_)
+            | SessionError::Stopped(_)
+            | SessionError::NotRunning
+            | SessionError::PtyError(_),
+        ) => "crash",
+        Err(SessionError::Cancelled) => "cancelled",
+        Err(_) => "error",
+    }
 }
 
 pub struct AgentSession {
     pub name: String,
     pub state: RwLock<AgentState>,
+    state_tx: broadcast::Sender<AgentState>,
+    reply_tx: broadcast::Sender<ReplyChunk>,
     pub adapter: Arc<dyn Agent>,
     pub log_provider: Arc<dyn LogProvider>,
     pub pty: RwLock<Option<Arc<PtyHandle>>>,
@@ -69,6 +210,18 @@ pub struct AgentSession {
     pub timeouts: TimeoutConfig,
     pub restart_count: Mutex<u32>,
     pub last_restart: Mutex<Option<Instant>>,
+    journal: Option<Arc<RequestJournal>>,
+    events: Option<Arc<EventBus>>,
+    metrics: Option<Arc<Metrics>>,
+    usage: Option<Arc<UsageTracker>>,
+    worktrees: Option<Arc<WorktreeManager>>,
+    change_tracker: Option<Arc<ChangeTracker>>,
+    pending_snapshot: Mutex<Option<PendingSnapshot>>,
+    sandbox: Option<Sandbox>,
+    launch: LaunchConfig,
+    memory_limit_mb: Option<u64>,
+    resource_sampler: parking_lot::Mutex<ResourceSampler>,
+    resources: parking_lot::Mutex<Option<ProcessStats>>,
 
     // Locks for concurrency control
     lifecycle_lock: Mutex<()>,
@@ -82,6 +235,7 @@ struct PreparedRequest {
     baseline_offset: u64,
     request_timeout: Duration,
     pty_start_offset: u64, // For ClaudeCode PTY parsing
+    span: tracing::Span,
 }
 
 impl AgentSession {
@@ -92,9 +246,13 @@ impl AgentSession {
         working_dir: PathBuf,
         timeouts: TimeoutConfig,
     ) -> Self {
+        let (state_tx, _) = broadcast::channel(64);
+        let (reply_tx, _) = broadcast::channel(REPLY_CHUNK_CAPACITY);
         Self {
             name,
             state: RwLock::new(AgentState::Stopped),
+            state_tx,
+            reply_tx,
             adapter,
             log_provider,
             pty: RwLock::new(None),
@@ -104,15 +262,185 @@ impl AgentSession {
             timeouts,
             restart_count: Mutex::new(0),
             last_restart: Mutex::new(None),
+            journal: None,
+            events: None,
+            metrics: None,
+            usage: None,
+            worktrees: None,
+            change_tracker: None,
+            pending_snapshot: Mutex::new(None),
+            sandbox: None,
+            launch: LaunchConfig::default(),
+            memory_limit_mb: None,
+            resource_sampler: parking_lot::Mutex::new(ResourceSampler::default()),
+            resources: parking_lot::Mutex::new(None),
             lifecycle_lock: Mutex::new(()),
             request_queue_lock: Mutex::new(()),
         }
     }
 
+    /// Record request lifecycle in the given journal
+    pub fn with_journal(mut self, journal: Arc<RequestJournal>) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    pub fn journal(&self) -> Option<&Arc<RequestJournal>> {
+        self.journal.as_ref()
+    }
+
+    /// Publish state transitions, request lifecycle, restarts and errors on the given bus
+    pub fn with_events(mut self, events: Arc<EventBus>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Record request latencies, state times and reply detection in the given metrics
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    fn record_reply_detection(&self, trigger: &'static str, completion: &'static str) {
+        if let Some(metrics) = &self.metrics {
+            metrics.record_reply_detection(&self.name, trigger, completion);
+        }
+    }
+
+    fn record_lag(&self, channel: &'static str, missed: u64) {
+        if let Some(metrics) = &self.metrics {
+            metrics.record_lag(channel, missed);
+        }
+    }
+
+    fn emit(&self, kind: EventKind) {
+        if let Some(events) = &self.events {
+            events.publish(&self.name, kind);
+        }
+    }
+
+    fn emit_state(&self, from: AgentState, to: AgentState) {
+        let _ = self.state_tx.send(to);
+        if let Some(metrics) = &self.metrics {
+            metrics.record_state(&self.name, &from.to_string(), &to.to_string());
+        }
+        self.emit(EventKind::StateChanged {
+            from: from.to_string(),
+            to: to.to_string(),
+        });
+    }
+
+    /// Account reply token usage in the given tracker and enforce its budget
+    pub fn with_usage_tracker(mut self, usage: Arc<UsageTracker>) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+
+    /// Capture the edits made for each request from the agent's worktree
+    pub fn with_worktrees(mut self, worktrees: Arc<WorktreeManager>) -> Self {
+        self.worktrees = Some(worktrees);
+        self
+    }
+
+    /// Report the files each request touched in the working directory
+    pub fn with_change_tracker(mut self, tracker: Arc<ChangeTracker>) -> Self {
+        self.change_tracker = Some(tracker);
+        self
+    }
+
+    /// Start the agent process inside a sandbox
+    pub fn with_sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
+
+    /// Environment and shell settings for starting the agent process
+    pub fn with_launch(mut self, launch: LaunchConfig) -> Self {
+        self.launch = launch;
+        self
+    }
+
+    /// Restart the agent when its process tree uses more than `limit_mb` of RSS
+    pub fn with_memory_limit(mut self, limit_mb: u64) -> Self {
+        self.memory_limit_mb = Some(limit_mb);
+        self
+    }
+
+    pub fn memory_limit_mb(&self) -> Option<u64> {
+        self.memory_limit_mb
+    }
+
+    /// Most recent resource sample of the agent's process tree
+    pub fn resources(&self) -> Option<ProcessStats> {
+        self.resources.lock().clone()
+    }
+
+    /// Sample the agent's process tree from `table` and remember the result
+    pub async fn sample_resources(&self, table: &ProcessTable) -> Option<ProcessStats> {
+        let root = self
+            .pty
+            .read()
+            .await
+            .as_ref()
+            .and_then(|pty| pty.process_root());
+        let stats = root.and_then(|root| self.resource_sampler.lock().sample(&table.tree(root)));
+        *self.resources.lock() = stats.clone();
+        stats
+    }
+
+    /// Send the result to the waiting caller and record it in the journal
+    fn respond(&self, req: Request, result: Result<AgentReply, SessionError>) {
+        if let Some(journal) = &self.journal {
+            match &result {
+                Ok(reply) => journal.record_completed(&req.id, &reply.content),
+                Err(SessionError::RequestTimeout) => journal.record_timed_out(&req.id),
+                Err(e) => journal.record_failed(&req.id, &e.to_string()),
+            }
+        }
+        if let Some(metrics) = &self.metrics {
+            metrics.record_request(
+                &self.name,
+                request_outcome(&result),
+                req.created_at.elapsed(),
+            );
+        }
+        self.emit(match &result {
+            Ok(_) => EventKind::RequestCompleted {
+                message_id: req.id.clone(),
+            },
+            Err(e) => EventKind::RequestFailed {
+                message_id: req.id.clone(),
+                error: e.to_string(),
+            },
+        });
+        let _ = req.response_tx.send(result);
+    }
+
     pub async fn get_state(&self) -> AgentState {
         *self.state.read().await
     }
 
+    /// Receive every state the session enters from now on
+    pub fn subscribe_state(&self) -> broadcast::Receiver<AgentState> {
+        self.state_tx.subscribe()
+    }
+
+    /// Receive reply text as it is detected in the agent's log, before the
+    /// reply is complete. The tool result remains the authoritative reply;
+    /// ClaudeCode replies are read from the terminal and are not streamed
+    pub fn subscribe_reply_chunks(&self) -> broadcast::Receiver<ReplyChunk> {
+        self.reply_tx.subscribe()
+    }
+
+    async fn set_state(&self, state: AgentState) {
+        let mut state_guard = self.state.write().await;
+        let from = *state_guard;
+        if from != state {
+            *state_guard = state;
+            self.emit_state(from, state);
+        }
+    }
+
     async fn apply_transition(
         &self,
         event: StateTransition,
@@ -124,6 +452,9 @@ impl AgentSession {
             .map_err(|e| SessionError::InvalidTransition(e.to_string()))?;
 
         *state_guard = result.new_state;
+        if current != result.new_state {
+            self.emit_state(current, result.new_state);
+        }
         drop(state_guard);
 
         // Process side effects
@@ -166,9 +497,7 @@ impl AgentSession {
             SideEffect::ClearQueue => {
                 let mut queue = self.request_queue.lock().await;
                 while let Some(req) = queue.pop_front() {
-                    let _ = req
-                        .response_tx
-                        .send(Err(SessionError::Stopped("Queue cleared".to_string())));
+                    self.respond(req, Err(SessionError::Stopped("Queue cleared".to_string())));
                 }
             }
             SideEffect::NotifyWaiters(msg) => {
@@ -199,22 +528,60 @@ impl AgentSession {
         self.apply_transition(StateTransition::StartAgent).await?;
 
         // Get startup command
-        let command = self.adapter.get_startup_command(&self.working_dir);
+        let mut command = self
+            .launch
+            .command(self.adapter.get_startup_command(&self.working_dir));
+        let mut env = self
+            .launch
+            .customizes_env()
+            .then(|| self.launch.environment(std::env::vars()));
+        if let Some(sandbox) = &self.sandbox {
+            command = sandbox.wrap(&command, &self.working_dir);
+            env = Some(self.launch.environment(sandbox.environment()));
+            tracing::info!(
+                "Starting {} in sandbox profile '{}'",
+                self.name,
+                sandbox.profile().name
+            );
+        }
 
         // Create PTY with command - rollback state on failure
         let pty = match pty_manager
-            .create(&self.name, &command, &self.working_dir)
+            .create_with_env(&self.name, &command, &self.working_dir, env.as_deref())
             .await
         {
             Ok(pty) => pty,
             Err(e) => {
                 // Rollback state to Dead on PTY creation failure
-                *self.state.write().await = AgentState::Dead;
+                self.set_state(AgentState::Dead).await;
                 tracing::error!("PTY creation failed for {}: {}", self.name, e);
                 return Err(SessionError::PtyError(e.to_string()));
             }
         };
 
+        self.install_pty(pty).await;
+        Ok(())
+    }
+
+    /// Start the session on an existing PTY instead of spawning the agent,
+    /// e.g. a [`PtyHandle::detached`] handle that replays a recorded session
+    pub async fn attach(self: &Arc<Self>, pty: PtyHandle) -> Result<(), SessionError> {
+        let _lifecycle = self.lifecycle_lock.lock().await;
+
+        let current = self.get_state().await;
+        if !current.can_start() {
+            return Err(SessionError::InvalidTransition(format!(
+                "Cannot start from state {}",
+                current
+            )));
+        }
+
+        self.apply_transition(StateTransition::StartAgent).await?;
+        self.install_pty(Arc::new(pty)).await;
+        Ok(())
+    }
+
+    async fn install_pty(self: &Arc<Self>, pty: Arc<PtyHandle>) {
         // Subscribe to output BEFORE storing PTY to avoid missing initial output
         let pty_rx = pty.subscribe_output();
 
@@ -222,8 +589,6 @@ impl AgentSession {
 
         // Start ready detection task with pre-subscribed receiver
         Arc::clone(self).start_ready_detection_with_rx(pty_rx).await;
-
-        Ok(())
     }
 
     /// Start agent with retry logic and exponential backoff.
@@ -249,6 +614,13 @@ impl AgentSession {
                     max_retries + 1,
                     delay_ms
                 );
+                self.emit(EventKind::RestartAttempt {
+                    attempt,
+                    reason: last_error
+                        .as_ref()
+                        .map(|e: &SessionError| format!("Start failed: {}", e))
+                        .unwrap_or_default(),
+                });
                 tokio::time::sleep(Duration::from_millis(delay_ms)).await;
             }
 
@@ -271,6 +643,9 @@ impl AgentSession {
                         max_retries + 1,
                         e
                     );
+                    self.emit(EventKind::Error {
+                        message: format!("Start failed: {}", e),
+                    });
                     last_error = Some(e);
                 }
             }
@@ -324,7 +699,7 @@ impl AgentSession {
                     Ok(Ok(data)) => {
                         let text = String::from_utf8_lossy(&data);
                         accumulated.push_str(&text);
-                        tracing::debug!("PTY output for {}: {:?}", name, text);
+                        tracing::debug!(target: CONTENT_TARGET, "PTY output for {}: {:?}", name, text);
 
                         // Check both current chunk and accumulated output
                         if pattern.is_match(&text) || pattern.is_match(&accumulated) {
@@ -402,7 +777,7 @@ impl AgentSession {
                         Ok(Ok(data)) => {
                             let text = String::from_utf8_lossy(&data);
                             accumulated.push_str(&text);
-                            tracing::debug!("PTY output for {}: {:?}", name, text);
+                            tracing::debug!(target: CONTENT_TARGET, "PTY output for {}: {:?}", name, text);
 
                             // Check both current chunk and accumulated output
                             if pattern.is_match(&text) || pattern.is_match(&accumulated) {
@@ -437,6 +812,18 @@ impl AgentSession {
         &self,
         force: bool,
         pty_manager: Option<&crate::pty::PtyManager>,
+    ) -> Result<(), SessionError> {
+        self.stop_with_reason(force, pty_manager, "Agent stopped by user")
+            .await
+    }
+
+    /// Stop the agent, failing queued and in-flight requests with `reason`,
+    /// and terminate its whole process tree
+    pub async fn stop_with_reason(
+        &self,
+        force: bool,
+        pty_manager: Option<&crate::pty::PtyManager>,
+        reason: &str,
     ) -> Result<(), SessionError> {
         let _lifecycle = self.lifecycle_lock.lock().await;
 
@@ -454,34 +841,56 @@ impl AgentSession {
             let _queue_lock = self.request_queue_lock.lock().await;
             let mut queue = self.request_queue.lock().await;
             while let Some(req) = queue.pop_front() {
-                let _ = req.response_tx.send(Err(SessionError::Stopped(
-                    "Agent stopped by user".to_string(),
-                )));
+                self.respond(req, Err(SessionError::Stopped(reason.to_string())));
             }
 
             // Also clear current request
             let mut current_req = self.current_request.lock().await;
             if let Some(req) = current_req.take() {
-                let _ = req.response_tx.send(Err(SessionError::Stopped(
-                    "Agent stopped by user".to_string(),
-                )));
+                self.respond(req, Err(SessionError::Stopped(reason.to_string())));
             }
         }
 
         // Set state to dead
-        *self.state.write().await = AgentState::Dead;
+        self.set_state(AgentState::Dead).await;
 
         // Clean up PTY from manager if provided
         if let Some(manager) = pty_manager {
             manager.remove(&self.name).await;
         }
 
-        // Clean up PTY reference
-        *self.pty.write().await = None;
+        // Clean up PTY reference and kill everything the agent spawned;
+        // other holders of the handle would otherwise keep it alive
+        let pty = self.pty.write().await.take();
+        if let Some(pty) = pty {
+            pty.terminate(STOP_GRACE).await;
+        }
+        *self.resources.lock() = None;
 
         Ok(())
     }
 
+    /// Stop and start the agent again
+    pub async fn restart(
+        self: &Arc<Self>,
+        pty_manager: &crate::pty::PtyManager,
+        reason: &str,
+    ) -> Result<(), SessionError> {
+        self.stop_with_reason(true, Some(pty_manager), reason)
+            .await?;
+        let attempt = {
+            let mut count = self.restart_count.lock().await;
+            *count += 1;
+            *count
+        };
+        *self.last_restart.lock().await = Some(Instant::now());
+        self.emit(EventKind::RestartAttempt {
+            attempt,
+            reason: reason.to_string(),
+        });
+        self.start_with_retry(pty_manager).await
+    }
+
     pub async fn interrupt(&self) -> Result<(), SessionError> {
         let _lifecycle = self.lifecycle_lock.lock().await;
 
@@ -502,59 +911,99 @@ impl AgentSession {
             let _queue_lock = self.request_queue_lock.lock().await;
             let mut queue = self.request_queue.lock().await;
             while let Some(req) = queue.pop_front() {
-                let _ = req.response_tx.send(Err(SessionError::Stopped(
-                    "Agent interrupted by user".to_string(),
-                )));
+                self.respond(
+                    req,
+                    Err(SessionError::Stopped(
+                        "Agent interrupted by user".to_string(),
+                    )),
+                );
             }
 
             // Also clear current request
             let mut current_req = self.current_request.lock().await;
             if let Some(req) = current_req.take() {
-                let _ = req.response_tx.send(Err(SessionError::Stopped(
-                    "Agent interrupted by user".to_string(),
-                )));
+                self.respond(
+                    req,
+                    Err(SessionError::Stopped(
+                        "Agent interrupted by user".to_string(),
+                    )),
+                );
             }
 
             // Reset state
-            *self.state.write().await = AgentState::Idle;
+            self.set_state(AgentState::Idle).await;
         }
 
         Ok(())
     }
 
+    /// Cancel a queued or in-flight request; false when the session has no
+    /// such request. An in-flight request is failed right away and the agent
+    /// interrupted; the session stays busy until reply detection sees the
+    /// interrupted turn end, so queued requests are not typed into it
+    pub async fn cancel(&self, message_id: &str) -> Result<bool, SessionError> {
+        let _queue_lock = self.request_queue_lock.lock().await;
+
+        let mut queue = self.request_queue.lock().await;
+        if let Some(pos) = queue.iter().position(|req| req.id == message_id) {
+            if let Some(req) = queue.remove(pos) {
+                self.respond(req, Err(SessionError::Cancelled));
+            }
+            return Ok(true);
+        }
+        drop(queue);
+
+        let mut current_req = self.current_request.lock().await;
+        if current_req.as_ref().map(|req| req.id.as_str()) != Some(message_id) {
+            return Ok(false);
+        }
+        if let Some(pty) = self.pty.read().await.as_ref() {
+            pty.write(self.adapter.get_interrupt_sequence())
+                .await
+                .map_err(|e| SessionError::PtyError(e.to_string()))?;
+        }
+        if let Some(req) = current_req.take() {
+            self.respond(req, Err(SessionError::Cancelled));
+        }
+        Ok(true)
+    }
+
     pub async fn ask(
         self: &Arc<Self>,
         message: String,
         timeout: Option<Duration>,
         pty_manager: &crate::pty::PtyManager,
     ) -> Result<String, SessionError> {
-        let timeout = timeout.unwrap_or(Duration::from_secs(self.timeouts.default));
+        self.ask_with_id(Uuid::new_v4().to_string(), message, timeout, pty_manager)
+            .await
+            .map(|reply| reply.content)
+    }
 
-        // Auto-start agent if stopped, with retry on failure
-        let current = self.get_state().await;
-        if !current.is_running() {
-            self.start_with_retry(pty_manager).await?;
-        }
+    /// Like `ask`, but with a caller-supplied message id so the result can be
+    /// fetched from the journal if the caller disconnects or ccgonext restarts.
+    #[tracing::instrument(name = "ask", skip_all, fields(agent = %self.name, message_id = %message_id))]
+    pub async fn ask_with_id(
+        self: &Arc<Self>,
+        message_id: String,
+        message: String,
+        timeout: Option<Duration>,
+        pty_manager: &crate::pty::PtyManager,
+    ) -> Result<AgentReply, SessionError> {
+        let timeout = timeout.unwrap_or(Duration::from_secs(self.timeouts.default));
 
-        // Wait for agent to become ready (Idle or ReadyTimeout)
-        let ready_timeout = Duration::from_secs(self.timeouts.ready_check);
-        let ready_deadline = Instant::now() + ready_timeout;
-        loop {
-            let state = self.get_state().await;
-            if state.can_accept_request() {
-                break;
-            }
-            if !state.is_running() {
-                return Err(SessionError::NotRunning);
+        if let Some(usage) = &self.usage {
+            if let Err(used) = usage.check_budget(&self.name) {
+                return Err(SessionError::BudgetExceeded {
+                    used,
+                    budget: usage.daily_budget().unwrap_or_default(),
+                });
             }
-            if Instant::now() >= ready_deadline {
-                return Err(SessionError::QueueTimeout);
-            }
-            tokio::time::sleep(Duration::from_millis(100)).await;
         }
 
+        self.wait_until_ready(pty_manager).await?;
+
         let (tx, rx) = oneshot::channel();
-        let request = Request::new(message, timeout, tx);
+        let request = Request::with_id(message_id.clone(), message, timeout, tx);
 
         // Add to queue and prepare for processing under the lock
         let prepared = {
@@ -565,7 +1014,13 @@ impl AgentSession {
                 return Err(SessionError::NotRunning);
             }
 
-            // Add to queue
+            // Journal and announce the request only once it is really queued
+            if let Some(journal) = &self.journal {
+                journal.record_queued(&request.id, &self.name, &request.message);
+            }
+            self.emit(EventKind::RequestQueued {
+                message_id: message_id.clone(),
+            });
             self.request_queue.lock().await.push_back(request);
 
             // If idle, prepare to process immediately
@@ -594,11 +1049,51 @@ impl AgentSession {
         // Wait for response with timeout
         match tokio::time::timeout(timeout, rx).await {
             Ok(Ok(result)) => result,
-            Ok(Err(_)) => Err(SessionError::Stopped("Channel closed".to_string())),
+            Ok(Err(_)) => {
+                // Dropped without a response, so nothing recorded its outcome
+                let error = SessionError::Stopped("Channel closed".to_string());
+                if let Some(journal) = &self.journal {
+                    journal.record_failed(&message_id, &error.to_string());
+                }
+                self.emit(EventKind::RequestFailed {
+                    message_id,
+                    error: error.to_string(),
+                });
+                Err(error)
+            }
             Err(_) => Err(SessionError::RequestTimeout),
         }
     }
 
+    /// Start the agent if stopped, with retry on failure, and wait for it to
+    /// become ready (Idle or ReadyTimeout)
+    #[tracing::instrument(name = "start", skip_all)]
+    async fn wait_until_ready(
+        self: &Arc<Self>,
+        pty_manager: &crate::pty::PtyManager,
+    ) -> Result<(), SessionError> {
+        let current = self.get_state().await;
+        if !current.is_running() {
+            self.start_with_retry(pty_manager).await?;
+        }
+
+        let ready_timeout = Duration::from_secs(self.timeouts.ready_check);
+        let ready_deadline = Instant::now() + ready_timeout;
+        loop {
+            let state = self.get_state().await;
+            if state.can_accept_request() {
+                return Ok(());
+            }
+            if !state.is_running() {
+                return Err(SessionError::NotRunning);
+            }
+            if Instant::now() >= ready_deadline {
+                return Err(SessionError::QueueTimeout);
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
     /// Prepare the next request for processing. Must be called while holding queue_lock.
     /// Returns Some(prepared) if a request was prepared, None if queue empty or state not ready.
     async fn prepare_next_request(&self) -> Option<PreparedRequest> {
@@ -626,6 +1121,7 @@ impl AgentSession {
 
         let message_id = request.id.clone();
         let request_timeout = request.timeout;
+        let span = request.span.clone();
 
         // Prepare message with sentinel
         let message_with_sentinel = self
@@ -633,17 +1129,22 @@ impl AgentSession {
             .inject_message_sentinel(&request.message, &message_id);
 
         // Lock session and get baseline offset for log detection
-        let baseline_offset = if let Some(locked) = self.log_provider.lock_session().await {
-            tracing::debug!(
-                "Sending message pending to {}, locked session: {:?}",
-                self.name,
-                locked.file_path
-            );
-            locked.baseline_offset
-        } else {
-            tracing::debug!("Sending message pending to {}", self.name);
-            self.log_provider.get_current_offset().await
-        };
+        let (baseline_offset, session_file) =
+            if let Some(locked) = self.log_provider.lock_session().await {
+                tracing::debug!(
+                    "Sending message pending to {}, locked session: {:?}",
+                    self.name,
+                    locked.file_path
+                );
+                (locked.baseline_offset, Some(locked.file_path))
+            } else {
+                tracing::debug!("Sending message pending to {}", self.name);
+                (self.log_provider.get_current_offset().await, None)
+            };
+
+        if let Some(journal) = &self.journal {
+            journal.record_sent(&message_id, baseline_offset, session_file);
+        }
 
         // Get PTY offset for ClaudeCode parsing (before writing)
         let pty_start_offset = {
@@ -664,12 +1165,18 @@ impl AgentSession {
             baseline_offset,
             request_timeout,
             pty_start_offset,
+            span,
         })
     }
 
     /// Send a prepared request to PTY and start reply detection.
     /// Called after releasing the queue lock to avoid blocking stop/interrupt.
     /// Returns (result, should_retry) - if should_retry is true, caller should try next request.
+    #[tracing::instrument(
+        parent = &prepared.span,
+        skip_all,
+        fields(agent = %self.name, message_id = %prepared.message_id)
+    )]
     async fn send_prepared_request(
         self: &Arc<Self>,
         prepared: PreparedRequest,
@@ -683,9 +1190,10 @@ impl AgentSession {
             {
                 let mut current_req = self.current_request.lock().await;
                 if let Some(req) = current_req.take() {
-                    let _ = req
-                        .response_tx
-                        .send(Err(SessionError::PtyError("No PTY available".to_string())));
+                    self.respond(
+                        req,
+                        Err(SessionError::PtyError("No PTY available".to_string())),
+                    );
                 }
             }
             let _ = self.apply_transition(StateTransition::ReplyReceived).await;
@@ -695,6 +1203,30 @@ impl AgentSession {
             );
         };
 
+        if let Some(worktrees) = &self.worktrees {
+            if let Err(e) = worktrees.begin_request(&self.name).await {
+                // Without a base the agent's edits could not be told apart
+                // from the project's, so the request is not sent
+                tracing::warn!(
+                    "[Session] Failed to prepare worktree for {}: {}",
+                    self.name,
+                    e
+                );
+                drop(pty_guard);
+                self.log_provider.unlock_session().await;
+                if let Some(req) = self.current_request.lock().await.take() {
+                    self.respond(req, Err(SessionError::Worktree(e.to_string())));
+                }
+                let _ = self.apply_transition(StateTransition::ReplyReceived).await;
+                return (Err(SessionError::Worktree(e.to_string())), true);
+            }
+        }
+
+        if let Some(tracker) = &self.change_tracker {
+            let snapshot = tracker.begin(&self.working_dir).await;
+            *self.pending_snapshot.lock().await = Some(snapshot);
+        }
+
         // Send message to PTY
         tracing::info!(
             "[Session] Sending message to {} PTY: {} bytes",
@@ -704,14 +1236,15 @@ impl AgentSession {
         if let Err(e) = pty.write_line(&prepared.message_with_sentinel).await {
             tracing::error!("[Session] PTY write failed for {}: {}", self.name, e);
             drop(pty_guard);
+            self.emit(EventKind::Error {
+                message: format!("PTY write failed: {}", e),
+            });
             // PTY write failed, unlock session and clear current request
             self.log_provider.unlock_session().await;
             {
                 let mut current_req = self.current_request.lock().await;
                 if let Some(req) = current_req.take() {
-                    let _ = req
-                        .response_tx
-                        .send(Err(SessionError::PtyError(e.to_string())));
+                    self.respond(req, Err(SessionError::PtyError(e.to_string())));
                 }
             }
             // Try to go back to Idle (may fail if state changed)
@@ -719,6 +1252,9 @@ impl AgentSession {
             return (Err(SessionError::PtyError(e.to_string())), true);
         }
         drop(pty_guard);
+        self.emit(EventKind::RequestStarted {
+            message_id: prepared.message_id.clone(),
+        });
 
         // Check if this is ClaudeCode agent (PTY-only parsing)
         let is_claudecode = self.adapter.as_any().is::<ClaudeCodeAgent>();
@@ -730,6 +1266,7 @@ impl AgentSession {
                 prepared.message_id,
                 prepared.pty_start_offset,
                 prepared.request_timeout,
+                &prepared.span,
             );
         } else {
             // Other agents: Use LogProvider
@@ -738,6 +1275,7 @@ impl AgentSession {
                 prepared.message_id,
                 prepared.baseline_offset,
                 prepared.request_timeout,
+                &prepared.span,
             );
         }
 
@@ -772,7 +1310,14 @@ impl AgentSession {
         message_id: String,
         baseline_offset: u64,
         timeout: Duration,
+        parent: &tracing::Span,
     ) {
+        let span = tracing::info_span!(
+            parent: parent,
+            "reply_detection",
+            agent = %session.name,
+            message_id = %message_id
+        );
         let log_provider = session.log_provider.clone();
         let adapter = session.adapter.clone();
         let name = session.name.clone();
@@ -786,6 +1331,9 @@ impl AgentSession {
 
         tokio::spawn(async move {
             let deadline = Instant::now() + timeout;
+            let mut stream = ReplyStream::new(&session, &message_id);
+            // Closed once the first reply text shows up in the log
+            let generate = tracing::info_span!("generate");
 
             // Debounce interval in milliseconds
             const DEBOUNCE_MS: u64 = 100;
@@ -812,15 +1360,18 @@ impl AgentSession {
                     name,
                     entry.content.len()
                 );
-                let entry = Self::wait_for_stable_reply(
+                drop(generate);
+                let (entry, completion) = Self::wait_for_stable_reply(
                     &log_provider,
                     &adapter,
                     &message_id,
                     baseline_offset,
                     entry,
                     deadline,
+                    &mut stream,
                 )
                 .await;
+                session.record_reply_detection("initial", completion);
                 Self::deliver_reply(&session, entry).await;
                 return;
             }
@@ -868,15 +1419,18 @@ impl AgentSession {
                                         entry.content.len()
                                     );
                                     drop(sub.handle); // Cancel watcher
-                                    let entry = Self::wait_for_stable_reply(
+                                    drop(generate);
+                                    let (entry, completion) = Self::wait_for_stable_reply(
                                         &log_provider,
                                         &adapter,
                                         &message_id,
                                         baseline_offset,
                                         entry,
                                         deadline,
+                                        &mut stream,
                                     )
                                     .await;
+                                    session.record_reply_detection("event", completion);
                                     Self::deliver_reply(&session, entry).await;
                                     return;
                                 }
@@ -891,6 +1445,7 @@ impl AgentSession {
                                     n,
                                     name
                                 );
+                                session.record_lag("log_watch", n);
                                 // Missed some events, check now
                                 if let Some(entry) =
                                     log_provider.get_latest_reply(baseline_offset).await
@@ -901,15 +1456,18 @@ impl AgentSession {
                                         entry.content.len()
                                     );
                                     drop(sub.handle);
-                                    let entry = Self::wait_for_stable_reply(
+                                    drop(generate);
+                                    let (entry, completion) = Self::wait_for_stable_reply(
                                         &log_provider,
                                         &adapter,
                                         &message_id,
                                         baseline_offset,
                                         entry,
                                         deadline,
+                                        &mut stream,
                                     )
                                     .await;
+                                    session.record_reply_detection("event", completion);
                                     Self::deliver_reply(&session, entry).await;
                                     return;
                                 }
@@ -930,15 +1488,18 @@ impl AgentSession {
                                         entry.content.len()
                                     );
                                     drop(sub.handle);
-                                    let entry = Self::wait_for_stable_reply(
+                                    drop(generate);
+                                    let (entry, completion) = Self::wait_for_stable_reply(
                                         &log_provider,
                                         &adapter,
                                         &message_id,
                                         baseline_offset,
                                         entry,
                                         deadline,
+                                        &mut stream,
                                     )
                                     .await;
+                                    session.record_reply_detection("poll", completion);
                                     Self::deliver_reply(&session, entry).await;
                                     return;
                                 }
@@ -975,15 +1536,18 @@ impl AgentSession {
                         name,
                         entry.content.len()
                     );
-                    let entry = Self::wait_for_stable_reply(
+                    drop(generate);
+                    let (entry, completion) = Self::wait_for_stable_reply(
                         &log_provider,
                         &adapter,
                         &message_id,
                         baseline_offset,
                         entry,
                         deadline,
+                        &mut stream,
                     )
                     .await;
+                    session.record_reply_detection("poll", completion);
                     Self::deliver_reply(&session, entry).await;
                     return;
                 }
@@ -996,9 +1560,10 @@ impl AgentSession {
                 name
             );
             Self::handle_reply_timeout(&session, &name).await;
-        });
+        }.instrument(span));
     }
 
+    #[tracing::instrument(name = "detect", skip_all)]
     async fn wait_for_stable_reply(
         log_provider: &Arc<dyn LogProvider>,
         adapter: &Arc<dyn Agent>,
@@ -1006,15 +1571,17 @@ impl AgentSession {
         baseline_offset: u64,
         mut entry: crate::log_provider::LogEntry,
         deadline: Instant,
-    ) -> crate::log_provider::LogEntry {
+        stream: &mut ReplyStream,
+    ) -> (crate::log_provider::LogEntry, &'static str) {
         // If done marker was detected, validate message-ID before returning immediately
         if entry.done_seen && adapter.is_reply_complete(&entry.content, message_id) {
             tracing::debug!(
                 "[StableReply] Done marker with valid message-ID detected, returning immediately with {} bytes",
                 entry.content.len()
             );
-            return entry;
+            return (entry, "done_marker");
         }
+        stream.update(adapter, &entry.content);
 
         // Some CLIs (notably Gemini) update a single log entry incrementally while streaming.
         // Returning immediately can capture only the first chunk.
@@ -1068,7 +1635,7 @@ impl AgentSession {
                     stable_check_count,
                     entry.content.len()
                 );
-                break;
+                return (entry, "stability");
             }
 
             tokio::time::sleep(poll).await;
@@ -1086,7 +1653,7 @@ impl AgentSession {
                     "[StableReply] Done marker with valid message-ID detected during polling, returning with {} bytes",
                     next.content.len()
                 );
-                return next;
+                return (next, "done_marker");
             }
 
             // Compare content: length first (fast), then full content if lengths match
@@ -1101,6 +1668,7 @@ impl AgentSession {
                     next.content.len()
                 );
                 entry = next;
+                stream.update(adapter, &entry.content);
                 last_content_len = entry.content.len();
                 last_change = Instant::now();
                 stable_check_count = 0; // Reset stable counter on any change
@@ -1115,10 +1683,10 @@ impl AgentSession {
         }
 
         tracing::debug!(
-            "[StableReply] Returning reply with {} bytes",
+            "[StableReply] Deadline reached, returning reply with {} bytes",
             entry.content.len()
         );
-        entry
+        (entry, "deadline")
     }
 
     /// ClaudeCode-specific reply detection using PTY parsing
@@ -1127,81 +1695,137 @@ impl AgentSession {
         message_id: String,
         pty_start_offset: u64,
         timeout: Duration,
+        parent: &tracing::Span,
     ) {
         let name = session.name.clone();
+        let span = tracing::info_span!(
+            parent: parent,
+            "reply_detection",
+            agent = %session.name,
+            message_id = %message_id
+        );
 
-        tokio::spawn(async move {
-            // Get PTY handle
-            let pty = {
-                let pty_guard = session.pty.read().await;
-                match pty_guard.as_ref() {
-                    Some(p) => Arc::clone(p),
+        tokio::spawn(
+            async move {
+                // Get PTY handle
+                let pty = {
+                    let pty_guard = session.pty.read().await;
+                    match pty_guard.as_ref() {
+                        Some(p) => Arc::clone(p),
+                        None => {
+                            tracing::error!("No PTY available for ClaudeCode parsing");
+                            Self::handle_reply_timeout(&session, &name).await;
+                            return;
+                        }
+                    }
+                };
+
+                // Downcast adapter to ClaudeCodeAgent
+                let claudecode = match session.adapter.as_any().downcast_ref::<ClaudeCodeAgent>() {
+                    Some(cc) => cc,
                     None => {
-                        tracing::error!("No PTY available for ClaudeCode parsing");
+                        tracing::error!("Failed to downcast to ClaudeCodeAgent");
                         Self::handle_reply_timeout(&session, &name).await;
                         return;
                     }
-                }
-            };
-
-            // Downcast adapter to ClaudeCodeAgent
-            let claudecode = match session.adapter.as_any().downcast_ref::<ClaudeCodeAgent>() {
-                Some(cc) => cc,
-                None => {
-                    tracing::error!("Failed to downcast to ClaudeCodeAgent");
-                    Self::handle_reply_timeout(&session, &name).await;
-                    return;
-                }
-            };
+                };
 
-            // Call parse_pty_response with per-request timeout
-            match tokio::time::timeout(
-                timeout,
-                claudecode.parse_pty_response(&pty, pty_start_offset, &message_id),
-            )
-            .await
-            {
-                Ok(Ok(response)) => {
-                    tracing::debug!("ClaudeCode reply detected for {}: {}", name, response);
-                    // Create a LogEntry for compatibility with deliver_reply
-                    let entry = crate::log_provider::LogEntry {
-                        offset: pty_start_offset,
-                        content: response,
-                        timestamp: chrono::Utc::now(),
-                        inode: None,
-                        done_seen: true, // ClaudeCode uses PTY parsing, assume complete
-                    };
-                    Self::deliver_reply(&session, entry).await;
-                }
-                Ok(Err(e)) => {
-                    tracing::error!("ClaudeCode PTY parsing failed for {}: {}", name, e);
-                    Self::deliver_reply_error(&session, SessionError::PtyError(e.to_string()))
-                        .await;
-                }
-                Err(_) => {
-                    tracing::warn!("ClaudeCode reply detection timed out for {}", name);
-                    Self::handle_reply_timeout(&session, &name).await;
+                // Call parse_pty_response with per-request timeout
+                match tokio::time::timeout(
+                    timeout,
+                    claudecode.parse_pty_response(&pty, pty_start_offset, &message_id),
+                )
+                .await
+                {
+                    Ok(Ok(response)) => {
+                        tracing::debug!(
+                            target: CONTENT_TARGET,
+                            "ClaudeCode reply detected for {}: {}",
+                            name,
+                            response
+                        );
+                        // Create a LogEntry for compatibility with deliver_reply
+                        let entry = crate::log_provider::LogEntry {
+                            offset: pty_start_offset,
+                            content: response,
+                            timestamp: chrono::Utc::now(),
+                            inode: None,
+                            done_seen: true, // ClaudeCode uses PTY parsing, assume complete
+                            usage: None,
+                            model: None,
+                            reply: None,
+                        };
+                        session.record_reply_detection("pty", "done_marker");
+                        Self::deliver_reply(&session, entry).await;
+                    }
+                    Ok(Err(e)) => {
+                        tracing::error!("ClaudeCode PTY parsing failed for {}: {}", name, e);
+                        Self::deliver_reply_error(&session, SessionError::PtyError(e.to_string()))
+                            .await;
+                    }
+                    Err(_) => {
+                        tracing::warn!("ClaudeCode reply detection timed out for {}", name);
+                        Self::handle_reply_timeout(&session, &name).await;
+                    }
                 }
             }
-        });
+            .instrument(span),
+        );
     }
 
+    #[tracing::instrument(name = "deliver", skip_all)]
     async fn deliver_reply(session: &Arc<Self>, entry: crate::log_provider::LogEntry) {
         // Unlock session after reply detection completes
         session.log_provider.unlock_session().await;
 
-        // Deliver result to waiting request
-        {
-            let mut current_req = session.current_request.lock().await;
-            if let Some(req) = current_req.take() {
-                // Strip done marker from content before sending to client
-                let content = if entry.done_seen {
-                    session.adapter.strip_done_marker(&entry.content, &req.id)
-                } else {
-                    entry.content.clone()
-                };
-                let _ = req.response_tx.send(Ok(content));
+        let snapshot = session.pending_snapshot.lock().await.take();
+        let files = match (&session.change_tracker, snapshot) {
+            (Some(tracker), Some(snapshot)) => Some(tracker.finish(snapshot).await),
+            _ => None,
+        };
+
+        // Deliver result to waiting request. Take it first so cancel and
+        // interrupt are not blocked while the worktree change is committed.
+        let current_req = session.current_request.lock().await.take();
+        if let Some(req) = current_req {
+            // Strip done marker from content before sending to client
+            let content = if entry.done_seen {
+                session.adapter.strip_done_marker(&entry.content, &req.id)
+            } else {
+                entry.content.clone()
+            };
+            if let (Some(tracker), Some(usage)) = (&session.usage, &entry.usage) {
+                tracker.record(&session.name, usage);
             }
+            let reply = entry.reply.clone().map(|mut r| {
+                r.text = content.clone();
+                r
+            });
+            let change = match &session.worktrees {
+                Some(worktrees) => match worktrees.capture(&session.name, &req.id).await {
+                    Ok(change) => change,
+                    Err(e) => {
+                        tracing::warn!(
+                            "[Session] Failed to capture worktree change for {}: {}",
+                            session.name,
+                            e
+                        );
+                        None
+                    }
+                },
+                None => None,
+            };
+            session.respond(
+                req,
+                Ok(AgentReply {
+                    content,
+                    usage: entry.usage,
+                    model: entry.model.clone(),
+                    reply,
+                    change,
+                    files: files.filter(|f| !f.is_empty()),
+                }),
+            );
         }
 
         // Apply state transition
@@ -1216,14 +1840,16 @@ impl AgentSession {
         let _ = session.process_next_request().await;
     }
 
+    #[tracing::instrument(name = "deliver", skip_all, fields(error = %error))]
     async fn deliver_reply_error(session: &Arc<Self>, error: SessionError) {
         // Unlock session after reply detection completes
         session.log_provider.unlock_session().await;
+        session.pending_snapshot.lock().await.take();
 
         {
             let mut current_req = session.current_request.lock().await;
             if let Some(req) = current_req.take() {
-                let _ = req.response_tx.send(Err(error));
+                session.respond(req, Err(error));
             }
         }
 
@@ -1237,9 +1863,11 @@ impl AgentSession {
         let _ = session.process_next_request().await;
     }
 
+    #[tracing::instrument(name = "deliver", skip_all, fields(error = "timeout"))]
     async fn handle_reply_timeout(session: &Arc<Self>, name: &str) {
         // Unlock session after reply detection completes
         session.log_provider.unlock_session().await;
+        session.pending_snapshot.lock().await.take();
 
         tracing::warn!("Reply detection timed out for {}", name);
 
@@ -1261,7 +1889,7 @@ impl AgentSession {
         {
             let mut current_req = session.current_request.lock().await;
             if let Some(req) = current_req.take() {
-                let _ = req.response_tx.send(Err(SessionError::RequestTimeout));
+                session.respond(req, Err(SessionError::RequestTimeout));
             }
         }
 
@@ -1280,6 +1908,14 @@ impl AgentSession {
 pub struct SessionManager {
     sessions: RwLock<std::collections::HashMap<String, Arc<AgentSession>>>,
     pty_manager: Arc<crate::pty::PtyManager>,
+    journal: Arc<RequestJournal>,
+    events: Arc<EventBus>,
+    metrics: Arc<Metrics>,
+    cache: Option<Arc<ResponseCache>>,
+    usage: Arc<UsageTracker>,
+    worktrees: Option<Arc<WorktreeManager>>,
+    change_tracker: Option<Arc<ChangeTracker>>,
+    prompts: Arc<PromptLibrary>,
 }
 
 impl SessionManager {
@@ -1287,14 +1923,164 @@ impl SessionManager {
         Self {
             sessions: RwLock::new(std::collections::HashMap::new()),
             pty_manager,
+            journal: Arc::new(RequestJournal::in_memory()),
+            events: Arc::new(EventBus::new()),
+            metrics: Arc::new(Metrics::new()),
+            cache: None,
+            usage: Arc::new(UsageTracker::new(None)),
+            worktrees: None,
+            change_tracker: None,
+            prompts: Arc::new(PromptLibrary::new(None)),
+        }
+    }
+
+    /// Use a (typically file-backed) journal instead of the default in-memory one
+    pub fn with_journal(mut self, journal: Arc<RequestJournal>) -> Self {
+        self.journal = journal;
+        self
+    }
+
+    pub fn journal(&self) -> &Arc<RequestJournal> {
+        &self.journal
+    }
+
+    /// Events of every registered session
+    pub fn events(&self) -> &Arc<EventBus> {
+        &self.events
+    }
+
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
+    }
+
+    /// Metrics of every registered session in the Prometheus text format
+    pub async fn render_metrics(&self) -> String {
+        let mut sessions: Vec<_> = self.sessions.read().await.values().cloned().collect();
+        sessions.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut agents = Vec::with_capacity(sessions.len());
+        for session in sessions {
+            let traffic = self.pty_manager.traffic(&session.name);
+            agents.push(AgentGauges {
+                queue_depth: session.request_queue.lock().await.len(),
+                restarts: *session.restart_count.lock().await,
+                pty_bytes_in: traffic.bytes_in(),
+                pty_bytes_out: traffic.bytes_out(),
+                agent: session.name.clone(),
+            });
         }
+        self.metrics.render(&agents)
+    }
+
+    /// Enable the response cache used by `ask_agents`
+    pub fn with_cache(mut self, cache: Arc<ResponseCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn cache(&self) -> Option<&Arc<ResponseCache>> {
+        self.cache.as_ref()
     }
 
-    pub async fn register(&self, session: AgentSession) {
+    /// Use a usage tracker with a budget or persistence instead of the default one
+    pub fn with_usage_tracker(mut self, usage: Arc<UsageTracker>) -> Self {
+        self.usage = usage;
+        self
+    }
+
+    pub fn usage(&self) -> &Arc<UsageTracker> {
+        &self.usage
+    }
+
+    /// Enable per-agent worktree isolation; worktrees are removed in `shutdown_all`
+    pub fn with_worktrees(mut self, worktrees: Arc<WorktreeManager>) -> Self {
+        self.worktrees = Some(worktrees);
+        self
+    }
+
+    pub fn worktrees(&self) -> Option<&Arc<WorktreeManager>> {
+        self.worktrees.as_ref()
+    }
+
+    /// Use a prompt library with a prompts directory instead of the built-ins only
+    pub fn with_prompts(mut self, prompts: Arc<PromptLibrary>) -> Self {
+        self.prompts = prompts;
+        self
+    }
+
+    pub fn prompts(&self) -> &Arc<PromptLibrary> {
+        &self.prompts
+    }
+
+    /// Enable per-request filesystem change tracking for all sessions
+    pub fn with_change_tracker(mut self, tracker: Arc<ChangeTracker>) -> Self {
+        self.change_tracker = Some(tracker);
+        self
+    }
+
+    pub async fn register(&self, mut session: AgentSession) {
+        if session.journal.is_none() {
+            session.journal = Some(Arc::clone(&self.journal));
+        }
+        if session.events.is_none() {
+            session.events = Some(Arc::clone(&self.events));
+        }
+        if session.metrics.is_none() {
+            session.metrics = Some(Arc::clone(&self.metrics));
+        }
+        if session.usage.is_none() {
+            session.usage = Some(Arc::clone(&self.usage));
+        }
+        if session.worktrees.is_none() {
+            session.worktrees = self.worktrees.clone();
+        }
+        if session.change_tracker.is_none() {
+            session.change_tracker = self.change_tracker.clone();
+        }
         let name = session.name.clone();
         self.sessions.write().await.insert(name, Arc::new(session));
     }
 
+    /// Resolve requests left unfinished by a previous run.
+    /// Replies for requests that were already sent are harvested from the agent's
+    /// log after the recorded baseline; everything else is marked lost.
+    pub async fn recover_journal(&self) {
+        let unfinished = self.journal.unfinished();
+        if unfinished.is_empty() {
+            return;
+        }
+
+        tracing::info!(
+            "[Journal] Recovering {} unfinished requests",
+            unfinished.len()
+        );
+
+        for record in unfinished {
+            let id = &record.message_id;
+            let session = self.get(&record.agent).await;
+            let (Some(session), Some(session_file)) = (session, record.session_file.clone()) else {
+                self.journal
+                    .record_lost(id, "ccgonext restarted before the reply was received");
+                continue;
+            };
+
+            let locked = crate::log_provider::LockedSession {
+                file_path: session_file,
+                baseline_offset: record.baseline_offset,
+            };
+            match session.log_provider.harvest_reply(&locked).await {
+                Some(entry) if session.adapter.is_reply_complete(&entry.content, id) => {
+                    let content = session.adapter.strip_done_marker(&entry.content, id);
+                    tracing::info!("[Journal] Recovered reply for {} from {}", id, record.agent);
+                    self.journal.record_completed(id, &content);
+                }
+                _ => {
+                    self.journal
+                        .record_lost(id, "ccgonext restarted and no complete reply was found");
+                }
+            }
+        }
+    }
+
     pub async fn get(&self, name: &str) -> Option<Arc<AgentSession>> {
         self.sessions.read().await.get(name).cloned()
     }
@@ -1347,6 +2133,49 @@ impl SessionManager {
         tracing::info!("All agents pre-started");
     }
 
+    /// Sample CPU and memory of every agent's process tree each `interval`
+    /// and restart agents that exceed their memory limit
+    pub fn spawn_resource_monitor(
+        self: &Arc<Self>,
+        interval: Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        let manager = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                let Some(manager) = manager.upgrade() else {
+                    return;
+                };
+                let table = tokio::task::spawn_blocking(ProcessTable::read)
+                    .await
+                    .unwrap_or_default();
+                let sessions: Vec<_> = manager.sessions.read().await.values().cloned().collect();
+                for session in sessions {
+                    let Some(stats) = session.sample_resources(&table).await else {
+                        continue;
+                    };
+                    let Some(limit) = session.memory_limit_mb() else {
+                        continue;
+                    };
+                    if stats.rss_mb() <= limit {
+                        continue;
+                    }
+                    let reason = format!(
+                        "Agent restarted: memory limit exceeded ({} MiB > {} MiB)",
+                        stats.rss_mb(),
+                        limit
+                    );
+                    tracing::warn!("[Monitor] {}: {}", session.name, reason);
+                    if let Err(e) = session.restart(&manager.pty_manager, &reason).await {
+                        tracing::warn!("[Monitor] Failed to restart {}: {}", session.name, e);
+                    }
+                }
+            }
+        })
+    }
+
     /// Shutdown all sessions and their PTY processes
     pub async fn shutdown_all(&self) {
         tracing::info!("Shutting down all sessions...");
@@ -1363,6 +2192,67 @@ impl SessionManager {
         // Then shutdown all PTY handles
         self.pty_manager.shutdown_all().await;
 
+        if let Some(worktrees) = &self.worktrees {
+            worktrees.cleanup().await;
+        }
+
         tracing::info!("All sessions shut down");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_provider::NullLogProvider;
+    use crate::pty::PtyManager;
+
+    #[tokio::test]
+    async fn test_session_stopping_before_enqueue_leaves_no_pending_record() {
+        let journal = Arc::new(RequestJournal::in_memory());
+        let events = Arc::new(EventBus::new());
+        let mut received = events.subscribe();
+        let session = Arc::new(
+            AgentSession::new(
+                "codex".to_string(),
+                Arc::new(ClaudeCodeAgent::new()),
+                Arc::new(NullLogProvider),
+                PathBuf::from("."),
+                TimeoutConfig::default(),
+            )
+            .with_journal(Arc::clone(&journal))
+            .with_events(events),
+        );
+        session.set_state(AgentState::Idle).await;
+        let pty_manager = PtyManager::new(1024);
+
+        // Hold the queue so the ask passes the readiness check and then waits
+        let queue_lock = session.request_queue_lock.lock().await;
+        let ask = {
+            let session = Arc::clone(&session);
+            tokio::spawn(async move {
+                session
+                    .ask_with_id(
+                        "m1".to_string(),
+                        "hello".to_string(),
+                        Some(Duration::from_secs(5)),
+                        &pty_manager,
+                    )
+                    .await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        session.set_state(AgentState::Stopped).await;
+        drop(queue_lock);
+
+        assert!(matches!(ask.await.unwrap(), Err(SessionError::NotRunning)));
+        assert!(journal.get("m1").is_none());
+        assert!(journal.unfinished().is_empty());
+        while let Ok(event) = received.try_recv() {
+            assert!(
+                !matches!(event.kind, EventKind::RequestQueued { .. }),
+                "unexpected {:?}",
+                event
+            );
+        }
+    }
+}
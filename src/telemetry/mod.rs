@@ -0,0 +1,191 @@
+//! OpenTelemetry trace export
+//!
+//! Spans from `tracing` (`tools/call`, `ask`, `start`,
+//! `send_prepared_request`, `reply_detection` with its `generate` and
+//! `detect` phases, and `deliver`) are exported over OTLP/HTTP to a
+//! collector, or appended to a file as OTLP JSON, one export request per
+//! line. Export runs on the SDK's own thread and never blocks a session.
+
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceRequest;
+use opentelemetry_proto::transform::common::tonic::ResourceAttributesWithSchema;
+use opentelemetry_proto::transform::trace::tonic::group_spans_by_resource_and_scope;
+use opentelemetry_sdk::error::{OTelSdkError, OTelSdkResult};
+use opentelemetry_sdk::trace::{SdkTracerProvider, SpanData, SpanExporter};
+use opentelemetry_sdk::Resource;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use tracing::Subscriber;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// Path collectors receive OTLP/HTTP traces on
+const TRACES_PATH: &str = "/v1/traces";
+
+static PROVIDER: OnceLock<SdkTracerProvider> = OnceLock::new();
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OtlpTarget {
+    /// Collector URL, e.g. `http://localhost:4318`
+    Endpoint(String),
+    /// File that receives OTLP JSON lines
+    File(PathBuf),
+}
+
+/// Start exporting spans to `target`; add the returned layer to the subscriber
+pub fn init<S>(target: &OtlpTarget) -> anyhow::Result<impl Layer<S>>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let builder = SdkTracerProvider::builder().with_resource(
+        Resource::builder()
+            .with_service_name("ccgonext")
+            .with_attribute(KeyValue::new("service.version", env!("CARGO_PKG_VERSION")))
+            .build(),
+    );
+    let provider = match target {
+        OtlpTarget::Endpoint(endpoint) => {
+            let exporter = opentelemetry_otlp::SpanExporter::builder()
+                .with_http()
+                .with_endpoint(traces_url(endpoint))
+                .build()?;
+            builder.with_batch_exporter(exporter).build()
+        }
+        OtlpTarget::File(path) => builder
+            .with_batch_exporter(FileExporter::create(path)?)
+            .build(),
+    };
+    let tracer = provider.tracer("ccgonext");
+    let _ = PROVIDER.set(provider);
+    Ok(tracing_opentelemetry::layer().with_tracer(tracer))
+}
+
+/// Export the spans still buffered; call before the process exits
+pub fn shutdown() {
+    if let Some(provider) = PROVIDER.get() {
+        if let Err(e) = provider.shutdown() {
+            eprintln!("Failed to flush traces: {}", e);
+        }
+    }
+}
+
+/// Collector URL with the traces path appended, unless already given
+fn traces_url(endpoint: &str) -> String {
+    let endpoint = endpoint.trim_end_matches('/');
+    if endpoint.ends_with(TRACES_PATH) {
+        endpoint.to_string()
+    } else {
+        format!("{}{}", endpoint, TRACES_PATH)
+    }
+}
+
+/// Appends each batch as an OTLP JSON `ExportTraceServiceRequest` line, the
+/// format of the OpenTelemetry collector's file exporter
+#[derive(Debug)]
+pub struct FileExporter {
+    file: Mutex<File>,
+    resource: ResourceAttributesWithSchema,
+}
+
+impl FileExporter {
+    pub fn create(path: &Path) -> std::io::Result<Self> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+            resource: ResourceAttributesWithSchema::default(),
+        })
+    }
+
+    fn write(&self, batch: Vec<SpanData>) -> Result<(), String> {
+        let request = ExportTraceServiceRequest {
+            resource_spans: group_spans_by_resource_and_scope(batch, &self.resource),
+        };
+        let mut line = serde_json::to_string(&request).map_err(|e| e.to_string())?;
+        line.push('\n');
+        let mut file = self.file.lock().map_err(|e| e.to_string())?;
+        file.write_all(line.as_bytes())
+            .and_then(|_| file.flush())
+            .map_err(|e| e.to_string())
+    }
+}
+
+impl SpanExporter for FileExporter {
+    async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+        self.write(batch).map_err(OTelSdkError::InternalFailure)
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.resource = resource.into();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use serde_json::Value;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_traces_url() {
+        assert_eq!(
+            traces_url("http://localhost:4318"),
+            "http://localhost:4318/v1/traces"
+        );
+        assert_eq!(
+            traces_url("http://localhost:4318/"),
+            "http://localhost:4318/v1/traces"
+        );
+        assert_eq!(
+            traces_url("http://collector:4318/v1/traces"),
+            "http://collector:4318/v1/traces"
+        );
+    }
+
+    #[test]
+    fn test_file_exporter_writes_nested_spans() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("traces").join("ccgonext.jsonl");
+        let provider = SdkTracerProvider::builder()
+            .with_resource(Resource::builder().with_service_name("ccgonext").build())
+            .with_simple_exporter(FileExporter::create(&path).unwrap())
+            .build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let ask = tracing::info_span!("ask", agent = "codex", message_id = "m1");
+            let _ask = ask.enter();
+            tracing::info_span!("generate").in_scope(|| {});
+        });
+        provider.shutdown().unwrap();
+
+        let spans: Vec<Value> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .flat_map(|request| {
+                let resource = &request["resourceSpans"][0];
+                let service = &resource["resource"]["attributes"];
+                assert!(service.to_string().contains("ccgonext"), "{}", service);
+                resource["scopeSpans"][0]["spans"]
+                    .as_array()
+                    .cloned()
+                    .unwrap()
+            })
+            .collect();
+        let ask = spans.iter().find(|s| s["name"] == "ask").unwrap();
+        let generate = spans.iter().find(|s| s["name"] == "generate").unwrap();
+        assert_eq!(generate["parentSpanId"], ask["spanId"]);
+        assert_eq!(generate["traceId"], ask["traceId"]);
+        let attributes = ask["attributes"].to_string();
+        assert!(attributes.contains("message_id") && attributes.contains("m1"));
+    }
+}
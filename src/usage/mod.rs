@@ -0,0 +1,192 @@
+//! Token usage accounting
+//!
+//! Keeps running token totals per agent and per (local) day, fed from the
+//! usage recorded in agent logs. An optional daily budget makes new asks to
+//! an agent fail once its total for the day has reached the cap.
+
+use crate::log_provider::TokenUsage;
+use chrono::{Local, NaiveDate};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Number of past days kept in the totals
+const RETAIN_DAYS: usize = 31;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageTotals {
+    /// Replies that reported usage
+    pub requests: u64,
+    #[serde(flatten)]
+    pub tokens: TokenUsage,
+}
+
+/// Totals keyed by day, then by agent
+pub type DailyTotals = BTreeMap<NaiveDate, HashMap<String, UsageTotals>>;
+
+pub struct UsageTracker {
+    daily_budget: Option<u64>,
+    path: Option<PathBuf>,
+    totals: Mutex<DailyTotals>,
+}
+
+impl UsageTracker {
+    /// Tracker that only lives for the lifetime of the process
+    pub fn new(daily_budget: Option<u64>) -> Self {
+        Self {
+            daily_budget,
+            path: None,
+            totals: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Open (or create) a persisted usage file
+    pub fn open(path: &Path, daily_budget: Option<u64>) -> std::io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+
+        let totals = match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                tracing::warn!("[Usage] Ignoring unreadable usage file: {}", e);
+                BTreeMap::new()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+
+        Ok(Self {
+            daily_budget,
+            path: Some(path.to_path_buf()),
+            totals: Mutex::new(totals),
+        })
+    }
+
+    pub fn daily_budget(&self) -> Option<u64> {
+        self.daily_budget
+    }
+
+    fn today() -> NaiveDate {
+        Local::now().date_naive()
+    }
+
+    fn persist(&self, totals: &DailyTotals) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let tmp_path = path.with_extension("json.tmp");
+        let result = serde_json::to_vec_pretty(totals)
+            .map_err(std::io::Error::from)
+            .and_then(|data| {
+                let mut tmp = File::create(&tmp_path)?;
+                tmp.write_all(&data)?;
+                tmp.sync_all()
+            })
+            .and_then(|_| fs::rename(&tmp_path, path));
+        if let Err(e) = result {
+            tracing::warn!("[Usage] Failed to persist usage totals: {}", e);
+        }
+    }
+
+    /// Add the usage of one reply to today's totals for `agent`
+    pub fn record(&self, agent: &str, usage: &TokenUsage) {
+        let mut totals = self.totals.lock();
+        let entry = totals
+            .entry(Self::today())
+            .or_default()
+            .entry(agent.to_string())
+            .or_default();
+        entry.requests += 1;
+        entry.tokens.accumulate(usage);
+
+        while totals.len() > RETAIN_DAYS {
+            totals.pop_first();
+        }
+        self.persist(&totals);
+    }
+
+    /// Today's totals for `agent`
+    pub fn today_for(&self, agent: &str) -> UsageTotals {
+        self.totals
+            .lock()
+            .get(&Self::today())
+            .and_then(|day| day.get(agent))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Err(used_tokens) when `agent` has reached the daily budget
+    pub fn check_budget(&self, agent: &str) -> Result<(), u64> {
+        let Some(budget) = self.daily_budget else {
+            return Ok(());
+        };
+        let used = self.today_for(agent).tokens.total_tokens;
+        if used >= budget {
+            Err(used)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn snapshot(&self) -> DailyTotals {
+        self.totals.lock().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn usage(total: u64) -> TokenUsage {
+        TokenUsage {
+            input_tokens: total / 2,
+            output_tokens: total - total / 2,
+            total_tokens: total,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_record_accumulates_per_agent() {
+        let tracker = UsageTracker::new(None);
+        tracker.record("codex", &usage(10));
+        tracker.record("codex", &usage(5));
+        tracker.record("gemini", &usage(7));
+
+        let codex = tracker.today_for("codex");
+        assert_eq!(codex.requests, 2);
+        assert_eq!(codex.tokens.total_tokens, 15);
+        assert_eq!(tracker.today_for("gemini").tokens.total_tokens, 7);
+        assert_eq!(tracker.today_for("opencode"), UsageTotals::default());
+    }
+
+    #[test]
+    fn test_budget_refuses_once_reached() {
+        let tracker = UsageTracker::new(Some(100));
+        assert!(tracker.check_budget("codex").is_ok());
+        tracker.record("codex", &usage(60));
+        assert!(tracker.check_budget("codex").is_ok());
+        tracker.record("codex", &usage(60));
+        assert_eq!(tracker.check_budget("codex"), Err(120));
+        assert!(tracker.check_budget("gemini").is_ok());
+    }
+
+    #[test]
+    fn test_totals_persist() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("usage.json");
+        {
+            let tracker = UsageTracker::open(&path, None).unwrap();
+            tracker.record("codex", &usage(42));
+        }
+        let tracker = UsageTracker::open(&path, Some(10)).unwrap();
+        assert_eq!(tracker.today_for("codex").tokens.total_tokens, 42);
+        assert!(tracker.check_budget("codex").is_err());
+    }
+}
@@ -0,0 +1,338 @@
+//! Origin, host and reverse-proxy policy
+//!
+//! Browsers on `localhost`, `127.0.0.1` and `::1` are always allowed.
+//! `--allowed-origins` adds origins such as `https://*.example.com`, and
+//! `--allowed-hosts` restricts the `Host` a request may be addressed to,
+//! which also admits pages served from those hosts. Behind a reverse proxy
+//! listed in `--trusted-proxies`, `X-Forwarded-For`, `X-Forwarded-Host` and
+//! `X-Forwarded-Proto` stand in for the connection's own address, host and
+//! scheme; from anyone else they are ignored. With TLS enabled, local pages
+//! on the server's own port must be served over `https://`.
+
+use crate::config::WebConfig;
+use crate::sandbox::env_pattern_matches;
+use axum::http::{header, HeaderMap};
+use ipnet::IpNet;
+use std::net::{IpAddr, SocketAddr};
+
+const LOCAL_HOSTS: &[&str] = &["localhost", "127.0.0.1", "::1"];
+
+/// Where a request came from, after applying trusted `X-Forwarded-*` headers
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientInfo {
+    /// Client address; unknown for in-process requests
+    pub ip: Option<IpAddr>,
+    /// Host the request was addressed to, with its port if given
+    pub host: Option<String>,
+    /// Whether the client connected over HTTPS
+    pub https: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AccessPolicy {
+    port: u16,
+    https: bool,
+    origins: Vec<String>,
+    hosts: Vec<String>,
+    trusted_proxies: Vec<IpNet>,
+}
+
+impl AccessPolicy {
+    pub fn new(config: &WebConfig, port: u16) -> Self {
+        let lower = |patterns: &[String]| -> Vec<String> {
+            patterns
+                .iter()
+                .map(|p| p.trim().trim_end_matches('/').to_ascii_lowercase())
+                .filter(|p| !p.is_empty())
+                .collect()
+        };
+        Self {
+            port,
+            https: config.tls.is_some(),
+            origins: lower(&config.allowed_origins),
+            hosts: lower(&config.allowed_hosts),
+            trusted_proxies: config.trusted_proxies.clone(),
+        }
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.trusted_proxies.iter().any(|net| net.contains(&ip))
+    }
+
+    pub fn client_info(&self, peer: Option<SocketAddr>, headers: &HeaderMap) -> ClientInfo {
+        let header_value = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+        };
+        let own_host = header_value(header::HOST.as_str()).map(String::from);
+        let mut client = ClientInfo {
+            ip: peer.map(|peer| peer.ip()),
+            host: own_host,
+            https: self.https,
+        };
+        if !client.ip.is_some_and(|ip| self.is_trusted(ip)) {
+            return client;
+        }
+
+        // The nearest address not itself a trusted proxy is the client
+        if let Some(forwarded_for) = header_value("x-forwarded-for") {
+            for hop in forwarded_for.rsplit(',') {
+                let Ok(ip) = hop.trim().parse::<IpAddr>() else {
+                    break;
+                };
+                client.ip = Some(ip);
+                if !self.is_trusted(ip) {
+                    break;
+                }
+            }
+        }
+        if let Some(host) = header_value("x-forwarded-host") {
+            client.host = host.split(',').next().map(|h| h.trim().to_string());
+        }
+        if let Some(proto) = header_value("x-forwarded-proto") {
+            client.https = proto
+                .split(',')
+                .next()
+                .is_some_and(|p| p.trim().eq_ignore_ascii_case("https"));
+        }
+        client
+    }
+
+    /// Any host when `--allowed-hosts` is unset; otherwise local hosts and
+    /// the listed ones, with or without a port
+    pub fn host_allowed(&self, host: Option<&str>) -> bool {
+        if self.hosts.is_empty() {
+            return true;
+        }
+        let Some(host_port) = host.map(str::to_ascii_lowercase) else {
+            return false;
+        };
+        let Some((name, _)) = split_host_port(&host_port) else {
+            return false;
+        };
+        LOCAL_HOSTS.contains(&name) || self.matches_host(&host_port, name)
+    }
+
+    fn matches_host(&self, host_port: &str, name: &str) -> bool {
+        self.hosts.iter().any(|pattern| {
+            env_pattern_matches(pattern, host_port) || env_pattern_matches(pattern, name)
+        })
+    }
+
+    pub fn origin_allowed(&self, origin: &str) -> bool {
+        let origin = origin.trim().trim_end_matches('/').to_ascii_lowercase();
+        if is_local_origin(&origin, self.port, self.https) {
+            return true;
+        }
+        if self
+            .origins
+            .iter()
+            .any(|pattern| env_pattern_matches(pattern, &origin))
+        {
+            return true;
+        }
+        let host_port = origin_host_port(&origin);
+        !self.hosts.is_empty()
+            && split_host_port(host_port)
+                .is_some_and(|(name, _)| self.matches_host(host_port, name))
+    }
+}
+
+/// `host[:port]` of an origin such as `https://example.com:8443`
+fn origin_host_port(origin: &str) -> &str {
+    let host_part = origin
+        .strip_prefix("http://")
+        .or_else(|| origin.strip_prefix("https://"))
+        .unwrap_or(origin);
+    host_part.split('/').next().unwrap_or(host_part)
+}
+
+/// Whether an origin uses HTTPS; `None` for other or missing schemes
+fn origin_is_https(origin: &str) -> Option<bool> {
+    let (scheme, _) = origin.split_once("://")?;
+    if scheme.eq_ignore_ascii_case("https") {
+        Some(true)
+    } else if scheme.eq_ignore_ascii_case("http") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Split `host:port`, including bracketed IPv6 (`[::1]:1234`)
+fn split_host_port(host_port: &str) -> Option<(&str, Option<u16>)> {
+    if let Some(host_port) = host_port.strip_prefix('[') {
+        let end = host_port.find(']')?;
+        let port = host_port[end + 1..]
+            .strip_prefix(':')
+            .and_then(|p| p.parse::<u16>().ok());
+        return Some((&host_port[..end], port));
+    }
+    Some(match host_port.rsplit_once(':') {
+        Some((host, port_str)) => (host, port_str.parse::<u16>().ok()),
+        None => (host_port, None),
+    })
+}
+
+/// A local origin on the server's port with the server's scheme, or on the
+/// scheme's default port (a local reverse proxy)
+fn is_local_origin(origin: &str, allowed_port: u16, https: bool) -> bool {
+    let origin = origin.trim();
+    let Some(origin_https) = origin_is_https(origin) else {
+        return false;
+    };
+    let Some((host, port)) = split_host_port(origin_host_port(origin)) else {
+        return false;
+    };
+
+    // Strict host matching
+    if !LOCAL_HOSTS.contains(&host) {
+        return false;
+    }
+
+    let default_port = if origin_https { 443 } else { 80 };
+    let port = port.unwrap_or(default_port);
+    port == default_port || (port == allowed_port && origin_https == https)
+}
+
+pub fn validate_origin(headers: &HeaderMap, allowed_port: u16, https: bool) -> bool {
+    let Some(origin) = headers.get("origin").and_then(|v| v.to_str().ok()) else {
+        // No origin header - might be same-origin request
+        return true;
+    };
+    is_local_origin(origin, allowed_port, https)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(origins: &[&str], hosts: &[&str], proxies: &[&str]) -> AccessPolicy {
+        let config = WebConfig {
+            allowed_origins: origins.iter().map(|s| s.to_string()).collect(),
+            allowed_hosts: hosts.iter().map(|s| s.to_string()).collect(),
+            trusted_proxies: proxies.iter().map(|s| s.parse().unwrap()).collect(),
+            ..WebConfig::default()
+        };
+        AccessPolicy::new(&config, 8765)
+    }
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_local_origins() {
+        let policy = policy(&[], &[], &[]);
+        assert!(policy.origin_allowed("http://localhost:8765"));
+        assert!(policy.origin_allowed("http://[::1]:8765"));
+        assert!(policy.origin_allowed("https://127.0.0.1"));
+        assert!(!policy.origin_allowed("http://localhost:9999"));
+        assert!(!policy.origin_allowed("http://evil.example.com:8765"));
+        assert!(!policy.origin_allowed("localhost:8765"));
+        assert!(validate_origin(&HeaderMap::new(), 8765, false));
+        assert!(!validate_origin(
+            &headers(&[("origin", "http://evil.example.com")]),
+            8765,
+            false
+        ));
+    }
+
+    #[test]
+    fn test_local_origins_with_tls() {
+        let config = WebConfig {
+            tls: Some(crate::config::TlsConfig::SelfSigned { dir: "tls".into() }),
+            ..WebConfig::default()
+        };
+        let policy = AccessPolicy::new(&config, 8765);
+        assert!(policy.origin_allowed("https://localhost:8765"));
+        assert!(policy.origin_allowed("https://127.0.0.1"));
+        assert!(policy.origin_allowed("http://localhost"));
+        assert!(!policy.origin_allowed("http://localhost:8765"));
+        assert!(!policy.origin_allowed("http://localhost:443"));
+        assert!(!policy.origin_allowed("https://localhost:80"));
+
+        // Plain HTTP servers only accept plain HTTP on their own port
+        let plain = AccessPolicy::new(&WebConfig::default(), 8765);
+        assert!(!plain.origin_allowed("https://localhost:8765"));
+
+        // Direct TLS connections count as HTTPS for cookies
+        assert!(policy.client_info(None, &HeaderMap::new()).https);
+    }
+
+    #[test]
+    fn test_origin_patterns() {
+        let policy = policy(
+            &["https://*.example.com", "http://192.168.1.10:8765"],
+            &[],
+            &[],
+        );
+        assert!(policy.origin_allowed("https://ccgo.example.com"));
+        assert!(policy.origin_allowed("HTTPS://CCGO.EXAMPLE.COM/"));
+        assert!(!policy.origin_allowed("http://ccgo.example.com"));
+        assert!(!policy.origin_allowed("https://example.com.evil.net"));
+        assert!(policy.origin_allowed("http://192.168.1.10:8765"));
+        assert!(!policy.origin_allowed("http://192.168.1.10:8766"));
+    }
+
+    #[test]
+    fn test_allowed_hosts() {
+        let open = policy(&[], &[], &[]);
+        assert!(open.host_allowed(Some("anything:1234")));
+        assert!(open.host_allowed(None));
+
+        let policy = policy(&[], &["*.corp.internal", "192.168.1.10:8765"], &[]);
+        assert!(policy.host_allowed(Some("localhost:8765")));
+        assert!(policy.host_allowed(Some("ccgo.corp.internal")));
+        assert!(policy.host_allowed(Some("ccgo.corp.internal:8443")));
+        assert!(policy.host_allowed(Some("192.168.1.10:8765")));
+        assert!(!policy.host_allowed(Some("192.168.1.10:80")));
+        assert!(!policy.host_allowed(Some("rebind.attacker.net:8765")));
+        assert!(!policy.host_allowed(None));
+
+        // Pages served from an allowed host may call the API
+        assert!(policy.origin_allowed("https://ccgo.corp.internal"));
+        assert!(!policy.origin_allowed("https://rebind.attacker.net"));
+    }
+
+    #[test]
+    fn test_forwarded_headers_only_from_trusted_proxies() {
+        let policy = policy(&[], &[], &["10.0.0.0/8"]);
+        let forwarded = headers(&[
+            ("host", "127.0.0.1:8765"),
+            ("x-forwarded-for", "203.0.113.7, 10.0.0.2"),
+            ("x-forwarded-host", "ccgo.example.com"),
+            ("x-forwarded-proto", "https"),
+        ]);
+
+        let direct = policy.client_info(Some("198.51.100.1:5000".parse().unwrap()), &forwarded);
+        assert_eq!(
+            direct,
+            ClientInfo {
+                ip: Some("198.51.100.1".parse().unwrap()),
+                host: Some("127.0.0.1:8765".to_string()),
+                https: false,
+            }
+        );
+
+        let proxied = policy.client_info(Some("10.0.0.1:5000".parse().unwrap()), &forwarded);
+        assert_eq!(
+            proxied,
+            ClientInfo {
+                ip: Some("203.0.113.7".parse().unwrap()),
+                host: Some("ccgo.example.com".to_string()),
+                https: true,
+            }
+        );
+
+        assert_eq!(policy.client_info(None, &forwarded).ip, None);
+    }
+}
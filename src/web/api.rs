@@ -0,0 +1,621 @@
+//! REST API for asking agents without speaking MCP
+//!
+//! `POST /api/ask` takes the `ask_agents` arguments and either waits for the
+//! replies or, with `?async=true`, answers 202 with the message ids at once.
+//! Requests are looked up and cancelled by message id; the journal knows
+//! every request sent to an agent, and async asks are also tracked here so
+//! cache hits and requests still waiting for their agent can be found.
+//! `GET /api/events` streams the session event bus as server-sent events.
+//! Asking needs the driver role and cancelling, interrupting or stopping the
+//! operator role; a token scoped to some agents only sees those agents.
+
+use super::{__path_api_login, __path_api_logout, AppState, Caller, LoginRequest, LoginResponse};
+use crate::config::Role;
+use crate::events::EventFilter;
+use crate::journal::RequestState;
+use crate::mcp::{
+    ask_agents, new_message_ids, AgentRequest, AgentResult, AskAgentsArgs, AskAgentsResponse,
+    ReplyDetail,
+};
+use crate::session::AgentSession;
+use axum::{
+    extract::{Extension, Path, Query, State},
+    http::StatusCode,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json, Response,
+    },
+};
+use futures::stream::{self, Stream};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::AbortHandle;
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{IntoParams, Modify, OpenApi, ToSchema};
+
+/// Async requests kept for lookup; the oldest finished ones are dropped first
+const MAX_API_REQUESTS: usize = 1000;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "ccgonext",
+        description = "Ask the agents managed by ccgonext and follow their requests"
+    ),
+    paths(
+        api_ask,
+        api_get_request,
+        api_cancel_request,
+        api_interrupt_agent,
+        api_stop_agent,
+        api_events,
+        api_login,
+        api_logout
+    ),
+    components(schemas(
+        AskAgentsArgs,
+        AgentRequest,
+        ReplyDetail,
+        crate::cache::CacheMode,
+        AskAgentsResponse,
+        AgentResult,
+        crate::log_provider::TokenUsage,
+        AskAccepted,
+        AcceptedRequest,
+        RequestStatus,
+        RequestState,
+        ActionResponse,
+        ErrorResponse,
+        LoginRequest,
+        LoginResponse
+    )),
+    modifiers(&BearerAuth),
+    security(("bearer" = []))
+)]
+pub struct ApiDoc;
+
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "bearer",
+                SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+            );
+        }
+    }
+}
+
+pub async fn api_openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+/// Error status with a JSON body
+#[derive(Debug)]
+pub struct ApiError(StatusCode, String);
+
+impl ApiError {
+    fn not_found(what: &str, name: &str) -> Self {
+        Self(StatusCode::NOT_FOUND, format!("Unknown {}: {}", what, name))
+    }
+}
+
+/// 403 unless `caller` has at least `role` for `agent`
+fn authorize(caller: &Caller, role: Role, agent: &str) -> Result<(), ApiError> {
+    caller.authorize(role, Some(agent)).map_err(|status| {
+        ApiError(
+            status,
+            format!(
+                "Token {} needs the {} role for agent {}",
+                caller.name, role, agent
+            ),
+        )
+    })
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(ErrorResponse { error: self.1 })).into_response()
+    }
+}
+
+struct ApiRequest {
+    agent: String,
+    created_at: Instant,
+    task: Option<AbortHandle>,
+    result: Option<AgentResult>,
+}
+
+/// Requests started by `POST /api/ask?async=true`
+#[derive(Default)]
+pub struct ApiRequests {
+    requests: parking_lot::Mutex<HashMap<String, ApiRequest>>,
+}
+
+impl ApiRequests {
+    /// Track `(message_id, agent)` pairs, evicting the oldest finished
+    /// requests to make room. Fails with 429, tracking none of them, when
+    /// they would take the unfinished requests past the bound.
+    fn insert(&self, new: &[(String, String)]) -> Result<(), ApiError> {
+        let mut requests = self.requests.lock();
+        let unfinished = requests.values().filter(|r| r.result.is_none()).count();
+        if unfinished + new.len() > MAX_API_REQUESTS {
+            return Err(ApiError(
+                StatusCode::TOO_MANY_REQUESTS,
+                format!(
+                    "{} asynchronous requests are still running; retry once some finish",
+                    unfinished
+                ),
+            ));
+        }
+        if requests.len() + new.len() > MAX_API_REQUESTS {
+            let mut finished: Vec<(Instant, String)> = requests
+                .iter()
+                .filter(|(_, r)| r.result.is_some())
+                .map(|(id, r)| (r.created_at, id.clone()))
+                .collect();
+            finished.sort();
+            let excess = requests.len() + new.len() - MAX_API_REQUESTS;
+            for (_, id) in finished.into_iter().take(excess) {
+                requests.remove(&id);
+            }
+        }
+        for (message_id, agent) in new {
+            requests.insert(
+                message_id.clone(),
+                ApiRequest {
+                    agent: agent.clone(),
+                    created_at: Instant::now(),
+                    task: None,
+                    result: None,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    fn set_task(&self, message_id: &str, task: AbortHandle) {
+        if let Some(request) = self.requests.lock().get_mut(message_id) {
+            request.task = Some(task);
+        }
+    }
+
+    fn finish(&self, message_id: &str, result: AgentResult) {
+        if let Some(request) = self.requests.lock().get_mut(message_id) {
+            request.task = None;
+            request.result.get_or_insert(result);
+        }
+    }
+
+    /// Agent and result of a tracked request
+    fn get(&self, message_id: &str) -> Option<(String, Option<AgentResult>)> {
+        self.requests
+            .lock()
+            .get(message_id)
+            .map(|r| (r.agent.clone(), r.result.clone()))
+    }
+
+    /// Stop the task running the request, if it is still running
+    fn abort(&self, message_id: &str) {
+        if let Some(task) = self
+            .requests
+            .lock()
+            .get_mut(message_id)
+            .and_then(|r| r.task.take())
+        {
+            task.abort();
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct AskParams {
+    /// Answer 202 with the message ids instead of waiting for the replies
+    #[serde(default, rename = "async")]
+    pub run_async: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AcceptedRequest {
+    pub agent: String,
+    pub message_id: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AskAccepted {
+    /// One entry per request, in request order
+    pub requests: Vec<AcceptedRequest>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RequestStatus {
+    pub message_id: String,
+    pub agent: String,
+    pub state: RequestState,
+    /// Set once the request has finished
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<AgentResult>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ActionResponse {
+    pub success: bool,
+    pub message: String,
+}
+
+/// Ask agents, with the same arguments as the `ask_agents` MCP tool
+/// (`stream` is ignored)
+#[utoipa::path(
+    post,
+    path = "/api/ask",
+    params(AskParams),
+    request_body = AskAgentsArgs,
+    responses(
+        (status = 200, description = "Replies, in request order", body = AskAgentsResponse),
+        (status = 202, description = "Accepted; poll /api/requests/{id}", body = AskAccepted),
+        (status = 400, description = "Invalid requests", body = ErrorResponse),
+        (status = 403, description = "Not a driver for every agent asked", body = ErrorResponse),
+        (status = 429, description = "Too many asynchronous requests still running", body = ErrorResponse)
+    )
+)]
+pub async fn api_ask(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Query(params): Query<AskParams>,
+    Json(mut args): Json<AskAgentsArgs>,
+) -> Result<Response, ApiError> {
+    args.prepare(state.session_manager.prompts())
+        .map_err(|e| ApiError(StatusCode::BAD_REQUEST, e.to_string()))?;
+    for request in &args.requests {
+        authorize(&caller, Role::Driver, &request.agent)?;
+    }
+    args.stream = false;
+    let message_ids = new_message_ids(&args);
+
+    if !params.run_async {
+        let response = ask_agents(args, message_ids, &state.session_manager, None).await;
+        return Ok(Json(response).into_response());
+    }
+
+    let tracked: Vec<(String, String)> = args
+        .requests
+        .iter()
+        .zip(&message_ids)
+        .map(|(request, id)| (id.clone(), request.agent.clone()))
+        .collect();
+    state.requests.insert(&tracked)?;
+
+    // One task per request, so each can be cancelled on its own
+    let mut accepted = Vec::with_capacity(message_ids.len());
+    for (request, message_id) in args.requests.into_iter().zip(message_ids) {
+        accepted.push(AcceptedRequest {
+            agent: request.agent.clone(),
+            message_id: message_id.clone(),
+        });
+        let single = AskAgentsArgs {
+            requests: vec![request],
+            template: None,
+            args: HashMap::new(),
+            timeout: args.timeout,
+            detail: args.detail,
+            stream: false,
+        };
+        let session_manager = state.session_manager.clone();
+        let requests = state.requests.clone();
+        let id = message_id.clone();
+        let task = tokio::spawn(async move {
+            let response = ask_agents(single, vec![id.clone()], &session_manager, None).await;
+            if let Some(result) = response.results.into_iter().next() {
+                requests.finish(&id, result);
+            }
+        });
+        state.requests.set_task(&message_id, task.abort_handle());
+    }
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(AskAccepted { requests: accepted }),
+    )
+        .into_response())
+}
+
+fn request_status(state: &AppState, message_id: &str) -> Option<RequestStatus> {
+    let tracked = state.requests.get(message_id);
+    let record = state.session_manager.journal().get(message_id);
+
+    if let Some((agent, Some(result))) = &tracked {
+        let request_state = match &record {
+            _ if result.success => RequestState::Completed,
+            Some(record) if record.state.is_finished() => record.state,
+            _ => RequestState::Failed,
+        };
+        return Some(RequestStatus {
+            message_id: message_id.to_string(),
+            agent: agent.clone(),
+            state: request_state,
+            result: Some(result.clone()),
+        });
+    }
+    if let Some(record) = record {
+        let result = record.state.is_finished().then(|| AgentResult {
+            agent: record.agent.clone(),
+            message_id: Some(record.message_id.clone()),
+            success: record.state == RequestState::Completed,
+            response: record.response.clone(),
+            error: record.error.clone(),
+            ..Default::default()
+        });
+        return Some(RequestStatus {
+            message_id: record.message_id,
+            agent: record.agent,
+            state: record.state,
+            result,
+        });
+    }
+    // Async request still waiting for its agent to start
+    tracked.map(|(agent, _)| RequestStatus {
+        message_id: message_id.to_string(),
+        agent,
+        state: RequestState::Pending,
+        result: None,
+    })
+}
+
+/// State and, once finished, result of a request
+#[utoipa::path(
+    get,
+    path = "/api/requests/{id}",
+    params(("id" = String, Path, description = "Message id")),
+    responses(
+        (status = 200, body = RequestStatus),
+        (status = 403, description = "Agent outside the token's scope", body = ErrorResponse),
+        (status = 404, description = "Unknown message id", body = ErrorResponse)
+    )
+)]
+pub async fn api_get_request(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<String>,
+) -> Result<Json<RequestStatus>, ApiError> {
+    let status =
+        request_status(&state, &id).ok_or_else(|| ApiError::not_found("message id", &id))?;
+    authorize(&caller, Role::Viewer, &status.agent)?;
+    Ok(Json(status))
+}
+
+/// Cancel a queued or running request. A running request fails at once and
+/// its agent is interrupted
+#[utoipa::path(
+    delete,
+    path = "/api/requests/{id}",
+    params(("id" = String, Path, description = "Message id")),
+    responses(
+        (status = 204, description = "Cancelled"),
+        (status = 403, description = "Not an operator for the agent", body = ErrorResponse),
+        (status = 404, description = "Unknown message id", body = ErrorResponse),
+        (status = 409, description = "Already finished", body = ErrorResponse)
+    )
+)]
+pub async fn api_cancel_request(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let status =
+        request_status(&state, &id).ok_or_else(|| ApiError::not_found("message id", &id))?;
+    authorize(&caller, Role::Operator, &status.agent)?;
+    if status.result.is_some() {
+        return Err(ApiError(
+            StatusCode::CONFLICT,
+            format!("Request {} already finished", id),
+        ));
+    }
+
+    // Stop the async task first so it cannot queue the request afterwards
+    let tracked = state.requests.get(&id).is_some();
+    state.requests.abort(&id);
+    let cancelled = match state.session_manager.get(&status.agent).await {
+        Some(session) => session
+            .cancel(&id)
+            .await
+            .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?,
+        None => false,
+    };
+    if tracked {
+        state.requests.finish(
+            &id,
+            AgentResult {
+                agent: status.agent,
+                message_id: Some(id.clone()),
+                error: Some(crate::session::SessionError::Cancelled.to_string()),
+                ..Default::default()
+            },
+        );
+    } else if !cancelled {
+        // Between the checks above the request finished
+        return Err(ApiError(
+            StatusCode::CONFLICT,
+            format!("Request {} already finished", id),
+        ));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn agent_session(state: &AppState, agent: &str) -> Result<Arc<AgentSession>, ApiError> {
+    state
+        .session_manager
+        .get(agent)
+        .await
+        .ok_or_else(|| ApiError::not_found("agent", agent))
+}
+
+/// Interrupt the agent, failing its running and queued requests
+#[utoipa::path(
+    post,
+    path = "/api/agents/{agent}/interrupt",
+    params(("agent" = String, Path, description = "Agent name")),
+    responses(
+        (status = 200, body = ActionResponse),
+        (status = 403, description = "Not an operator for the agent", body = ErrorResponse),
+        (status = 404, description = "Unknown agent", body = ErrorResponse)
+    )
+)]
+pub async fn api_interrupt_agent(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(agent): Path<String>,
+) -> Result<Json<ActionResponse>, ApiError> {
+    authorize(&caller, Role::Operator, &agent)?;
+    let session = agent_session(&state, &agent).await?;
+    Ok(Json(match session.interrupt().await {
+        Ok(()) => ActionResponse {
+            success: true,
+            message: format!("Agent {} interrupted", agent),
+        },
+        Err(e) => ActionResponse {
+            success: false,
+            message: format!("Failed to interrupt agent: {}", e),
+        },
+    }))
+}
+
+/// Stop the agent, even when busy; the next ask starts it again
+#[utoipa::path(
+    post,
+    path = "/api/agents/{agent}/stop",
+    params(("agent" = String, Path, description = "Agent name")),
+    responses(
+        (status = 200, body = ActionResponse),
+        (status = 403, description = "Not an operator for the agent", body = ErrorResponse),
+        (status = 404, description = "Unknown agent", body = ErrorResponse)
+    )
+)]
+pub async fn api_stop_agent(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(agent): Path<String>,
+) -> Result<Json<ActionResponse>, ApiError> {
+    authorize(&caller, Role::Operator, &agent)?;
+    let session = agent_session(&state, &agent).await?;
+    let result = session
+        .stop(true, Some(state.session_manager.pty_manager()))
+        .await;
+    Ok(Json(match result {
+        Ok(()) => ActionResponse {
+            success: true,
+            message: format!("Agent {} stopped", agent),
+        },
+        Err(e) => ActionResponse {
+            success: false,
+            message: format!("Failed to stop agent: {}", e),
+        },
+    }))
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct EventParams {
+    /// Comma-separated agent names; all agents when omitted
+    pub agent: Option<String>,
+    /// Comma-separated event types; all types when omitted
+    #[serde(rename = "type")]
+    #[param(rename = "type")]
+    pub kind: Option<String>,
+}
+
+/// Stream session events as server-sent events. Each event is named after
+/// its type and carries the JSON event; a `lagged` event reports how many
+/// events a slow client missed. Agents outside the token's scope are skipped
+#[utoipa::path(
+    get,
+    path = "/api/events",
+    params(EventParams),
+    responses(
+        (status = 200, description = "Event stream", content_type = "text/event-stream", body = String),
+        (status = 400, description = "Unknown event type", body = ErrorResponse)
+    )
+)]
+pub async fn api_events(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Query(params): Query<EventParams>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let filter = EventFilter::parse(params.agent.as_deref(), params.kind.as_deref())
+        .map_err(|e| ApiError(StatusCode::BAD_REQUEST, e))?;
+    let rx = state.session_manager.events().subscribe();
+    let metrics = Arc::clone(state.session_manager.metrics());
+
+    let stream = stream::unfold(
+        (rx, filter, caller, metrics),
+        |(mut rx, filter, caller, metrics)| async move {
+            loop {
+                let event = match rx.recv().await {
+                    Ok(event) if filter.matches(&event) && caller.can_access(&event.agent) => {
+                        Event::default()
+                            .event(event.kind.name())
+                            .json_data(&event)
+                            .unwrap_or_default()
+                    }
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(missed)) => {
+                        metrics.record_lag("events", missed);
+                        Event::default().event("lagged").data(missed.to_string())
+                    }
+                    Err(RecvError::Closed) => return None,
+                };
+                return Some((Ok(event), (rx, filter, caller, metrics)));
+            }
+        },
+    );
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(range: std::ops::Range<usize>) -> Vec<(String, String)> {
+        range
+            .map(|i| (format!("m{}", i), "codex".to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_unfinished_requests_are_bounded() {
+        let requests = ApiRequests::default();
+        requests.insert(&ids(0..MAX_API_REQUESTS - 1)).unwrap();
+
+        // A batch that does not fit is rejected as a whole
+        let err = requests
+            .insert(&ids(MAX_API_REQUESTS..MAX_API_REQUESTS + 2))
+            .err()
+            .unwrap();
+        assert_eq!(err.0, StatusCode::TOO_MANY_REQUESTS);
+        assert!(requests.get(&format!("m{}", MAX_API_REQUESTS)).is_none());
+        requests
+            .insert(&ids(MAX_API_REQUESTS..MAX_API_REQUESTS + 1))
+            .unwrap();
+
+        // Finished requests make room again, oldest first
+        let done = AgentResult {
+            agent: "codex".to_string(),
+            success: true,
+            ..AgentResult::default()
+        };
+        requests.finish("m0", done);
+        requests
+            .insert(&ids(MAX_API_REQUESTS + 1..MAX_API_REQUESTS + 2))
+            .unwrap();
+        assert!(requests.get("m0").is_none());
+        assert_eq!(requests.requests.lock().len(), MAX_API_REQUESTS);
+    }
+}
@@ -0,0 +1,91 @@
+//! Audit log of terminal input sent through the web UI
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::Path;
+
+#[derive(Debug, Serialize)]
+struct AuditEntry<'a> {
+    timestamp: DateTime<Utc>,
+    /// Token name of the sender
+    token: &'a str,
+    /// Sender's address, behind trusted proxies the forwarded one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client: Option<IpAddr>,
+    agent: &'a str,
+    /// Input as sent, decoded lossily as UTF-8
+    input: String,
+}
+
+/// Logs who sent how much at debug level and, when a file is configured,
+/// appends the input itself there as a JSON line
+#[derive(Debug, Default)]
+pub struct AuditLog {
+    file: Option<Mutex<File>>,
+}
+
+impl AuditLog {
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Some(Mutex::new(file)),
+        })
+    }
+
+    pub fn record(&self, token: &str, client: Option<IpAddr>, agent: &str, input: &[u8]) {
+        tracing::debug!("[Audit] {} sent {} bytes to {}", token, input.len(), agent);
+
+        let Some(file) = &self.file else {
+            return;
+        };
+        let entry = AuditEntry {
+            timestamp: Utc::now(),
+            token,
+            client,
+            agent,
+            input: String::from_utf8_lossy(input).into_owned(),
+        };
+        let Ok(mut line) = serde_json::to_string(&entry) else {
+            return;
+        };
+        line.push('\n');
+        if let Err(e) = file.lock().write_all(line.as_bytes()) {
+            tracing::warn!("[Audit] Failed to write audit log: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_appends_json_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit").join("input.jsonl");
+        let log = AuditLog::open(&path).unwrap();
+        log.record("alice", Some([203, 0, 113, 7].into()), "codex", b"ls\r");
+        log.record("bob", None, "gemini", &[0xff, b'y']);
+
+        let lines: Vec<serde_json::Value> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["token"], "alice");
+        assert_eq!(lines[0]["client"], "203.0.113.7");
+        assert!(lines[1].get("client").is_none());
+        assert_eq!(lines[0]["agent"], "codex");
+        assert_eq!(lines[0]["input"], "ls\r");
+        assert_eq!(lines[1]["input"], "\u{fffd}y");
+        assert!(lines[1]["timestamp"].is_string());
+    }
+}
@@ -1,98 +1,275 @@
 //! Web authentication
+//!
+//! Every request is resolved to a [`Caller`]: the named token it presented,
+//! the shared auth token, the browser session in its cookie, or anonymous
+//! when no token is configured. Handlers check the caller's role and agent
+//! scope with [`Caller::authorize`]. The login endpoint and the static UI
+//! are served without a token, so browsers can load the page and log in.
 
 use axum::{
-    extract::{Request, State},
+    extract::{ConnectInfo, Request, State},
     http::{HeaderMap, StatusCode},
     middleware::Next,
     response::Response,
 };
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::net::SocketAddr;
 
-use super::AppState;
+use super::{session_cookie, AppState, SessionId, LOGIN_PATH};
+use crate::config::{Role, WebConfig};
+use crate::digest::sha256_hex;
 
-const ALLOWED_HOSTS: &[&str] = &["localhost", "127.0.0.1", "::1"];
+/// Caller name used for the shared `--auth-token`
+pub const SHARED_TOKEN_NAME: &str = "auth-token";
+/// Caller name used when no token is configured
+pub const ANONYMOUS_NAME: &str = "anonymous";
 
-pub fn validate_origin(headers: &HeaderMap, allowed_port: u16) -> bool {
-    let Some(origin) = headers.get("origin").and_then(|v| v.to_str().ok()) else {
-        // No origin header - might be same-origin request
-        return true;
-    };
+pub fn validate_bearer_token(headers: &HeaderMap, expected_token: &str) -> bool {
+    bearer_token(headers).is_some_and(|token| secrets_match(token, expected_token))
+}
 
-    // Simple parsing without url crate
-    let origin = origin.trim();
+/// Compare in time independent of where the bytes differ
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
 
-    // Extract host part
-    let host_part = origin
-        .strip_prefix("http://")
-        .or_else(|| origin.strip_prefix("https://"))
-        .unwrap_or(origin);
+/// Compare secrets through their digests, so neither content nor length leaks
+fn secrets_match(presented: &str, expected: &str) -> bool {
+    constant_time_eq(
+        &Sha256::digest(presented.as_bytes()),
+        &Sha256::digest(expected.as_bytes()),
+    )
+}
 
-    let host_port = host_part.split('/').next().unwrap_or(host_part);
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|auth| auth.strip_prefix("Bearer "))
+}
 
-    // Split host:port (supports bracketed IPv6: [::1]:1234)
-    let (host, port) = if let Some(host_port) = host_port.strip_prefix('[') {
-        let Some(end) = host_port.find(']') else {
-            return false;
-        };
-        let host = &host_port[..end];
-        let port = host_port[end + 1..]
-            .strip_prefix(':')
-            .and_then(|p| p.parse::<u16>().ok());
-        (host, port)
-    } else {
-        let (host, port) = match host_port.rsplit_once(':') {
-            Some((host, port_str)) => (host, port_str.parse::<u16>().ok()),
-            None => (host_port, None),
-        };
-        (host, port)
-    };
+/// Lowercase hex SHA-256 of a token secret, as stored in the tokens file
+pub fn hash_token(secret: &str) -> String {
+    sha256_hex(secret)
+}
 
-    // Strict host matching
-    if !ALLOWED_HOSTS.contains(&host) {
-        return false;
-    }
+/// Who made a request and what they may do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Caller {
+    /// Token name, for the audit log
+    pub name: String,
+    pub role: Role,
+    /// Agents the caller may access; `None` for all agents
+    pub agents: Option<HashSet<String>>,
+}
 
-    // Check port if specified
-    if let Some(port) = port {
-        if port != allowed_port && port != 80 && port != 443 {
-            return false;
+impl Caller {
+    /// Full access, for servers without tokens and the shared auth token
+    pub fn unrestricted(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            role: Role::Driver,
+            agents: None,
         }
     }
 
-    true
+    pub fn can_access(&self, agent: &str) -> bool {
+        self.agents
+            .as_ref()
+            .is_none_or(|agents| agents.contains(agent))
+    }
+
+    /// 403 unless the caller has at least `role` and, if given, may access `agent`
+    pub fn authorize(&self, role: Role, agent: Option<&str>) -> Result<(), StatusCode> {
+        if self.role < role || agent.is_some_and(|agent| !self.can_access(agent)) {
+            return Err(StatusCode::FORBIDDEN);
+        }
+        Ok(())
+    }
 }
 
-pub fn validate_bearer_token(headers: &HeaderMap, expected_token: &str) -> bool {
-    let Some(auth) = headers.get("authorization").and_then(|v| v.to_str().ok()) else {
-        return false;
-    };
+/// Whether any token is configured; without one every caller is anonymous
+pub fn auth_required(config: &WebConfig) -> bool {
+    config.auth_token.is_some() || !config.tokens.is_empty()
+}
 
-    if !auth.starts_with("Bearer ") {
-        return false;
+/// Resolve the bearer token in `headers`; `None` if it is missing or unknown
+pub fn authenticate(headers: &HeaderMap, config: &WebConfig) -> Option<Caller> {
+    if !auth_required(config) {
+        return Some(Caller::unrestricted(ANONYMOUS_NAME));
     }
+    resolve_token(bearer_token(headers)?, config)
+}
 
-    let token = &auth[7..];
-    token == expected_token
+/// The caller a token secret stands for
+pub fn resolve_token(secret: &str, config: &WebConfig) -> Option<Caller> {
+    if !auth_required(config) {
+        return Some(Caller::unrestricted(ANONYMOUS_NAME));
+    }
+    if let Some(expected_token) = &config.auth_token {
+        if secrets_match(secret, expected_token) {
+            return Some(Caller::unrestricted(SHARED_TOKEN_NAME));
+        }
+    }
+    let hash = hash_token(secret);
+    config
+        .tokens
+        .iter()
+        .find(|token| {
+            constant_time_eq(
+                token.sha256.to_ascii_lowercase().as_bytes(),
+                hash.as_bytes(),
+            )
+        })
+        .map(|token| Caller {
+            name: token.name.clone(),
+            role: token.role,
+            agents: (!token.agents.is_empty()).then(|| token.agents.iter().cloned().collect()),
+        })
 }
 
 pub async fn auth_middleware(
     State(state): State<AppState>,
     headers: HeaderMap,
-    request: Request,
+    mut request: Request,
     next: Next,
 ) -> Result<Response, StatusCode> {
-    let port = state.server_port;
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0);
+    let client = state.access.client_info(peer, &headers);
 
-    // Validate origin for all requests
-    if !validate_origin(&headers, port) {
+    // Validate host and origin for all requests
+    if !state.access.host_allowed(client.host.as_deref()) {
+        tracing::warn!("[Auth] Rejected request for host {:?}", client.host);
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let origin = headers.get("origin").and_then(|v| v.to_str().ok());
+    if origin.is_some_and(|origin| !state.access.origin_allowed(origin)) {
         return Err(StatusCode::FORBIDDEN);
     }
+    request.extensions_mut().insert(client);
 
-    // Check bearer token if auth token is set
-    if let Some(ref expected_token) = state.config.web.auth_token {
-        if !validate_bearer_token(&headers, expected_token) {
-            return Err(StatusCode::UNAUTHORIZED);
-        }
+    if is_public(request.uri().path()) {
+        return Ok(next.run(request).await);
     }
 
+    // Bearer token first, then the browser session cookie
+    let caller = match authenticate(&headers, &state.config.web) {
+        Some(caller) => caller,
+        None => {
+            let id = session_cookie(&headers).ok_or(StatusCode::UNAUTHORIZED)?;
+            let caller = state.sessions.get(id).ok_or(StatusCode::UNAUTHORIZED)?;
+            request.extensions_mut().insert(SessionId(id.to_string()));
+            caller
+        }
+    };
+    request.extensions_mut().insert(caller);
+
     Ok(next.run(request).await)
 }
+
+/// The login endpoint and the static UI, which holds no data of its own
+fn is_public(path: &str) -> bool {
+    path == LOGIN_PATH
+        || !(path.starts_with("/api/") || path.starts_with("/ws/") || path == "/metrics")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::WebToken;
+
+    fn headers(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "authorization",
+            format!("Bearer {}", token).parse().unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn test_hash_token() {
+        assert_eq!(
+            hash_token("secret"),
+            "2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b"
+        );
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+        assert!(validate_bearer_token(&headers("secret"), "secret"));
+        assert!(!validate_bearer_token(&headers("secret"), "other"));
+    }
+
+    #[test]
+    fn test_is_public() {
+        assert!(is_public("/"));
+        assert!(is_public("/codex"));
+        assert!(is_public(LOGIN_PATH));
+        assert!(!is_public("/api/status"));
+        assert!(!is_public("/api/logout"));
+        assert!(!is_public("/ws/codex"));
+        assert!(!is_public("/metrics"));
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let open = WebConfig::default();
+        assert_eq!(
+            authenticate(&HeaderMap::new(), &open),
+            Some(Caller::unrestricted(ANONYMOUS_NAME))
+        );
+
+        let config = WebConfig {
+            auth_token: Some("shared".to_string()),
+            tokens: vec![WebToken {
+                name: "alice".to_string(),
+                sha256: hash_token("alice-secret").to_uppercase(),
+                role: Role::Viewer,
+                agents: vec!["codex".to_string()],
+            }],
+            ..WebConfig::default()
+        };
+        assert_eq!(authenticate(&HeaderMap::new(), &config), None);
+        assert_eq!(authenticate(&headers("wrong"), &config), None);
+        assert_eq!(
+            authenticate(&headers("shared"), &config),
+            Some(Caller::unrestricted(SHARED_TOKEN_NAME))
+        );
+
+        let alice = authenticate(&headers("alice-secret"), &config).unwrap();
+        assert_eq!(alice.name, "alice");
+        assert_eq!(alice.role, Role::Viewer);
+        assert!(alice.can_access("codex"));
+        assert!(!alice.can_access("gemini"));
+    }
+
+    #[test]
+    fn test_authorize() {
+        let operator = Caller {
+            name: "bob".to_string(),
+            role: Role::Operator,
+            agents: Some(HashSet::from(["codex".to_string()])),
+        };
+        assert!(operator.authorize(Role::Viewer, None).is_ok());
+        assert!(operator.authorize(Role::Operator, Some("codex")).is_ok());
+        assert_eq!(
+            operator.authorize(Role::Operator, Some("gemini")),
+            Err(StatusCode::FORBIDDEN)
+        );
+        assert_eq!(
+            operator.authorize(Role::Driver, Some("codex")),
+            Err(StatusCode::FORBIDDEN)
+        );
+        assert!(Caller::unrestricted(ANONYMOUS_NAME)
+            .authorize(Role::Driver, Some("gemini"))
+            .is_ok());
+    }
+}
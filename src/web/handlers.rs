@@ -1,10 +1,12 @@
 //! HTTP handlers
 
-use super::AppState;
+use super::{AppState, Caller};
+use crate::config::Role;
+use crate::procmon::ProcessStats;
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::Json,
+    extract::{Extension, Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json},
 };
 use serde::Serialize;
 
@@ -12,35 +14,89 @@ use serde::Serialize;
 pub struct AgentStatus {
     pub name: String,
     pub state: String,
+    pub restarts: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ProcessStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_limit_mb: Option<u64>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct StatusResponse {
     pub agents: Vec<AgentStatus>,
+    /// Whether this caller may type into agent terminals
     pub input_enabled: bool,
     pub project_root: String,
 }
 
 pub async fn api_get_status(
     State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
 ) -> Result<Json<StatusResponse>, StatusCode> {
     let statuses = state.session_manager.get_all_status().await;
 
-    let agents = statuses
-        .into_iter()
-        .map(|(name, s)| AgentStatus {
-            name,
+    let mut agents = Vec::with_capacity(statuses.len());
+    for (name, s) in statuses {
+        if !caller.can_access(&name) {
+            continue;
+        }
+        let session = state.session_manager.get(&name).await;
+        agents.push(AgentStatus {
             state: s.to_string(),
-        })
-        .collect();
+            restarts: match &session {
+                Some(session) => *session.restart_count.lock().await,
+                None => 0,
+            },
+            resources: session.as_ref().and_then(|s| s.resources()),
+            memory_limit_mb: session.as_ref().and_then(|s| s.memory_limit_mb()),
+            name,
+        });
+    }
 
     Ok(Json(StatusResponse {
         agents,
-        input_enabled: state.config.web.input_enabled,
+        input_enabled: state.config.web.input_enabled && caller.role >= Role::Driver,
         project_root: state.config.web.project_root.clone(),
     }))
 }
 
+#[derive(Debug, Serialize)]
+pub struct UsageResponse {
+    pub daily_token_budget: Option<u64>,
+    pub days: crate::usage::DailyTotals,
+}
+
+/// Prometheus metrics; only routed when enabled. They cover every agent, so
+/// tokens scoped to some agents get 403.
+pub async fn metrics_handler(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+) -> Result<impl IntoResponse, StatusCode> {
+    if caller.agents.is_some() {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.session_manager.render_metrics().await,
+    ))
+}
+
+pub async fn api_get_usage(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+) -> Json<UsageResponse> {
+    let usage = state.session_manager.usage();
+    let mut days = usage.snapshot();
+    for agents in days.values_mut() {
+        agents.retain(|agent, _| caller.can_access(agent));
+    }
+    days.retain(|_, agents| !agents.is_empty());
+    Json(UsageResponse {
+        daily_token_budget: usage.daily_budget(),
+        days,
+    })
+}
+
 #[derive(Debug, Serialize)]
 pub struct RestartResponse {
     pub success: bool,
@@ -49,33 +105,27 @@ pub struct RestartResponse {
 
 pub async fn api_restart_agent(
     State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
     Path(agent): Path<String>,
 ) -> Result<Json<RestartResponse>, StatusCode> {
+    caller.authorize(Role::Operator, Some(&agent))?;
     let session = state
         .session_manager
         .get(&agent)
         .await
         .ok_or(StatusCode::NOT_FOUND)?;
 
-    // Stop the agent (force=true to stop even if busy)
-    if let Err(e) = session
-        .stop(true, Some(state.session_manager.pty_manager().as_ref()))
-        .await
-    {
-        return Ok(Json(RestartResponse {
-            success: false,
-            message: format!("Failed to stop agent: {}", e),
-        }));
-    }
-
-    // Start the agent with retry
+    // Stops even if busy, killing the agent's whole process tree
     if let Err(e) = session
-        .start_with_retry(state.session_manager.pty_manager())
+        .restart(
+            state.session_manager.pty_manager(),
+            "Agent restarted by user",
+        )
         .await
     {
         return Ok(Json(RestartResponse {
             success: false,
-            message: format!("Failed to start agent: {}", e),
+            message: format!("Failed to restart agent: {}", e),
         }));
     }
 
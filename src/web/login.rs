@@ -0,0 +1,358 @@
+//! Browser sessions
+//!
+//! Browsers cannot set `Authorization` on a WebSocket upgrade, so the UI
+//! exchanges a token for a session at `POST /api/login`. The session id is
+//! sent back as an HttpOnly cookie, which the browser attaches to every
+//! request and upgrade on this origin. Sessions expire after the configured
+//! TTL and are revoked by `POST /api/logout`, which also closes their
+//! terminal WebSockets; only a hash of each id is kept. Repeated failed logins
+//! from one address lock it out for a growing time.
+
+use super::{hash_token, resolve_token, AppState, Caller, ClientInfo};
+use crate::config::Role;
+use axum::{
+    extract::{Extension, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+use utoipa::{IntoParams, ToSchema};
+
+pub const LOGIN_PATH: &str = "/api/login";
+/// Cookie holding the session id
+pub const SESSION_COOKIE: &str = "ccgonext_session";
+
+/// Failed logins from one address before it is locked out
+const FREE_LOGIN_FAILURES: u32 = 5;
+/// Lockout after the first failure past the free ones; doubles with each further one
+const LOGIN_LOCKOUT: Duration = Duration::from_secs(1);
+const MAX_LOGIN_LOCKOUT: Duration = Duration::from_secs(300);
+/// Failures are forgotten after this long without another one
+const LOGIN_FAILURE_WINDOW: Duration = Duration::from_secs(900);
+
+struct AuthSession {
+    caller: Caller,
+    expires_at: DateTime<Utc>,
+}
+
+/// Live browser sessions, keyed by the SHA-256 of their id
+#[derive(Default)]
+pub struct AuthSessions {
+    sessions: parking_lot::Mutex<HashMap<String, AuthSession>>,
+}
+
+impl AuthSessions {
+    /// Start a session for `caller`; returns its id and expiry
+    pub fn create(&self, caller: Caller, ttl: chrono::Duration) -> (String, DateTime<Utc>) {
+        let id = format!(
+            "{}{}",
+            uuid::Uuid::new_v4().simple(),
+            uuid::Uuid::new_v4().simple()
+        );
+        let now = Utc::now();
+        let expires_at = now + ttl;
+        let mut sessions = self.sessions.lock();
+        sessions.retain(|_, session| session.expires_at > now);
+        sessions.insert(hash_token(&id), AuthSession { caller, expires_at });
+        (id, expires_at)
+    }
+
+    /// The caller of an unexpired session
+    pub fn get(&self, id: &str) -> Option<Caller> {
+        let key = hash_token(id);
+        let mut sessions = self.sessions.lock();
+        match sessions.get(&key) {
+            Some(session) if session.expires_at > Utc::now() => Some(session.caller.clone()),
+            Some(_) => {
+                sessions.remove(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn revoke(&self, id: &str) -> bool {
+        self.sessions.lock().remove(&hash_token(id)).is_some()
+    }
+
+    /// Revoke every session of the named token; returns how many there were
+    pub fn revoke_token(&self, name: &str) -> usize {
+        let mut sessions = self.sessions.lock();
+        let before = sessions.len();
+        sessions.retain(|_, session| session.caller.name != name);
+        before - sessions.len()
+    }
+}
+
+/// Id of the browser session a request was authenticated with, set by the
+/// auth middleware
+#[derive(Debug, Clone)]
+pub struct SessionId(pub String);
+
+struct LoginFailures {
+    count: u32,
+    last: Instant,
+    locked_until: Option<Instant>,
+}
+
+/// Failed logins per client address (`None` for in-process requests)
+#[derive(Default)]
+pub struct LoginThrottle {
+    failures: parking_lot::Mutex<HashMap<Option<IpAddr>, LoginFailures>>,
+}
+
+impl LoginThrottle {
+    /// How long `client` must wait before trying again, if it is locked out
+    fn locked(&self, client: Option<IpAddr>, now: Instant) -> Option<Duration> {
+        let failures = self.failures.lock();
+        let until = failures.get(&client)?.locked_until?;
+        (until > now).then(|| until - now)
+    }
+
+    fn failed(&self, client: Option<IpAddr>, now: Instant) {
+        let mut failures = self.failures.lock();
+        failures.retain(|_, f| now.duration_since(f.last) < LOGIN_FAILURE_WINDOW);
+        let entry = failures.entry(client).or_insert(LoginFailures {
+            count: 0,
+            last: now,
+            locked_until: None,
+        });
+        entry.count += 1;
+        entry.last = now;
+        if entry.count > FREE_LOGIN_FAILURES {
+            let doublings = (entry.count - FREE_LOGIN_FAILURES - 1).min(16);
+            let lockout = (LOGIN_LOCKOUT * 2u32.pow(doublings)).min(MAX_LOGIN_LOCKOUT);
+            entry.locked_until = Some(now + lockout);
+        }
+    }
+
+    fn succeeded(&self, client: Option<IpAddr>) {
+        self.failures.lock().remove(&client);
+    }
+}
+
+/// The session id from the `Cookie` header
+pub fn session_cookie(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .find_map(|pair| {
+            pair.trim()
+                .strip_prefix(SESSION_COOKIE)
+                .and_then(|rest| rest.strip_prefix('='))
+        })
+        .filter(|id| !id.is_empty())
+}
+
+/// Scoped to the base path, and `Secure` when the client uses HTTPS
+fn set_cookie(state: &AppState, client: &ClientInfo, id: &str, max_age: i64) -> String {
+    format!(
+        "{}={}; Path={}/; HttpOnly; SameSite=Strict; Max-Age={}{}",
+        SESSION_COOKIE,
+        id,
+        state.config.web.base_path,
+        max_age,
+        if client.https { "; Secure" } else { "" }
+    )
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct LoginRequest {
+    /// A named token or the shared auth token
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LoginResponse {
+    /// Token name
+    pub name: String,
+    #[schema(value_type = String)]
+    pub role: Role,
+    /// Agents the session may access; all agents when absent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub agents: Option<Vec<String>>,
+    #[schema(value_type = String, format = DateTime)]
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Exchange a token for a session cookie, for browsers and WebSockets
+#[utoipa::path(
+    post,
+    path = "/api/login",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "Logged in; the session id is in the `ccgonext_session` cookie", body = LoginResponse),
+        (status = 401, description = "Unknown token"),
+        (status = 429, description = "Too many failed logins from this address; retry after `Retry-After` seconds")
+    ),
+    security(())
+)]
+pub async fn api_login(
+    State(state): State<AppState>,
+    Extension(client): Extension<ClientInfo>,
+    Json(login): Json<LoginRequest>,
+) -> Result<Response, StatusCode> {
+    let throttle = &state.login_throttle;
+    if let Some(wait) = throttle.locked(client.ip, Instant::now()) {
+        return Ok((
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, wait.as_secs().max(1).to_string())],
+        )
+            .into_response());
+    }
+    let Some(caller) = resolve_token(&login.token, &state.config.web) else {
+        tracing::warn!(
+            "[Auth] Rejected login with an unknown token from {:?}",
+            client.ip
+        );
+        throttle.failed(client.ip, Instant::now());
+        return Err(StatusCode::UNAUTHORIZED);
+    };
+    throttle.succeeded(client.ip);
+    let ttl = state.config.web.session_ttl_secs;
+    let (id, expires_at) = state
+        .sessions
+        .create(caller.clone(), chrono::Duration::seconds(ttl as i64));
+    tracing::info!(
+        "[Auth] {} logged in from {:?} until {}",
+        caller.name,
+        client.ip,
+        expires_at
+    );
+
+    let mut agents: Option<Vec<String>> = caller.agents.map(|a| a.into_iter().collect());
+    if let Some(agents) = agents.as_mut() {
+        agents.sort();
+    }
+    Ok((
+        [(
+            header::SET_COOKIE,
+            set_cookie(&state, &client, &id, ttl as i64),
+        )],
+        Json(LoginResponse {
+            name: caller.name,
+            role: caller.role,
+            agents,
+            expires_at,
+        }),
+    )
+        .into_response())
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct LogoutParams {
+    /// Also revoke every other session of the same token
+    #[serde(default)]
+    pub all: bool,
+}
+
+/// Revoke the current session and clear its cookie
+#[utoipa::path(
+    post,
+    path = "/api/logout",
+    params(LogoutParams),
+    responses((status = 204, description = "Logged out"))
+)]
+pub async fn api_logout(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Extension(client): Extension<ClientInfo>,
+    Query(params): Query<LogoutParams>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(id) = session_cookie(&headers) {
+        state.sessions.revoke(id);
+    }
+    if params.all {
+        let revoked = state.sessions.revoke_token(&caller.name);
+        tracing::info!("[Auth] Revoked {} sessions of {}", revoked, caller.name);
+    }
+    (
+        StatusCode::NO_CONTENT,
+        [(header::SET_COOKIE, set_cookie(&state, &client, "", 0))],
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_cookie() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(session_cookie(&headers), None);
+        headers.insert(
+            header::COOKIE,
+            "theme=dark; ccgonext_session=abc123; other=1"
+                .parse()
+                .unwrap(),
+        );
+        assert_eq!(session_cookie(&headers), Some("abc123"));
+
+        headers.insert(
+            header::COOKIE,
+            "ccgonext_sessionx=1; ccgonext_session=".parse().unwrap(),
+        );
+        assert_eq!(session_cookie(&headers), None);
+    }
+
+    #[test]
+    fn test_sessions_expire_and_revoke() {
+        let sessions = AuthSessions::default();
+        let alice = Caller::unrestricted("alice");
+        let (id, expires_at) = sessions.create(alice.clone(), chrono::Duration::hours(1));
+        assert!(expires_at > Utc::now());
+        assert_eq!(sessions.get(&id), Some(alice.clone()));
+        assert_eq!(sessions.get("unknown"), None);
+
+        assert!(sessions.revoke(&id));
+        assert_eq!(sessions.get(&id), None);
+        assert!(!sessions.revoke(&id));
+
+        let (expired, _) = sessions.create(alice.clone(), chrono::Duration::seconds(-1));
+        assert_eq!(sessions.get(&expired), None);
+
+        let (first, _) = sessions.create(alice.clone(), chrono::Duration::hours(1));
+        let (second, _) = sessions.create(alice, chrono::Duration::hours(1));
+        let (bob, _) = sessions.create(Caller::unrestricted("bob"), chrono::Duration::hours(1));
+        assert_eq!(sessions.revoke_token("alice"), 2);
+        assert_eq!(sessions.get(&first), None);
+        assert_eq!(sessions.get(&second), None);
+        assert!(sessions.get(&bob).is_some());
+    }
+
+    #[test]
+    fn test_login_throttle_backs_off() {
+        let throttle = LoginThrottle::default();
+        let client = Some(IpAddr::from([203, 0, 113, 7]));
+        let start = Instant::now();
+        for _ in 0..FREE_LOGIN_FAILURES {
+            throttle.failed(client, start);
+        }
+        assert_eq!(throttle.locked(client, start), None);
+
+        throttle.failed(client, start);
+        assert_eq!(throttle.locked(client, start), Some(LOGIN_LOCKOUT));
+        throttle.failed(client, start);
+        assert_eq!(throttle.locked(client, start), Some(LOGIN_LOCKOUT * 2));
+        assert_eq!(throttle.locked(None, start), None);
+        assert_eq!(throttle.locked(client, start + LOGIN_LOCKOUT * 2), None);
+
+        for _ in 0..20 {
+            throttle.failed(client, start);
+        }
+        assert_eq!(throttle.locked(client, start), Some(MAX_LOGIN_LOCKOUT));
+
+        throttle.succeeded(client);
+        assert_eq!(throttle.locked(client, start), None);
+        throttle.failed(client, start);
+        assert_eq!(throttle.locked(client, start), None);
+    }
+}
@@ -1,13 +1,25 @@
 //! Web service layer
 
+mod access;
+mod api;
+mod audit;
 mod auth;
 mod handlers;
+mod login;
+mod replay;
 mod static_files;
+mod tls;
 mod websocket;
 
+pub use access::*;
+pub use api::*;
+pub use audit::*;
 pub use auth::*;
 pub use handlers::*;
+pub use login::*;
+pub use replay::*;
 pub use static_files::*;
+pub use tls::*;
 pub use websocket::*;
 
 use crate::config::Config;
@@ -19,7 +31,7 @@ use axum::{
 };
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::sync::Arc;
-use tower_http::cors::{Any, CorsLayer};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
 
 #[derive(Debug, Clone, Copy, Default)]
 pub struct WebServerRunOptions {
@@ -55,8 +67,14 @@ impl WebServer {
         // Port may differ if base_port was 0 (ephemeral) or due to retry.
         let server_port = listener.local_addr()?.port();
 
+        // Load the certificate before announcing the URL
+        let tls = match config.web.tls {
+            Some(_) => Some(server_config(&config.web, host)?),
+            None => None,
+        };
+        let scheme = if tls.is_some() { "https" } else { "http" };
         let ui_addr = ui_addr_for_bind(host, server_port);
-        let ui_url = format!("http://{}", ui_addr);
+        let ui_url = format!("{}://{}{}/", scheme, ui_addr, config.web.base_path);
         tracing::info!("Web server bound to {}", listener.local_addr()?);
         tracing::info!("Web server UI available at {}", ui_url);
 
@@ -66,37 +84,79 @@ impl WebServer {
             }
         }
 
-        // Build CORS layer
-        let cors = CorsLayer::new()
-            .allow_origin(Any)
-            .allow_methods(Any)
-            .allow_headers(Any);
-
-        let state = AppState {
-            session_manager,
-            config,
-            server_port,
-        };
+        let mut state = AppState::new(session_manager, config.clone(), server_port);
+        if let Some(path) = &config.web.audit_log {
+            state = state.with_audit_log(AuditLog::open(path)?);
+        }
+        let app = router(state).into_make_service_with_connect_info::<SocketAddr>();
 
-        // Build router with auth middleware
-        let app = Router::new()
-            .route("/api/status", get(api_get_status))
-            .route("/api/restart/:agent", post(api_restart_agent))
-            .route("/ws/:agent", get(ws_handler))
-            .fallback(static_handler)
-            .layer(middleware::from_fn_with_state(
-                state.clone(),
-                auth_middleware,
-            ))
-            .layer(cors)
-            .with_state(state);
-
-        axum::serve(listener, app).await?;
+        match tls {
+            Some(tls) => {
+                let tls = axum_server::tls_rustls::RustlsConfig::from_config(Arc::new(tls));
+                axum_server::from_tcp_rustls(listener.into_std()?, tls)
+                    .serve(app)
+                    .await?;
+            }
+            None => axum::serve(listener, app).await?,
+        }
 
         Ok(())
     }
 }
 
+/// All routes, behind the auth middleware and under the base path
+pub fn router(state: AppState) -> Router {
+    // Build CORS layer, admitting the origins the auth middleware does
+    let access = Arc::clone(&state.access);
+    let cors = CorsLayer::new()
+        .allow_origin(AllowOrigin::predicate(move |origin, _| {
+            origin
+                .to_str()
+                .is_ok_and(|origin| access.origin_allowed(origin))
+        }))
+        .allow_methods(Any)
+        .allow_headers(Any);
+    let base_path = state.config.web.base_path.clone();
+
+    let mut routes = Router::new();
+    if state.config.web.metrics {
+        routes = routes.route("/metrics", get(metrics_handler));
+    }
+
+    let routes = routes
+        .route("/api/status", get(api_get_status))
+        .route("/api/openapi.json", get(api_openapi))
+        .route(LOGIN_PATH, post(api_login))
+        .route("/api/logout", post(api_logout))
+        .route("/api/ask", post(api_ask))
+        .route(
+            "/api/requests/:id",
+            get(api_get_request).delete(api_cancel_request),
+        )
+        .route("/api/agents/:agent/interrupt", post(api_interrupt_agent))
+        .route("/api/agents/:agent/stop", post(api_stop_agent))
+        .route("/api/events", get(api_events))
+        .route("/api/usage", get(api_get_usage))
+        .route("/api/restart/:agent", post(api_restart_agent))
+        .route("/api/recordings", get(api_list_recordings))
+        .route("/api/recordings/:agent/:file", get(api_get_recording))
+        .route("/ws/:agent", get(ws_handler))
+        .route("/ws/replay/:agent/:file", get(ws_replay_handler))
+        .fallback(static_handler)
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth_middleware,
+        ))
+        .layer(cors)
+        .with_state(state);
+
+    if base_path.is_empty() {
+        routes
+    } else {
+        Router::new().nest_service(&base_path, routes)
+    }
+}
+
 async fn bind_listener(
     host: IpAddr,
     base_port: u16,
@@ -164,4 +224,33 @@ pub struct AppState {
     pub session_manager: Arc<SessionManager>,
     pub config: Arc<Config>,
     pub server_port: u16,
+    pub requests: Arc<ApiRequests>,
+    pub audit: Arc<AuditLog>,
+    pub sessions: Arc<AuthSessions>,
+    pub login_throttle: Arc<LoginThrottle>,
+    pub access: Arc<AccessPolicy>,
+}
+
+impl AppState {
+    pub fn new(
+        session_manager: Arc<SessionManager>,
+        config: Arc<Config>,
+        server_port: u16,
+    ) -> Self {
+        Self {
+            access: Arc::new(AccessPolicy::new(&config.web, server_port)),
+            session_manager,
+            config,
+            server_port,
+            requests: Arc::new(ApiRequests::default()),
+            audit: Arc::new(AuditLog::default()),
+            sessions: Arc::new(AuthSessions::default()),
+            login_throttle: Arc::new(LoginThrottle::default()),
+        }
+    }
+
+    pub fn with_audit_log(mut self, audit: AuditLog) -> Self {
+        self.audit = Arc::new(audit);
+        self
+    }
 }
@@ -0,0 +1,141 @@
+//! Recording listing, download and replay into xterm.js
+
+use super::{AppState, Caller};
+use crate::config::Role;
+use crate::recording::{self, RecordingInfo};
+use axum::{
+    body::Body,
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Extension, Path, Query, State,
+    },
+    http::{header, Response, StatusCode},
+    response::{IntoResponse, Json},
+};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct ReplayParams {
+    /// Playback speed multiplier (default 1)
+    pub speed: Option<f64>,
+    /// Cap pauses between events at this many seconds
+    pub max_idle: Option<f64>,
+}
+
+pub async fn api_list_recordings(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+) -> Result<Json<Vec<RecordingInfo>>, StatusCode> {
+    let dir = state
+        .config
+        .recording
+        .as_ref()
+        .map(|r| r.dir.clone())
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let mut recordings = tokio::task::spawn_blocking(move || recording::list(&dir))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    recordings.retain(|r| caller.can_access(&r.agent));
+    Ok(Json(recordings))
+}
+
+fn resolve(
+    state: &AppState,
+    caller: &Caller,
+    agent: &str,
+    file: &str,
+) -> Result<std::path::PathBuf, StatusCode> {
+    caller.authorize(Role::Viewer, Some(agent))?;
+    let config = state
+        .config
+        .recording
+        .as_ref()
+        .ok_or(StatusCode::NOT_FOUND)?;
+    recording::resolve(&config.dir, agent, file).ok_or(StatusCode::NOT_FOUND)
+}
+
+/// The raw .cast file, e.g. for asciinema-player
+pub async fn api_get_recording(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path((agent, file)): Path<(String, String)>,
+) -> Result<Response<Body>, StatusCode> {
+    let path = resolve(&state, &caller, &agent, &file)?;
+    let content = tokio::fs::read(&path)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/x-asciicast")
+        .body(Body::from(content))
+        .unwrap())
+}
+
+/// Stream a recording with its original timing. Output is sent as binary
+/// frames; resizes as `\x00`-prefixed JSON control messages, the same
+/// framing the terminal page uses for its own resize commands.
+pub async fn ws_replay_handler(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path((agent, file)): Path<(String, String)>,
+    Query(params): Query<ReplayParams>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let path = resolve(&state, &caller, &agent, &file)?;
+    if params
+        .speed
+        .is_some_and(|speed| !recording::is_valid_speed(speed))
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    Ok(ws.on_upgrade(move |socket| replay_socket(socket, path, params)))
+}
+
+fn resize_message(cols: u16, rows: u16) -> Message {
+    Message::Text(format!(
+        "\x00{}",
+        serde_json::json!({ "type": "resize", "cols": cols, "rows": rows })
+    ))
+}
+
+async fn replay_socket(mut socket: WebSocket, path: std::path::PathBuf, params: ReplayParams) {
+    let parsed = tokio::task::spawn_blocking(move || recording::read(&path)).await;
+    let (header, events) = match parsed {
+        Ok(Ok(parsed)) => parsed,
+        Ok(Err(e)) => {
+            let _ = socket
+                .send(Message::Text(format!(
+                    "Error: cannot read recording: {}",
+                    e
+                )))
+                .await;
+            return;
+        }
+        Err(_) => return,
+    };
+
+    if socket
+        .send(resize_message(header.width, header.height))
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    let delays = recording::schedule(&events, params.speed.unwrap_or(1.0), params.max_idle);
+    for (event, delay) in events.iter().zip(delays) {
+        tokio::time::sleep(delay).await;
+        let message = match event.code() {
+            "o" => Message::Binary(event.data().as_bytes().to_vec()),
+            "r" => match event.size() {
+                Some((cols, rows)) => resize_message(cols, rows),
+                None => continue,
+            },
+            _ => continue,
+        };
+        if socket.send(message).await.is_err() {
+            return;
+        }
+    }
+    let _ = socket.send(Message::Close(None)).await;
+}
@@ -1,21 +1,26 @@
 //! Embedded static files handler
 
+use super::AppState;
 use axum::{
     body::Body,
+    extract::State,
     http::{header, Response, StatusCode, Uri},
     response::IntoResponse,
 };
 use rust_embed::Embed;
 
+/// Replaced in index.html by `--base-path`
+const BASE_PATH_PLACEHOLDER: &str = "__CCGONEXT_BASE_PATH__";
+
 #[derive(Embed)]
 #[folder = "static/"]
 struct StaticAssets;
 
-pub async fn static_handler(uri: Uri) -> impl IntoResponse {
+pub async fn static_handler(State(state): State<AppState>, uri: Uri) -> impl IntoResponse {
     let path = uri.path().trim_start_matches('/');
     let path = if path.is_empty() { "index.html" } else { path };
 
-    match StaticAssets::get(path) {
+    match StaticAssets::get(path).filter(|_| path != "index.html") {
         Some(content) => {
             let mime = mime_guess::from_path(path).first_or_octet_stream();
             Response::builder()
@@ -28,10 +33,12 @@ pub async fn static_handler(uri: Uri) -> impl IntoResponse {
             // Try index.html for SPA routing
             if let Some(content) = StaticAssets::get("index.html") {
                 let mime = mime_guess::from_path("index.html").first_or_octet_stream();
+                let html = String::from_utf8_lossy(&content.data)
+                    .replace(BASE_PATH_PLACEHOLDER, &state.config.web.base_path);
                 Response::builder()
                     .status(StatusCode::OK)
                     .header(header::CONTENT_TYPE, mime.as_ref())
-                    .body(Body::from(content.data.into_owned()))
+                    .body(Body::from(html))
                     .unwrap()
             } else {
                 Response::builder()
@@ -0,0 +1,226 @@
+//! HTTPS for the web server
+//!
+//! Either loads a PEM certificate chain and key, or generates a self-signed
+//! certificate for the local names, the bind address and `--allowed-hosts`
+//! on first use and keeps it in the config directory. Delete that directory
+//! to issue a new one, e.g. after changing the hosts.
+
+use crate::config::{TlsConfig, WebConfig};
+use anyhow::Context;
+use rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
+use rustls::ServerConfig;
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const SELF_SIGNED_CERT: &str = "cert.pem";
+const SELF_SIGNED_KEY: &str = "key.pem";
+
+/// Build the rustls config for the web server bound to `host`
+pub fn server_config(config: &WebConfig, host: IpAddr) -> anyhow::Result<ServerConfig> {
+    let (cert, key) = match config.tls.as_ref() {
+        Some(TlsConfig::Files { cert, key }) => (cert.clone(), key.clone()),
+        Some(TlsConfig::SelfSigned { dir }) => {
+            ensure_self_signed(dir, &certificate_names(host, &config.allowed_hosts))?
+        }
+        None => anyhow::bail!("TLS is not configured"),
+    };
+
+    let certs = CertificateDer::pem_file_iter(&cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("Failed to read certificate {}", cert.display()))?;
+    let Some(leaf) = certs.first() else {
+        anyhow::bail!("No certificate in {}", cert.display());
+    };
+    tracing::info!(
+        "[TLS] Serving {} (SHA-256 fingerprint {})",
+        cert.display(),
+        fingerprint(leaf)
+    );
+    let key = PrivateKeyDer::from_pem_file(&key)
+        .with_context(|| format!("Failed to read private key {}", key.display()))?;
+
+    let mut server_config =
+        ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .context("Certificate and private key do not match")?;
+    // WebSockets need HTTP/1.1 upgrades
+    server_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(server_config)
+}
+
+/// Names the self-signed certificate is issued for
+fn certificate_names(host: IpAddr, allowed_hosts: &[String]) -> Vec<String> {
+    let mut names: Vec<String> = ["localhost", "127.0.0.1", "::1"]
+        .into_iter()
+        .map(String::from)
+        .collect();
+    if !host.is_unspecified() && !host.is_loopback() {
+        names.push(host.to_string());
+    }
+    for pattern in allowed_hosts {
+        let pattern = pattern.trim().to_ascii_lowercase();
+        // Drop the port; bracketed or bare IPv6 addresses keep their colons
+        let name = match pattern.strip_prefix('[') {
+            Some(rest) => rest.split(']').next().unwrap_or(rest),
+            None if pattern.parse::<IpAddr>().is_ok() => &pattern,
+            None => pattern.split(':').next().unwrap_or(&pattern),
+        };
+        // Only a leading `*.` wildcard is valid in a certificate
+        let wildcard_ok = match name.strip_prefix("*.") {
+            Some(rest) => !rest.contains('*'),
+            None => !name.contains('*'),
+        };
+        if !name.is_empty() && wildcard_ok && !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+    }
+    names
+}
+
+/// Paths of the certificate and key in `dir`, generating them if missing
+fn ensure_self_signed(dir: &Path, names: &[String]) -> anyhow::Result<(PathBuf, PathBuf)> {
+    let cert_path = dir.join(SELF_SIGNED_CERT);
+    let key_path = dir.join(SELF_SIGNED_KEY);
+    if cert_path.exists() && key_path.exists() {
+        return Ok((cert_path, key_path));
+    }
+
+    let certified = rcgen::generate_simple_self_signed(names.to_vec())
+        .context("Failed to generate a self-signed certificate")?;
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    write_private(&key_path, certified.key_pair.serialize_pem().as_bytes())?;
+    std::fs::write(&cert_path, certified.cert.pem())
+        .with_context(|| format!("Failed to write {}", cert_path.display()))?;
+    tracing::info!(
+        "[TLS] Generated a self-signed certificate for {} in {}",
+        names.join(", "),
+        dir.display()
+    );
+    Ok((cert_path, key_path))
+}
+
+/// Write a file readable only by its owner where the platform allows
+fn write_private(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(contents))
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Colon-separated SHA-256 of a DER certificate, as browsers show it
+fn fingerprint(cert: &CertificateDer<'_>) -> String {
+    Sha256::digest(cert.as_ref())
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn self_signed(dir: &Path) -> WebConfig {
+        WebConfig {
+            tls: Some(TlsConfig::SelfSigned {
+                dir: dir.to_path_buf(),
+            }),
+            allowed_hosts: vec!["ccgo.lan:8765".to_string()],
+            ..WebConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_certificate_names() {
+        let hosts = [
+            "ccgo.corp.internal:8443".to_string(),
+            "*.lan".to_string(),
+            "192.168.*".to_string(),
+            "[fd00::1]:8765".to_string(),
+            "fd00::2".to_string(),
+            "LOCALHOST".to_string(),
+        ];
+        assert_eq!(
+            certificate_names("192.168.1.10".parse().unwrap(), &hosts),
+            [
+                "localhost",
+                "127.0.0.1",
+                "::1",
+                "192.168.1.10",
+                "ccgo.corp.internal",
+                "*.lan",
+                "fd00::1",
+                "fd00::2",
+            ]
+        );
+        assert_eq!(
+            certificate_names("0.0.0.0".parse().unwrap(), &[]),
+            ["localhost", "127.0.0.1", "::1"]
+        );
+    }
+
+    #[test]
+    fn test_self_signed_is_generated_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let tls_dir = dir.path().join("tls");
+        let config = self_signed(&tls_dir);
+
+        server_config(&config, "0.0.0.0".parse().unwrap()).unwrap();
+        let cert = std::fs::read(tls_dir.join(SELF_SIGNED_CERT)).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(tls_dir.join(SELF_SIGNED_KEY))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let server = server_config(&config, "0.0.0.0".parse().unwrap()).unwrap();
+        assert_eq!(std::fs::read(tls_dir.join(SELF_SIGNED_CERT)).unwrap(), cert);
+        assert_eq!(server.alpn_protocols, [b"http/1.1".to_vec()]);
+    }
+
+    #[test]
+    fn test_files_must_exist_and_match() {
+        let dir = tempfile::tempdir().unwrap();
+        server_config(&self_signed(dir.path()), "127.0.0.1".parse().unwrap()).unwrap();
+        let files = |cert: &str, key: &str| WebConfig {
+            tls: Some(TlsConfig::Files {
+                cert: dir.path().join(cert),
+                key: dir.path().join(key),
+            }),
+            ..WebConfig::default()
+        };
+
+        assert!(server_config(&files("cert.pem", "key.pem"), "127.0.0.1".parse().unwrap()).is_ok());
+        let missing = server_config(
+            &files("missing.pem", "key.pem"),
+            "127.0.0.1".parse().unwrap(),
+        );
+        assert!(missing.unwrap_err().to_string().contains("missing.pem"));
+
+        let other = tempfile::tempdir().unwrap();
+        server_config(&self_signed(other.path()), "127.0.0.1".parse().unwrap()).unwrap();
+        std::fs::copy(other.path().join("key.pem"), dir.path().join("other.pem")).unwrap();
+        assert!(server_config(
+            &files("cert.pem", "other.pem"),
+            "127.0.0.1".parse().unwrap()
+        )
+        .is_err());
+    }
+}
@@ -1,11 +1,14 @@
 //! WebSocket handler for real-time PTY output
 
-use super::AppState;
+use super::{AppState, AuditLog, Caller, ClientInfo, SessionId};
+use crate::config::Role;
+use crate::metrics::Metrics;
 use axum::{
     extract::{
         ws::{Message, WebSocket, WebSocketUpgrade},
-        Path, State,
+        Extension, Path, State,
     },
+    http::StatusCode,
     response::IntoResponse,
 };
 use futures_util::{SinkExt, StreamExt};
@@ -21,13 +24,13 @@ const MIN_TERMINAL_SIZE: u16 = 1;
 const MAX_TERMINAL_SIZE: u16 = 500;
 /// Interval to check for PTY availability when agent is not running
 const PTY_POLL_INTERVAL_MS: u64 = 1000;
+/// Interval to check that the browser session behind a socket is still valid
+const SESSION_CHECK_INTERVAL_MS: u64 = 5000;
 
 /// Control command from frontend (sent as JSON with \x00 prefix)
-/// Note: Control commands (like resize) are intentionally allowed even when
-/// input_enabled is false. This is because:
-/// 1. Resize is not user input - it's terminal synchronization
-/// 2. Correct terminal size is needed for proper output rendering
-/// 3. Read-only viewers still need accurate terminal dimensions
+/// Note: Resizing changes the terminal every other client and the agent see,
+/// so it needs an operator, but not input to be enabled. Viewers render at
+/// the size the output was produced for.
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type", rename_all = "lowercase")]
 enum ControlCommand {
@@ -43,13 +46,43 @@ enum PtyMessage {
 pub async fn ws_handler(
     ws: WebSocketUpgrade,
     State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Extension(client): Extension<ClientInfo>,
+    session: Option<Extension<SessionId>>,
     Path(agent_name): Path<String>,
-) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| handle_socket(socket, state, agent_name))
+) -> Result<impl IntoResponse, StatusCode> {
+    caller.authorize(Role::Viewer, Some(&agent_name))?;
+    let session = session.map(|Extension(id)| id);
+    Ok(ws.on_upgrade(move |socket| {
+        handle_socket(socket, state, caller, client, session, agent_name)
+    }))
 }
 
-async fn handle_socket(socket: WebSocket, state: AppState, agent_name: String) {
+/// Resolves once the browser session the socket was opened with has expired
+/// or been revoked; never for sockets opened with a bearer token
+async fn session_ended(state: &AppState, session: Option<&SessionId>) {
+    let Some(SessionId(id)) = session else {
+        return std::future::pending().await;
+    };
+    loop {
+        tokio::time::sleep(Duration::from_millis(SESSION_CHECK_INTERVAL_MS)).await;
+        if state.sessions.get(id).is_none() {
+            return;
+        }
+    }
+}
+
+async fn handle_socket(
+    socket: WebSocket,
+    state: AppState,
+    caller: Caller,
+    client: ClientInfo,
+    session: Option<SessionId>,
+    agent_name: String,
+) {
     let (mut sender, receiver) = socket.split();
+    let ended = session_ended(&state, session.as_ref());
+    tokio::pin!(ended);
 
     // Get the session for this agent
     let Some(session) = state.session_manager.get(&agent_name).await else {
@@ -83,7 +116,13 @@ async fn handle_socket(socket: WebSocket, state: AppState, agent_name: String) {
         }
 
         // Wait before checking again
-        tokio::time::sleep(Duration::from_millis(PTY_POLL_INTERVAL_MS)).await;
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_millis(PTY_POLL_INTERVAL_MS)) => {},
+            _ = &mut ended => {
+                let _ = sender.send(Message::Close(None)).await;
+                return;
+            }
+        }
     };
 
     // Send buffered output first
@@ -94,25 +133,41 @@ async fn handle_socket(socket: WebSocket, state: AppState, agent_name: String) {
 
     // Subscribe to new output
     let output_rx = pty.subscribe_output();
+    let metrics = state.session_manager.metrics();
+    let _subscriber = metrics.track_websocket(&agent_name);
 
     // Spawn task to forward PTY output to WebSocket
-    let mut send_task = spawn_send_task(sender, output_rx);
+    let mut send_task = spawn_send_task(sender, output_rx, Arc::clone(metrics));
 
     // Handle incoming messages using a channel to avoid Send issues
-    let input_enabled = state.config.web.input_enabled;
+    let input_enabled = state.config.web.input_enabled && caller.role >= Role::Driver;
+    let resize_enabled = caller.role >= Role::Operator;
     let (input_tx, input_rx) = mpsc::channel::<PtyMessage>(32);
 
     // Task to process input and control commands
-    let mut input_task = spawn_input_task(session.clone(), input_rx);
+    let mut input_task = spawn_input_task(
+        session.clone(),
+        input_rx,
+        Arc::clone(&state.audit),
+        caller.name.clone(),
+        client.ip,
+    );
 
     // Task to receive WebSocket messages
-    let mut recv_task = spawn_recv_task(receiver, input_tx, input_enabled);
+    let mut recv_task = spawn_recv_task(receiver, input_tx, input_enabled, resize_enabled);
 
     // Wait for either task to complete
     tokio::select! {
         _ = &mut send_task => {},
         _ = &mut recv_task => {},
         _ = &mut input_task => {},
+        _ = &mut ended => {
+            tracing::info!(
+                "[Auth] Closing {}'s terminal of {}: session ended",
+                caller.name,
+                agent_name
+            );
+        }
     }
 
     send_task.abort();
@@ -123,6 +178,7 @@ async fn handle_socket(socket: WebSocket, state: AppState, agent_name: String) {
 fn spawn_send_task(
     mut sender: futures_util::stream::SplitSink<WebSocket, Message>,
     mut output_rx: broadcast::Receiver<Vec<u8>>,
+    metrics: Arc<Metrics>,
 ) -> tokio::task::JoinHandle<()> {
     tokio::spawn(async move {
         loop {
@@ -134,6 +190,7 @@ fn spawn_send_task(
                 }
                 Err(broadcast::error::RecvError::Lagged(n)) => {
                     warn!("WebSocket output lagged by {} messages", n);
+                    metrics.record_lag("pty_output", n);
                     continue;
                 }
                 Err(broadcast::error::RecvError::Closed) => break,
@@ -145,6 +202,9 @@ fn spawn_send_task(
 fn spawn_input_task(
     session: Arc<crate::session::AgentSession>,
     mut input_rx: mpsc::Receiver<PtyMessage>,
+    audit: Arc<AuditLog>,
+    token: String,
+    client: Option<std::net::IpAddr>,
 ) -> tokio::task::JoinHandle<()> {
     tokio::spawn(async move {
         while let Some(msg) = input_rx.recv().await {
@@ -152,6 +212,7 @@ fn spawn_input_task(
             if let Some(pty) = pty_guard.as_ref() {
                 match msg {
                     PtyMessage::Input(data) => {
+                        audit.record(&token, client, &session.name, &data);
                         let _ = pty.write(&data).await;
                     }
                     PtyMessage::Resize { cols, rows } => {
@@ -168,6 +229,7 @@ fn spawn_recv_task(
     mut receiver: futures_util::stream::SplitStream<WebSocket>,
     input_tx: mpsc::Sender<PtyMessage>,
     input_enabled: bool,
+    resize_enabled: bool,
 ) -> tokio::task::JoinHandle<()> {
     tokio::spawn(async move {
         while let Some(Ok(msg)) = receiver.next().await {
@@ -177,12 +239,13 @@ fn spawn_recv_task(
                     if let Some(json_str) = text.strip_prefix('\x00') {
                         match serde_json::from_str::<ControlCommand>(json_str) {
                             Ok(cmd) => match cmd {
-                                ControlCommand::Resize { cols, rows } => {
+                                ControlCommand::Resize { cols, rows } if resize_enabled => {
                                     // Clamp values to reasonable bounds
                                     let cols = cols.clamp(MIN_TERMINAL_SIZE, MAX_TERMINAL_SIZE);
                                     let rows = rows.clamp(MIN_TERMINAL_SIZE, MAX_TERMINAL_SIZE);
                                     let _ = input_tx.send(PtyMessage::Resize { cols, rows }).await;
                                 }
+                                ControlCommand::Resize { .. } => {}
                             },
                             Err(e) => {
                                 warn!("Invalid control command: {}", e);
@@ -192,10 +255,8 @@ fn spawn_recv_task(
                         let _ = input_tx.send(PtyMessage::Input(text.into_bytes())).await;
                     }
                 }
-                Message::Binary(data) => {
-                    if input_enabled {
-                        let _ = input_tx.send(PtyMessage::Input(data)).await;
-                    }
+                Message::Binary(data) if input_enabled => {
+                    let _ = input_tx.send(PtyMessage::Input(data)).await;
                 }
                 Message::Close(_) => break,
                 _ => {}
@@ -207,6 +268,29 @@ fn spawn_recv_task(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::Config;
+    use crate::pty::PtyManager;
+    use crate::session::SessionManager;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_revoked_session_ends_socket() {
+        let manager = SessionManager::new(Arc::new(PtyManager::new(1024)));
+        let state = AppState::new(Arc::new(manager), Arc::new(Config::default()), 0);
+        let (id, _) = state
+            .sessions
+            .create(Caller::unrestricted("alice"), chrono::Duration::hours(1));
+        let session = SessionId(id.clone());
+
+        let ended = session_ended(&state, Some(&session));
+        tokio::pin!(ended);
+        let wait = Duration::from_millis(3 * SESSION_CHECK_INTERVAL_MS);
+        assert!(tokio::time::timeout(wait, &mut ended).await.is_err());
+        state.sessions.revoke(&id);
+        assert!(tokio::time::timeout(wait, &mut ended).await.is_ok());
+
+        let bearer = session_ended(&state, None);
+        assert!(tokio::time::timeout(wait, bearer).await.is_err());
+    }
 
     #[test]
     fn test_control_command_resize_parsing() {
@@ -0,0 +1,678 @@
+//! Per-agent git worktree isolation
+//!
+//! With isolation enabled every agent runs in its own `git worktree` on a
+//! scratch branch (`ccgonext/<agent>`) instead of the shared project
+//! directory. After each reply the agent's edits are committed on the scratch
+//! branch and captured as a change (diff plus commit) that the caller can
+//! apply to, merge into, or discard from the main checkout.
+
+use crate::changes::truncate_diff;
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+
+/// Branch prefix for scratch branches
+const BRANCH_PREFIX: &str = "ccgonext/";
+/// Ref namespace keeping captured change commits reachable
+const CHANGE_REF_PREFIX: &str = "refs/ccgonext/changes/";
+/// Identity used for scratch commits so they work without a configured user
+const COMMIT_IDENTITY: [&str; 4] = [
+    "-c",
+    "user.name=ccgonext",
+    "-c",
+    "user.email=ccgonext@localhost",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IsolationMode {
+    /// All agents share the project directory
+    #[default]
+    Off,
+    /// One worktree per agent; changes accumulate across requests
+    Session,
+    /// One worktree per agent, reset to the project HEAD before every request
+    Request,
+}
+
+impl std::str::FromStr for IsolationMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "off" | "none" | "" => Ok(Self::Off),
+            "session" => Ok(Self::Session),
+            "request" => Ok(Self::Request),
+            other => Err(format!(
+                "invalid isolation mode '{}' (expected off, session or request)",
+                other
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for IsolationMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Off => write!(f, "off"),
+            Self::Session => write!(f, "session"),
+            Self::Request => write!(f, "request"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeStatus {
+    Pending,
+    Applied,
+    Merged,
+    Discarded,
+}
+
+/// Edits an agent made while answering one request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorktreeChange {
+    /// Same as the request's message id
+    pub id: String,
+    pub agent: String,
+    pub branch: String,
+    /// Scratch branch commit the request started from
+    pub base: String,
+    /// Scratch branch commit holding the agent's edits
+    pub commit: String,
+    /// `git diff --name-status` lines
+    pub files: Vec<String>,
+    /// Unified diff from `base` to `commit`, cut at the change tracker's cap
+    pub diff: String,
+    /// `diff` was cut short; the full diff is `git diff <base> <commit>`,
+    /// whose commit stays reachable under `refs/ccgonext/changes/<id>`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub diff_truncated: bool,
+    pub status: ChangeStatus,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WorktreeError {
+    #[error("{0} is not inside a git repository")]
+    NotARepository(PathBuf),
+    #[error("unknown change: {0}")]
+    UnknownChange(String),
+    #[error("no request was started in the worktree of {0}")]
+    NoRequest(String),
+    #[error("change {0} is already {1:?}")]
+    NotPending(String, ChangeStatus),
+    #[error("git {args} failed: {message}")]
+    Git { args: String, message: String },
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+struct AgentWorktree {
+    /// Worktree checkout root
+    path: PathBuf,
+    branch: String,
+    /// HEAD of the scratch branch when the current request was sent
+    request_base: Option<String>,
+}
+
+pub struct WorktreeManager {
+    mode: IsolationMode,
+    /// Top level of the main checkout
+    repo_root: PathBuf,
+    /// Project directory relative to `repo_root`, mirrored inside each worktree
+    subdir: PathBuf,
+    /// Directory holding the worktrees (inside the git dir, so it never shows up in status)
+    worktrees_dir: PathBuf,
+    worktrees: Mutex<HashMap<String, AgentWorktree>>,
+    changes: Mutex<HashMap<String, WorktreeChange>>,
+}
+
+async fn git_with_input(
+    dir: &Path,
+    args: &[&str],
+    input: Option<&[u8]>,
+) -> Result<String, WorktreeError> {
+    let mut child = tokio::process::Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let (Some(data), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin.write_all(data).await?;
+    }
+    let output = child.wait_with_output().await?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(WorktreeError::Git {
+            args: args.join(" "),
+            message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        })
+    }
+}
+
+async fn git(dir: &Path, args: &[&str]) -> Result<String, WorktreeError> {
+    git_with_input(dir, args, None).await
+}
+
+/// Branch names may not contain most punctuation; keep agent names simple
+fn branch_for(agent: &str) -> String {
+    let name: String = agent
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!("{}{}", BRANCH_PREFIX, name)
+}
+
+impl WorktreeManager {
+    /// Prepare isolation for the repository containing `project_dir`
+    pub async fn new(project_dir: &Path, mode: IsolationMode) -> Result<Self, WorktreeError> {
+        let toplevel = git(project_dir, &["rev-parse", "--show-toplevel"])
+            .await
+            .map_err(|_| WorktreeError::NotARepository(project_dir.to_path_buf()))?;
+        let repo_root = PathBuf::from(toplevel.trim());
+        let prefix = git(project_dir, &["rev-parse", "--show-prefix"]).await?;
+        let common_dir = git(
+            project_dir,
+            &["rev-parse", "--path-format=absolute", "--git-common-dir"],
+        )
+        .await?;
+
+        Ok(Self {
+            mode,
+            repo_root,
+            subdir: PathBuf::from(prefix.trim()),
+            worktrees_dir: PathBuf::from(common_dir.trim()).join("ccgonext-worktrees"),
+            worktrees: Mutex::new(HashMap::new()),
+            changes: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn mode(&self) -> IsolationMode {
+        self.mode
+    }
+
+    pub fn repo_root(&self) -> &Path {
+        &self.repo_root
+    }
+
+    fn worktree_path(&self, agent: &str) -> Option<PathBuf> {
+        self.worktrees.lock().get(agent).map(|w| w.path.clone())
+    }
+
+    /// Create (or recreate) the agent's worktree and return the directory the
+    /// agent should run in. An existing scratch branch is checked out as is,
+    /// so work left on it by a previous run is kept; otherwise the branch is
+    /// created at the project HEAD.
+    pub async fn create(&self, agent: &str) -> Result<PathBuf, WorktreeError> {
+        let branch = branch_for(agent);
+        let path = self.worktrees_dir.join(&branch[BRANCH_PREFIX.len()..]);
+
+        // A worktree left behind by a previous run is replaced
+        if path.exists() {
+            let path_str = path.to_string_lossy().to_string();
+            let _ = git(
+                &self.repo_root,
+                &["worktree", "remove", "--force", &path_str],
+            )
+            .await;
+            if path.exists() {
+                std::fs::remove_dir_all(&path)?;
+            }
+        }
+        git(&self.repo_root, &["worktree", "prune"]).await?;
+        std::fs::create_dir_all(&self.worktrees_dir)?;
+
+        let path_str = path.to_string_lossy().to_string();
+        let branch_ref = format!("refs/heads/{}", branch);
+        let existing = git(
+            &self.repo_root,
+            &["rev-parse", "--verify", "--quiet", &branch_ref],
+        )
+        .await
+        .is_ok();
+        if existing {
+            tracing::info!("[Worktree] Reusing existing branch {}", branch);
+            git(
+                &self.repo_root,
+                &["worktree", "add", "--quiet", &path_str, &branch],
+            )
+            .await?;
+        } else {
+            git(
+                &self.repo_root,
+                &[
+                    "worktree", "add", "--quiet", "-b", &branch, &path_str, "HEAD",
+                ],
+            )
+            .await?;
+        }
+        tracing::info!(
+            "[Worktree] Created worktree for {} at {:?} on {}",
+            agent,
+            path,
+            branch
+        );
+
+        let agent_dir = path.join(&self.subdir);
+        std::fs::create_dir_all(&agent_dir)?;
+        self.worktrees.lock().insert(
+            agent.to_string(),
+            AgentWorktree {
+                path,
+                branch,
+                request_base: None,
+            },
+        );
+        Ok(agent_dir)
+    }
+
+    /// Called before a request is sent: resets the worktree in request mode
+    /// and records the commit the request's diff is taken against
+    pub async fn begin_request(&self, agent: &str) -> Result<(), WorktreeError> {
+        let Some(path) = self.worktree_path(agent) else {
+            return Ok(());
+        };
+
+        if self.mode == IsolationMode::Request {
+            let head = git(&self.repo_root, &["rev-parse", "HEAD"]).await?;
+            git(&path, &["reset", "--quiet", "--hard", head.trim()]).await?;
+            git(&path, &["clean", "--quiet", "-fd"]).await?;
+        }
+
+        let base = git(&path, &["rev-parse", "HEAD"]).await?;
+        if let Some(wt) = self.worktrees.lock().get_mut(agent) {
+            wt.request_base = Some(base.trim().to_string());
+        }
+        Ok(())
+    }
+
+    /// Commit the agent's edits on its scratch branch and record them as a
+    /// change. Returns None when the request left the tree untouched.
+    pub async fn capture(
+        &self,
+        agent: &str,
+        change_id: &str,
+    ) -> Result<Option<WorktreeChange>, WorktreeError> {
+        let (path, branch, base) = {
+            let mut worktrees = self.worktrees.lock();
+            let Some(wt) = worktrees.get_mut(agent) else {
+                return Ok(None);
+            };
+            let Some(base) = wt.request_base.take() else {
+                return Err(WorktreeError::NoRequest(agent.to_string()));
+            };
+            (wt.path.clone(), wt.branch.clone(), base)
+        };
+
+        git(&path, &["add", "-A"]).await?;
+        let staged = git(&path, &["diff", "--cached", "--name-only"]).await?;
+        if !staged.trim().is_empty() {
+            let message = format!("ccgonext: {} change {}", agent, change_id);
+            let mut args: Vec<&str> = COMMIT_IDENTITY.to_vec();
+            args.extend(["commit", "--quiet", "--no-verify", "-m", &message]);
+            git(&path, &args).await?;
+        }
+
+        let commit = git(&path, &["rev-parse", "HEAD"]).await?.trim().to_string();
+        if base == commit {
+            return Ok(None);
+        }
+
+        let mut diff = git(&path, &["diff", "--binary", &base, &commit]).await?;
+        let diff_truncated = truncate_diff(&mut diff);
+        let files = git(&path, &["diff", "--name-status", &base, &commit])
+            .await?
+            .lines()
+            .map(|l| l.to_string())
+            .collect();
+        let change_ref = format!("{}{}", CHANGE_REF_PREFIX, change_id);
+        git(&self.repo_root, &["update-ref", &change_ref, &commit]).await?;
+
+        let change = WorktreeChange {
+            id: change_id.to_string(),
+            agent: agent.to_string(),
+            branch,
+            base,
+            commit,
+            files,
+            diff,
+            diff_truncated,
+            status: ChangeStatus::Pending,
+            created_at: Utc::now(),
+        };
+        tracing::info!(
+            "[Worktree] Captured change {} from {} ({} files)",
+            change_id,
+            agent,
+            change.files.len()
+        );
+        self.changes
+            .lock()
+            .insert(change_id.to_string(), change.clone());
+        Ok(Some(change))
+    }
+
+    pub fn change(&self, id: &str) -> Option<WorktreeChange> {
+        self.changes.lock().get(id).cloned()
+    }
+
+    /// Pending change with `id`, or the reason it cannot be acted on
+    fn pending_change(&self, id: &str) -> Result<WorktreeChange, WorktreeError> {
+        let change = self
+            .change(id)
+            .ok_or_else(|| WorktreeError::UnknownChange(id.to_string()))?;
+        if change.status != ChangeStatus::Pending {
+            return Err(WorktreeError::NotPending(id.to_string(), change.status));
+        }
+        Ok(change)
+    }
+
+    async fn finish(&self, id: &str, status: ChangeStatus) {
+        let change_ref = format!("{}{}", CHANGE_REF_PREFIX, id);
+        if let Err(e) = git(&self.repo_root, &["update-ref", "-d", &change_ref]).await {
+            tracing::warn!("[Worktree] Failed to delete {}: {}", change_ref, e);
+        }
+        if let Some(change) = self.changes.lock().get_mut(id) {
+            change.status = status;
+        }
+    }
+
+    /// Apply the change to the main checkout's working tree without committing
+    pub async fn apply(&self, id: &str) -> Result<WorktreeChange, WorktreeError> {
+        let change = self.pending_change(id)?;
+        let full_diff;
+        let diff = if change.diff_truncated {
+            full_diff = git(
+                &self.repo_root,
+                &["diff", "--binary", &change.base, &change.commit],
+            )
+            .await?;
+            &full_diff
+        } else {
+            &change.diff
+        };
+        git_with_input(
+            &self.repo_root,
+            &["apply", "--3way", "--whitespace=nowarn"],
+            Some(diff.as_bytes()),
+        )
+        .await?;
+        self.finish(id, ChangeStatus::Applied).await;
+        Ok(self.change(id).unwrap_or(change))
+    }
+
+    /// Merge the change's commit into the main checkout's current branch
+    pub async fn merge(&self, id: &str) -> Result<WorktreeChange, WorktreeError> {
+        let change = self.pending_change(id)?;
+        let message = format!(
+            "Merge {} change {} from {}",
+            change.agent, id, change.branch
+        );
+        if let Err(e) = git(
+            &self.repo_root,
+            &[
+                "merge",
+                "--no-ff",
+                "--no-edit",
+                "-m",
+                &message,
+                &change.commit,
+            ],
+        )
+        .await
+        {
+            let _ = git(&self.repo_root, &["merge", "--abort"]).await;
+            return Err(e);
+        }
+        self.finish(id, ChangeStatus::Merged).await;
+        Ok(self.change(id).unwrap_or(change))
+    }
+
+    /// Drop the change; when it is still the tip of the scratch branch the
+    /// worktree is reset to the change's base so the agent no longer sees it
+    pub async fn discard(&self, id: &str) -> Result<WorktreeChange, WorktreeError> {
+        let change = self.pending_change(id)?;
+        if let Some(path) = self.worktree_path(&change.agent) {
+            let head = git(&path, &["rev-parse", "HEAD"]).await?;
+            if head.trim() == change.commit {
+                git(&path, &["reset", "--quiet", "--hard", &change.base]).await?;
+            }
+        }
+        self.finish(id, ChangeStatus::Discarded).await;
+        Ok(self.change(id).unwrap_or(change))
+    }
+
+    /// Remove all worktrees and scratch branches. Refs of changes that are
+    /// still pending are kept so their commits can be recovered with git.
+    pub async fn cleanup(&self) {
+        let worktrees: Vec<(String, AgentWorktree)> = self.worktrees.lock().drain().collect();
+        for (agent, wt) in worktrees {
+            let path_str = wt.path.to_string_lossy().to_string();
+            if let Err(e) = git(
+                &self.repo_root,
+                &["worktree", "remove", "--force", &path_str],
+            )
+            .await
+            {
+                tracing::warn!("[Worktree] Failed to remove worktree for {}: {}", agent, e);
+            }
+            if let Err(e) = git(&self.repo_root, &["branch", "-D", "--quiet", &wt.branch]).await {
+                tracing::warn!("[Worktree] Failed to delete branch {}: {}", wt.branch, e);
+            }
+        }
+        let _ = git(&self.repo_root, &["worktree", "prune"]).await;
+
+        let pending = self
+            .changes
+            .lock()
+            .values()
+            .filter(|c| c.status == ChangeStatus::Pending)
+            .count();
+        if pending > 0 {
+            tracing::info!(
+                "[Worktree] {} unresolved changes kept under {}",
+                pending,
+                CHANGE_REF_PREFIX
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    async fn init_repo(dir: &Path) {
+        git(dir, &["init", "--quiet", "-b", "main"]).await.unwrap();
+        fs::write(dir.join("a.txt"), "one\n").unwrap();
+        git(dir, &["add", "-A"]).await.unwrap();
+        let mut args: Vec<&str> = COMMIT_IDENTITY.to_vec();
+        args.extend(["commit", "--quiet", "-m", "init"]);
+        git(dir, &args).await.unwrap();
+    }
+
+    #[test]
+    fn test_isolation_mode_parse() {
+        assert_eq!(
+            "session".parse::<IsolationMode>(),
+            Ok(IsolationMode::Session)
+        );
+        assert_eq!(
+            "Request".parse::<IsolationMode>(),
+            Ok(IsolationMode::Request)
+        );
+        assert_eq!("off".parse::<IsolationMode>(), Ok(IsolationMode::Off));
+        assert!("bogus".parse::<IsolationMode>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_capture_and_apply_change() {
+        let repo = tempdir().unwrap();
+        init_repo(repo.path()).await;
+        let manager = WorktreeManager::new(repo.path(), IsolationMode::Session)
+            .await
+            .unwrap();
+
+        let dir = manager.create("codex").await.unwrap();
+        manager.begin_request("codex").await.unwrap();
+        assert!(manager.capture("codex", "m0").await.unwrap().is_none());
+
+        manager.begin_request("codex").await.unwrap();
+        fs::write(dir.join("a.txt"), "two\n").unwrap();
+        fs::write(dir.join("b.txt"), "new\n").unwrap();
+        let change = manager.capture("codex", "m1").await.unwrap().unwrap();
+        assert_eq!(change.files.len(), 2);
+        assert!(change.diff.contains("+two"));
+        // The main checkout is untouched until the change is applied
+        assert_eq!(
+            fs::read_to_string(repo.path().join("a.txt")).unwrap(),
+            "one\n"
+        );
+
+        manager.apply("m1").await.unwrap();
+        assert_eq!(
+            fs::read_to_string(repo.path().join("a.txt")).unwrap(),
+            "two\n"
+        );
+        assert!(repo.path().join("b.txt").exists());
+        assert!(matches!(
+            manager.apply("m1").await,
+            Err(WorktreeError::NotPending(_, ChangeStatus::Applied))
+        ));
+
+        manager.cleanup().await;
+        assert!(!dir.exists());
+        let branches = git(repo.path(), &["branch", "--list", "ccgonext/*"])
+            .await
+            .unwrap();
+        assert!(branches.trim().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_large_diff_is_truncated_but_applies_in_full() {
+        let repo = tempdir().unwrap();
+        init_repo(repo.path()).await;
+        let manager = WorktreeManager::new(repo.path(), IsolationMode::Session)
+            .await
+            .unwrap();
+
+        let dir = manager.create("codex").await.unwrap();
+        manager.begin_request("codex").await.unwrap();
+        let big = "generated line\n".repeat(100_000);
+        fs::write(dir.join("lock.txt"), &big).unwrap();
+        let change = manager.capture("codex", "m1").await.unwrap().unwrap();
+        assert!(change.diff_truncated);
+        assert!(change.diff.len() < big.len());
+        assert!(change.diff.ends_with("... diff truncated\n"));
+
+        manager.apply("m1").await.unwrap();
+        assert_eq!(
+            fs::read_to_string(repo.path().join("lock.txt")).unwrap(),
+            big
+        );
+        manager.cleanup().await;
+    }
+
+    #[tokio::test]
+    async fn test_discard_resets_worktree() {
+        let repo = tempdir().unwrap();
+        init_repo(repo.path()).await;
+        let manager = WorktreeManager::new(repo.path(), IsolationMode::Session)
+            .await
+            .unwrap();
+
+        let dir = manager.create("gemini").await.unwrap();
+        manager.begin_request("gemini").await.unwrap();
+        fs::write(dir.join("a.txt"), "scratch\n").unwrap();
+        manager.capture("gemini", "m1").await.unwrap().unwrap();
+
+        let change = manager.discard("m1").await.unwrap();
+        assert_eq!(change.status, ChangeStatus::Discarded);
+        assert_eq!(fs::read_to_string(dir.join("a.txt")).unwrap(), "one\n");
+        manager.cleanup().await;
+    }
+
+    #[tokio::test]
+    async fn test_merge_change() {
+        let repo = tempdir().unwrap();
+        init_repo(repo.path()).await;
+        let manager = WorktreeManager::new(repo.path(), IsolationMode::Request)
+            .await
+            .unwrap();
+
+        let dir = manager.create("opencode").await.unwrap();
+        manager.begin_request("opencode").await.unwrap();
+        fs::write(dir.join("c.txt"), "merged\n").unwrap();
+        manager.capture("opencode", "m1").await.unwrap().unwrap();
+
+        // merge uses the repository's own identity
+        git(repo.path(), &["config", "user.name", "test"])
+            .await
+            .unwrap();
+        git(repo.path(), &["config", "user.email", "test@localhost"])
+            .await
+            .unwrap();
+        manager.merge("m1").await.unwrap();
+        assert!(repo.path().join("c.txt").exists());
+        manager.cleanup().await;
+    }
+
+    #[tokio::test]
+    async fn test_capture_requires_a_started_request() {
+        let repo = tempdir().unwrap();
+        init_repo(repo.path()).await;
+        let manager = WorktreeManager::new(repo.path(), IsolationMode::Session)
+            .await
+            .unwrap();
+
+        // The project's own last commit is never reported as the agent's change
+        manager.create("codex").await.unwrap();
+        assert!(matches!(
+            manager.capture("codex", "m1").await,
+            Err(WorktreeError::NoRequest(agent)) if agent == "codex"
+        ));
+        manager.cleanup().await;
+    }
+
+    #[tokio::test]
+    async fn test_existing_branch_is_reused() {
+        let repo = tempdir().unwrap();
+        init_repo(repo.path()).await;
+        let manager = WorktreeManager::new(repo.path(), IsolationMode::Session)
+            .await
+            .unwrap();
+
+        let dir = manager.create("gemini").await.unwrap();
+        manager.begin_request("gemini").await.unwrap();
+        fs::write(dir.join("a.txt"), "kept\n").unwrap();
+        let change = manager.capture("gemini", "m1").await.unwrap().unwrap();
+
+        // Recreating the worktree keeps the branch's commits
+        let dir = manager.create("gemini").await.unwrap();
+        assert_eq!(fs::read_to_string(dir.join("a.txt")).unwrap(), "kept\n");
+        let head = git(&dir, &["rev-parse", "HEAD"]).await.unwrap();
+        assert_eq!(head.trim(), change.commit);
+        manager.cleanup().await;
+    }
+}
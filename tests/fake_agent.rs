@@ -0,0 +1,267 @@
+//! End-to-end session tests against `ccgonext-fake-agent`
+//!
+//! Each test starts the fake agent in a real PTY through `SessionManager`,
+//! with the agent's own adapter and log provider pointed at a temporary log
+//! root, so the whole path from prompt injection to reply harvesting runs
+//! without any AI CLI, network access or vendor account.
+
+use ccgonext::agent::create_agent;
+use ccgonext::config::{AgentConfig, TimeoutConfig};
+use ccgonext::log_provider::create_log_provider;
+use ccgonext::pty::PtyManager;
+use ccgonext::session::{AgentSession, SessionError, SessionManager};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+const FAKE_AGENT: &str = env!("CARGO_BIN_EXE_ccgonext-fake-agent");
+
+struct Fixture {
+    manager: Arc<SessionManager>,
+    session: Arc<AgentSession>,
+    _logs: TempDir,
+    _workdir: TempDir,
+}
+
+impl Fixture {
+    async fn ask(&self, message: &str, timeout_secs: u64) -> Result<String, SessionError> {
+        self.session
+            .ask(
+                message.to_string(),
+                Some(Duration::from_secs(timeout_secs)),
+                self.manager.pty_manager(),
+            )
+            .await
+    }
+
+    async fn screen(&self) -> String {
+        let pty = self
+            .session
+            .pty
+            .read()
+            .await
+            .clone()
+            .expect("agent started");
+        String::from_utf8_lossy(&pty.get_buffer().await).to_string()
+    }
+}
+
+/// Register the fake agent as `agent`, imitating its flavor, and start it
+async fn start(agent: &str, extra_args: &[&str]) -> Fixture {
+    let (config, flavor) = match agent {
+        "codex" => (AgentConfig::codex_default(), "codex"),
+        "gemini" => (AgentConfig::gemini_default(), "gemini"),
+        "opencode" => (AgentConfig::opencode_default(), "opencode"),
+        "claudecode" => (AgentConfig::claudecode_default(), "claude"),
+        other => panic!("unknown agent {}", other),
+    };
+    let logs = tempfile::tempdir().unwrap();
+    let workdir = tempfile::tempdir().unwrap();
+    let root = logs.path().to_string_lossy().to_string();
+
+    let mut args = vec!["--flavor", flavor, "--log-root", &root];
+    args.extend_from_slice(extra_args);
+    let config = config
+        .with_command(FAKE_AGENT.to_string())
+        .with_args(args.into_iter().map(String::from).collect());
+
+    let provider_config = HashMap::from([
+        ("path_pattern".to_string(), root.clone()),
+        (
+            "working_dir".to_string(),
+            workdir.path().to_string_lossy().to_string(),
+        ),
+    ]);
+    let provider = create_log_provider(&config.log_provider, Some(&provider_config));
+    let timeouts = TimeoutConfig {
+        ready_check: 10,
+        max_start_retries: 0,
+        ..TimeoutConfig::default()
+    };
+
+    let manager = Arc::new(SessionManager::new(Arc::new(PtyManager::new(1024 * 1024))));
+    manager
+        .register(AgentSession::new(
+            agent.to_string(),
+            Arc::from(create_agent(agent, &config)),
+            Arc::from(provider),
+            workdir.path().to_path_buf(),
+            timeouts,
+        ))
+        .await;
+    let session = manager.get(agent).await.unwrap();
+    session.start(manager.pty_manager()).await.unwrap();
+
+    Fixture {
+        manager,
+        session,
+        _logs: logs,
+        _workdir: workdir,
+    }
+}
+
+async fn assert_replies(agent: &str) {
+    let fixture = start(agent, &[]).await;
+
+    let reply = fixture.ask("What is 2+2?", 30).await.unwrap();
+    assert_eq!(reply, "Fake reply 1: What is 2+2?", "{}", agent);
+
+    // The second reply must come from after the new baseline, not the first turn
+    let reply = fixture.ask("And 3+3?", 30).await.unwrap();
+    assert_eq!(reply, "Fake reply 2: And 3+3?", "{}", agent);
+
+    fixture.manager.shutdown_all().await;
+}
+
+#[tokio::test]
+async fn test_codex_reply_from_rollout_log() {
+    assert_replies("codex").await;
+}
+
+#[tokio::test]
+async fn test_gemini_reply_from_chat_file() {
+    assert_replies("gemini").await;
+}
+
+#[tokio::test]
+async fn test_opencode_reply_from_part_files() {
+    assert_replies("opencode").await;
+}
+
+#[tokio::test]
+async fn test_claudecode_reply_from_pty() {
+    assert_replies("claudecode").await;
+}
+
+#[tokio::test]
+async fn test_missing_done_marker_falls_back_to_stability() {
+    let fixture = start("codex", &["--fail", "no-marker"]).await;
+
+    let reply = fixture.ask("What is 2+2?", 30).await.unwrap();
+    assert_eq!(reply, "Fake reply 1: What is 2+2?");
+
+    fixture.manager.shutdown_all().await;
+}
+
+#[tokio::test]
+async fn test_missing_done_marker_times_out_without_heuristic() {
+    // Gemini rewrites its chat file while streaming, so only the marker ends a reply
+    let fixture = start("gemini", &["--fail", "no-marker"]).await;
+
+    let result = fixture.ask("What is 2+2?", 4).await;
+    assert!(
+        matches!(result, Err(SessionError::RequestTimeout)),
+        "{:?}",
+        result
+    );
+
+    fixture.manager.shutdown_all().await;
+}
+
+#[tokio::test]
+async fn test_rate_limit_text_is_not_a_reply() {
+    let fixture = start("codex", &["--fail", "rate-limit"]).await;
+
+    let result = fixture.ask("What is 2+2?", 4).await;
+    assert!(
+        matches!(result, Err(SessionError::RequestTimeout)),
+        "{:?}",
+        result
+    );
+    assert!(fixture.screen().await.contains("429 Too Many Requests"));
+
+    // Only the first prompt is scripted to fail
+    let reply = fixture.ask("What is 2+2?", 30).await.unwrap();
+    assert_eq!(reply, "Fake reply 2: What is 2+2?");
+
+    fixture.manager.shutdown_all().await;
+}
+
+#[tokio::test]
+async fn test_hang_times_out() {
+    let fixture = start("opencode", &["--fail", "hang"]).await;
+
+    let started = Instant::now();
+    let result = fixture.ask("What is 2+2?", 3).await;
+    assert!(
+        matches!(result, Err(SessionError::RequestTimeout)),
+        "{:?}",
+        result
+    );
+    assert!(started.elapsed() < Duration::from_secs(10));
+
+    fixture.manager.shutdown_all().await;
+}
+
+#[tokio::test]
+async fn test_crash_mid_reply() {
+    let fixture = start("claudecode", &["--fail", "crash"]).await;
+
+    let result = fixture.ask("What is 2+2?", 4).await;
+    assert!(result.is_err(), "{:?}", result);
+    assert!(fixture.screen().await.contains("fake agent crashed"));
+
+    let pty = fixture.session.pty.read().await.clone().unwrap();
+    let status = pty.try_wait().await.unwrap().expect("agent exited");
+    assert_eq!(status.exit_code(), 1);
+
+    fixture.manager.shutdown_all().await;
+}
+
+#[tokio::test]
+async fn test_ask_spans_cover_phases() {
+    use ccgonext::telemetry::FileExporter;
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let fixture = start("codex", &[]).await;
+    let traces = tempfile::tempdir().unwrap();
+    let path = traces.path().join("traces.jsonl");
+    let provider = SdkTracerProvider::builder()
+        .with_simple_exporter(FileExporter::create(&path).unwrap())
+        .build();
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+
+    // The current-thread runtime keeps the spawned reply detection on this thread
+    {
+        let _guard = tracing::subscriber::set_default(subscriber);
+        let reply = fixture.ask("What is 2+2?", 30).await.unwrap();
+        assert_eq!(reply, "Fake reply 1: What is 2+2?");
+    }
+    fixture.manager.shutdown_all().await;
+    provider.shutdown().unwrap();
+
+    let spans: Vec<serde_json::Value> = std::fs::read_to_string(&path)
+        .unwrap()
+        .lines()
+        .flat_map(|line| {
+            let request: serde_json::Value = serde_json::from_str(line).unwrap();
+            request["resourceSpans"][0]["scopeSpans"][0]["spans"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default()
+        })
+        .collect();
+    let find = |name: &str| {
+        spans
+            .iter()
+            .find(|s| s["name"] == name)
+            .unwrap_or_else(|| panic!("no {} span in {:?}", name, spans))
+    };
+    let ask = find("ask");
+    for phase in [
+        "start",
+        "send_prepared_request",
+        "reply_detection",
+        "generate",
+        "detect",
+        "deliver",
+    ] {
+        assert_eq!(find(phase)["traceId"], ask["traceId"], "{}", phase);
+    }
+    assert_eq!(find("send_prepared_request")["parentSpanId"], ask["spanId"]);
+    assert!(ask["attributes"].to_string().contains("codex"));
+}
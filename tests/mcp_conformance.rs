@@ -0,0 +1,747 @@
+//! MCP protocol conformance over stdio
+//!
+//! Spawns `ccgonext serve` with Codex and Gemini played by
+//! `ccgonext-fake-agent` and talks JSON-RPC to it over both transports:
+//! line-delimited JSON and LSP-style Content-Length framing.
+
+use ccgonext::mcp::MAX_CONTENT_LENGTH;
+use serde_json::{json, Value};
+use std::process::Stdio;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::time::timeout;
+
+const CCGONEXT: &str = env!("CARGO_BIN_EXE_ccgonext");
+const FAKE_AGENT: &str = env!("CARGO_BIN_EXE_ccgonext-fake-agent");
+const RECV_TIMEOUT: Duration = Duration::from_secs(30);
+const LATEST_VERSION: &str = "2025-06-18";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Framing {
+    Lines,
+    Headers,
+}
+
+const BOTH: [Framing; 2] = [Framing::Lines, Framing::Headers];
+
+struct Server {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+    framing: Framing,
+    dir: TempDir,
+    /// Notifications received while waiting for responses
+    notifications: Vec<Value>,
+}
+
+impl Server {
+    /// Start `ccgonext serve`; `agent_env` adds `--agent-env` settings such
+    /// as `codex:CCGONEXT_FAKE_THINK_MS=3000`
+    async fn spawn(framing: Framing, agent_env: &[&str]) -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("project");
+        std::fs::create_dir(&project).unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+
+        let mut env = vec![
+            "codex:CCGONEXT_FAKE_FLAVOR=codex".to_string(),
+            format!("codex:CODEX_HOME={}/codex", root),
+            "gemini:CCGONEXT_FAKE_FLAVOR=gemini".to_string(),
+            format!("gemini:GEMINI_ROOT={}/gemini", root),
+        ];
+        env.extend(agent_env.iter().map(|s| s.to_string()));
+
+        let mut command = Command::new(CCGONEXT);
+        command
+            .args(["--port", "0", "--agents", "codex,gemini"])
+            .args(["--codex-cmd", FAKE_AGENT, "--gemini-cmd", FAKE_AGENT])
+            .args(["--timeout", "60", "--resource-interval", "0"])
+            .arg("--prompts-dir")
+            .arg(dir.path().join("prompts"));
+        for setting in &env {
+            command.args(["--agent-env", setting]);
+        }
+        let mut child = command
+            .arg("serve")
+            .current_dir(&project)
+            .env("HOME", dir.path())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
+
+        Self {
+            stdin: child.stdin.take(),
+            stdout: BufReader::new(child.stdout.take().unwrap()),
+            child,
+            framing,
+            dir,
+            notifications: Vec::new(),
+        }
+    }
+
+    async fn send_raw(&mut self, bytes: &[u8]) {
+        let stdin = self.stdin.as_mut().expect("stdin open");
+        stdin.write_all(bytes).await.unwrap();
+        stdin.flush().await.unwrap();
+    }
+
+    /// Send `text` as one message in this server's framing
+    async fn send_text(&mut self, text: &str) {
+        let framed = match self.framing {
+            Framing::Lines => format!("{}\n", text),
+            Framing::Headers => format!("Content-Length: {}\r\n\r\n{}", text.len(), text),
+        };
+        self.send_raw(framed.as_bytes()).await;
+    }
+
+    async fn send(&mut self, message: &Value) {
+        self.send_text(&message.to_string()).await;
+    }
+
+    async fn request(&mut self, id: u64, method: &str, params: Value) {
+        self.send(&json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params}))
+            .await;
+    }
+
+    async fn notify(&mut self, method: &str) {
+        self.send(&json!({"jsonrpc": "2.0", "method": method}))
+            .await;
+    }
+
+    /// The next response, setting server notifications aside
+    async fn recv(&mut self) -> Value {
+        loop {
+            let message = self.recv_any().await;
+            if message.get("method").is_some() && message.get("id").is_none() {
+                self.notifications.push(message);
+            } else {
+                return message;
+            }
+        }
+    }
+
+    async fn recv_notification(&mut self) -> Value {
+        if !self.notifications.is_empty() {
+            return self.notifications.remove(0);
+        }
+        let message = self.recv_any().await;
+        assert!(message.get("id").is_none(), "{}", message);
+        message
+    }
+
+    /// Wait for a `notifications/resources/updated` for `uri`
+    async fn recv_resource_update(&mut self, uri: &str) {
+        loop {
+            let message = self.recv_notification().await;
+            if message["method"] == "notifications/resources/updated"
+                && message["params"]["uri"] == uri
+            {
+                return;
+            }
+        }
+    }
+
+    async fn recv_any(&mut self) -> Value {
+        timeout(RECV_TIMEOUT, self.read_frame())
+            .await
+            .expect("timed out waiting for a message")
+    }
+
+    async fn read_frame(&mut self) -> Value {
+        match self.framing {
+            Framing::Lines => {
+                let mut line = String::new();
+                self.stdout.read_line(&mut line).await.unwrap();
+                assert!(line.ends_with('\n'), "unterminated line: {:?}", line);
+                serde_json::from_str(&line).unwrap()
+            }
+            Framing::Headers => {
+                let mut length = None;
+                loop {
+                    let mut header = String::new();
+                    self.stdout.read_line(&mut header).await.unwrap();
+                    assert!(!header.is_empty(), "EOF while reading headers");
+                    let header = header.trim();
+                    if header.is_empty() {
+                        break;
+                    }
+                    if let Some(value) = header.strip_prefix("Content-Length:") {
+                        length = Some(value.trim().parse::<usize>().unwrap());
+                    }
+                }
+                let mut body = vec![0u8; length.expect("Content-Length header")];
+                self.stdout.read_exact(&mut body).await.unwrap();
+                serde_json::from_slice(&body).unwrap()
+            }
+        }
+    }
+
+    async fn call(&mut self, id: u64, method: &str, params: Value) -> Value {
+        self.request(id, method, params).await;
+        let response = self.recv().await;
+        assert_eq!(response["id"], json!(id), "{}", response);
+        response
+    }
+
+    async fn initialize(&mut self) -> Value {
+        self.initialize_with(LATEST_VERSION).await
+    }
+
+    async fn initialize_with(&mut self, version: &str) -> Value {
+        let response = self
+            .call(
+                0,
+                "initialize",
+                json!({
+                    "protocolVersion": version,
+                    "capabilities": {},
+                    "clientInfo": {"name": "conformance", "version": "1.0"}
+                }),
+            )
+            .await;
+        self.notify("notifications/initialized").await;
+        response
+    }
+
+    /// Close stdin and wait for the server to exit
+    async fn close(mut self) -> std::process::ExitStatus {
+        drop(self.stdin.take());
+        timeout(Duration::from_secs(15), self.child.wait())
+            .await
+            .expect("server did not exit after stdin closed")
+            .unwrap()
+    }
+}
+
+fn ask(agent: &str, message: &str) -> Value {
+    json!({
+        "name": "ask_agents",
+        "arguments": {"requests": [{"agent": agent, "message": message}], "timeout": 60}
+    })
+}
+
+/// The reply text of a successful single-agent `ask_agents` result
+fn reply_of(response: &Value) -> String {
+    let text = response["result"]["content"][0]["text"]
+        .as_str()
+        .unwrap_or_else(|| panic!("no text content: {}", response));
+    let results: Value = serde_json::from_str(text).unwrap();
+    let result = &results["results"][0];
+    assert_eq!(result["success"], json!(true), "{}", result);
+    result["response"].as_str().unwrap().to_string()
+}
+
+fn error_code(response: &Value) -> i64 {
+    response["error"]["code"]
+        .as_i64()
+        .unwrap_or_else(|| panic!("not an error: {}", response))
+}
+
+#[tokio::test]
+async fn test_initialize() {
+    for framing in BOTH {
+        let mut server = Server::spawn(framing, &[]).await;
+        let response = server.initialize().await;
+        let result = &response["result"];
+        assert_eq!(result["protocolVersion"], LATEST_VERSION, "{:?}", framing);
+        assert_eq!(result["serverInfo"]["name"], "ccgonext");
+        assert_eq!(result["capabilities"]["tools"]["listChanged"], false);
+        assert!(result["capabilities"]["logging"].is_object());
+
+        let tools = server.call(1, "tools/list", json!({})).await;
+        let tools = tools["result"]["tools"].as_array().unwrap();
+        let ask_agents = tools.iter().find(|t| t["name"] == "ask_agents").unwrap();
+        assert_eq!(ask_agents["annotations"]["readOnlyHint"], false);
+        assert_eq!(ask_agents["outputSchema"]["type"], "object");
+        let get_result = tools.iter().find(|t| t["name"] == "get_result").unwrap();
+        assert_eq!(get_result["annotations"]["readOnlyHint"], true);
+
+        assert!(server.close().await.success());
+    }
+}
+
+#[tokio::test]
+async fn test_initialize_older_version() {
+    let mut server = Server::spawn(Framing::Lines, &[]).await;
+    let response = server.initialize_with("2024-11-05").await;
+    assert_eq!(response["result"]["protocolVersion"], "2024-11-05");
+
+    // Fields from later revisions are left out
+    let tools = server.call(1, "tools/list", json!({})).await;
+    for tool in tools["result"]["tools"].as_array().unwrap() {
+        assert!(tool.get("annotations").is_none(), "{}", tool);
+        assert!(tool.get("outputSchema").is_none(), "{}", tool);
+    }
+    assert!(server.close().await.success());
+}
+
+#[tokio::test]
+async fn test_initialize_unsupported_version() {
+    // The server answers with its newest version and leaves the choice to the client
+    let mut server = Server::spawn(Framing::Lines, &[]).await;
+    let response = server.initialize_with("1999-01-01").await;
+    assert_eq!(response["result"]["protocolVersion"], LATEST_VERSION);
+    assert!(server.close().await.success());
+}
+
+#[tokio::test]
+async fn test_ping() {
+    for framing in BOTH {
+        let mut server = Server::spawn(framing, &[]).await;
+        // Allowed before initialize
+        let response = server.call(1, "ping", json!({})).await;
+        assert_eq!(response["result"], json!({}));
+        server.initialize().await;
+        let response = server.call(2, "ping", Value::Null).await;
+        assert_eq!(response["result"], json!({}));
+        assert!(server.close().await.success());
+    }
+}
+
+#[tokio::test]
+async fn test_logging_set_level() {
+    for framing in BOTH {
+        let mut server = Server::spawn(framing, &[]).await;
+        server.initialize().await;
+
+        let response = server
+            .call(1, "logging/setLevel", json!({"level": "verbose"}))
+            .await;
+        assert_eq!(error_code(&response), -32602);
+
+        let response = server
+            .call(2, "logging/setLevel", json!({"level": "info"}))
+            .await;
+        assert_eq!(response["result"], json!({}));
+
+        // Logged at info level by the server
+        server.notify("notifications/initialized").await;
+        let message = server.recv_notification().await;
+        assert_eq!(message["method"], "notifications/message", "{:?}", framing);
+        assert_eq!(message["params"]["level"], "info");
+        assert_eq!(message["params"]["data"]["message"], "Client initialized");
+        assert!(message["params"]["logger"]
+            .as_str()
+            .unwrap()
+            .starts_with("ccgonext"));
+
+        assert!(server.close().await.success());
+    }
+}
+
+#[tokio::test]
+async fn test_notifications_get_no_response() {
+    for framing in BOTH {
+        let mut server = Server::spawn(framing, &[]).await;
+        server.initialize().await;
+        server.notify("notifications/cancelled").await;
+        server.notify("notifications/unknown").await;
+        // The first thing to come back answers the request after them
+        server.call(7, "tools/list", json!({})).await;
+        assert!(server.close().await.success());
+    }
+}
+
+#[tokio::test]
+async fn test_batch() {
+    for framing in BOTH {
+        let mut server = Server::spawn(framing, &[]).await;
+        server.initialize().await;
+
+        server
+            .send(&json!([
+                {"jsonrpc": "2.0", "id": 1, "method": "tools/list"},
+                {"jsonrpc": "2.0", "method": "notifications/initialized"},
+                {"jsonrpc": "2.0", "id": 2, "method": "no/such/method"},
+                {"jsonrpc": "2.0", "id": 3}
+            ]))
+            .await;
+        let responses = server.recv().await;
+        let responses = responses.as_array().expect("batch response");
+        assert_eq!(responses.len(), 3, "{:?}", responses);
+        let by_id = |id: u64| responses.iter().find(|r| r["id"] == json!(id)).unwrap();
+        assert!(by_id(1)["result"]["tools"].is_array());
+        assert_eq!(error_code(by_id(2)), -32601);
+        assert_eq!(error_code(by_id(3)), -32600);
+
+        // Nothing is sent back for a batch of notifications
+        server
+            .send(&json!([{"jsonrpc": "2.0", "method": "notifications/initialized"}]))
+            .await;
+        server.send_text("[]").await;
+        let response = server.recv().await;
+        assert_eq!(response["id"], Value::Null);
+        assert_eq!(error_code(&response), -32600);
+
+        assert!(server.close().await.success());
+    }
+}
+
+#[tokio::test]
+async fn test_malformed_input() {
+    for framing in BOTH {
+        let mut server = Server::spawn(framing, &[]).await;
+        server.initialize().await;
+
+        server.send_text("{not json").await;
+        let response = server.recv().await;
+        assert_eq!(response["id"], Value::Null);
+        assert_eq!(error_code(&response), -32700);
+
+        server
+            .send(&json!({"jsonrpc": "1.0", "id": 2, "method": "tools/list"}))
+            .await;
+        let response = server.recv().await;
+        assert_eq!(response["id"], json!(2));
+        assert_eq!(error_code(&response), -32600);
+
+        server.send_text("42").await;
+        assert_eq!(error_code(&server.recv().await), -32600);
+
+        let response = server.call(3, "tools/call", json!("not an object")).await;
+        assert_eq!(error_code(&response), -32602);
+
+        let response = server.call(4, "no/such/method", json!({})).await;
+        assert_eq!(error_code(&response), -32601);
+
+        // Still serving
+        server.call(5, "tools/list", json!({})).await;
+        assert!(server.close().await.success());
+    }
+}
+
+#[tokio::test]
+async fn test_oversized_message_is_rejected_and_skipped() {
+    for framing in BOTH {
+        let mut server = Server::spawn(framing, &[]).await;
+        server.initialize().await;
+
+        let size = MAX_CONTENT_LENGTH + 1;
+        let mut message = match framing {
+            Framing::Lines => Vec::new(),
+            Framing::Headers => format!("Content-Length: {}\r\n\r\n", size).into_bytes(),
+        };
+        message.push(b'"');
+        message.resize(message.len() + size - 2, b'x');
+        message.push(b'"');
+        if framing == Framing::Lines {
+            message.push(b'\n');
+        }
+        // The server reads while we write, so this does not fill the pipe
+        server.send_raw(&message).await;
+
+        let response = server.recv().await;
+        assert_eq!(response["id"], Value::Null, "{:?}", framing);
+        assert_eq!(error_code(&response), -32600);
+
+        server.call(1, "tools/list", json!({})).await;
+        assert!(server.close().await.success());
+    }
+}
+
+#[tokio::test]
+async fn test_lsp_header_errors() {
+    let mut server = Server::spawn(Framing::Headers, &[]).await;
+    server.initialize().await;
+
+    server
+        .send_raw(b"Content-Length: twelve\r\n\r\nContent-Type: application/json\r\n\r\n")
+        .await;
+    assert_eq!(error_code(&server.recv().await), -32700);
+    assert_eq!(error_code(&server.recv().await), -32700);
+
+    server.call(1, "tools/list", json!({})).await;
+    assert!(server.close().await.success());
+}
+
+fn resource_text(response: &Value) -> &str {
+    response["result"]["contents"][0]["text"]
+        .as_str()
+        .unwrap_or_else(|| panic!("no resource text: {}", response))
+}
+
+#[tokio::test]
+async fn test_resources() {
+    let mut server = Server::spawn(Framing::Lines, &[]).await;
+    let response = server.initialize().await;
+    assert_eq!(
+        response["result"]["capabilities"]["resources"]["subscribe"],
+        true
+    );
+
+    let list = server.call(1, "resources/list", json!({})).await;
+    let uris: Vec<&str> = list["result"]["resources"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["uri"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        uris,
+        [
+            "ccgonext://agent/codex/screen",
+            "ccgonext://agent/codex/history",
+            "ccgonext://agent/codex/last-reply",
+            "ccgonext://agent/gemini/screen",
+            "ccgonext://agent/gemini/history",
+            "ccgonext://agent/gemini/last-reply",
+        ]
+    );
+
+    let last_reply = "ccgonext://agent/gemini/last-reply";
+    let screen = "ccgonext://agent/gemini/screen";
+    for (id, uri) in [(2, last_reply), (3, screen)] {
+        let response = server
+            .call(id, "resources/subscribe", json!({"uri": uri}))
+            .await;
+        assert_eq!(response["result"], json!({}));
+    }
+
+    let response = server
+        .call(4, "tools/call", ask("gemini", "What is 2+2?"))
+        .await;
+    assert_eq!(reply_of(&response), "Fake reply 1: What is 2+2?");
+    server.recv_resource_update(last_reply).await;
+    server.recv_resource_update(screen).await;
+
+    let response = server
+        .call(5, "resources/read", json!({"uri": last_reply}))
+        .await;
+    assert_eq!(resource_text(&response), "Fake reply 1: What is 2+2?");
+    assert_eq!(response["result"]["contents"][0]["mimeType"], "text/plain");
+
+    let response = server
+        .call(6, "resources/read", json!({"uri": screen}))
+        .await;
+    assert!(
+        resource_text(&response).contains("Gemini CLI (fake)"),
+        "{}",
+        response
+    );
+
+    let history = "ccgonext://agent/gemini/history";
+    let response = server
+        .call(7, "resources/read", json!({"uri": history}))
+        .await;
+    let turns: Value = serde_json::from_str(resource_text(&response)).unwrap();
+    assert!(
+        turns
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|t| t["role"] == "assistant"
+                && t["content"].as_str().unwrap().contains("Fake reply 1")),
+        "{}",
+        turns
+    );
+
+    for (id, uri) in [
+        (8, "ccgonext://agent/opencode/screen"),
+        (9, "ccgonext://agent/gemini/logs"),
+    ] {
+        let response = server.call(id, "resources/read", json!({"uri": uri})).await;
+        assert_eq!(error_code(&response), -32002);
+    }
+
+    let response = server
+        .call(10, "resources/unsubscribe", json!({"uri": screen}))
+        .await;
+    assert_eq!(response["result"], json!({}));
+
+    assert!(server.close().await.success());
+}
+
+#[tokio::test]
+async fn test_prompts() {
+    let mut server = Server::spawn(Framing::Lines, &[]).await;
+    server.initialize().await;
+
+    // Prompt files are picked up without a restart
+    let prompts = server.dir.path().join("prompts");
+    std::fs::create_dir(&prompts).unwrap();
+    std::fs::write(
+        prompts.join("echo.json"),
+        r#"{"description": "Echo text", "agents": ["codex", "gemini"],
+            "arguments": [{"name": "text", "description": "What to echo", "required": true}],
+            "template": "Echo {text}"}"#,
+    )
+    .unwrap();
+
+    let list = server.call(1, "prompts/list", json!({})).await;
+    let list = list["result"]["prompts"].as_array().unwrap();
+    let echo = list.iter().find(|p| p["name"] == "echo").unwrap();
+    assert_eq!(echo["arguments"][0]["name"], "text");
+    assert_eq!(echo["arguments"][0]["required"], true);
+    assert!(list.iter().any(|p| p["name"] == "second-opinion"));
+
+    let response = server
+        .call(
+            2,
+            "prompts/get",
+            json!({"name": "echo", "arguments": {"text": "hello"}}),
+        )
+        .await;
+    let message = &response["result"]["messages"][0];
+    assert_eq!(message["role"], "user");
+    let text = message["content"]["text"].as_str().unwrap();
+    assert!(text.contains("codex, gemini"), "{}", text);
+    assert!(text.ends_with("Echo hello"), "{}", text);
+
+    let response = server.call(3, "prompts/get", json!({"name": "echo"})).await;
+    assert_eq!(error_code(&response), -32602);
+    let response = server.call(4, "prompts/get", json!({"name": "nope"})).await;
+    assert_eq!(error_code(&response), -32602);
+
+    let response = server
+        .call(
+            5,
+            "tools/call",
+            json!({
+                "name": "ask_agents",
+                "arguments": {"template": "echo", "args": {"text": "hello"}, "timeout": 60}
+            }),
+        )
+        .await;
+    let text = response["result"]["content"][0]["text"].as_str().unwrap();
+    let results: Value = serde_json::from_str(text).unwrap();
+    for (result, agent) in results["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .zip(["codex", "gemini"])
+    {
+        assert_eq!(result["agent"], agent);
+        assert_eq!(result["response"], "Fake reply 1: Echo hello", "{}", result);
+    }
+
+    assert!(server.close().await.success());
+}
+
+#[tokio::test]
+async fn test_concurrent_tool_calls() {
+    for framing in BOTH {
+        let mut server = Server::spawn(framing, &["codex:CCGONEXT_FAKE_THINK_MS=3000"]).await;
+        server.initialize().await;
+
+        server
+            .request(1, "tools/call", ask("codex", "slow question"))
+            .await;
+        server
+            .request(2, "tools/call", ask("gemini", "quick question"))
+            .await;
+        server.request(3, "tools/list", json!({})).await;
+
+        // Answered in the order they finish, not the order they were sent
+        let first = server.recv().await;
+        assert_eq!(first["id"], json!(3), "{}", first);
+        let second = server.recv().await;
+        assert_eq!(second["id"], json!(2), "{}", second);
+        assert_eq!(reply_of(&second), "Fake reply 1: quick question");
+        let third = server.recv().await;
+        assert_eq!(third["id"], json!(1), "{}", third);
+        assert_eq!(reply_of(&third), "Fake reply 1: slow question");
+        let text = third["result"]["content"][0]["text"].as_str().unwrap();
+        assert_eq!(
+            third["result"]["structuredContent"],
+            serde_json::from_str::<Value>(text).unwrap()
+        );
+
+        assert!(server.close().await.success());
+    }
+}
+
+#[tokio::test]
+async fn test_streamed_reply() {
+    let mut server = Server::spawn(Framing::Lines, &["gemini:CCGONEXT_FAKE_WORD_MS=150"]).await;
+    server.initialize().await;
+
+    let message = "one two three four five six seven eight";
+    let response = server
+        .call(
+            1,
+            "tools/call",
+            json!({
+                "name": "ask_agents",
+                "arguments": {
+                    "requests": [{"agent": "gemini", "message": message}],
+                    "timeout": 60,
+                    "stream": true
+                }
+            }),
+        )
+        .await;
+    let reply = reply_of(&response);
+    assert_eq!(reply, format!("Fake reply 1: {}", message));
+
+    // Every chunk arrived before the result; applied in order they give
+    // a prefix of the reply
+    let chunks: Vec<Value> = std::mem::take(&mut server.notifications)
+        .into_iter()
+        .filter(|n| n["params"]["logger"] == "ccgonext.reply")
+        .collect();
+    assert!(chunks.len() > 1, "{:?}", chunks);
+    let mut streamed: Vec<char> = Vec::new();
+    for chunk in &chunks {
+        let data = &chunk["params"]["data"];
+        assert_eq!(data["request_id"], json!(1));
+        assert_eq!(data["agent"], "gemini");
+        streamed.truncate(data["offset"].as_u64().unwrap() as usize);
+        streamed.extend(data["text"].as_str().unwrap().chars());
+    }
+    let streamed: String = streamed.into_iter().collect();
+    assert!(reply.starts_with(&streamed), "{:?}", streamed);
+    assert!(streamed.len() > "Fake reply 1:".len(), "{:?}", streamed);
+
+    // Without `stream`, nothing is sent
+    let response = server.call(2, "tools/call", ask("gemini", "quiet")).await;
+    assert_eq!(reply_of(&response), "Fake reply 2: quiet");
+    assert!(
+        server.notifications.is_empty(),
+        "{:?}",
+        server.notifications
+    );
+
+    assert!(server.close().await.success());
+}
+
+/// Processes whose environment points at `dir`, i.e. this test's agents
+#[cfg(target_os = "linux")]
+fn agents_under(dir: &std::path::Path) -> Vec<u32> {
+    let needle = format!("CODEX_HOME={}/codex", dir.display());
+    std::fs::read_dir("/proc")
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .filter_map(|e| e.file_name().to_string_lossy().parse::<u32>().ok())
+        .filter(|pid| {
+            std::fs::read(format!("/proc/{}/environ", pid))
+                .map(|env| env.split(|&b| b == 0).any(|var| var == needle.as_bytes()))
+                .unwrap_or(false)
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_shutdown_on_eof() {
+    for framing in BOTH {
+        // A call that never finishes must not keep the server alive
+        let mut server = Server::spawn(framing, &["codex:CCGONEXT_FAKE_FAIL=hang"]).await;
+        server.initialize().await;
+        server.request(1, "tools/call", ask("codex", "hang")).await;
+        tokio::time::sleep(Duration::from_secs(2)).await;
+
+        let dir = server.dir.path().to_path_buf();
+        #[cfg(target_os = "linux")]
+        assert!(!agents_under(&dir).is_empty());
+
+        assert!(server.close().await.success(), "{:?}", framing);
+        #[cfg(target_os = "linux")]
+        assert_eq!(agents_under(&dir), Vec::<u32>::new());
+        #[cfg(not(target_os = "linux"))]
+        let _ = dir;
+    }
+}
@@ -0,0 +1,347 @@
+//! Replay tests for reply detection
+//!
+//! Each scenario in `tests/fixtures/replay/<agent>/` plays a recorded PTY
+//! stream (asciicast v2) and a timeline of log file mutations against an
+//! `AgentSession` on a paused tokio clock, then checks the extracted reply,
+//! when `ask` returned and the states the session went through. Fixture times
+//! are seconds since the session was attached.
+
+use async_trait::async_trait;
+use ccgonext::agent::{create_agent, Agent};
+use ccgonext::config::{AgentConfig, TimeoutConfig};
+use ccgonext::log_provider::{
+    create_log_provider, FileChangeEvent, HistoryEntry, LockedSession, LogEntry, LogProvider,
+    WatchHandle, WatchSubscription,
+};
+use ccgonext::pty::{PtyHandle, PtyManager};
+use ccgonext::recording;
+use ccgonext::session::AgentSession;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::{broadcast, oneshot};
+use tokio::time::{sleep, sleep_until, Instant};
+
+/// How far `ask` may return from the expected time
+const COMPLETION_TOLERANCE_SECS: f64 = 0.05;
+
+#[derive(Debug, Deserialize)]
+struct Scenario {
+    description: String,
+    agent: String,
+    message_id: String,
+    prompt: String,
+    #[serde(default = "default_timeout")]
+    timeout_secs: u64,
+    /// Deliver a change event after each log mutation; false exercises the
+    /// polling fallback used when no file watcher is available
+    #[serde(default = "default_watch")]
+    watch: bool,
+    /// asciicast file next to the scenario, played from t=0
+    pty: Option<String>,
+    ask_at: f64,
+    #[serde(default)]
+    logs: Vec<Mutation>,
+    expect: Expect,
+}
+
+fn default_timeout() -> u64 {
+    60
+}
+
+fn default_watch() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+struct Mutation {
+    at: f64,
+    /// Relative to the agent's log root
+    path: String,
+    #[serde(flatten)]
+    op: Op,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Op {
+    /// Append one JSON document per line (Codex rollouts)
+    Append(Vec<serde_json::Value>),
+    /// Replace the whole file (Gemini chats, OpenCode messages and parts)
+    Write(serde_json::Value),
+}
+
+impl Mutation {
+    fn apply(&self, root: &Path, epoch: SystemTime) {
+        let path = root.join(&self.path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let file = match &self.op {
+            Op::Append(lines) => {
+                let mut file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .unwrap();
+                for line in lines {
+                    writeln!(file, "{}", line).unwrap();
+                }
+                file
+            }
+            Op::Write(value) => {
+                fs::write(&path, serde_json::to_string_pretty(value).unwrap()).unwrap();
+                OpenOptions::new().append(true).open(&path).unwrap()
+            }
+        };
+        // Real writes happen microseconds apart; give the file the mtime the
+        // timeline says so latest-file selection and caches see distinct times
+        file.set_modified(epoch + Duration::from_secs_f64(self.at))
+            .unwrap();
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Expect {
+    reply: Option<String>,
+    error: Option<String>,
+    completed_at: f64,
+    states: Vec<String>,
+    /// Partial replies streamed before completion, as (offset, text)
+    #[serde(default)]
+    chunks: Option<Vec<(usize, String)>>,
+}
+
+enum Step {
+    Output(String),
+    Log(Mutation),
+}
+
+/// Delegates to the agent's real provider, but change events come from the
+/// timeline instead of a filesystem watcher
+struct TimelineProvider {
+    inner: Box<dyn LogProvider>,
+    changes: Option<broadcast::Sender<FileChangeEvent>>,
+}
+
+#[async_trait]
+impl LogProvider for TimelineProvider {
+    async fn get_latest_reply(&self, since_offset: u64) -> Option<LogEntry> {
+        self.inner.get_latest_reply(since_offset).await
+    }
+
+    async fn get_history(&self, session_id: Option<&str>, count: usize) -> Vec<HistoryEntry> {
+        self.inner.get_history(session_id, count).await
+    }
+
+    async fn get_current_offset(&self) -> u64 {
+        self.inner.get_current_offset().await
+    }
+
+    fn get_inode(&self) -> Option<u64> {
+        self.inner.get_inode()
+    }
+
+    fn get_watch_path(&self) -> Option<PathBuf> {
+        self.inner.get_watch_path()
+    }
+
+    async fn lock_session(&self) -> Option<LockedSession> {
+        self.inner.lock_session().await
+    }
+
+    async fn unlock_session(&self) {
+        self.inner.unlock_session().await
+    }
+
+    async fn harvest_reply(&self, locked: &LockedSession) -> Option<LogEntry> {
+        self.inner.harvest_reply(locked).await
+    }
+
+    fn subscribe_changes(&self, _debounce_ms: u64) -> Option<WatchSubscription> {
+        let changes = self.changes.as_ref()?;
+        Some(WatchSubscription {
+            handle: WatchHandle::new(oneshot::channel().0),
+            receiver: changes.subscribe(),
+        })
+    }
+}
+
+fn fixture_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/replay")
+}
+
+fn agent_config(agent: &str) -> AgentConfig {
+    match agent {
+        "codex" => AgentConfig::codex_default(),
+        "gemini" => AgentConfig::gemini_default(),
+        "opencode" => AgentConfig::opencode_default(),
+        "claudecode" => AgentConfig::claudecode_default(),
+        other => panic!("unknown agent {}", other),
+    }
+}
+
+/// Play `tests/fixtures/replay/<name>.json` and check its expectations
+async fn replay(name: &str) {
+    let path = fixture_dir().join(format!("{}.json", name));
+    let scenario: Scenario = serde_json::from_str(&fs::read_to_string(&path).unwrap())
+        .unwrap_or_else(|e| panic!("{:?}: {}", path, e));
+    let context = format!("{} ({})", name, scenario.description);
+
+    let mut steps: Vec<(f64, Step)> = Vec::new();
+    if let Some(cast) = &scenario.pty {
+        let (_, events) = recording::read(&path.with_file_name(cast)).unwrap();
+        steps.extend(
+            events
+                .into_iter()
+                .filter(|e| e.code() == "o")
+                .map(|e| (e.time(), Step::Output(e.data().to_string()))),
+        );
+    }
+    steps.extend(scenario.logs.into_iter().map(|m| (m.at, Step::Log(m))));
+    steps.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let logs = tempfile::tempdir().unwrap();
+    let root = logs.path().to_path_buf();
+    let config = agent_config(&scenario.agent);
+    let provider_config = HashMap::from([(
+        "path_pattern".to_string(),
+        root.to_string_lossy().to_string(),
+    )]);
+    let (changes, _) = broadcast::channel(64);
+    let provider = Arc::new(TimelineProvider {
+        inner: create_log_provider(&config.log_provider, Some(&provider_config)),
+        changes: scenario.watch.then(|| changes.clone()),
+    });
+    let adapter: Arc<dyn Agent> = Arc::from(create_agent(&scenario.agent, &config));
+    let session = Arc::new(AgentSession::new(
+        scenario.agent.clone(),
+        adapter,
+        provider,
+        root.clone(),
+        TimeoutConfig::default(),
+    ));
+
+    let mut states = session.subscribe_state();
+    let mut chunks = session.subscribe_reply_chunks();
+    let (pty, driver) = PtyHandle::detached(1024 * 1024);
+    let start = Instant::now();
+    let epoch = SystemTime::now();
+    session.attach(pty).await.unwrap();
+
+    let timeline = tokio::spawn(async move {
+        for (at, step) in steps {
+            sleep_until(start + Duration::from_secs_f64(at)).await;
+            match step {
+                Step::Output(data) => driver.output(data.as_bytes()).await,
+                Step::Log(mutation) => {
+                    mutation.apply(&root, epoch);
+                    let _ = changes.send(FileChangeEvent {
+                        timestamp: std::time::Instant::now(),
+                    });
+                }
+            }
+        }
+        driver
+    });
+
+    sleep_until(start + Duration::from_secs_f64(scenario.ask_at)).await;
+    let pty_manager = PtyManager::new(1024);
+    let result = session
+        .ask_with_id(
+            scenario.message_id.clone(),
+            scenario.prompt.clone(),
+            Some(Duration::from_secs(scenario.timeout_secs)),
+            &pty_manager,
+        )
+        .await;
+    let completed_at = start.elapsed().as_secs_f64();
+
+    let mut driver = timeline.await.unwrap();
+    // Let the session finish its transition after the reply
+    sleep(Duration::from_secs(1)).await;
+
+    let input = String::from_utf8_lossy(&driver.drain_input()).to_string();
+    assert!(
+        input.contains(&scenario.message_id),
+        "{}: prompt with sentinel was not written: {:?}",
+        context,
+        input
+    );
+
+    match (&scenario.expect.reply, &scenario.expect.error, &result) {
+        (Some(expected), None, Ok(reply)) => {
+            assert_eq!(&reply.content, expected, "{}: reply", context)
+        }
+        (None, Some(expected), Err(e)) => {
+            assert_eq!(&e.to_string(), expected, "{}: error", context)
+        }
+        _ => panic!(
+            "{}: expected reply {:?} / error {:?}, got {:?}",
+            context,
+            scenario.expect.reply,
+            scenario.expect.error,
+            result.map(|r| r.content)
+        ),
+    }
+
+    assert!(
+        (completed_at - scenario.expect.completed_at).abs() <= COMPLETION_TOLERANCE_SECS,
+        "{}: ask returned at {:.3}s, expected {:.3}s",
+        context,
+        completed_at,
+        scenario.expect.completed_at
+    );
+
+    let mut seen = Vec::new();
+    while let Ok(state) = states.try_recv() {
+        seen.push(state.to_string());
+    }
+    assert_eq!(seen, scenario.expect.states, "{}: states", context);
+
+    if let Some(expected) = &scenario.expect.chunks {
+        let mut streamed = Vec::new();
+        while let Ok(chunk) = chunks.try_recv() {
+            assert_eq!(chunk.message_id, scenario.message_id, "{}: chunk", context);
+            streamed.push((chunk.offset, chunk.text));
+        }
+        assert_eq!(&streamed, expected, "{}: chunks", context);
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_codex_done_marker() {
+    replay("codex/done_marker").await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_codex_stability_heuristic() {
+    replay("codex/stability").await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_codex_polling_fallback() {
+    replay("codex/polling").await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_gemini_streaming_rewrites() {
+    replay("gemini/streaming").await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_gemini_rewrite_streams_changed_tail() {
+    replay("gemini/rewrite").await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_opencode_part_files() {
+    replay("opencode/parts").await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_claudecode_pty_reply() {
+    replay("claudecode/pty_reply").await;
+}
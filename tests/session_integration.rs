@@ -122,6 +122,8 @@ async fn test_claudecode_type_detection() {
         done_template: "CCGO_DONE: {id}".to_string(),
         done_regex: r"(?m)CCGO_DONE:\s*([a-f0-9-]+)".to_string(),
         use_stability_heuristic: true,
+        sandbox: None,
+        launch: Default::default(),
     };
     let codex = Arc::new(GenericAgent::new("codex".to_string(), &codex_config));
 
@@ -201,3 +203,70 @@ async fn test_claudecode_pty_offset_tracking() {
     // Cleanup
     let _ = session_arc.stop(true, Some(pty_manager.as_ref())).await;
 }
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_stop_kills_agent_process_tree() {
+    use ccgonext::procmon::{ProcessRoot, ProcessTable, ProcessTree};
+
+    // A fake agent that leaves helpers behind, one of them in its own session
+    let config = AgentConfig::codex_default()
+        .with_command("sh".to_string())
+        .with_args(vec![
+            "-c".to_string(),
+            "sleep 60 & setsid sleep 60 & echo '>'; wait".to_string(),
+        ]);
+    let adapter = Arc::new(GenericAgent::new("fake".to_string(), &config));
+    let session = Arc::new(
+        AgentSession::new(
+            "fake".to_string(),
+            adapter,
+            Arc::new(MockLogProvider),
+            std::env::temp_dir(),
+            TimeoutConfig::default(),
+        )
+        .with_memory_limit(4096),
+    );
+    let pty_manager = PtyManager::new(1024 * 1024);
+    session.start(&pty_manager).await.unwrap();
+
+    let root = session
+        .pty
+        .read()
+        .await
+        .as_ref()
+        .unwrap()
+        .process_root()
+        .unwrap();
+    let pid = root.pid;
+    let mut tree = ProcessTree::collect(root);
+    for _ in 0..50 {
+        if tree.pids().len() >= 3 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        tree = ProcessTree::collect(root);
+    }
+    assert!(tree.pids().len() >= 3, "tree: {:?}", tree.pids());
+
+    let stats = session
+        .sample_resources(&ProcessTable::read())
+        .await
+        .unwrap();
+    assert_eq!(stats.pid, pid);
+    assert!(stats.processes >= 3);
+    assert!(stats.rss_bytes > 0);
+    assert_eq!(session.resources(), Some(stats));
+
+    session.stop(true, Some(&pty_manager)).await.unwrap();
+    let survivors: Vec<u32> = tree
+        .pids()
+        .into_iter()
+        .filter(|pid| {
+            ProcessRoot::of(*pid)
+                .is_some_and(|root| ProcessTable::read().tree(root).pids().contains(pid))
+        })
+        .collect();
+    assert!(survivors.is_empty(), "survivors: {:?}", survivors);
+    assert!(session.resources().is_none());
+}
@@ -0,0 +1,695 @@
+//! REST API tests against `ccgonext-fake-agent`
+//!
+//! Requests go through the web router in-process (auth middleware
+//! included), with Codex played by the fake agent as in `fake_agent.rs`.
+
+use axum::body::{to_bytes, Body};
+use axum::extract::ConnectInfo;
+use axum::http::{HeaderMap, Method, Request, StatusCode};
+use axum::Router;
+use ccgonext::agent::create_agent;
+use ccgonext::config::{AgentConfig, Config, Role, TimeoutConfig, WebToken};
+use ccgonext::log_provider::create_log_provider;
+use ccgonext::pty::PtyManager;
+use ccgonext::session::{AgentSession, SessionManager};
+use ccgonext::state::AgentState;
+use ccgonext::web::{hash_token, router, AppState};
+use futures::StreamExt;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+use tower::ServiceExt;
+
+const FAKE_AGENT: &str = env!("CARGO_BIN_EXE_ccgonext-fake-agent");
+
+struct Fixture {
+    manager: Arc<SessionManager>,
+    app: Router,
+    _logs: TempDir,
+    _workdir: TempDir,
+}
+
+impl Fixture {
+    async fn request(&self, method: Method, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+        call(&self.app, None, method, uri, body).await
+    }
+}
+
+async fn call(
+    app: &Router,
+    token: Option<&str>,
+    method: Method,
+    uri: &str,
+    body: Option<Value>,
+) -> (StatusCode, Value) {
+    let mut request = Request::builder().method(method).uri(uri);
+    if let Some(token) = token {
+        request = request.header("authorization", format!("Bearer {}", token));
+    }
+    let request = match body {
+        Some(body) => request
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string())),
+        None => request.body(Body::empty()),
+    }
+    .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let value = if bytes.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_slice(&bytes).unwrap()
+    };
+    (status, value)
+}
+
+/// Register the fake agent as Codex behind a web router
+async fn start(extra_args: &[&str]) -> Fixture {
+    let logs = tempfile::tempdir().unwrap();
+    let workdir = tempfile::tempdir().unwrap();
+    let root = logs.path().to_string_lossy().to_string();
+
+    let mut args = vec!["--flavor", "codex", "--log-root", &root];
+    args.extend_from_slice(extra_args);
+    let config = AgentConfig::codex_default()
+        .with_command(FAKE_AGENT.to_string())
+        .with_args(args.into_iter().map(String::from).collect());
+    let provider_config = HashMap::from([("path_pattern".to_string(), root.clone())]);
+    let provider = create_log_provider(&config.log_provider, Some(&provider_config));
+    let timeouts = TimeoutConfig {
+        ready_check: 10,
+        max_start_retries: 0,
+        ..TimeoutConfig::default()
+    };
+
+    let manager = Arc::new(SessionManager::new(Arc::new(PtyManager::new(1024 * 1024))));
+    manager
+        .register(AgentSession::new(
+            "codex".to_string(),
+            Arc::from(create_agent("codex", &config)),
+            Arc::from(provider),
+            workdir.path().to_path_buf(),
+            timeouts,
+        ))
+        .await;
+
+    let state = AppState::new(manager.clone(), Arc::new(Config::default()), 0);
+    Fixture {
+        manager,
+        app: router(state),
+        _logs: logs,
+        _workdir: workdir,
+    }
+}
+
+fn ask_body(message: &str) -> Value {
+    json!({"requests": [{"agent": "codex", "message": message}], "timeout": 30})
+}
+
+#[tokio::test]
+async fn test_sync_ask_and_lookup() {
+    let fixture = start(&[]).await;
+
+    let (status, body) = fixture
+        .request(Method::POST, "/api/ask", Some(ask_body("What is 2+2?")))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let result = &body["results"][0];
+    assert_eq!(result["response"], "Fake reply 1: What is 2+2?");
+
+    let id = result["message_id"].as_str().unwrap();
+    let (status, body) = fixture
+        .request(Method::GET, &format!("/api/requests/{}", id), None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["state"], "completed");
+    assert_eq!(body["agent"], "codex");
+    assert_eq!(body["result"]["response"], "Fake reply 1: What is 2+2?");
+
+    let (status, _) = fixture
+        .request(Method::DELETE, &format!("/api/requests/{}", id), None)
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    fixture.manager.shutdown_all().await;
+}
+
+#[tokio::test]
+async fn test_async_ask_and_cancel() {
+    let fixture = start(&["--think-ms", "5000"]).await;
+
+    let (status, body) = fixture
+        .request(
+            Method::POST,
+            "/api/ask?async=true",
+            Some(ask_body("slow question")),
+        )
+        .await;
+    assert_eq!(status, StatusCode::ACCEPTED, "{}", body);
+    let id = body["requests"][0]["message_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let uri = format!("/api/requests/{}", id);
+
+    // Unfinished while the agent starts and thinks
+    let (status, body) = fixture.request(Method::GET, &uri, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.get("result").is_none(), "{}", body);
+
+    // Wait until it was written to the agent
+    let session = fixture.manager.get("codex").await.unwrap();
+    for _ in 0..100 {
+        if session.get_state().await == AgentState::Busy {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(session.get_state().await, AgentState::Busy);
+
+    let (status, _) = fixture.request(Method::DELETE, &uri, None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (_, body) = fixture.request(Method::GET, &uri, None).await;
+    assert_eq!(body["state"], "failed", "{}", body);
+    assert_eq!(body["result"]["success"], false);
+    assert_eq!(body["result"]["error"], "Request cancelled");
+
+    let (status, _) = fixture.request(Method::DELETE, &uri, None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    fixture.manager.shutdown_all().await;
+}
+
+#[tokio::test]
+async fn test_errors_and_agent_control() {
+    let fixture = start(&[]).await;
+
+    let (status, body) = fixture
+        .request(Method::POST, "/api/ask", Some(json!({"requests": []})))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body["error"].as_str().unwrap().contains("1-4"), "{}", body);
+
+    let (status, body) = fixture
+        .request(Method::GET, "/api/requests/nope", None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["error"], "Unknown message id: nope");
+
+    let (status, _) = fixture
+        .request(Method::POST, "/api/agents/nope/stop", None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let session = fixture.manager.get("codex").await.unwrap();
+    session.start(fixture.manager.pty_manager()).await.unwrap();
+    let (status, body) = fixture
+        .request(Method::POST, "/api/agents/codex/interrupt", None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["success"], true);
+    let (_, body) = fixture
+        .request(Method::POST, "/api/agents/codex/stop", None)
+        .await;
+    assert_eq!(body["success"], true, "{}", body);
+    assert!(!session.get_state().await.is_running());
+
+    fixture.manager.shutdown_all().await;
+}
+
+#[tokio::test]
+async fn test_openapi_document() {
+    let fixture = start(&[]).await;
+    let (status, body) = fixture
+        .request(Method::GET, "/api/openapi.json", None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["openapi"].as_str().unwrap().starts_with("3."));
+    for path in [
+        "/api/ask",
+        "/api/requests/{id}",
+        "/api/agents/{agent}/interrupt",
+        "/api/agents/{agent}/stop",
+        "/api/events",
+    ] {
+        assert!(body["paths"][path].is_object(), "{}", path);
+    }
+    assert!(body["paths"]["/api/requests/{id}"]["delete"].is_object());
+    let schemas = &body["components"]["schemas"];
+    assert!(schemas["AskAgentsArgs"]["properties"]["requests"].is_object());
+    assert!(schemas["AgentResult"]["properties"]["response"].is_object());
+}
+
+#[tokio::test]
+async fn test_event_stream() {
+    let fixture = start(&[]).await;
+
+    let (status, body) = fixture
+        .request(Method::GET, "/api/events?type=nope", None)
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body["error"].as_str().unwrap().contains("nope"), "{}", body);
+
+    let request = Request::builder()
+        .uri("/api/events?agent=codex&type=request_queued,request_started,request_completed")
+        .body(Body::empty())
+        .unwrap();
+    let response = fixture.app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+    let mut events = response.into_body().into_data_stream();
+
+    let (status, body) = fixture
+        .request(Method::POST, "/api/ask", Some(ask_body("What is 2+2?")))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let id = body["results"][0]["message_id"].as_str().unwrap();
+
+    let mut text = String::new();
+    while !text.contains("event: request_completed") {
+        let chunk = tokio::time::timeout(Duration::from_secs(5), events.next())
+            .await
+            .expect("event stream stalled")
+            .unwrap()
+            .unwrap();
+        text.push_str(&String::from_utf8_lossy(&chunk));
+    }
+    let names: Vec<&str> = text
+        .lines()
+        .filter_map(|line| line.strip_prefix("event: "))
+        .collect();
+    assert_eq!(
+        names,
+        ["request_queued", "request_started", "request_completed"]
+    );
+    let data: Vec<Value> = text
+        .lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .map(|data| serde_json::from_str(data).unwrap())
+        .collect();
+    assert!(data.iter().all(|event| event["agent"] == "codex"));
+    assert!(data.iter().all(|event| event["message_id"] == id));
+
+    fixture.manager.shutdown_all().await;
+}
+
+async fn get_text(app: &Router, uri: &str, token: Option<&str>) -> (StatusCode, String) {
+    let mut request = Request::builder().uri(uri);
+    if let Some(token) = token {
+        request = request.header("authorization", format!("Bearer {}", token));
+    }
+    let response = app
+        .clone()
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, String::from_utf8_lossy(&bytes).to_string())
+}
+
+#[tokio::test]
+async fn test_metrics() {
+    let fixture = start(&[]).await;
+    let (_, text) = get_text(&fixture.app, "/metrics", None).await;
+    assert!(
+        !text.contains("ccgonext_requests_total"),
+        "served while disabled"
+    );
+
+    let mut config = Config::default();
+    config.web.metrics = true;
+    config.web.auth_token = Some("secret".to_string());
+    let app = router(AppState::new(fixture.manager.clone(), Arc::new(config), 0));
+    let (status, _) = get_text(&app, "/metrics", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, body) = fixture
+        .request(Method::POST, "/api/ask", Some(ask_body("What is 2+2?")))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let (status, text) = get_text(&app, "/metrics", Some("secret")).await;
+    assert_eq!(status, StatusCode::OK);
+    let lines: Vec<&str> = text.lines().collect();
+    for expected in [
+        "ccgonext_requests_total{agent=\"codex\",outcome=\"success\"} 1",
+        "ccgonext_request_duration_seconds_count{agent=\"codex\",outcome=\"success\"} 1",
+        "ccgonext_queue_depth{agent=\"codex\"} 0",
+        "ccgonext_restarts_total{agent=\"codex\"} 0",
+    ] {
+        assert!(lines.contains(&expected), "missing {}:\n{}", expected, text);
+    }
+    assert!(text.contains("ccgonext_reply_detections_total{agent=\"codex\""));
+    assert!(text.contains("ccgonext_agent_state_seconds_total{agent=\"codex\",state=\"IDLE\"}"));
+    let bytes_in = lines
+        .iter()
+        .find_map(|l| l.strip_prefix("ccgonext_pty_bytes_total{agent=\"codex\",direction=\"in\"} "))
+        .unwrap();
+    assert!(bytes_in.parse::<u64>().unwrap() > 0, "{}", text);
+
+    fixture.manager.shutdown_all().await;
+}
+
+#[tokio::test]
+async fn test_token_roles_and_scope() {
+    let fixture = start(&[]).await;
+    let token = |name: &str, role: Role, agents: &[&str]| WebToken {
+        name: name.to_string(),
+        sha256: hash_token(&format!("{}-secret", name)),
+        role,
+        agents: agents.iter().map(|a| a.to_string()).collect(),
+    };
+    let mut config = Config::default();
+    config.web.input_enabled = true;
+    config.web.metrics = true;
+    config.web.tokens = vec![
+        token("outsider", Role::Driver, &["gemini"]),
+        token("viewer", Role::Viewer, &["codex"]),
+        token("operator", Role::Operator, &[]),
+        token("driver", Role::Driver, &["codex"]),
+    ];
+    let app = router(AppState::new(fixture.manager.clone(), Arc::new(config), 0));
+
+    let (status, _) = call(&app, None, Method::GET, "/api/status", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = call(&app, Some("driver"), Method::GET, "/api/status", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, body) = call(
+        &app,
+        Some("outsider-secret"),
+        Method::GET,
+        "/api/status",
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["agents"], json!([]));
+    let (_, body) = call(
+        &app,
+        Some("viewer-secret"),
+        Method::GET,
+        "/api/status",
+        None,
+    )
+    .await;
+    assert_eq!(body["agents"][0]["name"], "codex");
+    assert_eq!(body["input_enabled"], false);
+    let (_, body) = call(
+        &app,
+        Some("driver-secret"),
+        Method::GET,
+        "/api/status",
+        None,
+    )
+    .await;
+    assert_eq!(body["input_enabled"], true);
+
+    // Asking needs a driver for the agent
+    for name in ["outsider", "viewer", "operator"] {
+        let (status, body) = call(
+            &app,
+            Some(&format!("{}-secret", name)),
+            Method::POST,
+            "/api/ask",
+            Some(ask_body("What is 2+2?")),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{}", name);
+        assert!(body["error"].as_str().unwrap().contains(name), "{}", body);
+    }
+    let (status, body) = call(
+        &app,
+        Some("driver-secret"),
+        Method::POST,
+        "/api/ask",
+        Some(ask_body("What is 2+2?")),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let id = body["results"][0]["message_id"].as_str().unwrap();
+
+    let uri = format!("/api/requests/{}", id);
+    let (status, _) = call(&app, Some("viewer-secret"), Method::GET, &uri, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = call(&app, Some("outsider-secret"), Method::GET, &uri, None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // Usage only lists the caller's agents; metrics need an unscoped token
+    fixture.manager.usage().record("codex", &Default::default());
+    let (_, body) = call(&app, Some("viewer-secret"), Method::GET, "/api/usage", None).await;
+    assert!(body["days"]
+        .as_object()
+        .unwrap()
+        .values()
+        .any(|d| d.get("codex").is_some()));
+    let (_, body) = call(
+        &app,
+        Some("outsider-secret"),
+        Method::GET,
+        "/api/usage",
+        None,
+    )
+    .await;
+    assert_eq!(body["days"], json!({}));
+    let (status, _) = get_text(&app, "/metrics", Some("driver-secret")).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = get_text(&app, "/metrics", Some("operator-secret")).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Interrupting needs an operator
+    let uri = "/api/agents/codex/interrupt";
+    let (status, _) = call(&app, Some("viewer-secret"), Method::POST, uri, None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, body) = call(&app, Some("operator-secret"), Method::POST, uri, None).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    fixture.manager.shutdown_all().await;
+}
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, HeaderMap, Value) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let headers = response.headers().clone();
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let value = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+    (status, headers, value)
+}
+
+fn login_request(token: &str) -> Request<Body> {
+    Request::builder()
+        .method(Method::POST)
+        .uri("/api/login")
+        .header("content-type", "application/json")
+        .body(Body::from(json!({ "token": token }).to_string()))
+        .unwrap()
+}
+
+fn with_cookie(method: Method, uri: &str, cookie: &str) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("cookie", cookie)
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_login_session_cookie() {
+    let fixture = start(&[]).await;
+    let mut config = Config::default();
+    config.web.auth_token = Some("shared".to_string());
+    config.web.tokens = vec![WebToken {
+        name: "viewer".to_string(),
+        sha256: hash_token("viewer-secret"),
+        role: Role::Viewer,
+        agents: vec!["codex".to_string()],
+    }];
+    let app = router(AppState::new(fixture.manager.clone(), Arc::new(config), 0));
+
+    // The UI itself loads without a token
+    let (status, _) = get_text(&app, "/", None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, headers, _) = send(&app, login_request("wrong")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert!(headers.get("set-cookie").is_none());
+
+    let (status, headers, body) = send(&app, login_request("viewer-secret")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["name"], "viewer");
+    assert_eq!(body["role"], "viewer");
+    assert_eq!(body["agents"], json!(["codex"]));
+    let set_cookie = headers["set-cookie"].to_str().unwrap();
+    assert!(set_cookie.contains("HttpOnly"), "{}", set_cookie);
+    assert!(set_cookie.contains("SameSite=Strict"), "{}", set_cookie);
+    let cookie = set_cookie.split(';').next().unwrap().to_string();
+
+    let (status, _, body) = send(&app, with_cookie(Method::GET, "/api/status", &cookie)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["agents"][0]["name"], "codex");
+    assert_eq!(body["input_enabled"], false);
+    let (status, _, _) = send(
+        &app,
+        with_cookie(Method::POST, "/api/agents/codex/interrupt", &cookie),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // Logging out revokes the session and clears the cookie
+    let (status, headers, _) = send(&app, with_cookie(Method::POST, "/api/logout", &cookie)).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert!(headers["set-cookie"]
+        .to_str()
+        .unwrap()
+        .contains("Max-Age=0"));
+    let (status, _, _) = send(&app, with_cookie(Method::GET, "/api/status", &cookie)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // Sessions do not outlive their TTL
+    let mut config = Config::default();
+    config.web.auth_token = Some("shared".to_string());
+    config.web.session_ttl_secs = 0;
+    let app = router(AppState::new(fixture.manager.clone(), Arc::new(config), 0));
+    let (status, headers, _) = send(&app, login_request("shared")).await;
+    assert_eq!(status, StatusCode::OK);
+    let cookie = headers["set-cookie"]
+        .to_str()
+        .unwrap()
+        .split(';')
+        .next()
+        .unwrap()
+        .to_string();
+    let (status, _, _) = send(&app, with_cookie(Method::GET, "/api/status", &cookie)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // Repeated failures lock the address out, even for a valid token
+    for _ in 0..6 {
+        let (status, _, _) = send(&app, login_request("wrong")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+    let (status, headers, _) = send(&app, login_request("shared")).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert!(headers.contains_key("retry-after"));
+
+    fixture.manager.shutdown_all().await;
+}
+
+fn get_from(uri: &str, peer: &str, headers: &[(&str, &str)]) -> Request<Body> {
+    let mut request = Request::builder().uri(uri);
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let mut request = request.body(Body::empty()).unwrap();
+    request
+        .extensions_mut()
+        .insert(ConnectInfo(peer.parse::<SocketAddr>().unwrap()));
+    request
+}
+
+#[tokio::test]
+async fn test_base_path_hosts_and_proxies() {
+    let fixture = start(&[]).await;
+    let mut config = Config::default();
+    config.web.base_path = "/ccgonext".to_string();
+    config.web.allowed_origins = vec!["https://*.example.com".to_string()];
+    config.web.allowed_hosts = vec!["ccgo.corp.internal".to_string()];
+    config.web.trusted_proxies = vec!["10.0.0.0/8".parse().unwrap()];
+    config.web.auth_token = Some("shared".to_string());
+    let app = router(AppState::new(
+        fixture.manager.clone(),
+        Arc::new(config),
+        8765,
+    ));
+    let client = "198.51.100.1:5000";
+    let proxy = "10.0.0.1:5000";
+    let bearer = ("authorization", "Bearer shared");
+    let local = ("host", "127.0.0.1:8765");
+
+    // Everything lives under the base path, and the page knows it
+    let (status, _, _) = send(
+        &app,
+        get_from("/ccgonext/api/status", client, &[local, bearer]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = send(&app, get_from("/api/status", client, &[local, bearer])).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let response = app
+        .clone()
+        .oneshot(get_from("/ccgonext/codex", client, &[local]))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let html = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert!(String::from_utf8_lossy(&html).contains("const BASE_PATH = '/ccgonext';"));
+
+    // Hosts outside the list are refused, e.g. after DNS rebinding
+    let (status, _, _) = send(
+        &app,
+        get_from(
+            "/ccgonext/api/status",
+            client,
+            &[("host", "rebind.attacker.net:8765"), bearer],
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // Origins: wildcard list, allowed hosts and local ones
+    for (origin, allowed) in [
+        ("https://ccgo.example.com", true),
+        ("https://ccgo.corp.internal", true),
+        ("http://localhost:8765", true),
+        ("https://evil.example.net", false),
+    ] {
+        let (status, headers, _) = send(
+            &app,
+            get_from(
+                "/ccgonext/api/status",
+                client,
+                &[local, bearer, ("origin", origin)],
+            ),
+        )
+        .await;
+        assert_eq!(status == StatusCode::OK, allowed, "{}", origin);
+        let cors = headers
+            .get("access-control-allow-origin")
+            .map(|v| v.to_str().unwrap().to_string());
+        assert_eq!(cors, allowed.then(|| origin.to_string()), "{}", origin);
+    }
+
+    // X-Forwarded-* count only from trusted proxies
+    let forwarded = [
+        ("host", "rebind.attacker.net"),
+        ("x-forwarded-host", "ccgo.corp.internal"),
+        ("x-forwarded-proto", "https"),
+        ("x-forwarded-for", "203.0.113.7"),
+        bearer,
+    ];
+    let (status, _, _) = send(&app, get_from("/ccgonext/api/status", client, &forwarded)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _, _) = send(&app, get_from("/ccgonext/api/status", proxy, &forwarded)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let mut login = login_request("shared");
+    *login.uri_mut() = "/ccgonext/api/login".parse().unwrap();
+    for (name, value) in &forwarded[..3] {
+        login.headers_mut().insert(*name, value.parse().unwrap());
+    }
+    login
+        .extensions_mut()
+        .insert(ConnectInfo(proxy.parse::<SocketAddr>().unwrap()));
+    let (status, headers, _) = send(&app, login).await;
+    assert_eq!(status, StatusCode::OK);
+    let set_cookie = headers["set-cookie"].to_str().unwrap();
+    assert!(set_cookie.contains("Path=/ccgonext/"), "{}", set_cookie);
+    assert!(set_cookie.contains("; Secure"), "{}", set_cookie);
+
+    fixture.manager.shutdown_all().await;
+}